@@ -5,8 +5,8 @@ use ffmpeg::format::{input, Pixel};
 use ffmpeg::media::Type;
 use ffmpeg::software::scaling::{context::Context, flag::Flags};
 use ffmpeg::util::frame::video::Video;
-use ort::execution_providers::{CPUExecutionProvider, CUDAExecutionProvider};
 use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort_common::execution_provider::ExecutionProviderArgs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
@@ -16,10 +16,8 @@ use tracing_subscriber::prelude::*;
 pub struct Args {
     /// Path to input image (.jpeg/.png) or video file (.mp4/.mkv).
     input: PathBuf,
-    /// Whether to attempt to use `cuda` hw acceleration.
-    /// This may silently fail and fallback to cpu acceleration presently.
-    #[arg(long, action, default_value = "false")]
-    cuda: bool,
+    #[command(flatten)]
+    execution_provider: ExecutionProviderArgs,
     /// Yolov8 onnx model file to use.
     #[arg(long, short, default_value = "_models/yolov8s.onnx")]
     model: String,
@@ -30,7 +28,7 @@ fn main() -> anyhow::Result<()> {
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "warn,gstreamed_ort=info".into()),
+                .unwrap_or_else(|_| "warn,ffmpeg_ort=info".into()),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
@@ -38,13 +36,10 @@ fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     // Load model into ort.
-    let (_ep, ep_name) = if args.cuda {
-        (CUDAExecutionProvider::default().build(), "cuda")
-    } else {
-        (CPUExecutionProvider::default().build(), "cpu")
-    };
-
-    let session = SessionBuilder::new()?
+    let (builder, ep_name) = args
+        .execution_provider
+        .configure_session(SessionBuilder::new()?)?;
+    let session = builder
         .with_optimization_level(GraphOptimizationLevel::Level3)?
         // .with_intra_threads(1)?
         .commit_from_file(&args.model)?;
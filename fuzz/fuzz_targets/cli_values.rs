@@ -0,0 +1,22 @@
+//! Option values of the command line and the language bindings' configs, eg. `--views`, which
+//! are often pasted from camera setup notes.
+#![no_main]
+
+use inference_common::sub_view::ViewLayout;
+use inference_common::tracker::TrackerKind;
+use inference_common::undistort::LensModel;
+use inference_common::wall_time::UtcOffset;
+use inference_common::yuv::PixelFormat;
+use libfuzzer_sys::fuzz_target;
+use ort_common::model_input::parse_input_size;
+
+fuzz_target!(|value: &str| {
+    if let Ok(layout) = value.parse::<ViewLayout>() {
+        let _ = layout.views(1920, 1080);
+    }
+    let _ = value.parse::<LensModel>();
+    let _ = value.parse::<UtcOffset>();
+    let _ = value.parse::<PixelFormat>();
+    let _ = value.parse::<TrackerKind>();
+    let _ = parse_input_size(value);
+});
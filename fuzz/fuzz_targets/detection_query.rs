@@ -0,0 +1,9 @@
+//! Free-form text queries over detection logs.
+#![no_main]
+
+use inference_common::detection_query::DetectionQuery;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    let _ = DetectionQuery::parse(text);
+});
@@ -0,0 +1,18 @@
+//! Detections json files, as loaded by `compare` and the golden tests. They are often produced by
+//! other tools or edited by hand.
+#![no_main]
+
+use inference_common::detection_logger::DetectionLog;
+use inference_common::detection_query::DetectionQuery;
+use inference_common::run_diff::compare_runs;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(detections) = serde_json::from_slice::<Vec<DetectionLog>>(data) else {
+        return;
+    };
+    // Any coordinates, frame numbers and tracker ids must be comparable and queryable.
+    let diff = compare_runs(&detections, &detections, 0.5);
+    let _ = diff.mean_abs_confidence_delta();
+    let _ = DetectionQuery::default().matching_frames(&detections);
+});
@@ -2,25 +2,27 @@
 //! in yolov8 example code in candle repo.
 
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::time::Instant;
 
 use candle_core::{DType, Device, IndexOp, Module, Tensor};
 use candle_nn::VarBuilder;
 use clap::ValueEnum;
+use gstreamed_common::pipeline::BufferTiming;
 use gstreamer as gst;
-use image::{DynamicImage, RgbImage};
+use image::{DynamicImage, GenericImageView, RgbImage};
 use inference_common::bbox::{non_maximum_suppression, BBoxesByClass, Bbox};
 use inference_common::frame_times::AggregatedTimes;
 use inference_common::img_dimensions::ImgDimensions;
-use inference_common::tracker::similari::prelude::Sort;
-use inference_common::tracker::unflatten_bboxes;
+use inference_common::output_guard;
+use inference_common::preproc::{preprocess, stride_aligned_dims};
+use inference_common::tracker::{unflatten_bboxes, Tracker};
 use inference_common::{annotate::annotate_image_with_bboxes, frame_times::FrameTimes};
 
 use crate::yolov8::{Multiples, YoloV8};
 
-// TODO move this to args
+/// Yolov8 model size.
 #[derive(Clone, Copy, ValueEnum, Debug)]
 pub enum Which {
     N,
@@ -30,10 +32,35 @@ pub enum Which {
     X,
 }
 
-fn model(which: Which) -> anyhow::Result<PathBuf> {
-    // download model from hf hub, cache it locally
-    let api = hf_hub::api::sync::Api::new()?;
-    let api = api.model("lmz/candle-yolo-v8".into());
+/// Floating point precision used for model weights and activations.
+#[derive(Clone, Copy, ValueEnum, Debug)]
+pub enum Precision {
+    F16,
+    Bf16,
+    F32,
+}
+
+impl Precision {
+    pub fn dtype(self) -> DType {
+        match self {
+            Precision::F16 => DType::F16,
+            Precision::Bf16 => DType::BF16,
+            Precision::F32 => DType::F32,
+        }
+    }
+}
+
+const HF_REPO: &str = "lmz/candle-yolo-v8";
+
+/// Resolves the safetensors weights for `which` model size from the hf-hub cache,
+/// downloading them first unless `offline` is set.
+///
+/// Uses the default hf-hub cache (`$HF_HOME`) unless `cache_dir` is given.
+pub fn model_weights(
+    which: Which,
+    cache_dir: Option<&Path>,
+    offline: bool,
+) -> anyhow::Result<PathBuf> {
     let size = match which {
         Which::N => "n",
         Which::S => "s",
@@ -41,11 +68,38 @@ fn model(which: Which) -> anyhow::Result<PathBuf> {
         Which::L => "l",
         Which::X => "x",
     };
-    let path = api.get(&format!("yolov8{size}.safetensors"))?;
+    let filename = format!("yolov8{size}.safetensors");
+
+    let cache = match cache_dir {
+        Some(dir) => hf_hub::Cache::new(dir.to_path_buf()),
+        None => hf_hub::Cache::from_env(),
+    };
+    if let Some(path) = cache.model(HF_REPO.into()).get(&filename) {
+        log::info!("Using cached weights: {path:?}");
+        return Ok(path);
+    }
+    if offline {
+        anyhow::bail!(
+            "Offline mode: {filename} from {HF_REPO} is not cached in {:?}. \
+             Run once without --offline to download it, or pass a local file via --weights.",
+            cache.path()
+        );
+    }
+
+    // download model from hf hub, cache it locally
+    log::info!("Downloading {filename} from {HF_REPO}");
+    let api = hf_hub::api::sync::ApiBuilder::from_cache(cache).build()?;
+    let path = api.model(HF_REPO.into()).get(&filename)?;
     Ok(path)
 }
 
-pub fn load_model(which: Which, device: &Device) -> anyhow::Result<YoloV8> {
+/// Loads yolov8 of size `which` from the given safetensors `weights` file.
+pub fn load_model(
+    which: Which,
+    weights: &Path,
+    dtype: DType,
+    device: &Device,
+) -> anyhow::Result<YoloV8> {
     let multiples = match which {
         Which::N => Multiples::n(),
         Which::S => Multiples::s(),
@@ -53,8 +107,7 @@ pub fn load_model(which: Which, device: &Device) -> anyhow::Result<YoloV8> {
         Which::L => Multiples::l(),
         Which::X => Multiples::x(),
     };
-    let model = model(which)?;
-    let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[model], DType::F32, device)? };
+    let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], dtype, device)? };
     // let weights = unsafe { candle_core::safetensors::MmapedFile::new(model)? };
     // let weights = weights.deserialize()?;
     // let vb = VarBuilder::from_safetensors(vec![weights], DType::F32, &Device::Cpu);
@@ -62,8 +115,11 @@ pub fn load_model(which: Which, device: &Device) -> anyhow::Result<YoloV8> {
     Ok(model)
 }
 
+/// Boxes of `pred` in `scaled_dims` sized frame coordinates, broken ones dropped and logged, see
+/// [output_guard].
 fn post_process_preds(
     pred: &Tensor,
+    scaled_dims: ImgDimensions,
     confidence_threshold: f32,
     nms_threshold: f32,
     frame_times: &mut FrameTimes,
@@ -102,16 +158,22 @@ fn post_process_preds(
                     data: vec![],
                     class: class_index,
                     tracker_id: None,
+                    track: None,
                 };
                 bboxes[class_index].push(bbox)
             }
         }
     }
+    let mut issues = output_guard::sanitize(&mut bboxes, scaled_dims);
     frame_times.bbox_extraction = start.elapsed();
 
     let start = Instant::now();
     non_maximum_suppression(&mut bboxes, nms_threshold);
+    issues.excess = output_guard::drop_excess(&mut bboxes);
     frame_times.nms = start.elapsed();
+    if issues.is_bad() {
+        log::warn!("Dropped broken model output: {issues}");
+    }
     Ok(bboxes)
 }
 
@@ -125,7 +187,8 @@ pub fn process_frame(
     frame: DynamicImage,
     model: &YoloV8,
     device: &Device,
-    tracker: Option<&mut Sort>,
+    dtype: DType,
+    tracker: Option<&mut dyn Tracker>,
     conf_thresh: f32,
     nms_thresh: f32,
     legend_size: u32,
@@ -133,33 +196,27 @@ pub fn process_frame(
 ) -> anyhow::Result<(DynamicImage, BBoxesByClass)> {
     // Resize buffer to match input size of model.
     let start = Instant::now();
-    let (scaled_width, scaled_height) = {
-        let w = frame.width() as usize;
-        let h = frame.height() as usize;
-        if w < h {
-            let w = w * 640 / h;
-            // Sizes have to be divisible by 32.
-            (w / 32 * 32, 640)
-        } else {
-            let h = h * 640 / w;
-            (640, h / 32 * 32)
-        }
-    };
+    // Sizes have to be divisible by 32.
+    let model_input_dims = stride_aligned_dims(frame.dimensions().into(), 640, 32);
+    let (image_array, scaled_dims) = preprocess(&frame, model_input_dims);
+    let (scaled_width, scaled_height) = (scaled_dims.width as usize, scaled_dims.height as usize);
     log::debug!("scaled w: {scaled_width}, scaled h: {scaled_height}");
-    let scaled_img = frame.resize_exact(
-        scaled_width as u32,
-        scaled_height as u32,
-        image::imageops::FilterType::Nearest,
-        // image::imageops::FilterType::CatmullRom,
-    );
     frame_times.buffer_resize = start.elapsed();
 
     // Convert image buffer to tensor.
     let start = Instant::now();
-    let data = scaled_img.into_rgb8().into_raw();
-    let image_t =
-        Tensor::from_vec(data, (scaled_height, scaled_width, 3), device)?.permute((2, 0, 1))?;
-    let image_t = (image_t.unsqueeze(0)?.to_dtype(DType::F32)? * (1. / 255.))?;
+    let (data, _offset) = image_array.into_raw_vec_and_offset();
+    let image_t = Tensor::from_vec(
+        data,
+        (
+            1,
+            3,
+            model_input_dims.height as usize,
+            model_input_dims.width as usize,
+        ),
+        device,
+    )?
+    .to_dtype(dtype)?;
     frame_times.buffer_to_tensor = start.elapsed();
 
     // Run forward pass.
@@ -168,15 +225,20 @@ pub fn process_frame(
     frame_times.forward_pass = start.elapsed();
 
     // Postprocess predictions into bboxes.
-    let bboxes_per_class = post_process_preds(&predictions, conf_thresh, nms_thresh, frame_times)?;
+    let bboxes_per_class = post_process_preds(
+        &predictions,
+        scaled_dims,
+        conf_thresh,
+        nms_thresh,
+        frame_times,
+    )?;
 
     // Track bboxes if tracker is provided.
     let bboxes_per_class = if let Some(tracker) = tracker {
         let start = Instant::now();
-        let tracked_bboxes = inference_common::tracker::predict_tracked_bboxes(
-            tracker,
-            ImgDimensions::new(scaled_width as f32, scaled_height as f32),
+        let tracked_bboxes = tracker.update(
             &bboxes_per_class,
+            ImgDimensions::new(scaled_width as f32, scaled_height as f32),
         );
         frame_times.tracking = start.elapsed();
 
@@ -203,15 +265,23 @@ pub fn process_frame(
     Ok((annotated, bboxes_per_class))
 }
 
+/// Runs [process_frame] on a `buffer` of RGB frames, overwriting it with the annotated frame and
+/// returning the bboxes (in scaled image coordinates).
+#[allow(clippy::too_many_arguments)]
 pub fn process_buffer(
     frame_dims: ImgDimensions,
     model: &YoloV8,
     device: &Device,
-    tracker: &Mutex<Sort>,
+    dtype: DType,
+    tracker: &Mutex<Box<dyn Tracker>>,
     agg_times: &mut AggregatedTimes,
     buffer: &mut gst::Buffer,
-) {
-    let mut frame_times = FrameTimes::default();
+    timing: &BufferTiming,
+) -> BBoxesByClass {
+    let mut frame_times = FrameTimes {
+        queue_wait: timing.queue_wait,
+        ..Default::default()
+    };
 
     let start = Instant::now();
     // read buffer into an image
@@ -232,11 +302,12 @@ pub fn process_buffer(
 
     // process it using some model + draw overlays on the output image
     let mut tracker = tracker.lock().unwrap();
-    let (processed, _bboxes) = process_frame(
+    let (processed, bboxes) = process_frame(
         image,
         model,
         device,
-        Some(&mut tracker),
+        dtype,
+        Some(&mut **tracker),
         0.25,
         0.45,
         14,
@@ -251,7 +322,9 @@ pub fn process_buffer(
     let mut dst = writable.as_mut_slice();
     dst.write_all(processed.to_rgb8().as_raw()).unwrap();
     frame_times.buffer_to_frame = start.elapsed();
+    frame_times.latency = timing.latency().unwrap_or_default();
 
     log::debug!("{frame_times:?}");
     agg_times.push(frame_times);
+    bboxes
 }
@@ -0,0 +1,6 @@
+//! YOLOv8 inference on images and videos with candle, run by the `gstreamed_candle` binary.
+
+pub mod inference;
+pub mod process_image;
+pub mod process_video;
+pub mod yolov8;
@@ -1,12 +1,9 @@
-mod inference;
-mod process_image;
-mod process_video;
-mod yolov8;
-
-use crate::inference::Which;
 use candle_core::Device;
 use clap::Parser;
+use gstreamed_candle::inference::{self, Precision, Which};
+use gstreamed_candle::{process_image, process_video};
 use gstreamer as gst;
+use inference_common::tracker::TrackerKind;
 use std::path::PathBuf;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -14,9 +11,34 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 pub struct Args {
     /// Path to input image (.jpeg/.png) or video file (.mp4/.mkv).
     input: PathBuf,
+    /// Whether to use nvidia `cuda` hw acceleration.
+    /// Requires building with the `cuda` feature.
     #[arg(long, action, default_value = "false")]
     cuda: bool,
-    // TODO dtype switch
+    /// Whether to use Apple `metal` hw acceleration.
+    /// Requires building with the `metal` feature.
+    #[arg(long, action, default_value = "false", conflicts_with = "cuda")]
+    metal: bool,
+    /// Precision of model weights and activations.
+    /// Reduced precision (f16/bf16) is mainly useful on cuda/metal devices.
+    #[arg(long, value_enum, default_value = "f32")]
+    dtype: Precision,
+    /// Yolov8 model size, weights are fetched from hf-hub.
+    #[arg(long, value_enum, default_value = "s")]
+    which: Which,
+    /// Local yolov8 .safetensors file to use instead of hf-hub weights.
+    /// Must match the `--which` model size.
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// Directory for hf-hub cached weights, defaults to the hf-hub cache (`$HF_HOME`).
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+    /// Never download weights, fail if they're not cached already.
+    #[arg(long, action, default_value = "false")]
+    offline: bool,
+    /// Tracking algorithm for videos: sort, ocsort or botsort.
+    #[arg(long, default_value = "sort")]
+    tracker: TrackerKind,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -35,13 +57,22 @@ fn main() -> anyhow::Result<()> {
 
     let device = if args.cuda {
         Device::new_cuda(0)?
+    } else if args.metal {
+        Device::new_metal(0)?
     } else {
         Device::Cpu
     };
+    let dtype = args.dtype.dtype();
+    if device.is_cpu() && !matches!(args.dtype, Precision::F32) {
+        log::warn!("{dtype:?} on cpu is usually slower than f32");
+    }
 
-    // Load models using hf-hub.
-    let which = Which::S;
-    let model = inference::load_model(which, &device)?;
+    // Load model from local weights, or using hf-hub.
+    let weights = match args.weights {
+        Some(weights) => weights,
+        None => inference::model_weights(args.which, args.cache_dir.as_deref(), args.offline)?,
+    };
+    let model = inference::load_model(args.which, &weights, dtype, &device)?;
 
     // Branch on file extension: video vs image.
     let ext = args
@@ -51,9 +82,15 @@ fn main() -> anyhow::Result<()> {
         .map(|s| s.to_ascii_lowercase());
 
     match ext.as_deref() {
-        Some("mp4") | Some("mkv") => process_video::process_video(&args.input, model, device)?,
+        Some("mp4") | Some("mkv") => {
+            let detections =
+                process_video::process_video(&args.input, model, device, dtype, args.tracker)?;
+            let detections_path = args.input.with_extension("detections.json");
+            log::info!("Writing detection logs: {detections_path:?}");
+            serde_json::to_writer(std::fs::File::create(detections_path)?, &detections)?;
+        }
         Some("jpeg") | Some("jpg") | Some("png") => {
-            process_image::process_image(&args.input, model, device)?
+            process_image::process_image(&args.input, model, device, dtype)?
         }
         Some(unk) => log::error!("Unhandled file extension: {unk}"),
         None => log::error!(
@@ -1,12 +1,17 @@
 use std::path::Path;
 
-use candle_core::Device;
+use candle_core::{DType, Device};
 use inference_common::{frame_meta::FrameMeta, frame_times::FrameTimes};
 
 use crate::{inference, yolov8::YoloV8};
 
 /// Performs inference on a single image file using Candle.
-pub fn process_image(path: &Path, model: YoloV8, device: Device) -> anyhow::Result<()> {
+pub fn process_image(
+    path: &Path,
+    model: YoloV8,
+    device: Device,
+    dtype: DType,
+) -> anyhow::Result<()> {
     let mut frame_times = FrameTimes::default();
 
     // Read image.
@@ -17,6 +22,7 @@ pub fn process_image(path: &Path, model: YoloV8, device: Device) -> anyhow::Resu
         og_image,
         &model,
         &device,
+        dtype,
         None,
         0.25,
         0.45,
@@ -1,17 +1,30 @@
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use candle_core::Device;
-use gstreamed_common::{discovery, pipeline::build_pipeline};
+use candle_core::{DType, Device};
+use gstreamed_common::discovery;
+use gstreamed_common::pipeline::{build_pipeline, FrameDrops, QueueConfig};
 use gstreamer::{self as gst};
 use gstreamer::{prelude::*, MessageView};
+use inference_common::detection_logger::{DetectionLog, DetectionLogExt};
 use inference_common::frame_times::AggregatedTimes;
 use inference_common::img_dimensions::ImgDimensions;
+use inference_common::preproc::{stride_aligned_dims, Letterbox};
+use inference_common::tracker::TrackerKind;
+use inference_common::yuv::PixelFormat;
 
 use crate::{inference, yolov8::YoloV8};
 
-/// Performs inference on a video file, using a gstreamer pipeline + candle.
-pub fn process_video(input: &Path, model: YoloV8, device: Device) -> anyhow::Result<()> {
+/// Performs inference on a video file, using a gstreamer pipeline + candle, returning the
+/// detections of all frames (in frame coordinates).
+pub fn process_video(
+    input: &Path,
+    model: YoloV8,
+    device: Device,
+    dtype: DType,
+    tracker: TrackerKind,
+) -> anyhow::Result<Vec<DetectionLog>> {
     // First, find out resolution of input file.
     log::info!("Discovering media properties of {input:?}");
     let file_info = discovery::discover(input)?;
@@ -19,21 +32,56 @@ pub fn process_video(input: &Path, model: YoloV8, device: Device) -> anyhow::Res
     let frame_dims = ImgDimensions::new(file_info.width as f32, file_info.height as f32);
 
     let agg_times = Arc::new(Mutex::new(AggregatedTimes::default()));
+    let detections = Arc::new(Mutex::new(Vec::new()));
+    // Same model input size as chosen per frame by `inference::process_frame`.
+    let letterbox = Letterbox::new(frame_dims, stride_aligned_dims(frame_dims, 640, 32));
 
     // Use tracker for candle pipeline, too.
-    let tracker = inference_common::tracker::sort_tracker();
+    let tracker = inference_common::tracker::shared_tracker(tracker);
 
     let output_path = input.with_extension("out.mkv");
 
     // Build gst pipeline, which performs inference using the loaded model.
     let scoped_agg = Arc::clone(&agg_times);
+    let scoped_detections = Arc::clone(&detections);
+    let frame_count = AtomicU64::new(0);
     let pipeline = build_pipeline(
         input.to_str().unwrap(),
         output_path.to_str().unwrap(),
         false,
-        move |buf| {
+        PixelFormat::Rgb,
+        QueueConfig::default(),
+        &Arc::new(FrameDrops::default()),
+        move |buf, timing| {
             let mut agg_times = scoped_agg.lock().unwrap();
-            inference::process_buffer(frame_dims, &model, &device, &tracker, &mut agg_times, buf);
+            let bboxes = inference::process_buffer(
+                frame_dims,
+                &model,
+                &device,
+                dtype,
+                &tracker,
+                &mut agg_times,
+                buf,
+                timing,
+            );
+
+            // Frames are numbered from 1, as by gstreamed_ort.
+            let frame_number = frame_count.fetch_add(1, Ordering::Relaxed) + 1;
+            let mut detections = scoped_detections.lock().unwrap();
+            let timestamp_ms = buf.pts().unwrap_or_default().mseconds();
+            for (class_idx, class_bboxes) in bboxes.iter().enumerate() {
+                for bbox in class_bboxes {
+                    detections.push(DetectionLog::from_bbox(
+                        frame_number,
+                        timestamp_ms,
+                        &letterbox.unmap(bbox),
+                        class_idx,
+                        frame_dims.width,
+                        frame_dims.height,
+                        None,
+                    ));
+                }
+            }
         },
     )?;
     log::info!("Starting gst pipeline");
@@ -61,15 +109,8 @@ pub fn process_video(input: &Path, model: YoloV8, device: Device) -> anyhow::Res
     pipeline.set_state(gst::State::Null).unwrap();
 
     // Print perf stats, ignoring first (outlier) frame.
-    let agg = agg_times.lock().unwrap();
-    let avg = agg.avg(true);
-    log::info!("Average frame times: {avg:?}");
-
-    let min = agg.min(true);
-    log::info!("Min frame times: {min:?}");
-
-    let max = agg.max(true);
-    log::info!("Max frame times: {max:?}");
+    agg_times.lock().unwrap().log_summary(true);
 
-    Ok(())
+    let detections = std::mem::take(&mut *detections.lock().unwrap());
+    Ok(detections)
 }
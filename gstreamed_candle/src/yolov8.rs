@@ -1,4 +1,4 @@
-use candle_core::{DType, IndexOp, Result, Tensor, D};
+use candle_core::{IndexOp, Result, Tensor, D};
 use candle_nn::{batch_norm, conv2d, conv2d_no_bias, Conv2d, Conv2dConfig, Module, VarBuilder};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -482,13 +482,15 @@ fn make_anchors(
     grid_cell_offset: f64,
 ) -> Result<(Tensor, Tensor)> {
     let dev = xs0.device();
+    // Match the activations' dtype, so reduced precision models don't need casts.
+    let dtype = xs0.dtype();
     let mut anchor_points = vec![];
     let mut stride_tensor = vec![];
     for (xs, stride) in [(xs0, s0), (xs1, s1), (xs2, s2)] {
         // xs is only used to extract the h and w dimensions.
         let (_, _, h, w) = xs.dims4()?;
-        let sx = (Tensor::arange(0, w as u32, dev)?.to_dtype(DType::F32)? + grid_cell_offset)?;
-        let sy = (Tensor::arange(0, h as u32, dev)?.to_dtype(DType::F32)? + grid_cell_offset)?;
+        let sx = (Tensor::arange(0, w as u32, dev)?.to_dtype(dtype)? + grid_cell_offset)?;
+        let sy = (Tensor::arange(0, h as u32, dev)?.to_dtype(dtype)? + grid_cell_offset)?;
         let sx = sx
             .reshape((1, sx.elem_count()))?
             .repeat((h, 1))?
@@ -498,7 +500,7 @@ fn make_anchors(
             .repeat((1, w))?
             .flatten_all()?;
         anchor_points.push(Tensor::stack(&[&sx, &sy], D::Minus1)?);
-        stride_tensor.push((Tensor::ones(h * w, DType::F32, dev)? * stride as f64)?);
+        stride_tensor.push((Tensor::ones(h * w, dtype, dev)? * stride as f64)?);
     }
     let anchor_points = Tensor::cat(anchor_points.as_slice(), 0)?;
     let stride_tensor = Tensor::cat(stride_tensor.as_slice(), 0)?.unsqueeze(1)?;
@@ -0,0 +1,99 @@
+//! Golden file tests of the candle backend: runs the sample videos of gstreamed_ort
+//! (`gstreamed_ort/tests/golden/`) through the pipeline and compares their detections against
+//! the stored `<sample>.candle.golden.json`.
+//!
+//! Needs gstreamer, sample videos and the `yolov8s` weights at `GOLDEN_WEIGHTS` (the hf-hub cache
+//! by default), so it's ignored by default, see `gstreamed_ort/tests/golden/README.md`. Run with
+//! `UPDATE_GOLDEN=1` to (re)write the golden files after an intended change, and review their
+//! diff.
+
+use std::path::{Path, PathBuf};
+
+use candle_core::{DType, Device};
+use gstreamed_candle::inference::{self, Which};
+use gstreamed_candle::process_video;
+use inference_common::run_diff::{compare_runs, load_detections};
+use inference_common::tracker::TrackerKind;
+
+/// Detections overlapping their golden one less than this are missing.
+const IOU_THRESHOLD: f32 = 0.9;
+/// Largest confidence change of a detection still matching its golden one.
+const CONFIDENCE_TOLERANCE: f32 = 0.02;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../gstreamed_ort/tests/golden")
+}
+
+/// Sample videos, sorted.
+fn samples() -> Vec<PathBuf> {
+    let mut samples: Vec<_> = std::fs::read_dir(golden_dir())
+        .expect("golden dir is readable")
+        .map(|entry| entry.expect("golden dir is readable").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "mp4" || extension == "mkv")
+        })
+        .collect();
+    samples.sort();
+    samples
+}
+
+#[test]
+#[ignore = "needs sample videos and the weights, see gstreamed_ort/tests/golden/README.md"]
+fn golden_detections() -> anyhow::Result<()> {
+    let samples = samples();
+    anyhow::ensure!(
+        !samples.is_empty(),
+        "No sample videos in {:?}, see its README.md",
+        golden_dir()
+    );
+    let weights = match std::env::var_os("GOLDEN_WEIGHTS") {
+        Some(weights) => PathBuf::from(weights),
+        None => inference::model_weights(Which::S, None, true)?,
+    };
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    gstreamer::init()?;
+
+    let mut failures = Vec::new();
+    for sample in &samples {
+        let model = inference::load_model(Which::S, &weights, DType::F32, &Device::Cpu)?;
+        let detections = process_video::process_video(
+            sample,
+            model,
+            Device::Cpu,
+            DType::F32,
+            TrackerKind::default_for(true),
+        )?;
+        // Annotated output, written next to the sample.
+        std::fs::remove_file(sample.with_extension("out.mkv"))?;
+
+        let golden_path = sample.with_extension("candle.golden.json");
+        if update {
+            serde_json::to_writer(std::fs::File::create(&golden_path)?, &detections)?;
+            eprintln!("Updated {golden_path:?}");
+            continue;
+        }
+        let diff = compare_runs(&load_detections(&golden_path)?, &detections, IOU_THRESHOLD);
+        let changed = diff
+            .frames
+            .iter()
+            .filter(|frame| frame.has_changes(CONFIDENCE_TOLERANCE))
+            .count();
+        if changed > 0 {
+            failures.push(format!(
+                "{sample:?}: {changed} frames differ, {} missing and {} new detections, \
+                 {} id switches",
+                diff.missing_count(),
+                diff.new_count(),
+                diff.id_switches()
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Detections differ from the golden files, rerun with UPDATE_GOLDEN=1 if intended:\n{}",
+        failures.join("\n")
+    );
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! Continuous (24/7) recording of a live stream into rolling segments, pruned by age and total
+//! size.
+//!
+//! Segments are named after the unix time in ms they start at, eg. `1760616000000.mkv`, so
+//! footage of a time range is found by listing the directory, see
+//! [ContinuousRecorder::segments_overlapping].
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use gstreamer::prelude::*;
+use gstreamer::{self as gst, glib};
+
+/// Recorded segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub path: PathBuf,
+    pub start: SystemTime,
+    pub bytes: u64,
+}
+
+/// Settings of continuous recording into a directory, see [ContinuousRecorder::elements].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContinuousRecorder {
+    pub dir: PathBuf,
+    /// Length of each segment, cut at the next keyframe.
+    pub segment_duration: Duration,
+    /// Segments starting longer ago than this are deleted.
+    pub retention: Option<Duration>,
+    /// Oldest segments are deleted while all of them take up more than this.
+    pub max_bytes: Option<u64>,
+}
+
+impl ContinuousRecorder {
+    /// Output branch elements encoding the annotated frames into segments, see
+    /// [crate::output_branch::OutputBranch::add]. Old segments are pruned whenever a new one
+    /// starts.
+    pub fn elements(&self) -> Result<Vec<gst::Element>, glib::BoolError> {
+        let queue = gst::ElementFactory::make_with_name("queue", None)?;
+        let convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
+        let encoder = gst::ElementFactory::make_with_name("x264enc", None)?;
+        encoder.set_property_from_str("bitrate", "4096");
+        let parse = gst::ElementFactory::make_with_name("h264parse", None)?;
+        let sink = gst::ElementFactory::make_with_name("splitmuxsink", None)?;
+        sink.set_property("muxer-factory", "matroskamux");
+        sink.set_property("max-size-time", self.segment_duration.as_nanos() as u64);
+        // Cut segments on time, rather than waiting for the encoder's next keyframe.
+        sink.set_property("send-keyframe-requests", true);
+        // Don't wait for preroll, the rest of the pipeline is already playing.
+        sink.set_property("async-handling", true);
+
+        let recorder = self.clone();
+        sink.connect("format-location", false, move |_args| {
+            if let Err(e) = recorder.prune() {
+                log::warn!("Failed to prune recordings in {:?}: {e}", recorder.dir);
+            }
+            let start_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let path = recorder.dir.join(format!("{start_ms}.mkv"));
+            log::info!("Recording segment {path:?}");
+            Some(path.to_string_lossy().into_owned().to_value())
+        });
+        Ok(vec![queue, convert, encoder, parse, sink])
+    }
+
+    /// Recorded segments, oldest first.
+    pub fn segments(&self) -> std::io::Result<Vec<Segment>> {
+        let mut segments = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(start) = segment_start(&path) else {
+                continue;
+            };
+            let bytes = std::fs::metadata(&path)?.len();
+            segments.push(Segment { path, start, bytes });
+        }
+        segments.sort_by_key(|segment| segment.start);
+        Ok(segments)
+    }
+
+    /// Segments holding footage between `from` and `to`, oldest first.
+    pub fn segments_overlapping(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> std::io::Result<Vec<Segment>> {
+        let segments = self.segments()?;
+        // Segments end where the next one starts, the last one is still being written.
+        let ends: Vec<Option<SystemTime>> = segments
+            .iter()
+            .skip(1)
+            .map(|next| Some(next.start))
+            .chain([None])
+            .collect();
+        Ok(segments
+            .into_iter()
+            .zip(ends)
+            .filter(|(segment, end)| segment.start < to && end.is_none_or(|end| end > from))
+            .map(|(segment, _)| segment)
+            .collect())
+    }
+
+    /// Deletes segments past the retention, then the oldest ones while over the size limit.
+    /// The newest segment is kept, it may still be written.
+    pub fn prune(&self) -> std::io::Result<()> {
+        let mut segments = self.segments()?;
+        segments.pop();
+        let mut total: u64 = segments.iter().map(|segment| segment.bytes).sum();
+        let now = SystemTime::now();
+        for segment in segments {
+            let expired = self.retention.is_some_and(|retention| {
+                now.duration_since(segment.start).unwrap_or_default() > retention
+            });
+            let over_quota = self.max_bytes.is_some_and(|max_bytes| total > max_bytes);
+            if !expired && !over_quota {
+                break;
+            }
+            log::info!("Deleting recording {:?}", segment.path);
+            std::fs::remove_file(&segment.path)?;
+            total -= segment.bytes;
+        }
+        Ok(())
+    }
+}
+
+/// Start time of the segment at `path`, `None` for other files.
+fn segment_start(path: &Path) -> Option<SystemTime> {
+    if path.extension()? != "mkv" {
+        return None;
+    }
+    let start_ms: u64 = path.file_stem()?.to_str()?.parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_millis(start_ms))
+}
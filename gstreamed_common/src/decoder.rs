@@ -0,0 +1,193 @@
+//! Choice of the video decoders decodebin picks, to move decoding off the CPU on multi-camera
+//! boxes, and of the format webcams stream in.
+//!
+//! decodebin picks decoders by rank. Preferring a backend raises its decoders able to decode the
+//! input above all others and drops other hardware decoders, software decoders are kept as the
+//! fallback decodebin moves on to if a hardware decoder fails to start. Ranks are process wide.
+
+use std::fmt;
+use std::str::FromStr;
+
+use gstreamer as gst;
+use gstreamer::prelude::*;
+
+/// Decoder implementations to prefer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecoderBackend {
+    /// Decoders by their default rank, usually software ones.
+    #[default]
+    Auto,
+    /// CPU decoders only, eg. `avdec_h264`.
+    Software,
+    /// NVIDIA NVDEC: `nvh264dec`, ... or `nvv4l2decoder` on Jetson.
+    Nvdec,
+    /// VA-API on Intel and AMD GPUs: `vah264dec`, ... or the older `vaapih264dec`, ...
+    Vaapi,
+    /// V4L2 memory to memory decoders of SoCs, eg. Raspberry Pi or Rockchip: `v4l2h264dec`, ...
+    V4l2,
+}
+
+impl DecoderBackend {
+    pub const ALL: [DecoderBackend; 5] = [
+        DecoderBackend::Auto,
+        DecoderBackend::Software,
+        DecoderBackend::Nvdec,
+        DecoderBackend::Vaapi,
+        DecoderBackend::V4l2,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            DecoderBackend::Auto => "auto",
+            DecoderBackend::Software => "software",
+            DecoderBackend::Nvdec => "nvdec",
+            DecoderBackend::Vaapi => "vaapi",
+            DecoderBackend::V4l2 => "v4l2",
+        }
+    }
+
+    /// Hardware backend of the decoder element `factory_name`, [DecoderBackend::Software] if none.
+    fn of_decoder(factory_name: &str) -> Self {
+        if factory_name.starts_with("nv") {
+            DecoderBackend::Nvdec
+        } else if factory_name.starts_with("va") {
+            DecoderBackend::Vaapi
+        } else if factory_name.starts_with("v4l2") {
+            DecoderBackend::V4l2
+        } else {
+            DecoderBackend::Software
+        }
+    }
+
+    /// Makes decodebin prefer decoders of this backend, for input of `codec_caps` if known.
+    ///
+    /// Falls back to the default decoders with a warning if the backend has no decoder for the
+    /// input, eg. because its plugin isn't installed or doesn't support the codec.
+    pub fn prefer(self, codec_caps: Option<&gst::Caps>) {
+        if self == DecoderBackend::Auto {
+            return;
+        }
+        let decoders = gst::ElementFactory::factories_with_type(
+            gst::ElementFactoryType::DECODER | gst::ElementFactoryType::MEDIA_VIDEO,
+            gst::Rank::NONE,
+        );
+        let usable: Vec<_> = decoders
+            .iter()
+            .filter(|factory| Self::of_decoder(&factory.name()) == self)
+            .filter(|factory| codec_caps.is_none_or(|caps| factory.can_sink_any_caps(caps)))
+            .map(|factory| factory.name())
+            .collect();
+        let input = codec_caps.map_or("the input".to_string(), |caps| {
+            caps.structure(0)
+                .map_or(caps.to_string(), |s| s.name().to_string())
+        });
+        if usable.is_empty() {
+            log::warn!("No {self} decoder for {input}, falling back to the default decoders");
+            return;
+        }
+
+        for factory in decoders.iter() {
+            match Self::of_decoder(&factory.name()) {
+                DecoderBackend::Software => {}
+                backend if backend == self => factory.set_rank(gst::Rank::PRIMARY + 100),
+                _ => factory.set_rank(gst::Rank::NONE),
+            }
+        }
+        log::info!(
+            "Decoding {input} with {self} decoders: {}",
+            usable.join(", ")
+        );
+    }
+}
+
+impl fmt::Display for DecoderBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for DecoderBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown decoder {s:?}, expected one of: auto, software, nvdec, vaapi, v4l2"
+                )
+            })
+    }
+}
+
+/// Format to capture webcam frames in. Compressed formats are decoded by decodebin, many USB
+/// cameras only reach their full resolution and frame rate with MJPEG.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WebcamCodec {
+    /// Uncompressed frames, converted as they are.
+    #[default]
+    Raw,
+    Mjpeg,
+    H264,
+    H265,
+    Vp9,
+}
+
+impl WebcamCodec {
+    pub const ALL: [WebcamCodec; 5] = [
+        WebcamCodec::Raw,
+        WebcamCodec::Mjpeg,
+        WebcamCodec::H264,
+        WebcamCodec::H265,
+        WebcamCodec::Vp9,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            WebcamCodec::Raw => "raw",
+            WebcamCodec::Mjpeg => "mjpeg",
+            WebcamCodec::H264 => "h264",
+            WebcamCodec::H265 => "h265",
+            WebcamCodec::Vp9 => "vp9",
+        }
+    }
+
+    /// Caps to request from the camera.
+    pub fn caps(self) -> gst::Caps {
+        let media_type = match self {
+            WebcamCodec::Raw => "video/x-raw",
+            WebcamCodec::Mjpeg => "image/jpeg",
+            WebcamCodec::H264 => "video/x-h264",
+            WebcamCodec::H265 => "video/x-h265",
+            WebcamCodec::Vp9 => "video/x-vp9",
+        };
+        gst::Caps::new_empty_simple(media_type)
+    }
+}
+
+impl fmt::Display for WebcamCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for WebcamCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let s = match s.as_str() {
+            "hevc" => "h265",
+            "mjpg" | "jpeg" => "mjpeg",
+            s => s,
+        };
+        Self::ALL
+            .into_iter()
+            .find(|codec| codec.name() == s)
+            .ok_or_else(|| {
+                format!("unknown webcam codec {s:?}, expected one of: raw, mjpeg, h264, h265, vp9")
+            })
+    }
+}
@@ -1,7 +1,12 @@
 use gstreamer as gst;
-use gstreamer::prelude::Displayable;
+use gstreamer::glib;
+use gstreamer::prelude::*;
 use gstreamer_pbutils::prelude::DiscovererStreamInfoExt;
-use gstreamer_pbutils::{Discoverer, DiscovererInfo, DiscovererStreamInfo};
+use gstreamer_pbutils::{
+    Discoverer, DiscovererAudioInfo, DiscovererInfo, DiscovererResult, DiscovererStreamInfo,
+    DiscovererVideoInfo,
+};
+use serde::Serialize;
 use std::path::Path;
 
 /// Info about the media in the given file.
@@ -9,6 +14,8 @@ use std::path::Path;
 pub struct FileInfo {
     pub width: i32,
     pub height: i32,
+    /// Caps of the encoded video stream, eg. `video/x-h264`, to pick decoders for.
+    pub codec_caps: gst::Caps,
 }
 
 fn print_tags(info: &DiscovererInfo) {
@@ -68,7 +75,11 @@ fn discover_resolution_from_stream_info(stream_info: &DiscovererStreamInfo) -> O
                     height = Some(value.get().unwrap());
                 }
                 if let (Some(width), Some(height)) = (width, height) {
-                    return Some(FileInfo { width, height });
+                    return Some(FileInfo {
+                        width,
+                        height,
+                        codec_caps: caps.clone(),
+                    });
                 }
                 // println!("{name:?}: {value:?}");
             }
@@ -95,15 +106,109 @@ fn discover_resolution(info: &DiscovererInfo) -> anyhow::Result<FileInfo> {
 }
 
 fn raw_discoverer_info(path: &Path) -> anyhow::Result<DiscovererInfo> {
-    let timeout = gst::ClockTime::from_seconds(10);
-    let discoverer = Discoverer::new(timeout)?;
     // we need to pass absolute path to discoverer as file uri
     let file_uri = format!("file://{}", path.canonicalize()?.to_str().unwrap());
-    Ok(discoverer.discover_uri(&file_uri)?)
+    discover_uri(&file_uri)
+}
+
+fn discover_uri(uri: &str) -> anyhow::Result<DiscovererInfo> {
+    let timeout = gst::ClockTime::from_seconds(10);
+    let discoverer = Discoverer::new(timeout)?;
+    Ok(discoverer.discover_uri(uri)?)
+}
+
+/// Plugin providing the demuxer or decoder for `media_type`, for the common ones.
+fn plugin_for(media_type: &str) -> Option<&'static str> {
+    Some(match media_type {
+        "video/quicktime" => "qtdemux (gst-plugins-good)",
+        "video/x-matroska" | "video/webm" => "matroskademux (gst-plugins-good)",
+        "video/x-msvideo" => "avidemux (gst-plugins-good)",
+        "video/mpegts" => "tsdemux (gst-plugins-bad)",
+        "video/x-h264" | "video/x-h265" | "video/mpeg" | "video/x-divx" | "video/x-xvid" => {
+            "gst-libav, or a hardware decoder plugin"
+        }
+        "video/x-vp8" | "video/x-vp9" => "vpx (gst-plugins-good)",
+        "video/x-av1" => "dav1d (gst-plugins-rs)",
+        _ => return None,
+    })
 }
 
+/// Media type the container of `path` is expected to have, by its extension.
+fn container_media_type(path: &Path) -> Option<&'static str> {
+    Some(match path.extension()?.to_str()? {
+        "mp4" | "mov" | "m4v" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "webm" => "video/webm",
+        "avi" => "video/x-msvideo",
+        "ts" | "m2ts" => "video/mpegts",
+        _ => return None,
+    })
+}
+
+/// Human readable description of a missing element, from its installer details
+/// (`gstreamer|1.0|<app>|<description>|<type>-<caps>`).
+fn describe_missing(details: &str) -> String {
+    let mut fields = details.split('|').skip(3);
+    let description = fields.next().unwrap_or(details);
+    let media_type = fields
+        .next()
+        .and_then(|kind| kind.split_once('-'))
+        .map(|(_, caps)| caps.split(',').next().unwrap_or(caps).trim());
+    match media_type.and_then(plugin_for) {
+        Some(plugin) => format!("{description} (install {plugin})"),
+        None => description.to_string(),
+    }
+}
+
+/// Fails with an actionable error if the media in `info` can't be decoded.
+fn check_decodable(path: &Path, info: &DiscovererInfo) -> anyhow::Result<()> {
+    match info.result() {
+        DiscovererResult::Ok => {}
+        DiscovererResult::MissingPlugins => {
+            let missing: Vec<_> = info
+                .missing_elements_installer_details()
+                .iter()
+                .map(|details| describe_missing(details))
+                .collect();
+            anyhow::bail!(
+                "Can't decode {path:?}, GStreamer is missing: {}",
+                missing.join(", ")
+            );
+        }
+        DiscovererResult::Timeout => anyhow::bail!("Timed out discovering {path:?}"),
+        result => anyhow::bail!("Failed to discover {path:?}: {result:?}"),
+    }
+    anyhow::ensure!(
+        !info.video_streams().is_empty(),
+        "{path:?} has no video stream"
+    );
+    Ok(())
+}
+
+/// Error for a file the discoverer failed on, hinting at the plugin needed if that's the cause.
+fn discovery_error(path: &Path, error: anyhow::Error) -> anyhow::Error {
+    let missing_plugin = error.downcast_ref::<glib::Error>().is_some_and(|e| {
+        e.matches(gst::CoreError::MissingPlugin)
+            || e.matches(gst::StreamError::CodecNotFound)
+            || e.matches(gst::StreamError::TypeNotFound)
+    });
+    if !missing_plugin {
+        return error.context(format!("Failed to discover {path:?}"));
+    }
+    let hint = match container_media_type(path).and_then(plugin_for) {
+        Some(plugin) => format!("install {plugin}"),
+        None => "check `gst-inspect-1.0` for the demuxer and decoder needed".to_string(),
+    };
+    error.context(format!(
+        "Can't decode {path:?}, GStreamer is missing a plugin: {hint}"
+    ))
+}
+
+/// Discovers the resolution of the video in the file at `path`, failing with a hint at the
+/// GStreamer plugin to install if it can't be decoded.
 pub fn discover(path: &Path) -> anyhow::Result<FileInfo> {
-    let info = raw_discoverer_info(path)?;
+    let info = raw_discoverer_info(path).map_err(|e| discovery_error(path, e))?;
+    check_decodable(path, &info)?;
     // print_discoverer_info is str8 copypasta from https://gitlab.freedesktop.org/gstreamer/gstreamer-rs/-/blob/main/examples/src/bin/discoverer.rs
     // useful for debugging, but not necessary
     print_discoverer_info(&info)?;
@@ -111,3 +216,83 @@ pub fn discover(path: &Path) -> anyhow::Result<FileInfo> {
     // extract stuff we actually care about, which is just the resolution p much
     discover_resolution(&info)
 }
+
+/// Media info of a file or stream, see [probe].
+#[derive(Debug, Serialize)]
+pub struct MediaInfo {
+    pub uri: String,
+    pub duration_ms: Option<u64>,
+    pub live: bool,
+    pub seekable: bool,
+    /// Media type of the outermost stream, eg. `video/quicktime` for mp4 files.
+    pub container: Option<String>,
+    pub streams: Vec<StreamInfo>,
+}
+
+/// A single (elementary) stream of a [MediaInfo].
+#[derive(Debug, Serialize)]
+pub struct StreamInfo {
+    /// `video`, `audio`, `subtitles`, ...
+    pub kind: String,
+    pub stream_id: Option<String>,
+    /// Media type, eg. `video/x-h264`.
+    pub codec: Option<String>,
+    /// Full caps, including profile, format etc.
+    pub caps: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub framerate: Option<f64>,
+    pub bitrate: Option<u32>,
+    pub channels: Option<u32>,
+    pub sample_rate: Option<u32>,
+}
+
+impl StreamInfo {
+    fn new(stream: &DiscovererStreamInfo) -> Self {
+        let caps = stream.caps();
+        let video = stream.downcast_ref::<DiscovererVideoInfo>();
+        let audio = stream.downcast_ref::<DiscovererAudioInfo>();
+        Self {
+            kind: stream.stream_type_nick().to_string(),
+            stream_id: stream.stream_id().map(|id| id.to_string()),
+            codec: caps
+                .as_ref()
+                .and_then(|caps| caps.structure(0))
+                .map(|structure| structure.name().to_string()),
+            caps: caps.as_ref().map(|caps| caps.to_string()),
+            width: video.map(|video| video.width()),
+            height: video.map(|video| video.height()),
+            framerate: video
+                .map(|video| video.framerate())
+                .filter(|fps| fps.numer() > 0 && fps.denom() > 0)
+                .map(|fps| fps.numer() as f64 / fps.denom() as f64),
+            bitrate: video
+                .map(|video| video.bitrate())
+                .filter(|&bitrate| bitrate > 0),
+            channels: audio.map(|audio| audio.channels()),
+            sample_rate: audio.map(|audio| audio.sample_rate()),
+        }
+    }
+}
+
+/// Discovers codecs, resolution, framerate, duration and stream layout of `input`,
+/// a file path or URI (eg. `rtsp://camera/stream`).
+pub fn probe(input: &str) -> anyhow::Result<MediaInfo> {
+    gst::init()?;
+    let info = if input.contains("://") {
+        discover_uri(input)?
+    } else {
+        raw_discoverer_info(Path::new(input))?
+    };
+    Ok(MediaInfo {
+        uri: info.uri().to_string(),
+        duration_ms: info.duration().map(|duration| duration.mseconds()),
+        live: info.is_live(),
+        seekable: info.is_seekable(),
+        container: info
+            .stream_info()
+            .and_then(|stream| stream.caps())
+            .and_then(|caps| caps.structure(0).map(|s| s.name().to_string())),
+        streams: info.stream_list().iter().map(StreamInfo::new).collect(),
+    })
+}
@@ -1,2 +1,5 @@
+pub mod continuous_recorder;
+pub mod decoder;
 pub mod discovery;
+pub mod output_branch;
 pub mod pipeline;
@@ -0,0 +1,235 @@
+//! Output branches added to and removed from a running pipeline, eg. starting a recording of a
+//! live stream without restarting ingestion.
+//!
+//! Branches hang off the pipeline's [OUTPUT_TEE], which sits after inference & annotation.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, Once};
+
+use gstreamer::prelude::*;
+use gstreamer::{self as gst, glib, PadProbeReturn, PadProbeType};
+
+use crate::pipeline::lock;
+
+/// Name of the tee output branches are linked to.
+pub const OUTPUT_TEE: &str = "output_tee";
+
+/// Branch linked to a request pad of the [OUTPUT_TEE].
+#[derive(Debug)]
+pub struct OutputBranch {
+    bin: gst::Bin,
+    tee_pad: gst::Pad,
+    /// Sink pad of the last element, where the branch's EOS ends up.
+    last_sink: gst::Pad,
+}
+
+/// Elements encoding the annotated frames into the mkv file at `location`.
+pub fn recording_elements(location: &str) -> Result<Vec<gst::Element>, glib::BoolError> {
+    let queue = gst::ElementFactory::make_with_name("queue", None)?;
+    let convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
+    let encoder = gst::ElementFactory::make_with_name("x264enc", None)?;
+    encoder.set_property_from_str("bitrate", "8192");
+    let mux = gst::ElementFactory::make_with_name("matroskamux", None)?;
+    let file_sink = gst::ElementFactory::make_with_name("filesink", None)?;
+    file_sink.set_property_from_str("location", location);
+    // Don't wait for preroll, the rest of the pipeline is already playing.
+    file_sink.set_property("async", false);
+    Ok(vec![queue, convert, encoder, mux, file_sink])
+}
+
+/// Queue in front of live outputs, dropping the oldest frames rather than holding up inference
+/// if encoding falls behind.
+fn leaky_queue() -> Result<gst::Element, glib::BoolError> {
+    let queue = gst::ElementFactory::make_with_name("queue", None)?;
+    queue.set_property_from_str("leaky", "downstream");
+    Ok(queue)
+}
+
+/// Elements encoding the annotated frames into an HLS stream in `dir`: `playlist.m3u8` listing
+/// the last few one second segments, for watching in a browser behind any static file server.
+pub fn hls_elements(dir: &Path) -> Result<Vec<gst::Element>, glib::BoolError> {
+    let queue = leaky_queue()?;
+    let convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
+    let encoder = gst::ElementFactory::make_with_name("x264enc", None)?;
+    encoder.set_property_from_str("tune", "zerolatency");
+    encoder.set_property_from_str("bitrate", "4096");
+    // Segments can only start at keyframes, so keep them a second apart at common frame rates.
+    encoder.set_property_from_str("key-int-max", "30");
+    let parse = gst::ElementFactory::make_with_name("h264parse", None)?;
+    let sink = gst::ElementFactory::make_with_name("hlssink2", None)?;
+    sink.set_property(
+        "location",
+        dir.join("segment%05d.ts").to_string_lossy().as_ref(),
+    );
+    sink.set_property(
+        "playlist-location",
+        dir.join("playlist.m3u8").to_string_lossy().as_ref(),
+    );
+    sink.set_property_from_str("target-duration", "1");
+    sink.set_property_from_str("playlist-length", "5");
+    sink.set_property_from_str("max-files", "10");
+    sink.set_property("async-handling", true);
+    Ok(vec![queue, convert, encoder, parse, sink])
+}
+
+/// WebRTC data channels of the consumers of a [webrtc_elements] branch, by consumer id.
+#[derive(Debug, Clone, Default)]
+pub struct DataChannels(Arc<Mutex<Vec<(String, glib::Object)>>>);
+
+impl DataChannels {
+    /// Sends `message` to all consumers whose channel is open, ie. negotiated and not closing.
+    pub fn send(&self, message: &str) {
+        for (_, channel) in lock(&self.0).iter().filter(|(_, channel)| is_open(channel)) {
+            channel.emit_by_name::<()>("send-string", &[&message]);
+        }
+    }
+}
+
+/// Whether the `ready-state` of a `GstWebRTCDataChannel` is `open`.
+fn is_open(channel: &glib::Object) -> bool {
+    let state = channel.property_value("ready-state");
+    glib::EnumValue::from_value(&state).is_some_and(|(_, value)| value.nick() == "open")
+}
+
+/// Elements serving the annotated frames over WebRTC (`webrtcsink` of gst-plugins-rs), along
+/// with its signalling server and a web page to watch them at `web_addr` (`HOST:PORT`). Each
+/// consumer gets a `detections` data channel, fed through `channels`.
+pub fn webrtc_elements(
+    web_addr: &str,
+    channels: &DataChannels,
+) -> Result<Vec<gst::Element>, glib::BoolError> {
+    let queue = leaky_queue()?;
+    let convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
+    let sink = gst::ElementFactory::make_with_name("webrtcsink", None)?;
+    sink.set_property("run-signalling-server", true);
+    sink.set_property("run-web-server", true);
+    sink.set_property("web-server-host-addr", format!("http://{web_addr}/"));
+
+    let added = channels.clone();
+    sink.connect("consumer-added", false, move |args| {
+        let consumer: String = args[1].get().ok()?;
+        let webrtcbin: gst::Element = args[2].get().ok()?;
+        // Data channels have to be created before the session is negotiated, ie. right here.
+        let channel = webrtcbin.emit_by_name::<Option<glib::Object>>(
+            "create-data-channel",
+            &[&"detections", &None::<gst::Structure>],
+        );
+        log::info!("WebRTC consumer {consumer} connected");
+        match channel {
+            Some(channel) => lock(&added.0).push((consumer, channel)),
+            None => log::warn!("Failed to create a detections data channel for {consumer}"),
+        }
+        None
+    });
+    let removed = channels.clone();
+    sink.connect("consumer-removed", false, move |args| {
+        let consumer: String = args[1].get().ok()?;
+        log::info!("WebRTC consumer {consumer} disconnected");
+        lock(&removed.0).retain(|(id, _)| *id != consumer);
+        None
+    });
+    Ok(vec![queue, convert, sink])
+}
+
+impl OutputBranch {
+    /// Links `elements` (in order, the first one should be a queue) to the output tee of
+    /// `pipeline` as a bin named `name`, before starting it or while it's running.
+    pub fn add(
+        pipeline: &gst::Pipeline,
+        name: &str,
+        elements: &[gst::Element],
+    ) -> Result<Self, glib::BoolError> {
+        let (first, last) = match elements {
+            [first, .., last] => (first, last),
+            [single] => (single, single),
+            [] => return Err(glib::bool_error!("Output branch {name} has no elements")),
+        };
+        let tee = pipeline
+            .by_name(OUTPUT_TEE)
+            .ok_or_else(|| glib::bool_error!("Pipeline has no {OUTPUT_TEE}"))?;
+
+        let bin = gst::Bin::builder().name(name).build();
+        bin.add_many(elements)?;
+        gst::Element::link_many(elements)?;
+        let sink = gst::GhostPad::with_target(&first.static_pad("sink").unwrap())?;
+        // Start the branch's timestamps at zero, rather than the pipeline's running time.
+        if let Some(running_time) = pipeline.current_running_time() {
+            sink.set_offset(-(running_time.nseconds() as i64));
+        }
+        bin.add_pad(&sink)?;
+        pipeline.add(&bin)?;
+        bin.sync_state_with_parent()?;
+
+        let tee_pad = tee
+            .request_pad_simple("src_%u")
+            .ok_or_else(|| glib::bool_error!("Failed to request a {OUTPUT_TEE} pad"))?;
+        if let Err(e) = tee_pad.link(&sink) {
+            tee.release_request_pad(&tee_pad);
+            let _ = bin.set_state(gst::State::Null);
+            let _ = pipeline.remove(&bin);
+            return Err(glib::bool_error!(
+                "Failed to link output branch {name}: {e:?}"
+            ));
+        }
+        log::info!("Added output branch {name}");
+
+        // Sinks like hlssink2 only have request pads, linked above.
+        let last_sink = last
+            .static_pad("sink")
+            .or_else(|| last.sink_pads().into_iter().next())
+            .ok_or_else(|| glib::bool_error!("Last element of output branch {name} has no sink"))?;
+        Ok(Self {
+            bin,
+            tee_pad,
+            last_sink,
+        })
+    }
+
+    /// Unlinks the branch from the tee once no buffer is passing, then sends EOS through it so
+    /// muxers finalize their output, and finally removes it from `pipeline`.
+    ///
+    /// Returns right away, the removal finishes in the background.
+    pub fn remove(self, pipeline: &gst::Pipeline) {
+        let Self {
+            bin,
+            tee_pad,
+            last_sink,
+        } = self;
+
+        // Once EOS made it through, nothing's left to flush.
+        let pipeline = pipeline.downgrade();
+        let eos_bin = bin.clone();
+        last_sink.add_probe(PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+            let Some(gst::PadProbeData::Event(event)) = &info.data else {
+                return PadProbeReturn::Ok;
+            };
+            if event.type_() != gst::EventType::Eos {
+                return PadProbeReturn::Ok;
+            }
+            // State changes can't happen from the branch's own streaming thread.
+            let (bin, pipeline) = (eos_bin.clone(), pipeline.clone());
+            std::thread::spawn(move || {
+                let _ = bin.set_state(gst::State::Null);
+                if let Some(pipeline) = pipeline.upgrade() {
+                    let _ = pipeline.remove(&bin);
+                }
+                log::info!("Removed output branch {}", bin.name());
+            });
+            // Dropped, the pipeline is still running.
+            PadProbeReturn::Drop
+        });
+
+        let bin_sink = bin.static_pad("sink").unwrap();
+        let unlinked = Once::new();
+        tee_pad.add_probe(PadProbeType::IDLE, move |tee_pad, _info| {
+            unlinked.call_once(|| {
+                let _ = tee_pad.unlink(&bin_sink);
+                if let Some(tee) = tee_pad.parent_element() {
+                    tee.release_request_pad(tee_pad);
+                }
+                bin_sink.send_event(gst::event::Eos::new());
+            });
+            PadProbeReturn::Remove
+        });
+    }
+}
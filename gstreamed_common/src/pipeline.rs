@@ -1,17 +1,473 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
 use gstreamer::prelude::*;
 use gstreamer::{self as gst, Buffer};
 use gstreamer::{glib, PadProbeData, PadProbeReturn, PadProbeType};
+use gstreamer_video as gst_video;
+use inference_common::clock_skew::ClockSkew;
+use inference_common::yuv::{ColorMatrix, FrameLayout, PixelFormat};
+
+use crate::decoder::WebcamCodec;
+use crate::output_branch::OUTPUT_TEE;
+
+/// Timing information of a buffer, as seen by the inference probe.
+#[derive(Debug, Clone)]
+pub struct BufferTiming {
+    /// Time the buffer spent waiting in the queue in front of inference.
+    pub queue_wait: Duration,
+    /// Running time of the buffer PTS, ie. when it was captured, in pipeline time.
+    pts_running_time: Option<gst::ClockTime>,
+    /// Element inference runs in, used to query the current pipeline running time.
+    element: Option<gst::Element>,
+    /// Negotiated caps of the frame.
+    video_info: Option<gst_video::VideoInfo>,
+    /// Wall clock capture time by the source's clock, from a reference timestamp meta of the
+    /// buffer.
+    source_capture_time: Option<SystemTime>,
+    /// Wall clock capture time by the host's clock, for live sources.
+    host_capture_time: Option<SystemTime>,
+}
+
+/// Seconds from the NTP epoch (1900) to the unix epoch (1970).
+const NTP_UNIX_OFFSET: Duration = Duration::from_secs(2_208_988_800);
+
+/// Wall clock capture time from the `timestamp/x-ntp` or `timestamp/x-unix` reference timestamp
+/// meta of `buffer`, attached eg. by `rtspsrc add-reference-timestamp-meta=true` once the RTCP
+/// sender reports relate RTP time to the camera's NTP clock.
+fn reference_capture_time(buffer: &gst::BufferRef) -> Option<SystemTime> {
+    buffer
+        .iter_meta::<gst::ReferenceTimestampMeta>()
+        .find_map(|meta| {
+            let reference = meta.reference().structure(0)?;
+            let timestamp = Duration::from(meta.timestamp());
+            if reference.has_name("timestamp/x-ntp") {
+                Some(UNIX_EPOCH + timestamp.checked_sub(NTP_UNIX_OFFSET)?)
+            } else if reference.has_name("timestamp/x-unix") {
+                Some(UNIX_EPOCH + timestamp)
+            } else {
+                None
+            }
+        })
+}
+
+impl BufferTiming {
+    /// Timing of a `buffer` outside of a pipeline (eg. pulled from an appsink), as far as its
+    /// metas tell.
+    pub fn from_buffer(buffer: &gst::BufferRef) -> Self {
+        Self {
+            queue_wait: Duration::ZERO,
+            pts_running_time: None,
+            element: None,
+            video_info: None,
+            source_capture_time: reference_capture_time(buffer),
+            host_capture_time: None,
+        }
+    }
+
+    /// Frame width and height, as negotiated by the pipeline.
+    pub fn frame_size(&self) -> Option<(u32, u32)> {
+        let info = self.video_info.as_ref()?;
+        Some((info.width(), info.height()))
+    }
+
+    /// Layout of the frame in `buffer`: plane offsets and strides of its video meta (eg. from
+    /// decoders padding rows or planes), or else the negotiated caps, and the color matrix of
+    /// their colorimetry. `None` outside of a pipeline.
+    pub fn frame_layout(&self, buffer: &gst::BufferRef) -> Option<FrameLayout> {
+        let info = self.video_info.as_ref()?;
+        let (offsets, strides) = match buffer.meta::<gst_video::VideoMeta>() {
+            Some(meta) => planes(meta.offset(), meta.stride())?,
+            None => planes(info.offset(), info.stride())?,
+        };
+        let matrix = match info.colorimetry().matrix() {
+            gst_video::VideoColorMatrix::Bt709 => ColorMatrix::Bt709,
+            // BT.601 covers SD and JPEG (webcam) colorimetries, others aren't supported.
+            _ => ColorMatrix::Bt601,
+        };
+        Some(FrameLayout {
+            width: info.width(),
+            height: info.height(),
+            offsets,
+            strides,
+            matrix,
+        })
+    }
+
+    /// Capture to now latency, ie. current pipeline running time minus buffer PTS running time.
+    ///
+    /// Only meaningful for live sources (or pipelines synced to the clock),
+    /// file inputs are usually decoded ahead of the clock, in which case `None` is returned.
+    pub fn latency(&self) -> Option<Duration> {
+        let now = self.element.as_ref()?.current_running_time()?;
+        let captured = self.pts_running_time?;
+        now.checked_sub(captured).map(Duration::from)
+    }
+
+    /// Wall clock time the frame was captured at, in ms since the unix epoch.
+    ///
+    /// Taken from the source if it reports it (eg. RTSP cameras with RTCP sender reports, see
+    /// [Self::source_capture_time_ms]), otherwise from the pipeline clock for live sources.
+    /// Unlike the PTS it isn't affected by pipeline delay or restarts. Unknown for files.
+    pub fn capture_time_ms(&self) -> Option<u64> {
+        unix_ms(self.source_capture_time.or(self.host_capture_time)?)
+    }
+
+    /// Wall clock time the frame was captured at by the source's own clock, in ms since the unix
+    /// epoch.
+    pub fn source_capture_time_ms(&self) -> Option<u64> {
+        unix_ms(self.source_capture_time?)
+    }
+
+    /// Feeds the source capture time of the buffer (if known) and the current host time into
+    /// `skew`.
+    pub fn observe_clock_skew(&self, skew: &mut ClockSkew) {
+        let Some(source_ms) = self.source_capture_time_ms() else {
+            return;
+        };
+        if let Some(host_ms) = unix_ms(SystemTime::now()) {
+            skew.observe(source_ms, host_ms);
+        }
+    }
+
+    /// Capture time by the pipeline clock: the buffer was captured when the clock was at the base
+    /// time plus its PTS running time, [Self::latency] ago.
+    fn clock_capture_time(&self) -> Option<SystemTime> {
+        SystemTime::now().checked_sub(self.latency()?)
+    }
+}
+
+/// Offsets and strides of the first (up to) 3 planes, `None` for negative (bottom-up) strides.
+fn planes(offsets: &[usize], strides: &[i32]) -> Option<([usize; 3], [usize; 3])> {
+    let mut planes = ([0; 3], [0; 3]);
+    for (plane, (&offset, &stride)) in offsets.iter().zip(strides).take(3).enumerate() {
+        planes.0[plane] = offset;
+        planes.1[plane] = usize::try_from(stride).ok()?;
+    }
+    Some(planes)
+}
+
+fn unix_ms(time: SystemTime) -> Option<u64> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as u64)
+}
+
+/// Queue in front of inference, buffers wait there while the previous frame is being processed.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    /// Buffers the queue holds before blocking upstream (or dropping, if leaky).
+    pub max_buffers: u32,
+    /// Drop the oldest queued buffers when full instead of blocking upstream,
+    /// ie. skip frames when inference can't keep up with a live source.
+    pub leaky: bool,
+}
+
+impl Default for QueueConfig {
+    /// Defaults of the gst `queue` element.
+    fn default() -> Self {
+        Self {
+            max_buffers: 200,
+            leaky: false,
+        }
+    }
+}
+
+impl QueueConfig {
+    fn configure(&self, queue: &gst::Element) {
+        queue.set_property("max-size-buffers", self.max_buffers);
+        if self.leaky {
+            // Only limit by buffer count, so each overrun leaks exactly one buffer.
+            queue.set_property("max-size-bytes", 0u32);
+            queue.set_property("max-size-time", 0u64);
+            queue.set_property_from_str("leaky", "downstream");
+        }
+    }
+}
+
+/// Frames dropped inside a pipeline, per stage.
+///
+/// Frames skipped by processing itself (eg. failed inference) are counted by the processor.
+#[derive(Debug, Default)]
+pub struct FrameDrops {
+    /// Dropped by the leaky queue in front of inference, ie. inference couldn't keep up.
+    pub inference_queue: AtomicU64,
+    /// Dropped by the live playback queue, ie. the display couldn't keep up.
+    pub display: AtomicU64,
+}
+
+impl FrameDrops {
+    /// Logs the number of dropped frames of stages with any drops.
+    pub fn log_summary(&self) {
+        let stages = [
+            ("inference queue", &self.inference_queue),
+            ("display", &self.display),
+        ];
+        for (stage, dropped) in stages {
+            let dropped = dropped.load(Ordering::Relaxed);
+            if dropped > 0 {
+                log::warn!("Frames dropped by {stage}: {dropped}");
+            }
+        }
+    }
+}
+
+/// Counts buffers dropped by the leaky `queue` into `dropped`.
+fn count_leaked(queue: &gst::Element, dropped: impl Fn() + Send + Sync + 'static) {
+    // Emitted once per buffer arriving at a full queue, which then leaks one buffer.
+    queue.connect("overrun", false, move |_| {
+        dropped();
+        None
+    });
+}
+
+/// Panics caught in buffer processors of all pipelines, see [caught_panics].
+static CAUGHT_PANICS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of buffer processor panics caught since startup.
+///
+/// A panicking processor would otherwise take down the streaming thread, stalling the pipeline
+/// without an error message. Instead, the panic is logged and the buffer passed on unprocessed.
+pub fn caught_panics() -> u64 {
+    CAUGHT_PANICS.load(Ordering::Relaxed)
+}
+
+/// Locks `mutex`, ignoring poisoning: panics while processing a frame are caught by the pipeline
+/// (see [caught_panics]) and shouldn't fail all following frames.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Message of a caught panic `payload`.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+/// Runs `buffer_processor` on buffers leaving `queue`, measuring how long they've waited inside it.
+///
+/// If `config` makes the queue leaky, dropped buffers are counted into `drops`. Buffers of `live`
+/// sources get a capture time from the pipeline clock, see [BufferTiming::capture_time_ms].
+fn add_inference_probe(
+    queue: &gst::Element,
+    live: bool,
+    config: QueueConfig,
+    drops: &Arc<FrameDrops>,
+    buffer_processor: impl Fn(&mut Buffer, &BufferTiming) + Send + Sync + 'static,
+) {
+    config.configure(queue);
+    // Queue is FIFO, so arrival times can be matched up with buffers leaving it in order.
+    let arrivals = Arc::new(Mutex::new(VecDeque::<Instant>::new()));
+    if config.leaky {
+        // The oldest buffer is leaked, so drop its arrival time too.
+        let leaked_arrivals = Arc::clone(&arrivals);
+        let drops = Arc::clone(drops);
+        count_leaked(queue, move || {
+            leaked_arrivals.lock().unwrap().pop_front();
+            drops.inference_queue.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    let queue_sink = queue.static_pad("sink").unwrap();
+    let sink_arrivals = Arc::clone(&arrivals);
+    queue_sink.add_probe(PadProbeType::BUFFER, move |_pad, _pad_probe_info| {
+        sink_arrivals.lock().unwrap().push_back(Instant::now());
+        PadProbeReturn::Ok
+    });
+    // Flushing (eg. seeking) drops queued buffers, so forget their arrival times too.
+    let flush_arrivals = Arc::clone(&arrivals);
+    queue_sink.add_probe(PadProbeType::EVENT_FLUSH, move |_pad, _pad_probe_info| {
+        flush_arrivals.lock().unwrap().clear();
+        PadProbeReturn::Ok
+    });
+
+    let queue_src = queue.static_pad("src").unwrap();
+    queue_src.add_probe(PadProbeType::BUFFER, move |pad, pad_probe_info| {
+        // we're interested in the buffer
+        if let Some(PadProbeData::Buffer(buffer)) = &mut pad_probe_info.data {
+            let queue_wait = arrivals
+                .lock()
+                .unwrap()
+                .pop_front()
+                .map(|arrival| arrival.elapsed())
+                .unwrap_or_default();
+            let pts_running_time = pad
+                .sticky_event::<gst::event::Segment>(0)
+                .and_then(|event| {
+                    let segment = event.segment().downcast_ref::<gst::ClockTime>()?;
+                    segment.to_running_time(buffer.pts()?)
+                });
+            let video_info = pad
+                .current_caps()
+                .and_then(|caps| gst_video::VideoInfo::from_caps(&caps).ok());
+            let mut timing = BufferTiming {
+                queue_wait,
+                pts_running_time,
+                element: pad.parent_element(),
+                video_info,
+                ..BufferTiming::from_buffer(buffer)
+            };
+            if live {
+                timing.host_capture_time = timing.clock_capture_time();
+            }
+            let pts = buffer.pts();
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| buffer_processor(buffer, &timing)));
+            if let Err(payload) = result {
+                CAUGHT_PANICS.fetch_add(1, Ordering::Relaxed);
+                log::error!(
+                    "Processing buffer with pts {} panicked, passing it on unprocessed: {}",
+                    pts.display(),
+                    panic_message(payload.as_ref())
+                );
+            }
+        }
+
+        PadProbeReturn::Ok
+    });
+}
+
+/// Converter in front of the caps filter forcing the processed [PixelFormat].
+///
+/// It's a passthrough if the decoder already outputs that format, eg. NV12.
+#[cfg(not(feature = "jetson"))]
+fn video_convert(_format: PixelFormat) -> Result<gst::Element, glib::BoolError> {
+    // NB! If we use cuda device, use nvidia magic videoconvert at least once in pipeline
+    // so we can handle laptop scenarios (with built-in graphics + cuda).
+    let converter_factory = if let Some(factory) = gst::ElementFactory::find("nvvideoconvert") {
+        factory
+    } else {
+        gst::ElementFactory::find("videoconvert").unwrap()
+    };
+    converter_factory.create().build()
+}
+
+/// Converter in front of the caps filter forcing the processed [PixelFormat], for Jetson.
+///
+/// decodebin picks the hw `nvv4l2decoder` there, which outputs NVMM (device) memory only `nvvidconv`
+/// can read. It can't produce packed RGB though, so frames go through RGBA into `videoconvert`.
+/// YUV formats are copied out of NVMM by `nvvidconv` directly.
+#[cfg(feature = "jetson")]
+fn video_convert(format: PixelFormat) -> Result<gst::Element, glib::BoolError> {
+    if format != PixelFormat::Rgb {
+        return gst::ElementFactory::make_with_name("nvvidconv", None);
+    }
+    let bin = gst::Bin::new();
+    // nvvidconv -> capsfilter (RGBA) -> videoconvert
+    let nvvidconv = gst::ElementFactory::make_with_name("nvvidconv", None)?;
+    let caps = gst::caps::Caps::builder(glib::gstr!("video/x-raw"))
+        .field("format", "RGBA")
+        .build();
+    let caps_filter = gst::ElementFactory::make_with_name("capsfilter", None)?;
+    caps_filter.set_property("caps", &caps);
+    let video_convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
+
+    let elements = [&nvvidconv, &caps_filter, &video_convert];
+    bin.add_many(elements)?;
+    gst::Element::link_many(elements)?;
 
-fn webcam_src_bin(device: &str) -> Result<gst::Element, glib::BoolError> {
+    let sink_pad = gst::GhostPad::with_target(&nvvidconv.static_pad("sink").unwrap())?;
+    let src_pad = gst::GhostPad::with_target(&video_convert.static_pad("src").unwrap())?;
+    bin.add_pad(&sink_pad)?;
+    bin.add_pad(&src_pad)?;
+
+    Ok(bin.upcast())
+}
+
+/// Links the pads `decode_bin` adds once it knows the input format to `queue`.
+fn link_decoded_pads(decode_bin: &gst::Element, queue: &gst::Element) {
+    // perform late linking by adding a callback to decodebin's signal for "pad-added" event
+    // create a glib weak ref to queue, so we can safely look it up inside callback.
+    let queue_weak = queue.downgrade();
+    decode_bin.connect_pad_added(move |_decode_bin, pad| {
+        // check if queue's still around, it should be
+        if let Some(queue) = queue_weak.upgrade() {
+            let sink_pad = queue
+                .compatible_pad(pad, None)
+                .expect("Compatible sink pad not found for late linking");
+            pad.link(&sink_pad)
+                .expect("Could not link decodebin src pad to queue sink pad");
+        } else {
+            eprintln!("Late linking: source bin queue element has been dropped");
+        }
+    });
+}
+
+/// Moving test pattern frames at a fixed rate, standing in for a camera in load tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyntheticSource {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+/// Where a live pipeline gets its frames from, see [build_webcam_pipeline].
+#[derive(Debug, Clone, Copy)]
+pub enum LiveSource<'a> {
+    /// V4L2 device, eg. `/dev/video0`, capturing in `codec`.
+    Webcam { device: &'a str, codec: WebcamCodec },
+    /// Video stream of an RTSP camera, eg. `rtsp://camera/stream`.
+    Rtsp { url: &'a str },
+    Synthetic(SyntheticSource),
+}
+
+impl LiveSource<'_> {
+    /// Whether `input` is the URL of an RTSP stream.
+    pub fn is_rtsp(input: &str) -> bool {
+        input.starts_with("rtsp://") || input.starts_with("rtsps://")
+    }
+}
+
+fn synthetic_src_bin(source: SyntheticSource) -> Result<gst::Element, glib::BoolError> {
+    let bin = gst::Bin::new();
+    // videotestsrc -> capsfilter -> queue
+    let test_src = gst::ElementFactory::make_with_name("videotestsrc", None)?;
+    // Produce frames at the given rate like a camera, rather than as fast as possible.
+    test_src.set_property("is-live", true);
+    test_src.set_property_from_str("pattern", "ball");
+    let caps = gst::Caps::builder("video/x-raw")
+        .field("width", source.width as i32)
+        .field("height", source.height as i32)
+        .field("framerate", gst::Fraction::new(source.fps as i32, 1))
+        .build();
+    let caps_filter = gst::ElementFactory::make_with_name("capsfilter", None)?;
+    caps_filter.set_property("caps", &caps);
+    let queue = gst::ElementFactory::make_with_name("queue", None)?;
+
+    bin.add_many([&test_src, &caps_filter, &queue])?;
+    gst::Element::link_many([&test_src, &caps_filter, &queue])?;
+
+    let queue_src = queue.static_pad("src").unwrap();
+    bin.add_pad(&gst::GhostPad::with_target(&queue_src)?)?;
+
+    Ok(bin.upcast())
+}
+
+fn webcam_src_bin(device: &str, codec: WebcamCodec) -> Result<gst::Element, glib::BoolError> {
     let bin = gst::Bin::new();
-    // v4l2src -> queue
+    // v4l2src -> queue, or v4l2src -> capsfilter -> decodebin -> queue for compressed formats
     let source = gst::ElementFactory::make_with_name("v4l2src", None)?;
     source.set_property_from_str("device", device);
     
     let queue = gst::ElementFactory::make_with_name("queue", None)?;
     
-    bin.add_many([&source, &queue])?;
-    gst::Element::link_many([&source, &queue])?;
+    if codec == WebcamCodec::Raw {
+        bin.add_many([&source, &queue])?;
+        gst::Element::link_many([&source, &queue])?;
+    } else {
+        // Without the capsfilter the camera would pick any of its formats.
+        let caps_filter = gst::ElementFactory::make_with_name("capsfilter", None)?;
+        caps_filter.set_property("caps", codec.caps());
+        let decode_bin = gst::ElementFactory::make_with_name("decodebin", None)?;
+        bin.add_many([&source, &caps_filter, &decode_bin, &queue])?;
+        gst::Element::link_many([&source, &caps_filter, &decode_bin])?;
+        link_decoded_pads(&decode_bin, &queue);
+    }
     
     // construct ghost src pad for the bin
     let queue_src = queue.static_pad("src").unwrap();
@@ -22,6 +478,44 @@ fn webcam_src_bin(device: &str) -> Result<gst::Element, glib::BoolError> {
     Ok(bin.upcast())
 }
 
+fn rtsp_src_bin(url: &str) -> Result<gst::Element, glib::BoolError> {
+    let bin = gst::Bin::new();
+    // rtspsrc -> decodebin -> queue
+    let source = gst::ElementFactory::make_with_name("rtspsrc", None)?;
+    source.set_property("location", url);
+    // Camera capture times from RTCP sender reports, see [BufferTiming::source_capture_time_ms].
+    source.set_property("add-reference-timestamp-meta", true);
+    let decode_bin = gst::ElementFactory::make_with_name("decodebin", None)?;
+    let queue = gst::ElementFactory::make_with_name("queue", None)?;
+    bin.add_many([&source, &decode_bin, &queue])?;
+
+    // rtspsrc adds a pad per stream once the session is set up, link the (first) video one.
+    let decode_bin_weak = decode_bin.downgrade();
+    source.connect_pad_added(move |_source, pad| {
+        let Some(decode_bin) = decode_bin_weak.upgrade() else {
+            return;
+        };
+        let is_video = pad.current_caps().is_some_and(|caps| {
+            caps.structure(0)
+                .and_then(|structure| structure.get::<&str>("media").ok())
+                == Some("video")
+        });
+        let sink_pad = decode_bin.static_pad("sink").unwrap();
+        if !is_video || sink_pad.is_linked() {
+            return;
+        }
+        if let Err(e) = pad.link(&sink_pad) {
+            log::error!("Could not link rtspsrc pad to decodebin: {e}");
+        }
+    });
+    link_decoded_pads(&decode_bin, &queue);
+
+    let queue_src = queue.static_pad("src").unwrap();
+    bin.add_pad(&gst::GhostPad::with_target(&queue_src)?)?;
+
+    Ok(bin.upcast())
+}
+
 fn file_src_bin(input_file: &str) -> Result<gst::Element, glib::BoolError> {
     let bin = gst::Bin::new();
     // filesrc -> decodebin -> queue
@@ -47,21 +541,7 @@ fn file_src_bin(input_file: &str) -> Result<gst::Element, glib::BoolError> {
 
     bin.add_pad(&bin_ghost_src_pad)?;
 
-    // perform late linking by adding a callback to decodebin's signal for "pad-added" event
-    // create a glib weak ref to queue, so we can safely look it up inside callback.
-    let queue_weak = queue.downgrade();
-    decode_bin.connect_pad_added(move |_decode_bin, pad| {
-        // check if queue's still around, it should be
-        if let Some(queue) = queue_weak.upgrade() {
-            let sink_pad = queue
-                .compatible_pad(pad, None)
-                .expect("Compatible sink pad not found for late linking");
-            pad.link(&sink_pad)
-                .expect("Could not link decodebin src pad to queue sink pad");
-        } else {
-            eprintln!("Late linking: file_src_bin queue element has been dropped");
-        }
-    });
+    link_decoded_pads(&decode_bin, &queue);
 
     Ok(bin.upcast())
 }
@@ -75,44 +555,33 @@ fn file_src_bin(input_file: &str) -> Result<gst::Element, glib::BoolError> {
 /// If `live_playback` is enabled, then we create a parallel branch
 /// with a gst `autovideosink`, which usually manages to create a window
 /// with live playback of the annotated output.
+///
+/// `buffer_processor` gets frames in `format`, which the encoder reads as is for YUV formats.
 pub fn build_pipeline(
     input_file: &str,
     output_file: &str,
     live_playback: bool,
-    buffer_processor: impl Fn(&mut Buffer) + Send + Sync + 'static,
+    format: PixelFormat,
+    queue_config: QueueConfig,
+    drops: &Arc<FrameDrops>,
+    buffer_processor: impl Fn(&mut Buffer, &BufferTiming) + Send + Sync + 'static,
 ) -> Result<gst::Pipeline, glib::BoolError> {
     let pipeline = gst::Pipeline::new();
 
     // filesrc -> caps_filter -> video_convert -> [candle] -> queue -> encode -> mkvmux
     let file_src_bin = file_src_bin(input_file)?;
-    // add video_convert -> caps filter to force RGB buffers
-    // NB! If we use cuda device, use nvidia magic videoconvert at least once in pipeline
-    // so we can handle laptop scenarios (with built-in graphics + cuda).
-    let converter_factory = if let Some(factory) = gst::ElementFactory::find("nvvideoconvert") {
-        factory
-    } else {
-        gst::ElementFactory::find("videoconvert").unwrap()
-    };
-    let video_convert = converter_factory.create().build()?;
+    // add video_convert -> caps filter to force buffers of `format`
+    let video_convert = video_convert(format)?;
 
     let caps = gst::caps::Caps::builder(glib::gstr!("video/x-raw"))
-        .field("format", "RGB")
+        .field("format", format.caps_format())
         .build();
     let caps_filter = gst::ElementFactory::make_with_name("capsfilter", None)?;
     caps_filter.set_property("caps", &caps);
 
     let queue = gst::ElementFactory::make_with_name("queue", None)?;
     // perform inference between file_src_bin and queue using a probe on queue src pad
-    let queue_src = queue.static_pad("src").unwrap();
-    // println!("queue_src caps: {:?}", queue_src.caps());
-    queue_src.add_probe(PadProbeType::BUFFER, move |_pad, pad_probe_info| {
-        // we're interested in the buffer
-        if let Some(PadProbeData::Buffer(buffer)) = &mut pad_probe_info.data {
-            buffer_processor(buffer);
-        }
-
-        PadProbeReturn::Ok
-    });
+    add_inference_probe(&queue, false, queue_config, drops, buffer_processor);
 
     let encoder_convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
     // let encoder_factory =
@@ -138,6 +607,10 @@ pub fn build_pipeline(
         let display_queue = gst::ElementFactory::make_with_name("queue", Some("display_queue"))?;
         // Make display_queue leaky, so it doesn't block large pipelines.
         display_queue.set_property_from_str("leaky", "downstream");
+        let display_drops = Arc::clone(drops);
+        count_leaked(&display_queue, move || {
+            display_drops.display.fetch_add(1, Ordering::Relaxed);
+        });
         let encoder_queue = gst::ElementFactory::make_with_name("queue", Some("encoder_queue"))?;
         let display_convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
         let display_sink = gst::ElementFactory::make_with_name("autovideosink", None)?;
@@ -188,41 +661,43 @@ pub fn build_pipeline(
 
 /// Builds gst pipeline for webcam input with live display
 pub fn build_webcam_pipeline(
-    device: &str,
+    source: LiveSource<'_>,
     live_playback: bool,
-    buffer_processor: impl Fn(&mut Buffer) + Send + Sync + 'static,
+    format: PixelFormat,
+    queue_config: QueueConfig,
+    drops: &Arc<FrameDrops>,
+    buffer_processor: impl Fn(&mut Buffer, &BufferTiming) + Send + Sync + 'static,
 ) -> Result<gst::Pipeline, glib::BoolError> {
     let pipeline = gst::Pipeline::new();
 
-    let webcam_src_bin = webcam_src_bin(device)?;
-    
-    let converter_factory = if let Some(factory) = gst::ElementFactory::find("nvvideoconvert") {
-        factory
-    } else {
-        gst::ElementFactory::find("videoconvert").unwrap()
+    let webcam_src_bin = match source {
+        LiveSource::Webcam { device, codec } => webcam_src_bin(device, codec)?,
+        LiveSource::Rtsp { url } => rtsp_src_bin(url)?,
+        LiveSource::Synthetic(synthetic) => synthetic_src_bin(synthetic)?,
     };
-    let video_convert = converter_factory.create().build()?;
+    
+    let video_convert = video_convert(format)?;
 
     let caps = gst::caps::Caps::builder(glib::gstr!("video/x-raw"))
-        .field("format", "RGB")
+        .field("format", format.caps_format())
         .build();
     let caps_filter = gst::ElementFactory::make_with_name("capsfilter", None)?;
     caps_filter.set_property("caps", &caps);
 
     let queue = gst::ElementFactory::make_with_name("queue", None)?;
-    let queue_src = queue.static_pad("src").unwrap();
-    queue_src.add_probe(PadProbeType::BUFFER, move |_pad, pad_probe_info| {
-        if let Some(PadProbeData::Buffer(buffer)) = &mut pad_probe_info.data {
-            buffer_processor(buffer);
-        }
-        PadProbeReturn::Ok
-    });
+    add_inference_probe(&queue, true, queue_config, drops, buffer_processor);
+    // Output branches (eg. recordings) can be linked to the tee while running,
+    // see [OutputBranch](crate::output_branch::OutputBranch).
+    let tee = gst::ElementFactory::make_with_name("tee", Some(OUTPUT_TEE))?;
 
     if live_playback {
         // Use tee to split stream for processing and display
-        let tee = gst::ElementFactory::make_with_name("tee", None)?;
         let display_queue = gst::ElementFactory::make_with_name("queue", Some("display_queue"))?;
         display_queue.set_property_from_str("leaky", "downstream");
+        let display_drops = Arc::clone(drops);
+        count_leaked(&display_queue, move || {
+            display_drops.display.fetch_add(1, Ordering::Relaxed);
+        });
         let display_convert = gst::ElementFactory::make_with_name("videoconvert", None)?;
         // Try ximagesink instead of autovideosink for better stability
         let display_sink = gst::ElementFactory::make_with_name("ximagesink", None)?;
@@ -252,6 +727,7 @@ pub fn build_webcam_pipeline(
             &video_convert,
             &caps_filter,
             &queue,
+            &tee,
             &fake_sink,
         ];
         pipeline.add_many(elements)?;
@@ -0,0 +1,115 @@
+//! Capture times of buffers, as seen by buffer processors: from the source's reference timestamp
+//! metas (eg. RTCP of RTSP cameras), which clock skew is estimated from, or the pipeline clock of
+//! live sources.
+//!
+//! Needs gstreamer with the base plugins (`videotestsrc`).
+
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use gstreamed_common::pipeline::{
+    build_webcam_pipeline, BufferTiming, FrameDrops, LiveSource, QueueConfig, SyntheticSource,
+};
+use gstreamer::prelude::*;
+use gstreamer::{self as gst};
+use inference_common::clock_skew::ClockSkew;
+use inference_common::yuv::PixelFormat;
+
+/// Seconds from the NTP epoch (1900) to the unix epoch (1970).
+const NTP_UNIX_OFFSET: Duration = Duration::from_secs(2_208_988_800);
+
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap()
+}
+
+#[test]
+fn camera_capture_times_are_read_from_metas() {
+    gst::init().unwrap();
+    let ntp = gst::Caps::new_empty_simple("timestamp/x-ntp");
+    let captured = unix_now();
+    let mut buffer = gst::Buffer::new();
+    gst::ReferenceTimestampMeta::add(
+        buffer.get_mut().unwrap(),
+        &ntp,
+        gst::ClockTime::from_nseconds((captured + NTP_UNIX_OFFSET).as_nanos() as u64),
+        gst::ClockTime::NONE,
+    );
+    let timing = BufferTiming::from_buffer(&buffer);
+    assert_eq!(
+        timing.source_capture_time_ms(),
+        Some(captured.as_millis() as u64)
+    );
+    assert_eq!(timing.capture_time_ms(), timing.source_capture_time_ms());
+
+    let timing = BufferTiming::from_buffer(&gst::Buffer::new());
+    assert_eq!(timing.capture_time_ms(), None);
+}
+
+#[test]
+fn camera_capture_times_feed_clock_skew() {
+    gst::init().unwrap();
+    let ntp = gst::Caps::new_empty_simple("timestamp/x-ntp");
+    let mut skew = ClockSkew::new("camera", Duration::from_millis(500));
+
+    // The camera's clock runs 2 s ahead, frames arrive 30 ms after they're captured.
+    for _ in 0..10 {
+        let captured = unix_now() + Duration::from_millis(2_000 - 30);
+        let mut buffer = gst::Buffer::new();
+        gst::ReferenceTimestampMeta::add(
+            buffer.get_mut().unwrap(),
+            &ntp,
+            gst::ClockTime::from_nseconds((captured + NTP_UNIX_OFFSET).as_nanos() as u64),
+            gst::ClockTime::NONE,
+        );
+        BufferTiming::from_buffer(&buffer).observe_clock_skew(&mut skew);
+    }
+    let offset = skew.offset_ms().unwrap();
+    assert!((1_950..=1_970).contains(&offset), "{offset}");
+}
+
+#[test]
+fn live_sources_are_timed_by_the_pipeline_clock() {
+    gst::init().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let source = LiveSource::Synthetic(SyntheticSource {
+        width: 320,
+        height: 240,
+        fps: 30,
+    });
+    let pipeline = build_webcam_pipeline(
+        source,
+        false,
+        PixelFormat::Rgb,
+        QueueConfig::default(),
+        &Arc::new(FrameDrops::default()),
+        move |_buffer, timing| {
+            let received = unix_now().as_millis() as u64;
+            let _ = tx.send((timing.clone(), received));
+        },
+    )
+    .unwrap();
+    let started = unix_now().as_millis() as u64;
+    pipeline.set_state(gst::State::Playing).unwrap();
+    let timings: Vec<_> = (0..10)
+        .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect();
+    pipeline.set_state(gst::State::Null).unwrap();
+
+    // The pipeline and wall clocks are read at slightly different times.
+    let mut previous = started - 5;
+    for (timing, received) in timings {
+        assert_eq!(timing.frame_size(), Some((320, 240)));
+        assert_eq!(timing.source_capture_time_ms(), None);
+        // Host times aren't compared against the host clock.
+        let mut skew = ClockSkew::new("webcam", Duration::from_millis(500));
+        timing.observe_clock_skew(&mut skew);
+        assert_eq!(skew.offset_ms(), None);
+        let captured = timing.capture_time_ms().unwrap();
+        assert!(
+            (previous..=received).contains(&captured),
+            "captured at {captured}, after {previous} and before {received}"
+        );
+        previous = captured;
+    }
+}
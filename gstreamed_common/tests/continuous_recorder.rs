@@ -0,0 +1,116 @@
+//! Listing and pruning of continuous recording segments, in a temporary directory.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use gstreamed_common::continuous_recorder::{ContinuousRecorder, Segment};
+
+/// Empty recording directory named `name`, unique to this test run.
+fn recording_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn recorder_in(dir: &Path) -> ContinuousRecorder {
+    ContinuousRecorder {
+        dir: dir.to_path_buf(),
+        segment_duration: Duration::from_secs(60),
+        retention: None,
+        max_bytes: None,
+    }
+}
+
+/// Writes a `bytes` long segment starting at `start_ms`.
+fn write_segment(dir: &Path, start_ms: u64, bytes: usize) {
+    std::fs::write(dir.join(format!("{start_ms}.mkv")), vec![0; bytes]).unwrap();
+}
+
+/// Start times of `segments`, in ms since the unix epoch.
+fn starts_ms(segments: &[Segment]) -> Vec<u64> {
+    segments
+        .iter()
+        .map(|segment| {
+            segment
+                .start
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
+        })
+        .collect()
+}
+
+#[test]
+fn finds_segments_overlapping_a_time_range() {
+    let dir = recording_dir("segments-overlapping");
+    for start_ms in [3_000, 1_000, 2_000] {
+        write_segment(&dir, start_ms, 10);
+    }
+    // Not segments.
+    std::fs::write(dir.join("notes.txt"), "").unwrap();
+    std::fs::write(dir.join("recording.mkv"), "").unwrap();
+    let recorder = recorder_in(&dir);
+    assert_eq!(
+        starts_ms(&recorder.segments().unwrap()),
+        [1_000, 2_000, 3_000]
+    );
+
+    let at = |ms: u64| UNIX_EPOCH + Duration::from_millis(ms);
+    let overlapping =
+        |from: u64, to: u64| starts_ms(&recorder.segments_overlapping(at(from), at(to)).unwrap());
+    assert_eq!(overlapping(1_500, 2_500), [1_000, 2_000]);
+    // Segments end where the next one starts.
+    assert_eq!(overlapping(2_000, 2_001), [2_000]);
+    assert_eq!(overlapping(0, 1_000), Vec::<u64>::new());
+    // The last one is still being written.
+    assert_eq!(overlapping(10_000, 20_000), [3_000]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn prunes_by_age_and_size() {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    let dir = recording_dir("prune-retention");
+    for start_ms in [1_000, 2_000, now_ms - 2_000, now_ms - 1_000] {
+        write_segment(&dir, start_ms, 10);
+    }
+    let recorder = ContinuousRecorder {
+        retention: Some(Duration::from_secs(3600)),
+        ..recorder_in(&dir)
+    };
+    recorder.prune().unwrap();
+    assert_eq!(
+        starts_ms(&recorder.segments().unwrap()),
+        [now_ms - 2_000, now_ms - 1_000]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let dir = recording_dir("prune-size");
+    for start_ms in [now_ms - 3_000, now_ms - 2_000, now_ms - 1_000, now_ms] {
+        write_segment(&dir, start_ms, 10);
+    }
+    // Oldest ones go first, until the finished segments fit.
+    let recorder = ContinuousRecorder {
+        max_bytes: Some(25),
+        ..recorder_in(&dir)
+    };
+    recorder.prune().unwrap();
+    assert_eq!(
+        starts_ms(&recorder.segments().unwrap()),
+        [now_ms - 2_000, now_ms - 1_000, now_ms]
+    );
+    // The newest one is kept regardless, it may still be written.
+    let recorder = ContinuousRecorder {
+        max_bytes: Some(0),
+        ..recorder
+    };
+    recorder.prune().unwrap();
+    assert_eq!(starts_ms(&recorder.segments().unwrap()), [now_ms]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,77 @@
+#ifndef VIDEO_INTEL_H
+#define VIDEO_INTEL_H
+
+/* Generated with cbindgen from src/ffi.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Pipeline handle, opaque to C.
+typedef struct VideoIntelPipeline VideoIntelPipeline;
+
+// Called with the detections of each processed frame as a NUL terminated json object, only valid
+// during the call. `user_data` is passed through as given to
+// [video_intel_set_detection_callback].
+typedef void (*VideoIntelDetectionCallback)(void *user_data, const char *frame_json);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a pipeline running `model` (yolov8 onnx file) over `input`, a video file or a webcam
+// device (`/dev/videoN`). Returns NULL on failure.
+//
+// # Safety
+// `model` and `input` must be NUL terminated strings.
+VideoIntelPipeline *video_intel_create(const char *model, const char *input);
+
+// Sets the callback detections are passed to (NULL for none), before starting.
+//
+// # Safety
+// `pipeline` must be returned by [video_intel_create] and not shut down yet. `callback` must be
+// safe to call from another thread with `user_data` until the pipeline is shut down.
+int32_t video_intel_set_detection_callback(VideoIntelPipeline *pipeline,
+                                           VideoIntelDetectionCallback callback,
+                                           void *user_data);
+
+// Configures the pipeline with a json object, before starting. Keys, all optional:
+// `input_size` (eg. `"640x384"`), `tracker` (`sort`, `ocsort` or `botsort`), `output_dir` and
+// `providers` (execution provider flags as on the command line, eg. `["--cuda"]`).
+//
+// # Safety
+// `pipeline` must be returned by [video_intel_create] and not shut down yet, `config_json` must
+// be a NUL terminated string.
+int32_t video_intel_configure(VideoIntelPipeline *pipeline, const char *config_json);
+
+// Loads the model and starts processing in the background.
+//
+// # Safety
+// `pipeline` must be returned by [video_intel_create] and not shut down yet.
+int32_t video_intel_start(VideoIntelPipeline *pipeline);
+
+// Waits until a started pipeline reaches the end of its input (eg. of a video file), failing if
+// processing failed. Call [video_intel_shutdown] afterwards.
+//
+// # Safety
+// `pipeline` must be returned by [video_intel_create] and not shut down yet.
+int32_t video_intel_wait(VideoIntelPipeline *pipeline);
+
+// Stops processing, writing outputs as on the end of the input, and frees the pipeline. Fails if
+// processing failed, the pipeline is freed regardless. NULL is ignored.
+//
+// # Safety
+// `pipeline` must be NULL or returned by [video_intel_create] and not shut down yet.
+int32_t video_intel_shutdown(VideoIntelPipeline *pipeline);
+
+// Message of the last failure on the calling thread, NULL if none. Valid until the next failure
+// on the thread.
+const char *video_intel_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VIDEO_INTEL_H */
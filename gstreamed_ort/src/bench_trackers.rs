@@ -0,0 +1,73 @@
+//! `bench-trackers` subcommand, compares tracker implementations on a labeled clip.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use inference_common::img_dimensions::ImgDimensions;
+use inference_common::mot_metrics::{load_mot, MotAccumulator};
+use inference_common::tracker::{new_tracker, Tracker, TrackerKind};
+
+/// MOT annotations are in frame coordinates already, so tracked boxes are effectively not clamped.
+const UNBOUNDED_DIMS: ImgDimensions = ImgDimensions {
+    width: f32::MAX,
+    height: f32::MAX,
+};
+
+/// All tracker implementations, freshly initialized.
+fn trackers() -> Vec<Box<dyn Tracker>> {
+    TrackerKind::ALL.into_iter().map(new_tracker).collect()
+}
+
+/// Runs every tracker over the detections in `det_path`, scoring tracks against `gt_path`.
+/// Both files are in MOTChallenge format.
+pub fn bench_trackers(gt_path: &Path, det_path: &Path, iou_threshold: f32) -> anyhow::Result<()> {
+    let ground_truth = load_mot(gt_path)?;
+    let detections = load_mot(det_path)?;
+    let frame_count = ground_truth.len().max(detections.len());
+    log::info!(
+        "Loaded {frame_count} frames, {} ground truth and {} detected boxes",
+        ground_truth.iter().map(Vec::len).sum::<usize>(),
+        detections.iter().map(Vec::len).sum::<usize>()
+    );
+
+    println!(
+        "{:<10} {:>7} {:>7} {:>6} {:>6} {:>6} {:>10} {:>10}",
+        "tracker", "MOTA", "IDF1", "IDSW", "FP", "FN", "avg ms", "max ms"
+    );
+    for mut tracker in trackers() {
+        let mut acc = MotAccumulator::new(iou_threshold);
+        let mut total = Duration::ZERO;
+        let mut max = Duration::ZERO;
+        for frame in 0..frame_count {
+            let frame_detections = detections.get(frame).cloned().unwrap_or_default();
+            let start = Instant::now();
+            let tracked = tracker.update(&[frame_detections], UNBOUNDED_DIMS);
+            let elapsed = start.elapsed();
+            total += elapsed;
+            max = max.max(elapsed);
+
+            acc.update(
+                ground_truth
+                    .get(frame)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default(),
+                &tracked,
+            );
+        }
+
+        let metrics = acc.metrics();
+        log::debug!("{}: {metrics:?}", tracker.name());
+        println!(
+            "{:<10} {:>7.3} {:>7.3} {:>6} {:>6} {:>6} {:>10.3} {:>10.3}",
+            tracker.name(),
+            metrics.mota,
+            metrics.idf1,
+            metrics.id_switches,
+            metrics.false_positives,
+            metrics.misses,
+            total.as_secs_f64() * 1000.0 / frame_count.max(1) as f64,
+            max.as_secs_f64() * 1000.0,
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! `compare` subcommand, diffs detections of two runs over the same input.
+
+use std::path::Path;
+
+use inference_common::run_diff::{compare_runs, load_detections, FrameDiff};
+
+/// Confidence changes up to this are not reported per frame.
+const CONFIDENCE_TOLERANCE: f32 = 0.05;
+
+fn print_frame(frame: &FrameDiff) {
+    println!("frame {}:", frame.frame_number);
+    for missing in &frame.missing {
+        println!(
+            "  - missing  {} {:?} ({:.2})",
+            missing.class_name, missing.tracker_id, missing.confidence
+        );
+    }
+    for new in &frame.new {
+        println!(
+            "  + new      {} {:?} ({:.2})",
+            new.class_name, new.tracker_id, new.confidence
+        );
+    }
+    for matched in &frame.matched {
+        if matched.id_switch {
+            println!(
+                "  ! id switch {}: baseline {:?} now matches {:?}",
+                matched.class_name, matched.baseline_tracker_id, matched.candidate_tracker_id
+            );
+        } else if matched.confidence_delta.abs() > CONFIDENCE_TOLERANCE {
+            println!(
+                "  ~ conf     {} {:?} {:+.2} (iou {:.2})",
+                matched.class_name,
+                matched.baseline_tracker_id,
+                matched.confidence_delta,
+                matched.iou
+            );
+        }
+    }
+}
+
+/// Compares `candidate` detections json against `baseline`, printing per-frame differences
+/// and a summary. The full diff is written to `json_output` if given.
+pub fn compare(
+    baseline: &Path,
+    candidate: &Path,
+    iou_threshold: f32,
+    json_output: Option<&Path>,
+) -> anyhow::Result<()> {
+    let baseline_detections = load_detections(baseline)?;
+    let candidate_detections = load_detections(candidate)?;
+    let diff = compare_runs(&baseline_detections, &candidate_detections, iou_threshold);
+
+    let mut changed_frames = 0;
+    for frame in diff
+        .frames
+        .iter()
+        .filter(|f| f.has_changes(CONFIDENCE_TOLERANCE))
+    {
+        changed_frames += 1;
+        print_frame(frame);
+    }
+
+    println!();
+    println!(
+        "baseline:  {baseline:?} ({} detections)",
+        baseline_detections.len()
+    );
+    println!(
+        "candidate: {candidate:?} ({} detections)",
+        candidate_detections.len()
+    );
+    println!(
+        "frames: {} ({changed_frames} with changes)",
+        diff.frames.len()
+    );
+    println!("matched: {} (iou >= {iou_threshold})", diff.matched_count());
+    println!("missing: {}", diff.missing_count());
+    println!("new: {}", diff.new_count());
+    println!("id switches: {}", diff.id_switches());
+    println!(
+        "mean |confidence delta|: {:.3}",
+        diff.mean_abs_confidence_delta()
+    );
+
+    if let Some(path) = json_output {
+        serde_json::to_writer_pretty(std::fs::File::create(path)?, &diff)?;
+        println!("Wrote diff: {path:?}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,319 @@
+//! C ABI (`ffi` feature) for embedding processing into existing software, eg. NVRs, declared in
+//! `include/video_intel.h` (regenerate with `cbindgen --config cbindgen.toml --crate gstreamed_ort
+//! --output include/video_intel.h` in `gstreamed_ort/`).
+//!
+//! A pipeline is created for a model and an input, configured, started and eventually shut down.
+//! While it runs, the detection callback is called from a processing thread with a json object
+//! per frame: `{"frame_num": .., "timestamp_ms": .., "detections": [..]}`, detections shaped like
+//! the entries of the detections json logs.
+//!
+//! Functions returning `int32_t` return 0 on success and -1 on failure, with the error message
+//! available from [video_intel_last_error].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+use anyhow::Context;
+use inference_common::tracker::TrackerKind;
+use ort_common::model_input;
+use serde::Deserialize;
+
+use crate::inference;
+use crate::process_video::{self, ProcessOptions, StopHandle};
+use crate::tui::app::TuiMessage;
+
+/// Called with the detections of each processed frame as a NUL terminated json object, only valid
+/// during the call. `user_data` is passed through as given to
+/// [video_intel_set_detection_callback].
+pub type VideoIntelDetectionCallback =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, frame_json: *const c_char)>;
+
+/// Settings of [video_intel_configure], the command line's defaults if left out.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Model input resolution, eg. `"640x384"`.
+    input_size: Option<String>,
+    /// Tracking algorithm: `sort`, `ocsort` or `botsort`.
+    tracker: Option<String>,
+    /// Directory to write outputs into, instead of next to the input.
+    output_dir: Option<PathBuf>,
+    /// Execution provider flags as on the command line, eg. `["--cuda"]`.
+    providers: Vec<String>,
+}
+
+/// Callback along with its user data, which the embedding application has to make safe to use
+/// from the processing thread.
+struct Callback {
+    callback: unsafe extern "C" fn(*mut c_void, *const c_char),
+    user_data: *mut c_void,
+}
+
+unsafe impl Send for Callback {}
+
+/// Pipeline handle, opaque to C.
+pub struct VideoIntelPipeline {
+    model: String,
+    input: String,
+    config: Config,
+    callback: Option<Callback>,
+    stop: StopHandle,
+    /// Processing and callback threads, once started.
+    threads: Option<(JoinHandle<anyhow::Result<()>>, JoinHandle<()>)>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(e: impl fmt::Display) {
+    let message = CString::new(format!("{e:#}").replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+/// Runs `f`, turning errors and panics into -1 with the message kept for
+/// [video_intel_last_error].
+fn ffi_result(f: impl FnOnce() -> anyhow::Result<()>) -> i32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => 0,
+        Ok(Err(e)) => {
+            set_last_error(e);
+            -1
+        }
+        Err(_) => {
+            set_last_error("panicked, see the log");
+            -1
+        }
+    }
+}
+
+/// `value` as a str, failing on NULL or invalid UTF-8.
+///
+/// # Safety
+/// `value` must be NULL or point to a NUL terminated string.
+unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> anyhow::Result<&'a str> {
+    anyhow::ensure!(!value.is_null(), "{name} is NULL");
+    CStr::from_ptr(value)
+        .to_str()
+        .with_context(|| format!("{name} isn't valid UTF-8"))
+}
+
+/// # Safety
+/// `pipeline` must be NULL or returned by [video_intel_create] and not shut down yet.
+unsafe fn pipeline_arg<'a>(
+    pipeline: *mut VideoIntelPipeline,
+) -> anyhow::Result<&'a mut VideoIntelPipeline> {
+    pipeline.as_mut().context("pipeline is NULL")
+}
+
+impl VideoIntelPipeline {
+    fn ensure_not_started(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.threads.is_none(), "Pipeline is started already");
+        Ok(())
+    }
+
+    /// Loads the model and starts processing the input, and passing frames to the callback.
+    fn start(&mut self) -> anyhow::Result<()> {
+        self.ensure_not_started()?;
+        let input_size = self
+            .config
+            .input_size
+            .as_deref()
+            .map(model_input::parse_input_size)
+            .transpose()
+            .map_err(anyhow::Error::msg)?;
+        let tracker = self
+            .config
+            .tracker
+            .as_deref()
+            .map(str::parse::<TrackerKind>)
+            .transpose()
+            .map_err(anyhow::Error::msg)?;
+        let (sessions, input_dims) =
+            inference::load_model(&self.model, input_size, &self.config.providers)?;
+
+        let mut options = ProcessOptions::new(input_dims);
+        options.tracker = tracker.unwrap_or_default();
+        options.output_dir = self.config.output_dir.clone();
+        options.stop = Some(self.stop.clone());
+
+        let (tx, rx) = mpsc::channel();
+        let input = self.input.clone();
+        let processing = thread::spawn(move || {
+            if input.starts_with("/dev/video") {
+                process_video::process_webcam_internal(&input, options, sessions, Some(tx))?;
+            } else {
+                process_video::process_video_internal(
+                    Path::new(&input),
+                    options,
+                    sessions,
+                    Some(tx),
+                )?;
+            }
+            Ok(())
+        });
+        let callback = self.callback.take();
+        let forwarding = thread::spawn(move || {
+            for message in rx {
+                match message {
+                    TuiMessage::FrameProcessed {
+                        frame_num,
+                        timestamp_ms,
+                        detections,
+                        ..
+                    } => {
+                        let Some(Callback {
+                            callback,
+                            user_data,
+                        }) = &callback
+                        else {
+                            continue;
+                        };
+                        let frame = serde_json::json!({
+                            "frame_num": frame_num,
+                            "timestamp_ms": timestamp_ms,
+                            "detections": detections,
+                        });
+                        let frame = CString::new(frame.to_string()).expect("json has no NUL");
+                        unsafe { (*callback)(*user_data, frame.as_ptr()) };
+                    }
+                    TuiMessage::VideoInfo { .. } | TuiMessage::Thermal { .. } => {}
+                    // Errors are returned by the processing thread.
+                    TuiMessage::Finished | TuiMessage::Error(_) => break,
+                }
+            }
+        });
+        self.threads = Some((processing, forwarding));
+        Ok(())
+    }
+
+    /// Waits for processing to end, returning its error if it failed.
+    fn join(&mut self) -> anyhow::Result<()> {
+        let (processing, forwarding) = self.threads.take().context("Pipeline isn't started")?;
+        let result = processing
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Processing panicked, see the log")));
+        let _ = forwarding.join();
+        result
+    }
+}
+
+/// Creates a pipeline running `model` (yolov8 onnx file) over `input`, a video file or a webcam
+/// device (`/dev/videoN`). Returns NULL on failure.
+///
+/// # Safety
+/// `model` and `input` must be NUL terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn video_intel_create(
+    model: *const c_char,
+    input: *const c_char,
+) -> *mut VideoIntelPipeline {
+    let mut pipeline = ptr::null_mut();
+    ffi_result(|| {
+        pipeline = Box::into_raw(Box::new(VideoIntelPipeline {
+            model: str_arg(model, "model")?.to_string(),
+            input: str_arg(input, "input")?.to_string(),
+            config: Config::default(),
+            callback: None,
+            stop: StopHandle::default(),
+            threads: None,
+        }));
+        Ok(())
+    });
+    pipeline
+}
+
+/// Sets the callback detections are passed to (NULL for none), before starting.
+///
+/// # Safety
+/// `pipeline` must be returned by [video_intel_create] and not shut down yet. `callback` must be
+/// safe to call from another thread with `user_data` until the pipeline is shut down.
+#[no_mangle]
+pub unsafe extern "C" fn video_intel_set_detection_callback(
+    pipeline: *mut VideoIntelPipeline,
+    callback: VideoIntelDetectionCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    ffi_result(|| {
+        let pipeline = pipeline_arg(pipeline)?;
+        pipeline.ensure_not_started()?;
+        pipeline.callback = callback.map(|callback| Callback {
+            callback,
+            user_data,
+        });
+        Ok(())
+    })
+}
+
+/// Configures the pipeline with a json object, before starting. Keys, all optional:
+/// `input_size` (eg. `"640x384"`), `tracker` (`sort`, `ocsort` or `botsort`), `output_dir` and
+/// `providers` (execution provider flags as on the command line, eg. `["--cuda"]`).
+///
+/// # Safety
+/// `pipeline` must be returned by [video_intel_create] and not shut down yet, `config_json` must
+/// be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn video_intel_configure(
+    pipeline: *mut VideoIntelPipeline,
+    config_json: *const c_char,
+) -> i32 {
+    ffi_result(|| {
+        let pipeline = pipeline_arg(pipeline)?;
+        pipeline.ensure_not_started()?;
+        pipeline.config =
+            serde_json::from_str(str_arg(config_json, "config_json")?).context("Invalid config")?;
+        Ok(())
+    })
+}
+
+/// Loads the model and starts processing in the background.
+///
+/// # Safety
+/// `pipeline` must be returned by [video_intel_create] and not shut down yet.
+#[no_mangle]
+pub unsafe extern "C" fn video_intel_start(pipeline: *mut VideoIntelPipeline) -> i32 {
+    ffi_result(|| pipeline_arg(pipeline)?.start())
+}
+
+/// Waits until a started pipeline reaches the end of its input (eg. of a video file), failing if
+/// processing failed. Call [video_intel_shutdown] afterwards.
+///
+/// # Safety
+/// `pipeline` must be returned by [video_intel_create] and not shut down yet.
+#[no_mangle]
+pub unsafe extern "C" fn video_intel_wait(pipeline: *mut VideoIntelPipeline) -> i32 {
+    ffi_result(|| pipeline_arg(pipeline)?.join())
+}
+
+/// Stops processing, writing outputs as on the end of the input, and frees the pipeline. Fails if
+/// processing failed, the pipeline is freed regardless. NULL is ignored.
+///
+/// # Safety
+/// `pipeline` must be NULL or returned by [video_intel_create] and not shut down yet.
+#[no_mangle]
+pub unsafe extern "C" fn video_intel_shutdown(pipeline: *mut VideoIntelPipeline) -> i32 {
+    if pipeline.is_null() {
+        return 0;
+    }
+    let mut pipeline = Box::from_raw(pipeline);
+    ffi_result(|| {
+        pipeline.stop.stop();
+        match pipeline.threads {
+            Some(_) => pipeline.join(),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Message of the last failure on the calling thread, NULL if none. Valid until the next failure
+/// on the thread.
+#[no_mangle]
+pub extern "C" fn video_intel_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
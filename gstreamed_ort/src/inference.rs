@@ -1,107 +1,40 @@
+use std::sync::Arc;
 use std::time::Instant;
 
-use fast_image_resize::{ResizeOptions, Resizer};
-use image::{DynamicImage, GenericImageView, RgbImage};
-use inference_common::tracker::{similari::prelude::Sort, unflatten_bboxes};
+use clap::Parser;
+use image::DynamicImage;
+use inference_common::tracker::{unflatten_bboxes, Tracker};
 use inference_common::{
     annotate::annotate_image_with_bboxes,
     bbox::{BBoxesByClass, Bbox},
+    box_smoothing::BoxSmoother,
+    camera_motion::MotionEstimator,
     coco_classes,
     frame_times::FrameTimes,
     img_dimensions::ImgDimensions,
+    output_guard::OutputIssues,
+    preproc::preprocess,
 };
-use ndarray::{Array, Array4, CowArray};
+use ndarray::CowArray;
 use ort::session::Session;
 use ort::value::TensorRef;
+use ort_common::execution_provider::ExecutionProviderArgs;
+use ort_common::model_input;
+use ort_common::session_pool::SessionPool;
 use ort_common::yolo_parser::parse_predictions;
 
-/// Transforms the input `image` by converting colors, resizing and loading the image buffer into an [Array].
+/// Runs the model on `og_image` letterboxed into `input_dims`, returning untracked bboxes
+/// (in scaled image coordinates), the scaled image dims and issues found in the model output.
 ///
-/// Returns the scaled image inside ndarray [Array4] and scaled dims inside [ImgDimensions].
-fn preprocess_image(
-    image: &DynamicImage,
-    target_dims: ImgDimensions,
-) -> anyhow::Result<(Array4<f32>, ImgDimensions)> {
-    log::debug!("image.dimensions: {:?}", image.dimensions());
-    log::debug!("image.color: {:?}", image.color());
-
-    // Convert image to rgb8 to ensure pixel values are just that.
-    let image = image.to_rgb8();
-
-    // Resize image to our target size.
-    // Target size is not the model input size, but based on the smallest ratio between input and target dims.
-    let og_dims: ImgDimensions = image.dimensions().into();
-    let ratio = (target_dims.width / og_dims.width).min(target_dims.height / og_dims.height);
-    log::debug!("scale ratio: {ratio:?}");
-    let scaled_dims = og_dims.scale(ratio);
-
-    // Use `fast_image_resize` crate to resize the image.
-    // It has unsafe, but it is way faster than plain `image`, unfortunately...
-    let mut scaled_image = fast_image_resize::images::Image::new(
-        scaled_dims.width as u32,
-        scaled_dims.height as u32,
-        fast_image_resize::PixelType::U8x3,
-    );
-
-    let mut resizer = Resizer::new();
-
-    let image = DynamicImage::ImageRgb8(image);
-    resizer.resize(
-        &image,
-        &mut scaled_image,
-        &ResizeOptions::new().resize_alg(fast_image_resize::ResizeAlg::Nearest),
-    )?;
-
-    let scaled_image = RgbImage::from_raw(
-        scaled_dims.width as u32,
-        scaled_dims.height as u32,
-        scaled_image.into_vec(),
-    )
-    .unwrap();
-
-    // FIXME resize with image crate below is way slower than fast image resize above
-    // let scaled_image = image::imageops::resize(
-    //     &image,
-    //     scaled_dims.width as u32,
-    //     scaled_dims.height as u32,
-    //     image::imageops::FilterType::Nearest,
-    // );
-    log::debug!("scaled_image.dimensions: {:?}", scaled_image.dimensions());
-
-    // Load it into ndarray.
-    // Array shape: [bsz, channels, height, width];
-    let target_shape = [
-        1,
-        3,
-        target_dims.height as usize,
-        target_dims.width as usize,
-    ];
-    let mut image_array = Array::zeros(target_shape);
-
-    // Copy over the pixels starting from the top
-    for (x, y, rgb) in scaled_image.enumerate_pixels() {
-        let x = x as usize;
-        let y = y as usize;
-        let [r, g, b] = rgb.0;
-        image_array[[0, 0, y, x]] = (r as f32) / 255.0;
-        image_array[[0, 1, y, x]] = (g as f32) / 255.0;
-        image_array[[0, 2, y, x]] = (b as f32) / 255.0;
-    }
-
-    Ok((image_array, scaled_dims))
-}
-
-pub fn infer_on_image(
+/// Broken boxes (eg. NaN) are dropped already, see [parse_predictions].
+pub fn detect(
     session: &mut Session,
-    tracker: Option<&mut Sort>,
-    og_image: DynamicImage,
+    input_dims: ImgDimensions,
+    og_image: &DynamicImage,
     frame_times: &mut FrameTimes,
-) -> anyhow::Result<(DynamicImage, BBoxesByClass)> {
-    // FIXME determine target_dims based on model?
-    let model_input_dims = ImgDimensions::new(640f32, 384f32);
-
+) -> anyhow::Result<(BBoxesByClass, ImgDimensions, OutputIssues)> {
     let start = Instant::now();
-    let (scaled_image_array, scaled_dims) = preprocess_image(&og_image, model_input_dims)?;
+    let (scaled_image_array, scaled_dims) = preprocess(og_image, input_dims);
     frame_times.buffer_resize = start.elapsed();
 
     // Load image into ndarray, and that into ort.
@@ -123,10 +56,10 @@ pub fn infer_on_image(
     // embedding is 4 bbox "coords" (center_x, center_y, width, height) + 80 COCO classes long
     log::debug!("got outputs: {outputs:?}");
 
-    // Parse and annotate outputs.
+    // Parse outputs.
     let conf_threshold = 0.25;
     let nms_threshold = 0.45;
-    let bboxes = parse_predictions(
+    let (bboxes, issues) = parse_predictions(
         outputs,
         scaled_dims,
         coco_classes::NAMES.len() as u32,
@@ -139,16 +72,40 @@ pub fn infer_on_image(
         "after nms bboxes, len: {:?}",
         bboxes.iter().map(|v| v.len()).sum::<usize>()
     );
+    Ok((bboxes, scaled_dims, issues))
+}
+
+/// Runs inference (+ tracking if `tracker` is given) on `og_image`, returning it annotated along
+/// with bboxes and issues found in the model output, see [detect].
+///
+/// `og_image` is letterboxed into `input_dims` for the model, the annotated image keeps its resolution.
+///
+/// If `motion` is given, camera motion is estimated and compensated for in tracking.
+///
+/// If `smoother` is given, annotations are drawn with smoothed boxes,
+/// the returned bboxes are always the raw ones.
+pub fn infer_on_image(
+    session: &mut Session,
+    input_dims: ImgDimensions,
+    tracker: Option<&mut dyn Tracker>,
+    motion: Option<&mut MotionEstimator>,
+    smoother: Option<&mut BoxSmoother>,
+    og_image: DynamicImage,
+    frame_times: &mut FrameTimes,
+) -> anyhow::Result<(DynamicImage, BBoxesByClass, OutputIssues)> {
+    let (bboxes, scaled_dims, issues) = detect(session, input_dims, &og_image, frame_times)?;
 
     // Perform tracking.
     let mut tracked_bboxes: Option<Vec<Bbox>> = None;
     if let Some(tracker) = tracker {
         let start = Instant::now();
-        tracked_bboxes = Some(inference_common::tracker::predict_tracked_bboxes(
-            tracker,
-            scaled_dims,
-            &bboxes,
-        ));
+        if let Some(motion) = motion {
+            let objects: Vec<Bbox> = bboxes.iter().flatten().cloned().collect();
+            if let Some(warp) = motion.estimate(&og_image, scaled_dims, &objects) {
+                tracker.set_camera_motion(warp);
+            }
+        }
+        tracked_bboxes = Some(tracker.update(&bboxes, scaled_dims));
         frame_times.tracking = start.elapsed();
     }
     log::debug!("{tracked_bboxes:?}");
@@ -163,14 +120,38 @@ pub fn infer_on_image(
         None => bboxes,
     };
 
+    let smoothed = smoother.map(|smoother| smoother.smooth(&bboxes));
     let annotated = annotate_image_with_bboxes(
         og_image,
         scaled_dims.width as usize,
         scaled_dims.height as usize,
         legend_size,
-        &bboxes,
+        smoothed.as_ref().unwrap_or(&bboxes),
     );
     frame_times.annotation = start.elapsed();
 
-    Ok((annotated, bboxes))
+    Ok((annotated, bboxes, issues))
+}
+
+/// Execution provider flags, parsed like on the command line.
+#[derive(Debug, Parser)]
+#[command(no_binary_name = true)]
+struct ProviderFlags {
+    #[command(flatten)]
+    execution_provider: ExecutionProviderArgs,
+}
+
+/// Loads `model` for the execution provider selected by command line flags `provider_flags`
+/// (eg. `["--cuda"]`), along with the input dimensions to use, see
+/// [model_input::resolve_input_dims]. Used by the language bindings, the binary configures
+/// sessions from its own flags.
+pub fn load_model(
+    model: &str,
+    input_size: Option<ImgDimensions>,
+    provider_flags: &[String],
+) -> anyhow::Result<(Arc<SessionPool>, ImgDimensions)> {
+    let execution_provider = ProviderFlags::try_parse_from(provider_flags)?.execution_provider;
+    let (session, _) = execution_provider.load_session(model)?;
+    let input_dims = model_input::resolve_input_dims(&session, input_size)?;
+    Ok((SessionPool::new(vec![session])?, input_dims))
 }
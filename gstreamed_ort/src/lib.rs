@@ -0,0 +1,14 @@
+//! Video, webcam & image inference with a GStreamer pipeline + ort, driven by the
+//! `gstreamed_ort` binary, from Python with the `pyo3` feature (see [python]) or from C with the
+//! `ffi` feature (see [ffi]).
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod inference;
+pub mod process_image;
+pub mod process_video;
+#[cfg(feature = "pyo3")]
+pub mod python;
+pub mod service;
+pub mod stress;
+pub mod tui;
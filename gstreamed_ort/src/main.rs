@@ -1,38 +1,322 @@
-mod inference;
-mod process_image;
-mod process_video;
-mod tui;
+mod bench_trackers;
+mod compare;
+mod replay;
 
 use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::Parser;
-use ort::execution_providers::CPUExecutionProvider;
-use ort::execution_providers::CUDAExecutionProvider;
-use ort::session::builder::GraphOptimizationLevel;
-use ort::session::builder::SessionBuilder;
+use clap::{Parser, Subcommand};
+use gstreamed_common::continuous_recorder::ContinuousRecorder;
+use gstreamed_common::decoder::{DecoderBackend, WebcamCodec};
+use gstreamed_common::pipeline::{LiveSource, QueueConfig};
+use gstreamed_ort::process_video::{
+    LargeModelOptions, ProcessOptions, ShadowOptions, ThrottleOptions,
+};
+use gstreamed_ort::stress::StressConfig;
+use gstreamed_ort::{process_image, process_video, service, stress, tui};
+use inference_common::model_switch::ActivityPolicy;
+use inference_common::profile::{Profile, ProfileEnv};
+use inference_common::sub_view::ViewLayout;
+use inference_common::thermal::ThermalLimits;
+use inference_common::tracker::TrackerKind;
+use inference_common::undistort::LensModel;
+use inference_common::wall_time::UtcOffset;
+use inference_common::yuv::PixelFormat;
+use inference_common::img_dimensions::ImgDimensions;
+use inference_common::preview::FramePreview;
+use ort_common::execution_provider::ExecutionProviderArgs;
+use ort_common::model_input;
+use ort_common::session_pool::SessionPool;
 use tracing_subscriber::prelude::*;
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Args {
-    /// Path to input image (.jpeg/.png) or video file (.mp4/.mkv).
-    /// Use "webcam" or specify device path like "/dev/video0" for webcam input.
-    input: PathBuf,
-    /// Whether to attempt to use `cuda` hw acceleration.
-    /// This may silently fail and fallback to cpu acceleration presently.
-    #[arg(long, action, default_value = "false")]
-    cuda: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Path to input image (.jpeg/.png) or video file (.mp4/.mkv/.mov/.avi/.ts/.webm/...).
+    /// Use "webcam" or specify device path like "/dev/video0" for webcam input, or an
+    /// `rtsp://` URL for a camera stream (processed like a webcam).
+    #[arg(required_unless_present = "stress")]
+    input: Option<PathBuf>,
+    #[command(flatten)]
+    process: ProcessArgs,
+    /// Load test instead of processing the input: runs synthetic cameras through the webcam
+    /// pipeline and reports the frame rate each kept up, eg. `fps=120 cams=8` (also `width`,
+    /// `height` and `seconds`).
+    #[arg(long, conflicts_with = "tui")]
+    stress: Option<StressConfig>,
+    /// Webcam device (e.g., /dev/video0). Use with input "webcam".
+    #[arg(long, default_value = "/dev/video0")]
+    device: String,
+    /// Write the process id into this file while running.
+    #[arg(long)]
+    pidfile: Option<PathBuf>,
+    /// Enable interactive TUI dashboard
+    #[cfg_attr(
+        target_os = "linux",
+        arg(
+            long,
+            action,
+            default_value = "false",
+            conflicts_with_all = ["service", "preview_inline"]
+        )
+    )]
+    #[cfg_attr(
+        not(target_os = "linux"),
+        arg(long, action, default_value = "false", conflicts_with = "preview_inline")
+    )]
+    tui: bool,
+    /// Write per-frame timings and environment info to this json file.
+    /// A chrome trace (for chrome://tracing, Perfetto or speedscope) is written next to it as `*.trace.json`.
+    #[arg(long)]
+    profile: Option<PathBuf>,
+}
+
+/// Model and processing options, of the input or the recordings of `replay`.
+#[derive(Debug, clap::Args)]
+struct ProcessArgs {
+    #[command(flatten)]
+    execution_provider: ExecutionProviderArgs,
     /// Yolov8 onnx model file to use.
     #[arg(long, short, default_value = "_models/yolov8s.onnx")]
     model: String,
+    /// Model input resolution `WIDTHxHEIGHT` (multiples of 32), frames are letterboxed into it
+    /// while the output keeps the input resolution. Defaults to the model's static input size,
+    /// or 640x384 for models with dynamic input axes.
+    #[arg(long, value_parser = model_input::parse_input_size)]
+    input_size: Option<ImgDimensions>,
+    /// Shadow model run next to `--model` on sampled frames, its detections are only logged
+    /// (into `<input>.shadow.detections.json`, with the primary detections of the same frames in
+    /// `<input>.shadow-primary.detections.json`) for comparison. Video files only.
+    #[arg(long)]
+    shadow_model: Option<String>,
+    /// Run the shadow model on every Nth frame.
+    #[arg(
+        long,
+        default_value_t = 10,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "shadow_model"
+    )]
+    shadow_every: u64,
+    /// Webcam only: larger model run instead of `--model` (eg. a nano one) while the scene is
+    /// busy or hard, see `--activity`. Must take the same input size as `--model`.
+    #[arg(long)]
+    large_model: Option<String>,
+    /// When to switch to `--large-model`: on frames with at least `detections` detections or one
+    /// below `confidence`, until `hold` seconds after the last such frame,
+    /// eg. `detections=5 confidence=0.4 hold=10` (the defaults).
+    #[arg(long, requires = "large_model")]
+    activity: Option<ActivityPolicy>,
     /// Whether to live playback the inference results.
     #[arg(long, action, default_value = "false")]
     live: bool,
-    /// Webcam device (e.g., /dev/video0). Use with input "webcam".
-    #[arg(long, default_value = "/dev/video0")]
-    device: String,
-    /// Enable interactive TUI dashboard
+    /// Pixel format frames are decoded into: rgb, nv12 or i420. YUV formats usually skip the
+    /// per-frame conversion in the pipeline (decoders and the encoder use them natively):
+    /// frames are converted to RGB only for inference, and annotations written back into them.
+    #[arg(long, default_value = "rgb")]
+    pixel_format: PixelFormat,
+    /// Decoders to prefer for video files and compressed webcam codecs: auto, software, nvdec,
+    /// vaapi or v4l2. Falls back to the default decoders if the backend can't decode the input.
+    #[arg(long, default_value = "auto")]
+    decoder: DecoderBackend,
+    /// Webcam only: format to capture in, raw, mjpeg, h264, h265 or vp9. Many USB cameras only
+    /// reach their full resolution and frame rate with mjpeg.
+    #[arg(long, default_value = "raw")]
+    webcam_codec: WebcamCodec,
+    /// Frames buffered in front of inference.
+    #[arg(long, default_value_t = QueueConfig::default().max_buffers)]
+    queue_max_buffers: u32,
+    /// Drop the oldest buffered frames when inference can't keep up, instead of slowing down
+    /// the source. Drops are counted and logged at the end.
+    #[arg(long)]
+    leaky_queue: bool,
+    /// Webcam only: enter `r` on stdin to start or stop recording the annotated stream into
+    /// this directory, without restarting the pipeline.
+    #[arg(long)]
+    record_dir: Option<PathBuf>,
+    /// Webcam only: also stream the annotated frames as HLS (`playlist.m3u8` and one second
+    /// segments) into this directory, to watch in a browser behind any static file server.
+    #[arg(long)]
+    hls_dir: Option<PathBuf>,
+    /// Webcam only: serve the annotated frames over WebRTC (needs gst-plugins-rs' webrtcsink),
+    /// watch them in a browser at this `HOST:PORT`, eg. `0.0.0.0:8080`. Viewers also get the
+    /// detections of each frame as json on a `detections` data channel.
+    #[arg(long)]
+    webrtc: Option<String>,
+    /// Webcam only: throttle processing while the hottest thermal zone of the device (eg. a
+    /// Jetson or Raspberry Pi) is above this temperature in °C.
+    #[arg(long)]
+    max_temp: Option<f32>,
+    /// Webcam only: throttle processing while the power draw of the device (its VDD_IN rail on
+    /// Jetsons, otherwise the total of its hwmon power sensors) is above this in W.
+    #[arg(long)]
+    max_power: Option<f32>,
+    /// Power sensor file (in µW, eg. `/sys/class/hwmon/hwmon1/power1_input`) compared against
+    /// `--max-power`, for devices with other sensors than above.
+    #[arg(long, requires = "max_power")]
+    power_sensor: Option<PathBuf>,
+    /// Run inference on every Nth frame while throttled.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    throttle_every: u64,
+    /// Smaller model to switch to while throttled, taking the same input size as `--model`.
+    #[arg(long)]
+    throttle_model: Option<String>,
+    /// Webcam only: record the annotated stream around the clock into rolling segments in this
+    /// directory, named after their start (unix time in ms).
+    #[arg(long)]
+    continuous_dir: Option<PathBuf>,
+    /// Length of continuous recording segments.
+    #[arg(
+        long,
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u64).range(1..=u64::MAX / 60),
+        requires = "continuous_dir"
+    )]
+    segment_minutes: u64,
+    /// Delete continuous recording segments older than this.
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..=u64::MAX / 3600),
+        requires = "continuous_dir"
+    )]
+    retention_hours: Option<u64>,
+    /// Delete the oldest continuous recording segments while they take up more than this.
+    #[arg(long, value_parser = parse_disk_gb, requires = "continuous_dir")]
+    max_disk_gb: Option<f64>,
+    /// Webcam only: run as a systemd service (`Type=notify`), with readiness and watchdog
+    /// notifications, graceful shutdown on SIGTERM and a non-zero exit on pipeline errors.
+    /// Linux only.
+    #[cfg_attr(target_os = "linux", arg(long))]
+    #[cfg_attr(not(target_os = "linux"), arg(skip))]
+    service: bool,
+    /// Write every Nth annotated frame to `--preview-path`, to check on headless machines.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    preview_every: Option<u64>,
+    /// Preview image (.jpg or .png), replaced atomically.
+    #[arg(long, default_value = "preview.jpg", requires = "preview_every")]
+    preview_path: PathBuf,
+    /// Also print previews into the terminal (kitty or iTerm2 image protocol).
+    #[arg(long, requires = "preview_every")]
+    preview_inline: bool,
+    /// Also write tracked detections as ONVIF analytics metadata (`*.onvif.xml`), video files only.
+    /// It's a file export, there's no live RTSP metadata stream.
+    #[arg(long)]
+    onvif_metadata: bool,
+    /// UTC offset of the site (`+HH:MM`/`-HH:MM`), capture times in detection logs and the TUI
+    /// are also given in this local time.
+    #[arg(long, default_value = "Z")]
+    utc_offset: UtcOffset,
+    /// Tracking algorithm: sort, ocsort (handles occlusions better)
+    /// or botsort (supports camera motion compensation). Defaults to sort, or to ocsort with
+    /// `--deterministic` (sort isn't reproducible).
+    #[arg(long)]
+    tracker: Option<TrackerKind>,
+    /// Estimate camera motion (pan, zoom, shake) between frames from optical flow
+    /// and compensate track predictions for it, for PTZ or shaky cameras.
+    /// Used by the ocsort and botsort trackers.
     #[arg(long, action, default_value = "false")]
-    tui: bool,
+    camera_motion: bool,
+    /// Also log boxes of tracks without a detection in the frame, while the tracker keeps them
+    /// alive (eg. through short occlusions), predicted from their motion and flagged as such.
+    /// Used by the ocsort and botsort trackers.
+    #[arg(long, action, default_value = "false")]
+    report_predicted: bool,
+    /// Undistort frames before inference, for wide-angle or fisheye cameras:
+    /// `radial:K1[,K2]` (Brown-Conrady, coords normalized by half diagonal, barrel is negative)
+    /// or `fisheye:FOV` (equidistant fisheye with FOV degrees across the shorter side).
+    #[arg(long)]
+    lens: Option<LensModel>,
+    /// Split frames of multi-imager or 360 cameras into views, each tracked and logged
+    /// as a separate camera (into `<input>.<view>.detections.json`): a `COLUMNSxROWS` grid,
+    /// eg. `2x1`, or named regions `NAME=X,Y,W,H;...`. Video files only.
+    #[arg(long)]
+    views: Option<ViewLayout>,
+    /// Smooth tracked boxes in the annotated output to reduce jitter, eg. 0.3.
+    /// Lower is smoother but lags more, logged detections are never smoothed.
+    #[arg(long, value_parser = parse_smoothing)]
+    smooth_boxes: Option<f32>,
+    /// Save frames (+ model input tensor) which fail inference or produce broken output
+    /// (eg. NaN boxes, more than 500 boxes) into this directory, for reproducing issues offline.
+    #[arg(long)]
+    dump_failures: Option<PathBuf>,
+    /// Leave frames out of the detection logs which look like the last logged frame (at most
+    /// this many of 64 perceptual hash bits differ, eg. 2) and have the same detections,
+    /// for near-static cameras. The annotated video keeps all frames.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(0..=64))]
+    dedupe_frames: Option<u32>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare detections json files of two runs over the same input,
+    /// eg. to validate a model or threshold change.
+    Compare {
+        /// Detections json of the reference run.
+        baseline: PathBuf,
+        /// Detections json of the run to compare against the baseline.
+        candidate: PathBuf,
+        /// Minimum IoU for two detections of the same class to be considered the same object.
+        #[arg(long, default_value_t = 0.5)]
+        iou: f32,
+        /// Also write the full per-frame diff to this json file.
+        #[arg(long)]
+        json: Option<PathBuf>,
+    },
+    /// Compare tracker implementations on a labeled clip (MOTChallenge `gt.txt` + `det.txt`),
+    /// reporting MOTA, IDF1 and per-frame update latency.
+    BenchTrackers {
+        /// Ground truth tracks, eg. `MOT17-02/gt/gt.txt`.
+        #[arg(long)]
+        gt: PathBuf,
+        /// Detections to feed into the trackers, eg. `MOT17-02/det/det.txt`.
+        #[arg(long)]
+        det: PathBuf,
+        /// Minimum IoU for a tracked box to match a ground truth box.
+        #[arg(long, default_value_t = 0.5)]
+        iou: f32,
+    },
+    /// Re-run processing over stored recordings (faster than realtime), writing all outputs
+    /// into `<runs-dir>/<run>`, eg. after a model upgrade:
+    /// `gstreamed_ort replay recordings/ --run new-model --model new.onnx`.
+    Replay {
+        /// Recordings (.mp4/.mkv/.mov/.avi/.ts/.webm) or directories containing them.
+        #[arg(required = true)]
+        recordings: Vec<PathBuf>,
+        /// Name of this reprocessing run, must not exist yet.
+        #[arg(long)]
+        run: String,
+        /// Directory holding all runs.
+        #[arg(long, default_value = "runs")]
+        runs_dir: PathBuf,
+        #[command(flatten)]
+        process: Box<ProcessArgs>,
+    },
+    /// Print codecs, resolution, framerate, duration and stream layout of a file or URL
+    /// (eg. `rtsp://...`) as json, to debug inputs before a full run.
+    Probe {
+        /// File path or URI.
+        input: String,
+    },
+}
+
+fn parse_smoothing(value: &str) -> Result<f32, String> {
+    let factor: f32 = value.parse().map_err(|e| format!("{e}"))?;
+    if factor > 0.0 && factor <= 1.0 {
+        Ok(factor)
+    } else {
+        Err(format!("smoothing factor must be in (0, 1], got {factor}"))
+    }
+}
+
+fn parse_disk_gb(value: &str) -> Result<f64, String> {
+    let gb: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    // Up to what fits into u64 bytes.
+    if gb > 0.0 && gb * 1e9 < u64::MAX as f64 {
+        Ok(gb)
+    } else {
+        Err(format!("disk space must be a positive number of GB, got {gb}"))
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -62,57 +346,205 @@ fn main() -> anyhow::Result<()> {
         log::set_max_level(log::LevelFilter::Off);
     }
 
-    // Load model into ort.
-    let (ep, ep_name) = if args.cuda {
-        (CUDAExecutionProvider::default().build(), "cuda")
-    } else {
-        (CPUExecutionProvider::default().build(), "cpu")
+    match &args.command {
+        Some(Command::Compare {
+            baseline,
+            candidate,
+            iou,
+            json,
+        }) => return compare::compare(baseline, candidate, *iou, json.as_deref()),
+        Some(Command::BenchTrackers { gt, det, iou }) => {
+            return bench_trackers::bench_trackers(gt, det, *iou)
+        }
+        Some(Command::Probe { input }) => {
+            let info = gstreamed_common::discovery::probe(input)?;
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            return Ok(());
+        }
+        Some(Command::Replay { .. }) | None => {}
+    }
+    let process = match &args.command {
+        Some(Command::Replay { process, .. }) => process.as_ref(),
+        _ => &args.process,
     };
-    // TODO test trt exec provider, but requires a rebuild of onnxruntime with trt enabled
-    // TODO warmup with synthetic image of the same dims?
-
-    ort::init().with_execution_providers([ep]).commit()?;
-
-    let session = SessionBuilder::new()?
-        .with_optimization_level(GraphOptimizationLevel::Level3)?
-        // .with_intra_threads(1)?
-        .commit_from_file(&args.model)?;
-    log::debug!("{session:?}");
 
+    // Load model into ort.
+    // TODO warmup with synthetic image of the same dims?
+    let (session, ep_name) = process.execution_provider.load_session(&process.model)?;
     log::info!(
         "Prepared ort {ep_name} session with model: {:?}",
-        args.model
+        process.model
     );
+    let input_dims = model_input::resolve_input_dims(&session, process.input_size)?;
+    let sessions = SessionPool::new(vec![session])?;
+    let shadow = match &process.shadow_model {
+        Some(model) => {
+            let (session, _) = process.execution_provider.load_session(model)?;
+            log::info!("Prepared shadow session with model: {model:?}");
+            Some(ShadowOptions {
+                input_dims: model_input::resolve_input_dims(&session, process.input_size)?,
+                sessions: SessionPool::new(vec![session])?,
+                every: process.shadow_every,
+            })
+        }
+        None => None,
+    };
+    let large_model = match &process.large_model {
+        Some(model) => {
+            let (session, _) = process.execution_provider.load_session(model)?;
+            log::info!("Prepared large session with model: {model:?}");
+            Some(LargeModelOptions {
+                input_dims: model_input::resolve_input_dims(&session, process.input_size)?,
+                sessions: SessionPool::new(vec![session])?,
+                policy: process.activity.unwrap_or_default(),
+            })
+        }
+        None => None,
+    };
+    let throttle = if process.max_temp.is_some() || process.max_power.is_some() {
+        let fallback = match &process.throttle_model {
+            Some(model) => {
+                let (session, _) = process.execution_provider.load_session(model)?;
+                log::info!("Prepared throttle session with model: {model:?}");
+                let input_dims = model_input::resolve_input_dims(&session, process.input_size)?;
+                Some((SessionPool::new(vec![session])?, input_dims))
+            }
+            None => None,
+        };
+        Some(ThrottleOptions {
+            limits: ThermalLimits {
+                max_temp_c: process.max_temp,
+                max_power_w: process.max_power,
+            },
+            power_sensor: process.power_sensor.clone(),
+            every: process.throttle_every,
+            fallback,
+        })
+    } else {
+        anyhow::ensure!(
+            process.throttle_model.is_none(),
+            "--throttle-model needs --max-temp or --max-power"
+        );
+        None
+    };
 
-    // Check if input is "webcam" or a device path
-    let input_str = args.input.to_string_lossy();
-    if input_str == "webcam" || input_str.starts_with("/dev/video") {
+    let deterministic = process.execution_provider.deterministic;
+    let tracker = process.tracker.unwrap_or(TrackerKind::default_for(deterministic));
+    let options = ProcessOptions {
+        input_dims,
+        live_playback: process.live,
+        pixel_format: process.pixel_format,
+        decoder: process.decoder,
+        webcam_codec: process.webcam_codec,
+        queue: QueueConfig {
+            max_buffers: process.queue_max_buffers,
+            leaky: process.leaky_queue,
+        },
+        record_dir: process.record_dir.clone(),
+        hls_dir: process.hls_dir.clone(),
+        webrtc_addr: process.webrtc.clone(),
+        continuous: process.continuous_dir.clone().map(|dir| ContinuousRecorder {
+            dir,
+            segment_duration: Duration::from_secs(process.segment_minutes * 60),
+            retention: process
+                .retention_hours
+                .map(|hours| Duration::from_secs(hours * 3600)),
+            max_bytes: process.max_disk_gb.map(|gb| (gb * 1e9) as u64),
+        }),
+        service: process.service,
+        preview: process.preview_every.map(|every| FramePreview {
+            path: process.preview_path.clone(),
+            every,
+            inline: process.preview_inline,
+        }),
+        onvif_metadata: process.onvif_metadata,
+        utc_offset: process.utc_offset,
+        box_smoothing: process.smooth_boxes,
+        dump_failures: process.dump_failures.clone(),
+        dedupe_frames: process.dedupe_frames,
+        tracker,
+        camera_motion: process.camera_motion,
+        report_predicted: process.report_predicted,
+        lens: process.lens,
+        views: process.views.clone().unwrap_or_default(),
+        output_dir: None,
+        shadow,
+        large_model,
+        throttle,
+        stop: None,
+    };
+    if process.camera_motion && tracker == TrackerKind::Sort {
+        log::warn!("--camera-motion has no effect with the sort tracker, use ocsort or botsort");
+    }
+    if process.report_predicted && tracker == TrackerKind::Sort {
+        log::warn!("--report-predicted has no effect with the sort tracker, use ocsort or botsort");
+    }
+    if deterministic {
+        anyhow::ensure!(
+            !process.leaky_queue && !process.live,
+            "--deterministic processes every frame, it conflicts with --leaky-queue and --live"
+        );
+        anyhow::ensure!(
+            tracker.is_reproducible(),
+            "The sort tracker assigns tracks differently on every run, use ocsort or botsort \
+             with --deterministic"
+        );
+    }
+    if let Some(Command::Replay {
+        recordings,
+        run,
+        runs_dir,
+        ..
+    }) = &args.command
+    {
+        return replay::replay(recordings, runs_dir, run, options, sessions);
+    }
+    if let Some(config) = args.stress {
+        return stress::run(config, options, sessions);
+    }
+    let input = args.input.clone().expect("input is required without a subcommand");
+    let _pidfile = args
+        .pidfile
+        .as_deref()
+        .map(service::Pidfile::create)
+        .transpose()?;
+
+    // Check if input is "webcam", a device path or camera stream
+    let input_str = input.to_string_lossy();
+    let stats = if input_str == "webcam"
+        || input_str.starts_with("/dev/video")
+        || LiveSource::is_rtsp(&input_str)
+    {
         let device = if input_str == "webcam" {
             &args.device
         } else {
             input_str.as_ref()
         };
         if args.tui {
-            tui::process_webcam_with_tui(device, args.live, session)?;
+            Some(tui::process_webcam_with_tui(device, options, sessions)?)
         } else {
-            process_video::process_webcam(device, args.live, session)?;
+            Some(process_video::process_webcam(device, options, sessions)?)
         }
     } else {
-        match args.input.extension().and_then(|os_str| os_str.to_str()) {
-            Some("mp4" | "mkv") => {
+        match input.extension().and_then(|os_str| os_str.to_str()) {
+            Some("jpeg" | "jpg" | "png") => {
+                Some(process_image::process_image(&input, input_dims, sessions)?)
+            }
+            // Anything else is handed to GStreamer, discovery fails on what it can't decode.
+            _ => {
                 if args.tui {
-                    tui::process_video_with_tui(&args.input, args.live, session)?;
+                    Some(tui::process_video_with_tui(&input, options, sessions)?)
                 } else {
-                    process_video::process_video(&args.input, args.live, session)?;
+                    Some(process_video::process_video(&input, options, sessions)?)
                 }
             }
-            Some("jpeg" | "jpg" | "png") => process_image::process_image(&args.input, session)?,
-            Some(unk) => log::error!("Unhandled file extension: {unk}"),
-            None => log::error!(
-                "Input path does not have valid file extension: {:?}",
-                args.input
-            ),
         }
+    };
+
+    if let (Some(profile_path), Some(stats)) = (&args.profile, stats) {
+        let env = ProfileEnv::new(&process.model, ep_name, stats.frame_dims);
+        let trace_path = Profile::new(env, stats.times).write(profile_path)?;
+        log::info!("Wrote profile: {profile_path:?}, chrome trace: {trace_path:?}");
     }
 
     Ok(())
@@ -1,32 +1,55 @@
 use std::path::Path;
+use std::sync::Arc;
 
 use image::GenericImageView;
-use inference_common::{frame_meta::FrameMeta, frame_times::FrameTimes};
-use inference_common::detection_logger::{DetectionLog, DetectionLogger};
+use inference_common::frame_meta::FrameMeta;
+use inference_common::frame_times::{AggregatedTimes, FrameTimes};
+use inference_common::img_dimensions::ImgDimensions;
+use inference_common::preproc::Letterbox;
+use inference_common::detection_logger::{DetectionLog, DetectionLogExt, DetectionLogger};
 use inference_common::color_extractor;
-use ort::session::Session;
+use ort_common::session_pool::SessionPool;
 
 use crate::inference;
+use crate::process_video::StreamStats;
 
 /// Performs inference on a single image file.
-pub fn process_image(path: &Path, mut session: Session) -> anyhow::Result<()> {
+pub fn process_image(
+    path: &Path,
+    input_dims: ImgDimensions,
+    sessions: Arc<SessionPool>,
+) -> anyhow::Result<StreamStats> {
     let mut frame_times = FrameTimes::default();
+    let stream = sessions.register_stream(path.display().to_string());
+    let mut session = stream.acquire();
 
     // Read image.
     let og_image = image::open(path)?;
     let (img_width, img_height) = og_image.dimensions();
 
     // Process image.
-    let (img, bboxes) =
-        inference::infer_on_image(&mut session, None, og_image.clone(), &mut frame_times)?;
+    let (img, bboxes, issues) = inference::infer_on_image(
+        &mut session,
+        input_dims,
+        None,
+        None,
+        None,
+        og_image.clone(),
+        &mut frame_times,
+    )?;
+    if issues.is_bad() {
+        log::warn!("Dropped broken model output: {issues}");
+    }
     
-    // Enhanced logging with color extraction
+    // Enhanced logging with color extraction, in image coordinates.
+    let letterbox = Letterbox::new(og_image.dimensions().into(), input_dims);
     let mut detection_logger = DetectionLogger::new();
     let mut frame_detections = Vec::new();
     
     println!("\nDetections in {:?}:", path);
     for (class_idx, class_bboxes) in bboxes.iter().enumerate() {
         for bbox in class_bboxes {
+            let bbox = &letterbox.unmap(bbox);
             // Extract dominant color for the detected object
             let dominant_color = color_extractor::extract_dominant_color(
                 &og_image,
@@ -75,5 +98,10 @@ pub fn process_image(path: &Path, mut session: Session) -> anyhow::Result<()> {
     detection_logger.export_json(&detections_path)?;
     println!("Detection logs saved to: {:?}", detections_path);
 
-    Ok(())
+    let mut times = AggregatedTimes::default();
+    times.push(frame_times);
+    Ok(StreamStats {
+        frame_dims: ImgDimensions::new(img_width as f32, img_height as f32),
+        times,
+    })
 }
@@ -1,143 +1,803 @@
-use std::io::Write;
-use std::path::Path;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::sync::mpsc::Sender;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use gstreamed_common::{discovery, pipeline::build_pipeline};
+use gstreamed_common::continuous_recorder::ContinuousRecorder;
+use gstreamed_common::decoder::{DecoderBackend, WebcamCodec};
+use gstreamed_common::discovery;
+use gstreamed_common::output_branch::{self, DataChannels, OutputBranch};
+use gstreamed_common::pipeline::{
+    build_pipeline, lock, BufferTiming, FrameDrops, LiveSource, QueueConfig, SyntheticSource,
+};
 use gstreamer::{self as gst};
 use gstreamer::{prelude::*, MessageView};
-use image::{DynamicImage, RgbImage};
+use image::{DynamicImage, GenericImageView, RgbImage};
+use inference_common::box_smoothing::BoxSmoother;
+use inference_common::camera_motion::MotionEstimator;
+use inference_common::clock_skew::ClockSkew;
+use inference_common::failure_dump::FailureDumper;
+use inference_common::frame_dedup::FrameDedup;
 use inference_common::frame_meta::FrameMeta;
 use inference_common::frame_times::{AggregatedTimes, FrameTimes};
 use inference_common::img_dimensions::ImgDimensions;
-use inference_common::tracker::similari::prelude::Sort;
+use inference_common::model_switch::{ActivityPolicy, ModelSwitch};
+use inference_common::onvif_metadata;
+use inference_common::output_guard::OutputIssues;
+use inference_common::preproc::Letterbox;
+use inference_common::preview::FramePreview;
+use inference_common::processing_error::{
+    ErrorCategory, ErrorStats, ProcessingError, RecoveryPolicy,
+};
+use inference_common::sub_view::{SubView, ViewLayout};
+use inference_common::thermal::{ThermalLimits, ThermalThrottle};
+use inference_common::tracker::{Tracker, TrackerKind};
+use inference_common::undistort::{LensModel, Undistorter};
 use inference_common::video_meta::VideoMeta;
-use inference_common::detection_logger::{DetectionLog, DetectionLogger};
+use inference_common::wall_time::UtcOffset;
+use inference_common::yuv::{FrameLayout, PixelFormat};
+use inference_common::detection_logger::{
+    DetectionLog, DetectionLogExt, DetectionLogger, ObjectAttributes,
+};
 use inference_common::onnx_attributes::AttributeDetector;
 use ort::session::Session;
+use ort_common::session_pool::{SessionPool, StreamHandle};
 
 use crate::inference;
+#[cfg(target_os = "linux")]
+use crate::service;
 use crate::tui::app::TuiMessage;
 
+/// Options for video & webcam processing.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    /// Model input dimensions, frames are letterboxed into these for inference.
+    pub input_dims: ImgDimensions,
+    /// Whether to live playback the inference results.
+    pub live_playback: bool,
+    /// Pixel format frames are decoded into, see [PixelFormat].
+    pub pixel_format: PixelFormat,
+    /// Decoders to prefer, for video files and compressed webcam formats.
+    pub decoder: DecoderBackend,
+    /// Format to capture webcam frames in.
+    pub webcam_codec: WebcamCodec,
+    /// Process synthetic frames instead of the webcam's, see [crate::stress].
+    pub synthetic: Option<SyntheticSource>,
+    /// Queue in front of inference.
+    pub queue: QueueConfig,
+    /// Directory recordings of a webcam stream are toggled into, from stdin.
+    pub record_dir: Option<PathBuf>,
+    /// Directory the annotated webcam stream is served into as HLS, see
+    /// [output_branch::hls_elements].
+    pub hls_dir: Option<PathBuf>,
+    /// Address (`HOST:PORT`) the annotated webcam stream and its detections are served at over
+    /// WebRTC, see [output_branch::webrtc_elements].
+    pub webrtc_addr: Option<String>,
+    /// Record the annotated webcam stream around the clock into rolling segments.
+    pub continuous: Option<ContinuousRecorder>,
+    /// Run as a systemd service, webcams only, see [crate::service].
+    pub service: bool,
+    /// Write the latest annotated frame every few frames.
+    pub preview: Option<FramePreview>,
+    /// Also write detections as ONVIF analytics metadata, video files only.
+    pub onvif_metadata: bool,
+    /// Local time of the site, capture times are also logged in it.
+    pub utc_offset: UtcOffset,
+    /// Smooth tracked boxes drawn in the output with this factor, see [BoxSmoother::new].
+    pub box_smoothing: Option<f32>,
+    /// Save frames failing inference (or with pathological output) into this directory.
+    pub dump_failures: Option<PathBuf>,
+    /// Leave frames duplicating the last logged one out of the detection logs, with this
+    /// maximum hash distance, see [FrameDedup].
+    pub dedupe_frames: Option<u32>,
+    /// Tracking algorithm.
+    pub tracker: TrackerKind,
+    /// Estimate camera motion between frames and compensate track predictions for it.
+    pub camera_motion: bool,
+    /// Also log boxes of tracks kept alive without a detection, as predicted ones.
+    pub report_predicted: bool,
+    /// Undistort frames of this lens before inference.
+    pub lens: Option<LensModel>,
+    /// Split frames into views, each processed as a separate camera.
+    pub views: ViewLayout,
+    /// Write outputs into this directory instead of next to the input.
+    pub output_dir: Option<PathBuf>,
+    /// Run a shadow model next to the primary one, video files only.
+    pub shadow: Option<ShadowOptions>,
+    /// Run a larger model on webcam frames while the scene is busy, see [LargeModelOptions].
+    pub large_model: Option<LargeModelOptions>,
+    /// Throttle webcam processing while the device runs hot, see [ThrottleOptions].
+    pub throttle: Option<ThrottleOptions>,
+    /// Lets another thread finish the stream, see [StopHandle].
+    pub stop: Option<StopHandle>,
+}
+
+/// Finishes a stream from another thread (eg. of an application embedding processing), by sending
+/// EOS into its pipeline so outputs are written as on a regular end of stream.
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle {
+    state: Arc<Mutex<StopState>>,
+}
+
+#[derive(Debug, Default)]
+struct StopState {
+    pipeline: Option<gst::Pipeline>,
+    stopped: bool,
+}
+
+impl StopHandle {
+    pub fn stop(&self) {
+        let mut state = lock(&self.state);
+        state.stopped = true;
+        if let Some(pipeline) = &state.pipeline {
+            pipeline.send_event(gst::event::Eos::new());
+        }
+    }
+
+    /// Attaches the stream's playing `pipeline`, finishing it right away if stopped already.
+    fn attach(&self, pipeline: &gst::Pipeline) {
+        let mut state = lock(&self.state);
+        if state.stopped {
+            pipeline.send_event(gst::event::Eos::new());
+        }
+        state.pipeline = Some(pipeline.clone());
+    }
+}
+
+/// Second model run on sampled frames next to the primary one, for offline comparison
+/// (eg. before a model upgrade). Its detections are only logged, never drawn or tracked.
+#[derive(Debug, Clone)]
+pub struct ShadowOptions {
+    pub sessions: Arc<SessionPool>,
+    pub input_dims: ImgDimensions,
+    /// Run on every `every`th frame.
+    pub every: u64,
+}
+
+/// Throttling of webcam processing while sensor readings of the device (eg. a Jetson) are over
+/// their limits, see [ThermalThrottle].
+#[derive(Debug, Clone)]
+pub struct ThrottleOptions {
+    pub limits: ThermalLimits,
+    /// Power sensor file (in µW) read instead of the device's input rail, see
+    /// [ThermalThrottle::with_power_sensor].
+    pub power_sensor: Option<PathBuf>,
+    /// Run inference on every `every`th frame while throttled, the others pass through
+    /// unannotated.
+    pub every: u64,
+    /// Smaller model (and its input dimensions, the same as [ProcessOptions::input_dims]) switched
+    /// to while throttled.
+    pub fallback: Option<(Arc<SessionPool>, ImgDimensions)>,
+}
+
+/// Larger model run instead of the primary (small) one while the [ActivityPolicy] sees activity,
+/// see [ModelSwitch].
+#[derive(Debug, Clone)]
+pub struct LargeModelOptions {
+    pub sessions: Arc<SessionPool>,
+    /// Same as [ProcessOptions::input_dims], as tracks are kept across switches.
+    pub input_dims: ImgDimensions,
+    pub policy: ActivityPolicy,
+}
+
+impl ProcessOptions {
+    /// Options with the command line defaults, for models taking `input_dims` frames.
+    pub fn new(input_dims: ImgDimensions) -> Self {
+        Self {
+            input_dims,
+            live_playback: false,
+            pixel_format: PixelFormat::default(),
+            decoder: DecoderBackend::default(),
+            webcam_codec: WebcamCodec::default(),
+            synthetic: None,
+            queue: QueueConfig::default(),
+            record_dir: None,
+            hls_dir: None,
+            webrtc_addr: None,
+            continuous: None,
+            service: false,
+            preview: None,
+            onvif_metadata: false,
+            utc_offset: UtcOffset::UTC,
+            box_smoothing: None,
+            dump_failures: None,
+            dedupe_frames: None,
+            tracker: TrackerKind::default(),
+            camera_motion: false,
+            report_predicted: false,
+            lens: None,
+            views: ViewLayout::default(),
+            output_dir: None,
+            shadow: None,
+            large_model: None,
+            throttle: None,
+            stop: None,
+        }
+    }
+
+    /// Path output file names of `input` are derived from, by replacing its extension.
+    pub fn output_base(&self, input: &Path) -> PathBuf {
+        match (&self.output_dir, input.file_name()) {
+            (Some(dir), Some(name)) => dir.join(name),
+            _ => input.to_path_buf(),
+        }
+    }
+
+    /// A new tracker of the chosen kind.
+    fn new_tracker(&self) -> Box<dyn Tracker> {
+        let mut tracker = inference_common::tracker::new_tracker(self.tracker);
+        tracker.set_report_predicted(self.report_predicted);
+        tracker
+    }
+}
+
+/// Mean wait for a pooled session above which a stream is reported as starved.
+const SESSION_STARVATION: Duration = Duration::from_millis(20);
+/// Source clocks off from the host by more than this are warned about.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(1);
+
+/// Warns about frames whose processing panicked, caught by the pipeline.
+fn log_caught_panics() {
+    let panics = gstreamed_common::pipeline::caught_panics();
+    if panics > 0 {
+        log::warn!("Processing panicked on {panics} frames (since startup), see errors above");
+    }
+}
+
+/// Writes `frame` into the `preview` if it's due, failures only warn.
+fn write_preview(preview: Option<&FramePreview>, frame_num: u64, frame: &RgbImage) {
+    let Some(preview) = preview.filter(|preview| preview.is_due(frame_num)) else {
+        return;
+    };
+    if let Err(e) = preview.write(frame) {
+        log::warn!("Failed to write preview {:?}: {e:#}", preview.path);
+    }
+}
+
+/// Application message toggling the recording of a webcam stream, posted on the pipeline's bus.
+const TOGGLE_RECORDING: &str = "toggle-recording";
+
+/// Posts [TOGGLE_RECORDING] on `bus` whenever `r` is entered on stdin.
+fn spawn_recording_toggle(bus: gst::Bus) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim() == "r" {
+                let structure = gst::Structure::new_empty(TOGGLE_RECORDING);
+                if bus.post(gst::message::Application::new(structure)).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Stops the running `recording`, or starts a new one into `dir` if there's none,
+/// without interrupting the rest of `pipeline`.
+fn toggle_recording(
+    pipeline: &gst::Pipeline,
+    dir: &Path,
+    recording: &mut Option<OutputBranch>,
+) -> anyhow::Result<()> {
+    if let Some(branch) = recording.take() {
+        log::info!("Stopping recording");
+        branch.remove(pipeline);
+        return Ok(());
+    }
+    let name = format!(
+        "recording-{}",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs()
+    );
+    let path = dir.join(&name).with_extension("mkv");
+    let elements = output_branch::recording_elements(&path.to_string_lossy())?;
+    *recording = Some(OutputBranch::add(pipeline, &name, &elements)?);
+    log::info!("Recording into {path:?}");
+    Ok(())
+}
+
+/// Frame dimensions and per-frame timings of a processed input, used for `--profile` reports.
+pub struct StreamStats {
+    pub frame_dims: ImgDimensions,
+    pub times: AggregatedTimes,
+}
+
+/// Shadow model detections of a view, along with the primary detections of the same frames.
+pub struct ShadowState {
+    stream: StreamHandle<Session>,
+    input_dims: ImgDimensions,
+    every: u64,
+    primary_logger: DetectionLogger,
+    shadow_logger: DetectionLogger,
+}
+
+impl ShadowState {
+    /// Runs the shadow model on sampled frames, logging its and the `primary` detections.
+    fn process(
+        &mut self,
+        frame_num: u64,
+        timestamp_ms: u64,
+        image: &DynamicImage,
+        primary: &[DetectionLog],
+    ) {
+        if frame_num % self.every != 0 {
+            return;
+        }
+        for detection in primary {
+            self.primary_logger.log_detection(detection.clone());
+        }
+
+        let mut session = self.stream.acquire();
+        let mut times = FrameTimes::default();
+        let bboxes = match inference::detect(&mut session, self.input_dims, image, &mut times) {
+            Ok((bboxes, _, _)) => bboxes,
+            Err(e) => {
+                log::warn!("Shadow model inference error on frame {frame_num}: {e}");
+                return;
+            }
+        };
+        let letterbox = Letterbox::new(image.dimensions().into(), self.input_dims);
+        for (class_idx, class_bboxes) in bboxes.iter().enumerate() {
+            for bbox in class_bboxes {
+                self.shadow_logger.log_detection(DetectionLog::from_bbox_with_attributes(
+                    frame_num,
+                    timestamp_ms,
+                    &letterbox.unmap(bbox),
+                    class_idx,
+                    image.width() as f32,
+                    image.height() as f32,
+                    ObjectAttributes::default(),
+                ));
+            }
+        }
+    }
+}
+
+/// Processing state of a single view.
+///
+/// Each view of a split frame is tracked and logged as its own camera.
+pub struct ViewState {
+    /// Region of the frame, `None` for the whole frame.
+    pub view: Option<SubView>,
+    pub tracker: Box<dyn Tracker>,
+    pub motion: Option<MotionEstimator>,
+    pub smoother: Option<BoxSmoother>,
+    pub dumper: Option<FailureDumper>,
+    pub video_meta: VideoMeta,
+    pub detection_logger: DetectionLogger,
+    /// Leaves duplicate frames out of `detection_logger`.
+    pub dedup: Option<FrameDedup>,
+    pub shadow: Option<ShadowState>,
+    /// Wall clock time processing started (ms since the unix epoch), time base of the ONVIF
+    /// metadata of frames without a capture time. `None` if ONVIF metadata isn't written.
+    pub onvif_start_ms: Option<u64>,
+    /// Local time of the site, for capture times in detection logs.
+    pub utc_offset: UtcOffset,
+}
+
+impl ViewState {
+    /// States of all views of `frame_dims` sized frames of `input`, annotated into `output`.
+    pub fn for_input(
+        input: &Path,
+        output: &Path,
+        frame_dims: ImgDimensions,
+        options: &ProcessOptions,
+    ) -> anyhow::Result<Vec<Self>> {
+        let (width, height) = (frame_dims.width as u32, frame_dims.height as u32);
+        let onvif_start_ms = if options.onvif_metadata {
+            Some(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+        } else {
+            None
+        };
+        let views: Vec<Option<SubView>> = match options.views.views(width, height)? {
+            views if views.is_empty() => vec![None],
+            views => views.into_iter().map(Some).collect(),
+        };
+        views
+            .into_iter()
+            .map(|view| {
+                let (width, height) =
+                    view.as_ref().map_or((width, height), |v| (v.width, v.height));
+                let dump_dir = match (&options.dump_failures, &view) {
+                    (Some(dir), Some(view)) => Some(dir.join(&view.name)),
+                    (dir, None) => dir.clone(),
+                    (None, _) => None,
+                };
+                Ok(Self {
+                    tracker: options.new_tracker(),
+                    motion: options.camera_motion.then(MotionEstimator::new),
+                    smoother: options.box_smoothing.map(BoxSmoother::new),
+                    dumper: dump_dir.as_deref().map(FailureDumper::new).transpose()?,
+                    video_meta: VideoMeta {
+                        model_input_size: Some([
+                            options.input_dims.width as u32,
+                            options.input_dims.height as u32,
+                        ]),
+                        ..VideoMeta::new(
+                            input.to_path_buf(),
+                            Some(output.to_path_buf()),
+                            width,
+                            height,
+                        )
+                    },
+                    detection_logger: DetectionLogger::new(),
+                    dedup: options.dedupe_frames.map(FrameDedup::new),
+                    shadow: options.shadow.as_ref().map(|shadow| ShadowState {
+                        stream: shadow.sessions.register_stream(match &view {
+                            Some(view) => format!("{} {} (shadow)", input.display(), view.name),
+                            None => format!("{} (shadow)", input.display()),
+                        }),
+                        input_dims: shadow.input_dims,
+                        every: shadow.every,
+                        primary_logger: DetectionLogger::new(),
+                        shadow_logger: DetectionLogger::new(),
+                    }),
+                    onvif_start_ms,
+                    utc_offset: options.utc_offset,
+                    view,
+                })
+            })
+            .collect()
+    }
+
+    /// `output_base` with `extension`, prefixed by the view name for split frames.
+    fn output_path(&self, output_base: &Path, extension: &str) -> PathBuf {
+        match &self.view {
+            Some(view) => output_base.with_extension(format!("{}.{extension}", view.name)),
+            None => output_base.with_extension(extension),
+        }
+    }
+
+    /// Writes the video meta and detection logs of this view, named after `output_base`.
+    fn write_outputs(&self, output_base: &Path) -> Result<(), ProcessingError> {
+        self.try_write_outputs(output_base)
+            .map_err(|e| ProcessingError::new(ErrorCategory::Storage, e))
+    }
+
+    fn try_write_outputs(&self, output_base: &Path) -> anyhow::Result<()> {
+        let output_json_path = self.output_path(output_base, "json");
+        log::info!(
+            "Writing output json file, {} frames: {output_json_path:?}",
+            self.video_meta.frames.len()
+        );
+        serde_json::to_writer(std::fs::File::create(&output_json_path)?, &self.video_meta)?;
+
+        // Export detection logs
+        let detections_path = self.output_path(output_base, "detections.json");
+        log::info!("Writing detection logs: {detections_path:?}");
+        if let Some(dedup) = &self.dedup {
+            log::info!("Left {} duplicate frames out of detection logs", dedup.skipped());
+        }
+        self.detection_logger.export_json(&detections_path)?;
+
+        if let Some(shadow) = &self.shadow {
+            let primary_path = self.output_path(output_base, "shadow-primary.detections.json");
+            let shadow_path = self.output_path(output_base, "shadow.detections.json");
+            log::info!("Writing shadow model detection logs: {primary_path:?}, {shadow_path:?}");
+            shadow.primary_logger.export_json(&primary_path)?;
+            shadow.shadow_logger.export_json(&shadow_path)?;
+        }
+
+        if let Some(start_ms) = self.onvif_start_ms {
+            let onvif_path = self.output_path(output_base, "onvif.xml");
+            log::info!("Writing ONVIF metadata: {onvif_path:?}");
+            onvif_metadata::write_metadata_stream(
+                BufWriter::new(std::fs::File::create(&onvif_path)?),
+                self.detection_logger.get_logs(),
+                self.video_meta.width,
+                self.video_meta.height,
+                start_ms,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Layout of the frame in `buffer`, as the pipeline negotiated it, or the default one of a
+/// `frame_dims` sized frame in `format`.
+fn frame_layout(
+    buffer: &gst::BufferRef,
+    timing: &BufferTiming,
+    frame_dims: ImgDimensions,
+    format: PixelFormat,
+) -> FrameLayout {
+    timing.frame_layout(buffer).unwrap_or_else(|| {
+        format.default_layout(frame_dims.width as u32, frame_dims.height as u32)
+    })
+}
+
+/// Copies a frame of `layout` in `format` out of `buffer`, as RGB.
+fn read_frame(
+    buffer: &gst::BufferRef,
+    layout: &FrameLayout,
+    format: PixelFormat,
+) -> Result<RgbImage, ProcessingError> {
+    let source_error = |e: anyhow::Error| ProcessingError::new(ErrorCategory::Source, e);
+    let readable = buffer.map_readable().map_err(|e| source_error(e.into()))?;
+    format.to_rgb(&readable, layout).map_err(source_error)
+}
+
+/// Overwrites the contents of `buffer` (holding `original`, a frame of `layout` in `format`)
+/// with `frame`.
+fn write_frame(
+    buffer: &mut gst::Buffer,
+    layout: &FrameLayout,
+    format: PixelFormat,
+    frame: &RgbImage,
+    original: &RgbImage,
+) -> Result<(), ProcessingError> {
+    let sink_error = |e: anyhow::Error| ProcessingError::new(ErrorCategory::Sink, e);
+    let buffer_mut = buffer
+        .get_mut()
+        .ok_or_else(|| sink_error(anyhow::anyhow!("buffer is not writable")))?;
+    let mut writable = buffer_mut.map_writable().map_err(|e| sink_error(e.into()))?;
+    format
+        .write_rgb(writable.as_mut_slice(), layout, frame, original)
+        .map_err(sink_error)
+}
+
+/// Records `error` raised on `frame_num`, returning whether its [RecoveryPolicy] keeps processing
+/// the frame.
+fn recover(errors: &mut ErrorStats, frame_num: u64, error: &ProcessingError) -> bool {
+    errors.record(frame_num, error) == RecoveryPolicy::Continue
+}
+
 pub fn process_buffer(
     frame_dims: ImgDimensions,
+    format: PixelFormat,
+    input_dims: ImgDimensions,
     session: &mut Session,
-    tracker: &Mutex<Sort>,
+    undistorter: Option<&mut Undistorter>,
+    views: &mut [ViewState],
     agg_times: &mut AggregatedTimes,
-    video_meta: &mut VideoMeta,
-    detection_logger: &mut DetectionLogger,
     buffer: &mut gst::Buffer,
+    timing: &BufferTiming,
     attr_detector: &mut AttributeDetector,
+    errors: &mut ErrorStats,
+    preview: Option<&FramePreview>,
     tui_tx: &Option<Sender<TuiMessage>>,
 ) {
-    let mut frame_times = FrameTimes::default();
+    let mut frame_times = FrameTimes {
+        queue_wait: timing.queue_wait,
+        ..Default::default()
+    };
+    let frame_num = views[0].video_meta.frames.len() as u64;
+    let timestamp_ms = buffer.pts().unwrap_or_default().mseconds();
+    errors.frame();
 
     let start = Instant::now();
-    // read buffer into an image
-    let image = {
-        let readable = buffer.map_readable().unwrap();
-        let readable_vec = readable.to_vec();
-
-        // buffer size is: width x height x 3
-        let image = RgbImage::from_vec(
-            frame_dims.width as u32,
-            frame_dims.height as u32,
-            readable_vec,
-        )
-        .unwrap();
-        DynamicImage::ImageRgb8(image)
+    // read buffer into an image, keeping what the buffer holds if undistortion changes it
+    let layout = frame_layout(buffer, timing, frame_dims, format);
+    let (frame, original) = match read_frame(buffer, &layout, format) {
+        Ok(image) => match undistorter {
+            Some(undistorter) => (undistorter.apply(&image), Some(image)),
+            None => (image, None),
+        },
+        Err(e) => {
+            // Pass the buffer through, with empty metadata so frame numbering stays intact.
+            recover(errors, frame_num, &e);
+            for state in views.iter_mut() {
+                state.video_meta.push(FrameMeta {
+                    pts: buffer.pts().unwrap_or_default().into(),
+                    dts: buffer.dts().unwrap_or_default().into(),
+                    bboxes_by_class: vec![],
+                });
+            }
+            return;
+        }
     };
     frame_times.frame_to_buffer = start.elapsed();
 
-    // process it using some model + draw overlays on the output image
-    let mut tracker = tracker.lock().unwrap();
-    let (processed, bboxes) =
-        inference::infer_on_image(session, Some(&mut *tracker), image.clone(), &mut frame_times).unwrap();
-    
-    // Enhanced logging with color extraction
-    let frame_num = video_meta.frames.len() as u64;
-    let timestamp_ms = buffer.pts().unwrap_or_default().mseconds();
+    // Annotated frame, `None` while no view has been processed.
+    let mut output: Option<RgbImage> = None;
+    let mut view_times = Vec::with_capacity(views.len());
     let mut frame_detections = Vec::new();
-    
-    for (class_idx, class_bboxes) in bboxes.iter().enumerate() {
-        for bbox in class_bboxes {
-            // Get class name for this detection
-            let class_name = inference_common::coco_classes::NAMES
-                .get(class_idx)
-                .unwrap_or(&"unknown");
-            
-            // Extract attributes using ONNX model
-            let attributes = attr_detector.detect_attributes(
-                &image,
-                bbox.xmin,
-                bbox.ymin,
-                bbox.xmax,
-                bbox.ymax,
-                class_name,
-            ).unwrap_or_default();
-            
-            let detection = DetectionLog::from_bbox_with_attributes(
-                frame_num,
-                timestamp_ms,
-                bbox,
-                class_idx,
-                frame_dims.width,
-                frame_dims.height,
-                attributes,
-            );
-            
-            frame_detections.push(detection.clone());
-            detection_logger.log_detection(detection);
+    for state in views.iter_mut() {
+        let image = match &state.view {
+            Some(view) => DynamicImage::ImageRgb8(view.crop(&frame)),
+            None => DynamicImage::ImageRgb8(frame.clone()),
+        };
+        let view_name = state.view.as_ref().map_or("", |view| view.name.as_str());
+
+        // process it using some model + draw overlays on the output image
+        let mut times = FrameTimes::default();
+        let result = inference::infer_on_image(
+            session,
+            input_dims,
+            Some(&mut *state.tracker),
+            state.motion.as_mut(),
+            state.smoother.as_mut(),
+            image.clone(),
+            &mut times,
+        );
+        view_times.push(times);
+        let (mut processed, mut bboxes, issues) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                // Pass the frame through without detections, so frame numbering stays intact.
+                let e = match &state.view {
+                    Some(view) => e.context(format!("view {}", view.name)),
+                    None => e,
+                };
+                if let Some(dumper) = state.dumper.as_mut() {
+                    let reason = format!("inference error: {e:#}");
+                    dumper.dump(frame_num, timestamp_ms, &image, input_dims, &reason);
+                }
+                let e = ProcessingError::new(ErrorCategory::Inference, e);
+                recover(errors, frame_num, &e);
+                (image.clone(), vec![], OutputIssues::default())
+            }
+        };
+        if issues.is_bad() {
+            if let Some(dumper) = state.dumper.as_mut() {
+                let reason = format!("broken model output: {issues}");
+                dumper.dump(frame_num, timestamp_ms, &image, input_dims, &reason);
+            }
+            let e = match &state.view {
+                Some(view) => anyhow::anyhow!("view {}: {issues}", view.name),
+                None => anyhow::anyhow!("{issues}"),
+            };
+            let e = ProcessingError::new(ErrorCategory::ModelOutput, e);
+            if !recover(errors, frame_num, &e) {
+                // Pass the view through without any of its boxes.
+                (processed, bboxes) = (image.clone(), vec![]);
+            }
         }
+        match &state.view {
+            Some(view) => view.paste(
+                output.get_or_insert_with(|| frame.clone()),
+                &processed.to_rgb8(),
+            ),
+            None => output = Some(processed.into_rgb8()),
+        }
+
+        // Enhanced logging with color extraction, in view (frame) coordinates.
+        let letterbox = Letterbox::new(image.dimensions().into(), input_dims);
+        let mut view_detections = Vec::new();
+
+        for (class_idx, class_bboxes) in bboxes.iter().enumerate() {
+            for bbox in class_bboxes {
+                let bbox = &letterbox.unmap(bbox);
+                // Get class name for this detection
+                let class_name = inference_common::coco_classes::NAMES
+                    .get(class_idx)
+                    .unwrap_or(&"unknown");
+
+                // Extract attributes using ONNX model
+                let attributes = match attr_detector.detect_attributes(
+                    &image,
+                    bbox.xmin,
+                    bbox.ymin,
+                    bbox.xmax,
+                    bbox.ymax,
+                    class_name,
+                ) {
+                    Ok(attributes) => attributes,
+                    Err(e) => {
+                        let e = ProcessingError::new(ErrorCategory::Integration, e);
+                        if !recover(errors, frame_num, &e) {
+                            // Leave the detection out rather than logging it incomplete.
+                            continue;
+                        }
+                        Default::default()
+                    }
+                };
+
+                let detection = DetectionLog::from_bbox_with_attributes(
+                    frame_num,
+                    timestamp_ms,
+                    bbox,
+                    class_idx,
+                    image.width() as f32,
+                    image.height() as f32,
+                    attributes,
+                )
+                .with_capture_time(timing.capture_time_ms(), state.utc_offset);
+
+                view_detections.push(detection);
+            }
+        }
+        let duplicate = state
+            .dedup
+            .as_mut()
+            .is_some_and(|dedup| dedup.is_duplicate(&image, &view_detections));
+        if !duplicate {
+            for detection in &view_detections {
+                state.detection_logger.log_detection(detection.clone());
+            }
+        }
+
+        if let Some(shadow) = state.shadow.as_mut() {
+            shadow.process(frame_num, timestamp_ms, &image, &view_detections);
+        }
+
+        // Print frame summary with enhanced formatting (only if not using TUI)
+        if tui_tx.is_none() {
+            if !view_name.is_empty() && !view_detections.is_empty() {
+                println!("\n[{view_name}]");
+            }
+            state.detection_logger.print_frame_summary(frame_num, &view_detections);
+        }
+        frame_detections.extend(view_detections);
+
+        let frame_meta = FrameMeta {
+            pts: buffer.pts().unwrap_or_default().into(),
+            dts: buffer.dts().unwrap_or_default().into(),
+            bboxes_by_class: bboxes,
+        };
+        state.video_meta.push(frame_meta);
     }
-    
-    // Print frame summary with enhanced formatting (only if not using TUI)
-    if tui_tx.is_none() {
-        detection_logger.print_frame_summary(frame_num, &frame_detections);
-    }
-    
+    // Views share the frame, so their stage times add up.
+    frame_times = FrameTimes {
+        queue_wait: frame_times.queue_wait,
+        frame_to_buffer: frame_times.frame_to_buffer,
+        ..view_times.into_iter().sum()
+    };
+
     // Send to TUI if available
     if let Some(tx) = tui_tx {
         let _ = tx.send(TuiMessage::FrameProcessed {
             frame_num,
             timestamp_ms,
-            detections: frame_detections.clone(),
+            detections: frame_detections,
             performance: frame_times.clone(),
         });
     }
-    
-    let frame_meta = FrameMeta {
-        pts: buffer.pts().unwrap_or_default().into(),
-        dts: buffer.dts().unwrap_or_default().into(),
-        bboxes_by_class: bboxes,
-    };
-    video_meta.push(frame_meta);
 
     // overwrite the buffer with our overlaid processed image
     let start = Instant::now();
-    let buffer_mut = buffer.get_mut().unwrap();
-    let mut writable = buffer_mut.map_writable().unwrap();
-    let mut dst = writable.as_mut_slice();
-    dst.write_all(processed.to_rgb8().as_raw()).unwrap();
+    let processed = output.as_ref().unwrap_or(&frame);
+    write_preview(preview, frame_num, processed);
+    let original = original.as_ref().unwrap_or(&frame);
+    if let Err(e) = write_frame(buffer, &layout, format, processed, original) {
+        recover(errors, frame_num, &e);
+    }
     frame_times.buffer_to_frame = start.elapsed();
+    frame_times.latency = timing.latency().unwrap_or_default();
 
     log::debug!("{frame_times:?}");
     agg_times.push(frame_times);
 }
 
 /// Performs inference on a video file, using a gstreamer pipeline + ort.
-pub fn process_video(input: &Path, live_playback: bool, session: Session) -> anyhow::Result<()> {
-    process_video_internal(input, live_playback, session, None)
+pub fn process_video(
+    input: &Path,
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
+) -> anyhow::Result<StreamStats> {
+    process_video_internal(input, options, sessions, None)
 }
 
 /// Internal version with optional TUI sender
 pub fn process_video_internal(
     input: &Path, 
-    live_playback: bool, 
-    session: Session,
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
     tui_tx: Option<Sender<TuiMessage>>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<StreamStats> {
+    anyhow::ensure!(
+        options.record_dir.is_none(),
+        "Toggling recordings is only supported for webcams"
+    );
+    anyhow::ensure!(
+        options.hls_dir.is_none() && options.webrtc_addr.is_none(),
+        "HLS and WebRTC output is only supported for webcams"
+    );
+    anyhow::ensure!(
+        options.continuous.is_none(),
+        "Continuous recording is only supported for webcams"
+    );
+    anyhow::ensure!(
+        options.throttle.is_none(),
+        "Thermal throttling is only supported for webcams"
+    );
+    anyhow::ensure!(
+        options.large_model.is_none(),
+        "Switching to a large model is only supported for webcams"
+    );
+    anyhow::ensure!(!options.service, "Service mode is only supported for webcams");
     gst::init()?;
 
     let agg_times = Arc::new(Mutex::new(AggregatedTimes::default()));
@@ -146,6 +806,7 @@ pub fn process_video_internal(
     log::info!("Discovering media properties of {input:?}");
     let file_info = discovery::discover(input)?;
     log::info!("{file_info:?}");
+    options.decoder.prefer(Some(&file_info.codec_caps));
     let frame_dims = ImgDimensions::new(file_info.width as f32, file_info.height as f32);
 
     // Send video info to TUI
@@ -161,55 +822,71 @@ pub fn process_video_internal(
         });
     }
 
-    let output_path = input.with_extension("out.mkv");
+    let output_base = options.output_base(input);
+    let output_path = output_base.with_extension("out.mkv");
 
-    // Configure tracker, we use similari library, which provides iou/sort trackers.
-    let tracker = inference_common::tracker::sort_tracker();
+    let input_dims = options.input_dims;
+    let undistorter = Mutex::new(options.lens.map(Undistorter::new));
+    let clock_skew = Arc::new(Mutex::new(ClockSkew::new(
+        input.display().to_string(),
+        MAX_CLOCK_SKEW,
+    )));
+    let views = Arc::new(Mutex::new(ViewState::for_input(
+        input,
+        &output_path,
+        frame_dims,
+        &options,
+    )?));
     
     // Create attribute detector
     let attr_detector = Arc::new(Mutex::new(
         AttributeDetector::new(None, None).expect("Failed to initialize attribute detector")
     ));
-    
-    // Create detection logger
-    let detection_logger = Arc::new(Mutex::new(DetectionLogger::new()));
 
     // Wrap TUI sender in Arc for sharing
     let tui_tx = Arc::new(tui_tx);
 
     // Build gst pipeline, which performs inference using the loaded model.
     let scoped_agg = Arc::clone(&agg_times);
-    let video_meta = Arc::new(Mutex::new(VideoMeta::new(
-        input.to_path_buf(),
-        Some(output_path.clone()),
-        frame_dims.width as u32,
-        frame_dims.height as u32,
-    )));
-    let scoped_meta = Arc::clone(&video_meta);
-    let scoped_logger = Arc::clone(&detection_logger);
+    let scoped_views = Arc::clone(&views);
     let scoped_attr = Arc::clone(&attr_detector);
     let scoped_tui_tx = Arc::clone(&tui_tx);
-    // FIXME can we do it without Mutex? it's not gonna be contested much, tho...
-    let session = Arc::new(Mutex::new(session));
+    let scoped_skew = Arc::clone(&clock_skew);
+    let errors = Arc::new(Mutex::new(ErrorStats::default()));
+    let scoped_errors = Arc::clone(&errors);
+    let stream = sessions.register_stream(input.display().to_string());
+    let scoped_stream = stream.clone();
+    let drops = Arc::new(FrameDrops::default());
+    let preview = options.preview.clone();
+    let pixel_format = options.pixel_format;
     let pipeline = build_pipeline(
         input.to_str().unwrap(),
         output_path.to_str().unwrap(),
-        live_playback,
-        move |buf| {
-            let mut agg_times = scoped_agg.lock().unwrap();
-            let mut video_meta = scoped_meta.lock().unwrap();
-            let mut session = session.lock().unwrap();
-            let mut logger = scoped_logger.lock().unwrap();
-            let mut attr_detector = scoped_attr.lock().unwrap();
+        options.live_playback,
+        pixel_format,
+        options.queue,
+        &drops,
+        move |buf, timing| {
+            timing.observe_clock_skew(&mut lock(&scoped_skew));
+            let mut agg_times = lock(&scoped_agg);
+            let mut views = lock(&scoped_views);
+            let mut session = scoped_stream.acquire();
+            let mut attr_detector = lock(&scoped_attr);
+            let mut undistorter = lock(&undistorter);
+            let mut errors = lock(&scoped_errors);
             process_buffer(
                 frame_dims,
+                pixel_format,
+                input_dims,
                 &mut session,
-                &tracker,
+                undistorter.as_mut(),
+                &mut views,
                 &mut agg_times,
-                &mut video_meta,
-                &mut logger,
                 buf,
+                timing,
                 &mut attr_detector,
+                &mut errors,
+                preview.as_ref(),
                 &scoped_tui_tx.as_ref(),
             );
         },
@@ -218,8 +895,12 @@ pub fn process_video_internal(
 
     // Make it play and listen to events to know when it's done.
     pipeline.set_state(gst::State::Playing).unwrap();
+    if let Some(stop) = &options.stop {
+        stop.attach(&pipeline);
+    }
 
     let bus = pipeline.bus().unwrap();
+    let mut pipeline_error = None;
     for msg in bus.iter_timed(gst::ClockTime::NONE) {
         match msg.view() {
             MessageView::Error(err) => {
@@ -229,6 +910,7 @@ pub fn process_video_internal(
                 if let Some(ref tx) = tui_tx.as_ref() {
                     let _ = tx.send(TuiMessage::Error(format!("{}", err.error())));
                 }
+                pipeline_error = Some(err.error());
                 break;
             }
             MessageView::Eos(..) => {
@@ -242,56 +924,99 @@ pub fn process_video_internal(
         }
     }
 
-    let video_meta = video_meta.lock().unwrap();
-    let output_json_path = input.with_extension("json");
-    log::info!(
-        "Writing output json file, {} frames: {output_json_path:?}",
-        video_meta.frames.len()
-    );
-    serde_json::to_writer(std::fs::File::create(&output_json_path)?, &*video_meta)?;
-    
-    // Export detection logs
-    let detection_logger = detection_logger.lock().unwrap();
-    let detections_path = input.with_extension("detections.json");
-    log::info!("Writing detection logs: {detections_path:?}");
-    detection_logger.export_json(&detections_path)?;
-
     pipeline.set_state(gst::State::Null).unwrap();
 
-    // Print perf stats, ignoring first (outlier) frame.
-    let agg = agg_times.lock().unwrap();
-    let avg = agg.avg(true);
-    log::info!("Average frame times: {avg:?}");
-
-    let min = agg.min(true);
-    log::info!("Min frame times: {min:?}");
+    for view in lock(&views).iter() {
+        view.write_outputs(&output_base)?;
+    }
 
-    let max = agg.max(true);
-    log::info!("Max frame times: {max:?}");
+    // Print perf stats, ignoring first (outlier) frame.
+    let mut agg = lock(&agg_times);
+    agg.log_summary(true);
+    stream.log_summary(SESSION_STARVATION);
+    lock(&clock_skew).log_summary();
+    lock(&errors).log_summary();
+    drops.log_summary();
+    log_caught_panics();
+    // Outputs of the frames processed so far are written, but the run failed.
+    if let Some(e) = pipeline_error {
+        anyhow::bail!("Video pipeline failed: {e}");
+    }
 
-    Ok(())
+    Ok(StreamStats {
+        frame_dims,
+        times: std::mem::take(&mut *agg),
+    })
 }
 
 /// Performs inference on webcam stream
-pub fn process_webcam(device: &str, live_playback: bool, session: Session) -> anyhow::Result<()> {
-    process_webcam_internal(device, live_playback, session, None)
+pub fn process_webcam(
+    device: &str,
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
+) -> anyhow::Result<StreamStats> {
+    process_webcam_internal(device, options, sessions, None)
 }
 
 /// Internal version with optional TUI sender
 pub fn process_webcam_internal(
     device: &str,
-    live_playback: bool,
-    session: Session,
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
     tui_tx: Option<Sender<TuiMessage>>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<StreamStats> {
+    anyhow::ensure!(
+        options.views == ViewLayout::Whole,
+        "Splitting frames into views is only supported for video files"
+    );
+    anyhow::ensure!(
+        options.shadow.is_none(),
+        "Shadow models are only supported for video files"
+    );
+    anyhow::ensure!(
+        !options.onvif_metadata,
+        "ONVIF metadata is only written for video files"
+    );
+    anyhow::ensure!(
+        options.decoder == DecoderBackend::Auto
+            || options.webcam_codec != WebcamCodec::Raw
+            || LiveSource::is_rtsp(device),
+        "Raw webcam frames aren't decoded, choosing decoders needs a compressed webcam codec"
+    );
+    anyhow::ensure!(
+        cfg!(target_os = "linux") || !options.service,
+        "Service mode is only supported on Linux"
+    );
+    // Tracks and smoothed boxes are kept in model input coordinates across model switches.
+    anyhow::ensure!(
+        options
+            .large_model
+            .as_ref()
+            .is_none_or(|large| large.input_dims == options.input_dims),
+        "The large model must take the same input size as the primary one"
+    );
+    anyhow::ensure!(
+        options
+            .throttle
+            .as_ref()
+            .and_then(|throttle| throttle.fallback.as_ref())
+            .is_none_or(|(_, fallback_dims)| *fallback_dims == options.input_dims),
+        "The throttle model must take the same input size as the primary one"
+    );
     gst::init()?;
+    let record_dir = options.record_dir.clone();
+    let service = options.service;
 
     let agg_times = Arc::new(Mutex::new(AggregatedTimes::default()));
     
     // For webcam, we'll detect dimensions from the first buffer
-    // Start with a default that will be updated
-    let frame_dims = Arc::new(Mutex::new(ImgDimensions::new(640.0, 480.0)));
-    let dims_detected = Arc::new(Mutex::new(false));
+    // Start with a default that will be updated, synthetic frames have the size they're made with
+    let (initial_dims, detected) = match options.synthetic {
+        Some(synthetic) => ((synthetic.width, synthetic.height), true),
+        None => ((640, 480), false),
+    };
+    let frame_dims = Arc::new(Mutex::new(ImgDimensions::from(initial_dims)));
+    let dims_detected = Arc::new(Mutex::new(detected));
     
     log::info!("Starting webcam inference from device: {device}");
     
@@ -299,14 +1024,20 @@ pub fn process_webcam_internal(
     if let Some(ref tx) = tui_tx {
         let _ = tx.send(TuiMessage::VideoInfo {
             filename: format!("Webcam: {}", device),
-            width: 640,
-            height: 480,
+            width: initial_dims.0,
+            height: initial_dims.1,
             total_frames: None,
         });
     }
     
-    let tracker = inference_common::tracker::sort_tracker();
+    let input_dims = options.input_dims;
+    let tracker = Mutex::new(options.new_tracker());
+    let undistorter = Mutex::new(options.lens.map(Undistorter::new));
+    let motion = Mutex::new(options.camera_motion.then(MotionEstimator::new));
+    let smoother = Mutex::new(options.box_smoothing.map(BoxSmoother::new));
+    let dumper = Mutex::new(options.dump_failures.as_deref().map(FailureDumper::new).transpose()?);
     let detection_logger = Arc::new(Mutex::new(DetectionLogger::new()));
+    let dedup = Mutex::new(options.dedupe_frames.map(FrameDedup::new));
     let attr_detector = Arc::new(Mutex::new(
         AttributeDetector::new(None, None).expect("Failed to initialize attribute detector")
     ));
@@ -317,23 +1048,76 @@ pub fn process_webcam_internal(
     let scoped_logger = Arc::clone(&detection_logger);
     let scoped_attr = Arc::clone(&attr_detector);
     let scoped_tui = Arc::clone(&tui_tx);
-    let session = Arc::new(Mutex::new(session));
+    let clock_skew = Arc::new(Mutex::new(ClockSkew::new(device, MAX_CLOCK_SKEW)));
+    let scoped_skew = Arc::clone(&clock_skew);
+    let errors = Arc::new(Mutex::new(ErrorStats::default()));
+    let scoped_errors = Arc::clone(&errors);
+    let stream = sessions.register_stream(device);
+    let scoped_stream = stream.clone();
     let frame_count = Arc::new(Mutex::new(0u64));
+    #[cfg(target_os = "linux")]
+    let watchdog_frames = Arc::clone(&frame_count);
+    let drops = Arc::new(FrameDrops::default());
+    let preview = options.preview.clone();
+    let utc_offset = options.utc_offset;
+    let pixel_format = options.pixel_format;
+    let data_channels = options.webrtc_addr.as_ref().map(|_| DataChannels::default());
+    let scoped_channels = data_channels.clone();
+    let throttle = Arc::new(Mutex::new(
+        options.throttle.as_ref().map(|throttle| {
+            let thermal = ThermalThrottle::new(throttle.limits);
+            match &throttle.power_sensor {
+                Some(path) => thermal.with_power_sensor(path.clone()),
+                None => thermal,
+            }
+        }),
+    ));
+    let scoped_throttle = Arc::clone(&throttle);
+    let throttle_every = options.throttle.as_ref().map_or(1, |throttle| throttle.every);
+    let throttled_frames = Mutex::new(0u64);
+    let fallback = options
+        .throttle
+        .as_ref()
+        .and_then(|throttle| throttle.fallback.as_ref())
+        .map(|(sessions, _)| sessions.register_stream(device));
+    let model_switch = Arc::new(Mutex::new(
+        options.large_model.as_ref().map(|large| ModelSwitch::new(large.policy)),
+    ));
+    let scoped_switch = Arc::clone(&model_switch);
+    let large_model = options
+        .large_model
+        .as_ref()
+        .map(|large| large.sessions.register_stream(device));
     
+    let rtsp = LiveSource::is_rtsp(device);
+    if rtsp {
+        options.decoder.prefer(None);
+    } else if options.webcam_codec != WebcamCodec::Raw {
+        options.decoder.prefer(Some(&options.webcam_codec.caps()));
+    }
+    let source = match options.synthetic {
+        Some(synthetic) => LiveSource::Synthetic(synthetic),
+        None if rtsp => LiveSource::Rtsp { url: device },
+        None => LiveSource::Webcam {
+            device,
+            codec: options.webcam_codec,
+        },
+    };
     let pipeline = gstreamed_common::pipeline::build_webcam_pipeline(
-        device,
-        live_playback,
-        move |buf| {
-            // Detect dimensions from buffer size if not yet detected
+        source,
+        options.live_playback,
+        pixel_format,
+        options.queue,
+        &drops,
+        move |buf, timing| {
+            timing.observe_clock_skew(&mut lock(&scoped_skew));
+            // Detect dimensions from the caps, or buffer size, if not yet detected
             let dims = {
-                let detected = scoped_detected.lock().unwrap();
+                let detected = lock(&scoped_detected);
                 if !*detected {
                     drop(detected);
-                    let readable = buf.map_readable().unwrap();
-                    let buffer_size = readable.len();
-                    drop(readable);
+                    let buffer_size = buf.size();
                     
-                    // RGB format: buffer_size = width * height * 3
                     // Common webcam resolutions to try
                     let common_resolutions = [
                         (640, 480),
@@ -343,107 +1127,200 @@ pub fn process_webcam_internal(
                         (320, 240),
                     ];
                     
-                    for (w, h) in common_resolutions {
-                        if w * h * 3 == buffer_size {
-                            let mut dims_lock = scoped_dims.lock().unwrap();
-                            *dims_lock = ImgDimensions::new(w as f32, h as f32);
-                            log::info!("Detected webcam resolution: {}x{}", w, h);
-                            let mut detected_lock = scoped_detected.lock().unwrap();
-                            *detected_lock = true;
-                            break;
-                        }
+                    let size = timing.frame_size().or_else(|| {
+                        common_resolutions
+                            .into_iter()
+                            .find(|&(w, h)| pixel_format.frame_size(w, h) == buffer_size)
+                    });
+                    if let Some((w, h)) = size {
+                        let mut dims_lock = lock(&scoped_dims);
+                        *dims_lock = ImgDimensions::new(w as f32, h as f32);
+                        log::info!("Detected webcam resolution: {}x{}", w, h);
+                        let mut detected_lock = lock(&scoped_detected);
+                        *detected_lock = true;
                     }
                 }
-                *scoped_dims.lock().unwrap()
+                *lock(&scoped_dims)
             };
             
-            let mut frame_times = FrameTimes::default();
+            // Skipped and failed frames count too, so frame numbers match the output video.
+            let frame_num = {
+                let mut frame_count = lock(&frame_count);
+                *frame_count += 1;
+                *frame_count
+            };
+            let throttled = match lock(&scoped_throttle).as_mut() {
+                Some(throttle) => {
+                    let throttled = throttle.poll();
+                    if let Some(ref tx) = scoped_tui.as_ref() {
+                        let _ = tx.send(TuiMessage::Thermal {
+                            reading: throttle.reading(),
+                            throttled,
+                        });
+                    }
+                    throttled
+                }
+                None => false,
+            };
+            if throttled {
+                let mut throttled_frames = lock(&throttled_frames);
+                *throttled_frames += 1;
+                if (*throttled_frames - 1) % throttle_every != 0 {
+                    return;
+                }
+            }
+            // Throttling wins over activity, it protects the device.
+            let use_large = lock(&scoped_switch).as_ref().is_some_and(ModelSwitch::use_large);
+            let stream = match (&fallback, &large_model) {
+                (Some(fallback_stream), _) if throttled => fallback_stream,
+                (_, Some(large_stream)) if use_large && !throttled => large_stream,
+                _ => &scoped_stream,
+            };
+
+            let mut frame_times = FrameTimes {
+                queue_wait: timing.queue_wait,
+                ..Default::default()
+            };
             let start = Instant::now();
+            let mut errors = lock(&scoped_errors);
+            errors.frame();
             
-            // Read buffer into an image
-            let image = {
-                let readable = buf.map_readable().unwrap();
-                let readable_vec = readable.to_vec();
-                
-                let image = RgbImage::from_vec(
-                    dims.width as u32,
-                    dims.height as u32,
-                    readable_vec,
-                );
-                
-                if let Some(img) = image {
-                    DynamicImage::ImageRgb8(img)
-                } else {
-                    log::error!("Failed to create image from buffer with dims {}x{}", dims.width, dims.height);
+            // Read buffer into an image, keeping what the buffer holds if undistortion changes it
+            let layout = frame_layout(buf, timing, dims, pixel_format);
+            let (image, original) = match read_frame(buf, &layout, pixel_format) {
+                Ok(img) => match lock(&undistorter).as_mut() {
+                    Some(undistorter) => {
+                        (DynamicImage::ImageRgb8(undistorter.apply(&img)), Some(img))
+                    }
+                    None => (DynamicImage::ImageRgb8(img), None),
+                },
+                Err(e) => {
+                    recover(&mut errors, frame_num, &e);
                     return;
                 }
             };
             frame_times.frame_to_buffer = start.elapsed();
             
             // Process with inference
-            let mut session = session.lock().unwrap();
-            let mut tracker = tracker.lock().unwrap();
-            let (processed, bboxes) = match inference::infer_on_image(&mut *session, Some(&mut *tracker), image.clone(), &mut frame_times) {
+            let mut session = stream.acquire();
+            let mut tracker = lock(&tracker);
+            let mut motion = lock(&motion);
+            let mut smoother = lock(&smoother);
+            let (processed, bboxes, issues) = match inference::infer_on_image(
+                &mut *session,
+                input_dims,
+                Some(&mut **tracker),
+                motion.as_mut(),
+                smoother.as_mut(),
+                image.clone(),
+                &mut frame_times,
+            ) {
                 Ok(result) => result,
                 Err(e) => {
-                    log::error!("Inference error: {}", e);
+                    if let Some(dumper) = lock(&dumper).as_mut() {
+                        let timestamp_ms = buf.pts().unwrap_or_default().mseconds();
+                        let reason = format!("inference error: {e}");
+                        dumper.dump(frame_num, timestamp_ms, &image, input_dims, &reason);
+                    }
+                    let e = ProcessingError::new(ErrorCategory::Inference, e);
+                    recover(&mut errors, frame_num, &e);
                     return;
                 }
             };
             
-            // Enhanced logging with color extraction
-            let mut frame_num = frame_count.lock().unwrap();
-            *frame_num += 1;
+            // Enhanced logging with color extraction, in frame coordinates.
+            let letterbox = Letterbox::new(dims, input_dims);
             let timestamp_ms = buf.pts().unwrap_or_default().mseconds();
+            if issues.is_bad() {
+                if let Some(dumper) = lock(&dumper).as_mut() {
+                    let reason = format!("broken model output: {issues}");
+                    dumper.dump(frame_num, timestamp_ms, &image, input_dims, &reason);
+                }
+                let e = anyhow::anyhow!("{issues}");
+                let e = ProcessingError::new(ErrorCategory::ModelOutput, e);
+                if !recover(&mut errors, frame_num, &e) {
+                    return;
+                }
+            }
             let mut frame_detections = Vec::new();
             
             for (class_idx, class_bboxes) in bboxes.iter().enumerate() {
                 for bbox in class_bboxes {
+                    let bbox = &letterbox.unmap(bbox);
                     // Get class name for this detection
                     let class_name = inference_common::coco_classes::NAMES
                         .get(class_idx)
                         .unwrap_or(&"unknown");
                     
                     // Extract attributes using ONNX model
-                    let mut attr_detector = scoped_attr.lock().unwrap();
-                    let attributes = attr_detector.detect_attributes(
+                    let mut attr_detector = lock(&scoped_attr);
+                    let attributes = match attr_detector.detect_attributes(
                         &image,
                         bbox.xmin,
                         bbox.ymin,
                         bbox.xmax,
                         bbox.ymax,
                         class_name,
-                    ).unwrap_or_default();
+                    ) {
+                        Ok(attributes) => attributes,
+                        Err(e) => {
+                            let e = ProcessingError::new(ErrorCategory::Integration, e);
+                            if !recover(&mut errors, frame_num, &e) {
+                                // Leave the detection out rather than logging it incomplete.
+                                continue;
+                            }
+                            Default::default()
+                        }
+                    };
                     
                     let detection = DetectionLog::from_bbox_with_attributes(
-                        *frame_num,
+                        frame_num,
                         timestamp_ms,
                         bbox,
                         class_idx,
                         dims.width,
                         dims.height,
                         attributes,
-                    );
+                    )
+                    .with_capture_time(timing.capture_time_ms(), utc_offset);
                     
                     frame_detections.push(detection.clone());
                 }
             }
+            if let Some(switch) = lock(&scoped_switch).as_mut() {
+                let confidences = frame_detections.iter().map(|detection| detection.confidence);
+                switch.update(timestamp_ms, confidences);
+            }
             
             // Print frame summary with enhanced formatting (skip if using TUI)
+            let duplicate = lock(&dedup)
+                .as_mut()
+                .is_some_and(|dedup| dedup.is_duplicate(&image, &frame_detections));
             if !frame_detections.is_empty() {
-                let mut logger = scoped_logger.lock().unwrap();
-                for detection in &frame_detections {
-                    logger.log_detection(detection.clone());
+                let mut logger = lock(&scoped_logger);
+                if !duplicate {
+                    for detection in &frame_detections {
+                        logger.log_detection(detection.clone());
+                    }
                 }
                 if scoped_tui.is_none() {
-                    logger.print_frame_summary(*frame_num, &frame_detections);
+                    logger.print_frame_summary(frame_num, &frame_detections);
                 }
             }
             
+            if let Some(channels) = &scoped_channels {
+                let frame = serde_json::json!({
+                    "frame_num": frame_num,
+                    "timestamp_ms": timestamp_ms,
+                    "detections": &frame_detections,
+                });
+                channels.send(&frame.to_string());
+            }
+
             // Send to TUI if available
             if let Some(ref tx) = scoped_tui.as_ref() {
                 let _ = tx.send(TuiMessage::FrameProcessed {
-                    frame_num: *frame_num,
+                    frame_num,
                     timestamp_ms,
                     detections: frame_detections.clone(),
                     performance: frame_times.clone(),
@@ -452,43 +1329,83 @@ pub fn process_webcam_internal(
             
             // Overwrite the buffer with processed image
             let start = Instant::now();
-            if let Some(buffer_mut) = buf.get_mut() {
-                if let Ok(mut writable) = buffer_mut.map_writable() {
-                    let mut dst = writable.as_mut_slice();
-                    let processed_raw = processed.to_rgb8();
-                    let src = processed_raw.as_raw();
-                    
-                    // Only write if sizes match
-                    if dst.len() == src.len() {
-                        if let Err(e) = dst.write_all(src) {
-                            log::error!("Failed to write processed frame: {}", e);
-                        }
-                    } else {
-                        log::warn!("Buffer size mismatch: dst={}, src={}", dst.len(), src.len());
-                    }
-                } else {
-                    log::error!("Failed to map buffer as writable");
-                }
-            } else {
-                log::error!("Failed to get mutable buffer");
+            let processed_raw = processed.to_rgb8();
+            write_preview(preview.as_ref(), frame_num, &processed_raw);
+            let original = match &original {
+                Some(original) => original,
+                None => image.as_rgb8().expect("frames are read as RGB"),
+            };
+            if let Err(e) = write_frame(buf, &layout, pixel_format, &processed_raw, original) {
+                recover(&mut errors, frame_num, &e);
             }
             frame_times.buffer_to_frame = start.elapsed();
+            frame_times.latency = timing.latency().unwrap_or_default();
             
             log::debug!("{frame_times:?}");
-            let mut agg = scoped_agg.lock().unwrap();
+            let mut agg = lock(&scoped_agg);
             agg.push(frame_times);
         },
     )?;
     
+    // Outputs are set up before playing, so failing to doesn't leave a running pipeline behind.
+    if let Some(dir) = &options.hls_dir {
+        std::fs::create_dir_all(dir)?;
+        OutputBranch::add(&pipeline, "hls", &output_branch::hls_elements(dir)?)?;
+        log::info!("Streaming HLS into {:?}", dir.join("playlist.m3u8"));
+    }
+    if let (Some(addr), Some(channels)) = (&options.webrtc_addr, &data_channels) {
+        let elements = output_branch::webrtc_elements(addr, channels)?;
+        OutputBranch::add(&pipeline, "webrtc", &elements)?;
+        log::info!("Streaming WebRTC, watch at http://{addr}/");
+    }
+    if let Some(recorder) = &options.continuous {
+        std::fs::create_dir_all(&recorder.dir)?;
+        OutputBranch::add(&pipeline, "continuous", &recorder.elements()?)?;
+        log::info!("Recording continuously into {:?}", recorder.dir);
+    }
+    if let Some(dir) = &record_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+
     log::info!("Starting webcam pipeline");
     pipeline.set_state(gst::State::Playing).unwrap();
-    
+    if let Some(stop) = &options.stop {
+        stop.attach(&pipeline);
+    }
     let bus = pipeline.bus().unwrap();
     if tui_tx.is_none() {
         println!("Webcam inference running. Press Ctrl+C to stop.");
     }
+    if let Some(dir) = &record_dir {
+        if tui_tx.is_none() {
+            println!("Enter r to start/stop recording into {dir:?}.");
+            spawn_recording_toggle(bus.clone());
+        } else {
+            log::warn!("Recordings can't be toggled with the TUI, ignoring --record-dir");
+        }
+    }
+    #[cfg(target_os = "linux")]
+    if service {
+        service::notify("READY=1");
+        service::spawn_watchdog(move || *lock(&watchdog_frames));
+        let pipeline = pipeline.clone();
+        // Finish with EOS, so outputs (and recordings) are written as on a regular end of stream.
+        service::handle_signals(move || {
+            pipeline.send_event(gst::event::Eos::new());
+        });
+    }
+    let mut recording = None;
+    let mut pipeline_error = None;
     for msg in bus.iter_timed(gst::ClockTime::NONE) {
         match msg.view() {
+            MessageView::Application(app)
+                if app.structure().is_some_and(|s| s.has_name(TOGGLE_RECORDING)) =>
+            {
+                let dir = record_dir.as_deref().expect("toggled without a record dir");
+                if let Err(e) = toggle_recording(&pipeline, dir, &mut recording) {
+                    log::error!("Failed to toggle recording: {e:#}");
+                }
+            }
             MessageView::Error(err) => {
                 pipeline.debug_to_dot_file(gst::DebugGraphDetails::all(), "pipeline.error");
                 let name = err.src().map(|e| e.name().to_string());
@@ -496,6 +1413,7 @@ pub fn process_webcam_internal(
                 if let Some(ref tx) = tui_tx.as_ref() {
                     let _ = tx.send(TuiMessage::Error(format!("{}", err.error())));
                 }
+                pipeline_error = Some(err.error());
                 break;
             }
             MessageView::Eos(..) => {
@@ -512,15 +1430,26 @@ pub fn process_webcam_internal(
     pipeline.set_state(gst::State::Null).unwrap();
     
     // Print perf stats
-    let agg = agg_times.lock().unwrap();
-    let avg = agg.avg(true);
-    log::info!("Average frame times: {avg:?}");
-    
-    let min = agg.min(true);
-    log::info!("Min frame times: {min:?}");
-    
-    let max = agg.max(true);
-    log::info!("Max frame times: {max:?}");
+    let mut agg = lock(&agg_times);
+    agg.log_summary(true);
+    stream.log_summary(SESSION_STARVATION);
+    lock(&clock_skew).log_summary();
+    lock(&errors).log_summary();
+    drops.log_summary();
+    if let Some(switch) = lock(&model_switch).as_ref() {
+        switch.log_summary();
+    }
+    if let Some(throttle) = lock(&throttle).as_ref() {
+        throttle.log_summary();
+    }
+    log_caught_panics();
+    // Exit non-zero, so the service gets restarted.
+    if let (true, Some(e)) = (service, pipeline_error) {
+        anyhow::bail!("Webcam pipeline failed: {e}");
+    }
     
-    Ok(())
+    Ok(StreamStats {
+        frame_dims: *lock(&frame_dims),
+        times: std::mem::take(&mut *agg),
+    })
 }
@@ -0,0 +1,138 @@
+//! Python bindings (`pyo3` feature), to drive video processing from notebooks. Build and install
+//! into the active virtualenv with `maturin develop --release` in `gstreamed_ort/`, then:
+//!
+//! ```python
+//! import gstreamed_ort
+//!
+//! def on_frame(frame):
+//!     print(frame["frame_num"], len(frame["detections"]))
+//!
+//! detections = gstreamed_ort.run_video(
+//!     "video.mp4", "_models/yolov8s.onnx", tracker="ocsort", on_frame=on_frame
+//! )
+//! ```
+//!
+//! Detections are dicts shaped like the entries of the detections json logs, output files are
+//! written like on the command line.
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+
+use inference_common::tracker::TrackerKind;
+use ort_common::model_input;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::inference;
+use crate::process_video::{self, ProcessOptions};
+use crate::tui::app::TuiMessage;
+
+fn runtime_error(e: impl std::fmt::Display) -> PyErr {
+    PyRuntimeError::new_err(format!("{e:#}"))
+}
+
+/// `value` as the equivalent Python object (dicts, lists, ...).
+fn to_python(py: Python<'_>, value: serde_json::Value) -> PyResult<Bound<'_, PyAny>> {
+    py.import("json")?
+        .call_method1("loads", (value.to_string(),))
+}
+
+/// Runs `model` over the video file at `path`, returning all detections.
+///
+/// `on_frame` is called with a dict (`frame_num`, `timestamp_ms` and `detections`) for every
+/// processed frame, while the video is still being processed. Exceptions it raises stop further
+/// calls and are re-raised once the video is done. `providers` takes the command line's
+/// execution provider flags, eg. `["--cuda"]`.
+#[pyfunction]
+#[pyo3(signature = (
+    path,
+    model,
+    *,
+    input_size = None,
+    tracker = None,
+    output_dir = None,
+    providers = Vec::new(),
+    on_frame = None
+))]
+#[allow(clippy::too_many_arguments)]
+fn run_video<'py>(
+    py: Python<'py>,
+    path: PathBuf,
+    model: &str,
+    input_size: Option<&str>,
+    tracker: Option<&str>,
+    output_dir: Option<PathBuf>,
+    providers: Vec<String>,
+    on_frame: Option<Bound<'py, PyAny>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let input_size = input_size
+        .map(model_input::parse_input_size)
+        .transpose()
+        .map_err(PyValueError::new_err)?;
+    let tracker = tracker
+        .map(str::parse::<TrackerKind>)
+        .transpose()
+        .map_err(PyValueError::new_err)?;
+    let (sessions, input_dims) = py
+        .allow_threads(|| inference::load_model(model, input_size, &providers))
+        .map_err(runtime_error)?;
+
+    let mut options = ProcessOptions::new(input_dims);
+    options.tracker = tracker.unwrap_or_default();
+    options.output_dir = output_dir;
+
+    let (tx, mut rx) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        process_video::process_video_internal(&path, options, sessions, Some(tx))
+    });
+
+    let mut detections = Vec::new();
+    let mut callback_error = None;
+    loop {
+        // Waits without holding the GIL, so other Python threads keep running.
+        let (message, returned) = py.allow_threads(move || (rx.recv(), rx));
+        rx = returned;
+        match message {
+            Ok(TuiMessage::FrameProcessed {
+                frame_num,
+                timestamp_ms,
+                detections: frame_detections,
+                ..
+            }) => {
+                if let (Some(on_frame), None) = (&on_frame, &callback_error) {
+                    let frame = serde_json::json!({
+                        "frame_num": frame_num,
+                        "timestamp_ms": timestamp_ms,
+                        "detections": &frame_detections,
+                    });
+                    if let Err(e) = to_python(py, frame).and_then(|frame| on_frame.call1((frame,)))
+                    {
+                        callback_error = Some(e);
+                    }
+                }
+                detections.extend(frame_detections);
+            }
+            Ok(TuiMessage::VideoInfo { .. } | TuiMessage::Thermal { .. }) => {}
+            // Errors are returned by the worker.
+            Ok(TuiMessage::Finished | TuiMessage::Error(_)) | Err(_) => break,
+        }
+    }
+
+    py.allow_threads(move || worker.join())
+        .map_err(|_| PyRuntimeError::new_err("Video processing panicked"))?
+        .map_err(runtime_error)?;
+    if let Some(e) = callback_error {
+        return Err(e);
+    }
+    to_python(
+        py,
+        serde_json::to_value(&detections).map_err(runtime_error)?,
+    )
+}
+
+#[pymodule]
+fn gstreamed_ort(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(run_video, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! `replay` subcommand, re-runs processing over stored recordings into a separate run directory,
+//! eg. after a model or threshold upgrade. Runs can then be diffed with `compare`.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use gstreamed_ort::process_video::{self, ProcessOptions};
+use ort_common::session_pool::SessionPool;
+
+/// Extensions of the video files searched for in directories.
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "mov", "avi", "ts", "webm"];
+
+/// Video files found in `paths`, directories are searched (non-recursively) for
+/// [VIDEO_EXTENSIONS] files. Explicitly given files are taken as is, discovery rejects
+/// what GStreamer can't decode.
+fn collect_recordings(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let is_video = |path: &Path| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext))
+    };
+    let mut recordings = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut found = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                let entry_path = entry?.path();
+                // Skip annotated outputs of earlier runs stored alongside the recordings.
+                let is_output = entry_path.to_string_lossy().ends_with(".out.mkv");
+                if entry_path.is_file() && is_video(&entry_path) && !is_output {
+                    found.push(entry_path);
+                }
+            }
+            found.sort();
+            recordings.extend(found);
+        } else {
+            anyhow::ensure!(path.is_file(), "Recording not found: {path:?}");
+            recordings.push(path.clone());
+        }
+    }
+
+    // Outputs are named after the file name, so these would overwrite each other.
+    let mut names: Vec<_> = recordings.iter().map(|path| path.file_name()).collect();
+    names.sort();
+    if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+        anyhow::bail!("Multiple recordings named {:?}", pair[0]);
+    }
+    Ok(recordings)
+}
+
+/// Processes `recordings` (files or directories) as fast as possible,
+/// writing all outputs into `runs_dir/run`.
+pub fn replay(
+    recordings: &[PathBuf],
+    runs_dir: &Path,
+    run: &str,
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
+) -> anyhow::Result<()> {
+    let recordings = collect_recordings(recordings)?;
+    anyhow::ensure!(!recordings.is_empty(), "No recordings to replay");
+
+    let run_dir = runs_dir.join(run);
+    anyhow::ensure!(
+        !run_dir.exists(),
+        "Run {run_dir:?} already exists, pick another run name"
+    );
+    std::fs::create_dir_all(&run_dir)?;
+    log::info!("Replaying {} recordings into {run_dir:?}", recordings.len());
+
+    let options = ProcessOptions {
+        // Not synced to the clock, so files are processed faster than realtime.
+        live_playback: false,
+        output_dir: Some(run_dir.clone()),
+        ..options
+    };
+    let mut failed = Vec::new();
+    for (i, recording) in recordings.iter().enumerate() {
+        log::info!("[{}/{}] {recording:?}", i + 1, recordings.len());
+        let result =
+            process_video::process_video(recording, options.clone(), Arc::clone(&sessions));
+        if let Err(e) = result {
+            log::error!("Failed to replay {recording:?}: {e}");
+            failed.push(recording);
+        }
+    }
+
+    println!(
+        "Replayed {} of {} recordings into {run_dir:?}",
+        recordings.len() - failed.len(),
+        recordings.len()
+    );
+    anyhow::ensure!(failed.is_empty(), "Failed to replay {failed:?}");
+    Ok(())
+}
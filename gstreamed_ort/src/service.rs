@@ -0,0 +1,129 @@
+//! `--service` mode, for running webcam inference as a systemd service (`Type=notify`).
+//!
+//! Readiness and watchdog pings are sent over `$NOTIFY_SOCKET`, SIGTERM/SIGINT finish the stream
+//! gracefully (outputs are written), and pipeline errors exit non-zero so `Restart=on-failure`
+//! restarts the service. Service mode is Linux only, [Pidfile]s work everywhere.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+#[cfg(target_os = "linux")]
+pub use systemd::{handle_signals, notify, spawn_watchdog};
+
+#[cfg(target_os = "linux")]
+mod systemd {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// Sends `state` (eg. `READY=1`) to systemd, if started with a notification socket.
+    pub fn notify(state: &str) {
+        let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return;
+        };
+        let result = (|| -> std::io::Result<usize> {
+            let addr = match socket_path.as_encoded_bytes().strip_prefix(b"@") {
+                Some(name) => SocketAddr::from_abstract_name(name)?,
+                None => SocketAddr::from_pathname(Path::new(&socket_path))?,
+            };
+            UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)
+        })();
+        if let Err(e) = result {
+            log::warn!("Failed to notify systemd of {state:?}: {e}");
+        }
+    }
+
+    /// Watchdog timeout requested by systemd (`WatchdogSec=`) for this process, if any.
+    fn watchdog_timeout() -> Option<Duration> {
+        let usec = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        // Set if the watchdog is meant for another process, eg. a wrapper script.
+        if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+            if pid.parse() != Ok(std::process::id()) {
+                return None;
+            }
+        }
+        Some(Duration::from_micros(usec))
+    }
+
+    /// Pings the systemd watchdog as long as `frames` (processed frame count) keeps increasing,
+    /// so a stalled pipeline gets restarted rather than only a crashed one.
+    pub fn spawn_watchdog(frames: impl Fn() -> u64 + Send + 'static) {
+        let Some(timeout) = watchdog_timeout() else {
+            return;
+        };
+        std::thread::spawn(move || {
+            let mut last = frames();
+            loop {
+                std::thread::sleep(timeout / 2);
+                let current = frames();
+                if current != last {
+                    notify("WATCHDOG=1");
+                } else {
+                    log::warn!(
+                        "No frames processed in {:?}, not pinging the watchdog",
+                        timeout / 2
+                    );
+                }
+                last = current;
+            }
+        });
+    }
+
+    static TERMINATE: AtomicBool = AtomicBool::new(false);
+    static HANGUP: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        // Only async-signal-safe work in here, the watcher thread does the rest.
+        match signal {
+            libc::SIGHUP => HANGUP.store(true, Ordering::SeqCst),
+            _ => TERMINATE.store(true, Ordering::SeqCst),
+        }
+    }
+
+    /// Handles SIGTERM/SIGINT by calling `terminate` (once), which should finish the stream.
+    ///
+    /// SIGHUP is logged and otherwise ignored rather than killing the process: all settings are
+    /// command line flags, so there's nothing to reload.
+    pub fn handle_signals(terminate: impl FnOnce() + Send + 'static) {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            // SAFETY: the handler only stores into atomics.
+            unsafe { libc::signal(signal, handler) };
+        }
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_millis(100));
+            if HANGUP.swap(false, Ordering::SeqCst) {
+                log::info!("Received SIGHUP, nothing to reload: settings are fixed at startup");
+            }
+            if TERMINATE.load(Ordering::SeqCst) {
+                log::info!("Received termination signal, finishing the stream");
+                notify("STOPPING=1");
+                terminate();
+                break;
+            }
+        });
+    }
+}
+
+/// File with the process id, removed again when dropped.
+#[derive(Debug)]
+pub struct Pidfile(PathBuf);
+
+impl Pidfile {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write pidfile {path:?}"))?;
+        Ok(Self(path.to_path_buf()))
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            log::warn!("Failed to remove pidfile {:?}: {e}", self.0);
+        }
+    }
+}
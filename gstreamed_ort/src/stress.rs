@@ -0,0 +1,148 @@
+//! Load tests (`--stress`): the full webcam pipeline of several cameras, fed with synthetic
+//! frames, to measure the headroom of target hardware and check drop behavior (eg. with
+//! `--leaky-queue`) before deploying.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gstreamed_common::pipeline::SyntheticSource;
+use ort_common::session_pool::SessionPool;
+
+use crate::process_video::{self, ProcessOptions, StopHandle};
+
+/// Load test settings, parsed from `KEY=VALUE` pairs, eg. `fps=120 cams=8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StressConfig {
+    /// Frame rate of each camera.
+    pub fps: u32,
+    /// Number of cameras, each running its own pipeline.
+    pub cams: u32,
+    pub width: u32,
+    pub height: u32,
+    pub seconds: u64,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            fps: 30,
+            cams: 1,
+            width: 1920,
+            height: 1080,
+            seconds: 30,
+        }
+    }
+}
+
+impl fmt::Display for StressConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cameras at {} fps ({}x{}) for {}s",
+            self.cams, self.fps, self.width, self.height, self.seconds
+        )
+    }
+}
+
+impl FromStr for StressConfig {
+    type Err = String;
+
+    /// Parses space or comma separated `fps`, `cams`, `width`, `height` and `seconds` values,
+    /// left out ones keep their defaults.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        for pair in s.split([' ', ',']).filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {pair:?}"))?;
+            let value: u32 = value
+                .parse()
+                .ok()
+                .filter(|value| *value > 0)
+                .ok_or_else(|| format!("{key} must be a positive integer, got {value:?}"))?;
+            match key {
+                "fps" => config.fps = value,
+                "cams" => config.cams = value,
+                "width" => config.width = value,
+                "height" => config.height = value,
+                "seconds" => config.seconds = value.into(),
+                _ => {
+                    return Err(format!(
+                        "unknown stress setting {key:?}, expected fps, cams, width, height or \
+                         seconds"
+                    ))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Runs `config.cams` synthetic cameras through the webcam pipeline with `options` for
+/// `config.seconds`, then prints the frame rate each one kept up.
+pub fn run(
+    config: StressConfig,
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !options.service && options.record_dir.is_none() && options.continuous.is_none(),
+        "Stress tests can't run as a service or record"
+    );
+    log::info!("Stress test: {config}");
+    let synthetic = SyntheticSource {
+        width: config.width,
+        height: config.height,
+        fps: config.fps,
+    };
+
+    let start = Instant::now();
+    let mut cameras = Vec::new();
+    for cam in 0..config.cams {
+        let stop = StopHandle::default();
+        let options = ProcessOptions {
+            synthetic: Some(synthetic),
+            stop: Some(stop.clone()),
+            ..options.clone()
+        };
+        let sessions = Arc::clone(&sessions);
+        let name = format!("synthetic{cam}");
+        let worker = thread::spawn(move || {
+            process_video::process_webcam_internal(&name, options, sessions, None)
+        });
+        cameras.push((stop, worker));
+    }
+    thread::sleep(Duration::from_secs(config.seconds));
+    for (stop, _) in &cameras {
+        stop.stop();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!("Stress test: {config}");
+    let mut total_fps = 0.;
+    for (cam, (_, worker)) in cameras.into_iter().enumerate() {
+        let stats = worker
+            .join()
+            .map_err(|_| anyhow::anyhow!("Camera {cam} panicked"))??;
+        let frames = stats.times.frames().len();
+        let fps = frames as f64 / elapsed;
+        total_fps += fps;
+        let p99 = stats.times.percentile(0.99, true);
+        println!(
+            "  synthetic{cam}: {frames} frames, {fps:.1} fps ({:.0}% of target), \
+             p99 latency {:?}, p99 processing {:?}",
+            fps / f64::from(config.fps) * 100.,
+            p99.latency,
+            p99.total()
+        );
+    }
+    let target_fps = f64::from(config.fps) * f64::from(config.cams);
+    println!(
+        "Total: {total_fps:.1} of {target_fps} fps ({:.0}%)",
+        total_fps / target_fps * 100.
+    );
+    Ok(())
+}
@@ -2,6 +2,7 @@ use std::collections::{HashMap, VecDeque};
 use std::time::Instant;
 use inference_common::detection_logger::DetectionLog;
 use inference_common::frame_times::FrameTimes;
+use inference_common::thermal::ThermalReading;
 
 const MAX_HISTORY: usize = 1000;
 const PERF_HISTORY_SIZE: usize = 60;
@@ -20,6 +21,11 @@ pub enum TuiMessage {
         detections: Vec<DetectionLog>,
         performance: FrameTimes,
     },
+    /// Latest sensor reading of the device, with thermal throttling enabled.
+    Thermal {
+        reading: ThermalReading,
+        throttled: bool,
+    },
     Error(String),
     Finished,
 }
@@ -80,6 +86,8 @@ pub struct App {
     pub current_perf: PerformanceStats,
     pub perf_history: VecDeque<PerformanceStats>,
     pub avg_fps: f32,
+    /// Latest sensor reading and whether processing is throttled, with thermal throttling.
+    pub thermal: Option<(ThermalReading, bool)>,
     
     // UI state
     pub selected_index: usize,
@@ -126,6 +134,7 @@ impl App {
             },
             perf_history: VecDeque::with_capacity(PERF_HISTORY_SIZE),
             avg_fps: 0.0,
+            thermal: None,
             selected_index: 0,
             scroll_offset: 0,
             last_frame_time: Instant::now(),
@@ -208,6 +217,9 @@ impl App {
                 
                 self.last_frame_time = Instant::now();
             }
+            TuiMessage::Thermal { reading, throttled } => {
+                self.thermal = Some((reading, throttled));
+            }
             TuiMessage::Error(err) => {
                 log::error!("TUI received error: {}", err);
             }
@@ -278,4 +290,12 @@ impl App {
         }
         0.0
     }
+
+    /// Local capture time of the current frame, if the source reports it.
+    pub fn capture_time(&self) -> Option<&str> {
+        self.current_detections
+            .first()?
+            .capture_time_local
+            .as_deref()
+    }
 }
@@ -5,7 +5,8 @@ mod events;
 use std::io;
 use std::path::Path;
 use std::sync::mpsc::{self, Receiver};
-use std::thread;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
@@ -14,10 +15,11 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use ort::session::Session;
+use gstreamed_common::pipeline::panic_message;
+use ort_common::session_pool::SessionPool;
 use ratatui::{backend::CrosstermBackend, Terminal};
 
-use crate::process_video;
+use crate::process_video::{self, ProcessOptions, StreamStats};
 use app::{App, TuiMessage};
 
 const UI_FPS: u64 = 30;
@@ -25,9 +27,9 @@ const UI_FRAME_TIME: Duration = Duration::from_millis(1000 / UI_FPS);
 
 pub fn process_video_with_tui(
     path: &Path,
-    live: bool,
-    session: Session,
-) -> Result<()> {
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
+) -> Result<StreamStats> {
     // Disable GStreamer debug output to prevent TUI interference
     std::env::set_var("GST_DEBUG", "0");
     std::env::set_var("GST_DEBUG_NO_COLOR", "1");
@@ -46,7 +48,7 @@ pub fn process_video_with_tui(
     // Spawn worker thread for video processing
     let path_clone = path.to_path_buf();
     let worker = thread::spawn(move || {
-        process_video::process_video_internal(&path_clone, live, session, Some(tx))
+        process_video::process_video_internal(&path_clone, options, sessions, Some(tx))
     });
 
     // Run TUI
@@ -62,16 +64,16 @@ pub fn process_video_with_tui(
     terminal.show_cursor()?;
 
     // Wait for worker thread
-    let _ = worker.join();
+    let stats = join_worker(worker)?;
 
-    result
+    result.map(|_| stats)
 }
 
 pub fn process_webcam_with_tui(
     device: &str,
-    live: bool,
-    session: Session,
-) -> Result<()> {
+    options: ProcessOptions,
+    sessions: Arc<SessionPool>,
+) -> Result<StreamStats> {
     // Disable GStreamer debug output to prevent TUI interference
     std::env::set_var("GST_DEBUG", "0");
     std::env::set_var("GST_DEBUG_NO_COLOR", "1");
@@ -90,7 +92,7 @@ pub fn process_webcam_with_tui(
     // Spawn worker thread
     let device_clone = device.to_string();
     let worker = thread::spawn(move || {
-        process_video::process_webcam_internal(&device_clone, live, session, Some(tx))
+        process_video::process_webcam_internal(&device_clone, options, sessions, Some(tx))
     });
 
     // Run TUI
@@ -105,9 +107,16 @@ pub fn process_webcam_with_tui(
     )?;
     terminal.show_cursor()?;
 
-    let _ = worker.join();
+    let stats = join_worker(worker)?;
+
+    result.map(|_| stats)
+}
 
-    result
+/// Waits for the processing thread, failing with its error or panic.
+fn join_worker(worker: JoinHandle<Result<StreamStats>>) -> Result<StreamStats> {
+    worker.join().unwrap_or_else(|payload| {
+        anyhow::bail!("Processing panicked: {}", panic_message(payload.as_ref()))
+    })
 }
 
 fn run_tui_loop(
@@ -8,8 +8,16 @@ use ratatui::{
     Frame,
 };
 
+use inference_common::palette;
+
 use crate::tui::app::App;
 
+/// Same color as the track's box in the annotated video.
+fn track_color(tracker_id: Option<i64>) -> Color {
+    let [r, g, b] = palette::bbox_color(tracker_id);
+    Color::Rgb(r, g, b)
+}
+
 pub fn draw(f: &mut Frame, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -82,7 +90,7 @@ fn draw_main_content(f: &mut Frame, app: &App, area: Rect) {
 }
 
 fn draw_video_info(f: &mut Frame, app: &App, area: Rect) {
-    let progress_text = if let Some(total) = app.total_frames {
+    let mut progress_text = if let Some(total) = app.total_frames {
         format!(
             "Frame: {}/{} ({:.1}%) | {}x{} | FPS: {:.1}",
             app.frame_num, total, app.progress_percentage(), app.width, app.height, app.fps
@@ -93,6 +101,9 @@ fn draw_video_info(f: &mut Frame, app: &App, area: Rect) {
             app.frame_num, app.width, app.height, app.fps
         )
     };
+    if let Some(capture_time) = app.capture_time() {
+        progress_text.push_str(&format!(" | Captured: {capture_time}"));
+    }
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -137,7 +148,7 @@ fn draw_left_panel(f: &mut Frame, app: &App, area: Rect) {
 fn draw_performance_stats(f: &mut Frame, app: &App, area: Rect) {
     let perf = &app.current_perf;
     
-    let text = vec![
+    let mut text = vec![
         Line::from(format!("  Inference:   {:.2} ms", perf.inference_ms)),
         Line::from(format!("  Preprocess:  {:.2} ms", perf.preprocess_ms)),
         Line::from(format!("  Postprocess: {:.2} ms", perf.postprocess_ms)),
@@ -145,6 +156,13 @@ fn draw_performance_stats(f: &mut Frame, app: &App, area: Rect) {
         Line::from(""),
         Line::from(format!("  Avg FPS: {:.1}", app.avg_fps)),
     ];
+    if let Some((reading, throttled)) = &app.thermal {
+        let line = Line::from(format!(
+            "  Thermal: {reading}{}",
+            if *throttled { " (throttled)" } else { "" }
+        ));
+        text.push(if *throttled { line.style(Style::default().fg(Color::Red)) } else { line });
+    }
 
     // Create sparkline data for inference time
     let sparkline_data: Vec<u64> = app
@@ -295,7 +313,7 @@ fn draw_detections_table(f: &mut Frame, app: &App, area: Rect) {
             );
 
             Row::new(vec![
-                Cell::from(id),
+                Cell::from(id).style(Style::default().fg(track_color(det.tracker_id))),
                 Cell::from(det.class_name.clone()),
                 Cell::from(format!("{:.2}", det.confidence)),
                 Cell::from(color_info),
@@ -333,7 +351,10 @@ fn draw_selected_detail(f: &mut Frame, app: &App, area: Rect) {
         ];
 
         if let Some(id) = det.tracker_id {
-            lines.push(Line::from(format!("  Tracking ID: #{}", id)));
+            lines.push(Line::from(vec![
+                Span::raw("  Tracking ID: "),
+                Span::styled(format!("#{}", id), Style::default().fg(track_color(Some(id)))),
+            ]));
         }
 
         lines.push(Line::from(format!(
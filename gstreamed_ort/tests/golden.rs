@@ -0,0 +1,112 @@
+//! Golden file tests: runs the sample videos in `tests/golden/` through the pipeline and compares
+//! their detections against the stored `<sample>.golden.json`, catching regressions in
+//! preprocessing, NMS and tracking.
+//!
+//! Needs gstreamer, sample videos and the model at `GOLDEN_MODEL` (the workspace's
+//! `_models/yolov8s.onnx` by default), so it's ignored by default, see `tests/golden/README.md`
+//! to run it. Run with `UPDATE_GOLDEN=1` to (re)write the golden files after an intended change,
+//! and review their diff.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use gstreamed_ort::inference;
+use gstreamed_ort::process_video::{self, ProcessOptions};
+use inference_common::run_diff::{compare_runs, load_detections};
+use inference_common::tracker::TrackerKind;
+
+/// Detections overlapping their golden one less than this are missing.
+const IOU_THRESHOLD: f32 = 0.9;
+/// Largest confidence change of a detection still matching its golden one.
+const CONFIDENCE_TOLERANCE: f32 = 0.02;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// Sample videos, sorted.
+fn samples() -> Vec<PathBuf> {
+    let mut samples: Vec<_> = std::fs::read_dir(golden_dir())
+        .expect("golden dir is readable")
+        .map(|entry| entry.expect("golden dir is readable").path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "mp4" || extension == "mkv")
+        })
+        .collect();
+    samples.sort();
+    samples
+}
+
+#[test]
+#[ignore = "needs sample videos and the model, see tests/golden/README.md"]
+fn golden_detections() -> anyhow::Result<()> {
+    let model = std::env::var_os("GOLDEN_MODEL")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../_models/yolov8s.onnx"));
+    let samples = samples();
+    anyhow::ensure!(
+        !samples.is_empty(),
+        "No sample videos in {:?}, see its README.md",
+        golden_dir()
+    );
+    anyhow::ensure!(
+        model.exists(),
+        "Missing the model {model:?}, set GOLDEN_MODEL"
+    );
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let (sessions, input_dims) = inference::load_model(
+        &model.to_string_lossy(),
+        None,
+        &["--deterministic".to_string()],
+    )?;
+    let output_dir = std::env::temp_dir().join(format!("golden-{}", std::process::id()));
+    std::fs::create_dir_all(&output_dir)?;
+
+    let mut failures = Vec::new();
+    for sample in &samples {
+        let mut options = ProcessOptions::new(input_dims);
+        // As chosen by the command line with `--deterministic`.
+        options.tracker = TrackerKind::default_for(true);
+        options.output_dir = Some(output_dir.clone());
+        let detections_path = options
+            .output_base(sample)
+            .with_extension("detections.json");
+        process_video::process_video(sample, options, Arc::clone(&sessions))?;
+
+        let golden_path = sample.with_extension("golden.json");
+        if update {
+            std::fs::copy(&detections_path, &golden_path)?;
+            eprintln!("Updated {golden_path:?}");
+            continue;
+        }
+        let diff = compare_runs(
+            &load_detections(&golden_path)?,
+            &load_detections(&detections_path)?,
+            IOU_THRESHOLD,
+        );
+        let changed = diff
+            .frames
+            .iter()
+            .filter(|frame| frame.has_changes(CONFIDENCE_TOLERANCE))
+            .count();
+        if changed > 0 {
+            failures.push(format!(
+                "{sample:?}: {changed} frames differ, {} missing and {} new detections, \
+                 {} id switches",
+                diff.missing_count(),
+                diff.new_count(),
+                diff.id_switches()
+            ));
+        }
+    }
+    std::fs::remove_dir_all(&output_dir)?;
+
+    assert!(
+        failures.is_empty(),
+        "Detections differ from the golden files, rerun with UPDATE_GOLDEN=1 if intended:\n{}",
+        failures.join("\n")
+    );
+    Ok(())
+}
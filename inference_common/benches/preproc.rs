@@ -0,0 +1,60 @@
+//! Compares the naive per-pixel image to tensor conversion with [preproc::image_to_tensor].
+//!
+//! Run with `cargo bench -p inference_common --bench preproc`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use image::RgbImage;
+use inference_common::img_dimensions::ImgDimensions;
+use inference_common::preproc;
+use ndarray::Array4;
+
+const ITERATIONS: u32 = 50;
+
+/// The per-pixel indexed conversion used before [preproc::rgb_to_planar_f32].
+fn naive_image_to_tensor(image: &RgbImage, target_dims: ImgDimensions) -> Array4<f32> {
+    let (target_w, target_h) = (target_dims.width as usize, target_dims.height as usize);
+    let mut array = Array4::zeros([1, 3, target_h, target_w]);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (x, y) = (x as usize, y as usize);
+        array[[0, 0, y, x]] = pixel[0] as f32 / 255.0;
+        array[[0, 1, y, x]] = pixel[1] as f32 / 255.0;
+        array[[0, 2, y, x]] = pixel[2] as f32 / 255.0;
+    }
+    array
+}
+
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    // Warm up caches and the rayon thread pool.
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let avg = start.elapsed() / ITERATIONS;
+    println!("{name:>24}: {avg:?}");
+    avg
+}
+
+fn main() {
+    for (width, height) in [(640, 384), (1280, 720), (1920, 1080)] {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        });
+        let dims = ImgDimensions::from((width, height));
+        println!("{width}x{height}:");
+
+        let naive = bench("naive", || {
+            black_box(naive_image_to_tensor(black_box(&image), dims));
+        });
+        let chunked = bench("preproc::image_to_tensor", || {
+            black_box(preproc::image_to_tensor(black_box(&image), dims));
+        });
+        println!(
+            "{:>24}: {:.1}x",
+            "speedup",
+            naive.as_secs_f64() / chunked.as_secs_f64()
+        );
+    }
+}
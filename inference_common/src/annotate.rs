@@ -1,10 +1,15 @@
 //! Largely modified candle code.
 
-use crate::{bbox::Bbox, coco_classes};
+use crate::label_layout::{contrasting_text_color, layout_labels, LabelRect};
+use crate::{bbox::Bbox, coco_classes, palette};
 use image::DynamicImage;
 
 /// Draws bboxes on the given image.
 /// Returns the same image (just annotated now).
+///
+/// Legends are laid out with [layout_labels], so they don't overlap each other,
+/// and more confident detections get their legend closest to the box.
+/// Tracked boxes are colored by their track id, see [palette::track_color].
 pub fn annotate_image_with_bboxes(
     og_img: DynamicImage,
     scaled_width: usize,
@@ -18,6 +23,9 @@ pub fn annotate_image_with_bboxes(
     let font = Vec::from(include_bytes!("roboto-mono-stripped.ttf") as &[u8]);
     let font = ab_glyph::FontRef::try_from_slice(&font);
     let mut img = og_img.into_rgb8();
+
+    // Draw all boxes first, so legends end up on top of them.
+    let mut legends = vec![];
     for (class_index, bboxes_for_class) in bboxes.iter().enumerate() {
         for b in bboxes_for_class.iter() {
             log::trace!("{}: {:?}", coco_classes::NAMES[class_index], b);
@@ -25,39 +33,64 @@ pub fn annotate_image_with_bboxes(
             let ymin = (b.ymin * h_ratio) as i32;
             let dx = (b.xmax - b.xmin) * w_ratio;
             let dy = (b.ymax - b.ymin) * h_ratio;
+            let color = palette::bbox_color(b.tracker_id);
             if dx >= 0. && dy >= 0. {
                 imageproc::drawing::draw_hollow_rect_mut(
                     &mut img,
                     imageproc::rect::Rect::at(xmin, ymin).of_size(dx as u32, dy as u32),
-                    image::Rgb([255, 0, 0]),
+                    image::Rgb(color),
                 );
             }
-            if legend_size > 0 {
-                if let Ok(font) = font.as_ref() {
-                    imageproc::drawing::draw_filled_rect_mut(
-                        &mut img,
-                        imageproc::rect::Rect::at(xmin, ymin).of_size(dx as u32, legend_size),
-                        image::Rgb([170, 0, 0]),
-                    );
-                    let legend = format!(
-                        "{} {:?}   {:.0}% {:.0}%",
-                        coco_classes::NAMES[class_index],
-                        b.tracker_id,
-                        100. * b.detector_confidence,
-                        100. * b.tracker_confidence,
-                    );
-                    imageproc::drawing::draw_text_mut(
-                        &mut img,
-                        image::Rgb([255, 255, 255]),
-                        xmin,
-                        ymin,
-                        ab_glyph::PxScale::from(legend_size as f32 - 1.),
-                        font,
-                        &legend,
-                    )
-                }
-            }
+            let legend = format!(
+                "{} {:?}   {:.0}% {:.0}%",
+                coco_classes::NAMES[class_index],
+                b.tracker_id,
+                100. * b.detector_confidence,
+                100. * b.tracker_confidence,
+            );
+            let rect = LabelRect::new(xmin, ymin, dx.max(0.) as u32, dy.max(0.) as u32);
+            legends.push((b.detector_confidence, rect, color, legend));
+        }
+    }
+
+    let Ok(font) = font.as_ref() else {
+        return DynamicImage::ImageRgb8(img);
+    };
+    if legend_size == 0 || legends.is_empty() {
+        return DynamicImage::ImageRgb8(img);
+    }
+
+    legends.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let scale = ab_glyph::PxScale::from(legend_size as f32 - 1.);
+    let boxes: Vec<LabelRect> = legends.iter().map(|(_, rect, _, _)| *rect).collect();
+    let sizes: Vec<(u32, u32)> = legends
+        .iter()
+        .map(|(_, _, _, legend)| {
+            let (text_w, _) = imageproc::drawing::text_size(scale, font, legend);
+            (text_w + 4, legend_size)
+        })
+        .collect();
+    let placements = layout_labels(&boxes, &sizes, img.dimensions());
+
+    for ((_, _, color, legend), placement) in legends.iter().zip(placements) {
+        let rect = placement.rect;
+        if let Some((from, to)) = placement.leader {
+            imageproc::drawing::draw_line_segment_mut(&mut img, from, to, image::Rgb(*color));
         }
+        imageproc::drawing::draw_filled_rect_mut(
+            &mut img,
+            imageproc::rect::Rect::at(rect.x, rect.y).of_size(rect.width, rect.height),
+            image::Rgb(*color),
+        );
+        imageproc::drawing::draw_text_mut(
+            &mut img,
+            image::Rgb(contrasting_text_color(*color)),
+            rect.x + 2,
+            rect.y,
+            scale,
+            font,
+            legend,
+        )
     }
     DynamicImage::ImageRgb8(img)
 }
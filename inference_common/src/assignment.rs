@@ -0,0 +1,110 @@
+//! Optimal detection to track assignment (Hungarian algorithm).
+
+/// Cost of pairs that must never be assigned, eg. boxes of different classes.
+pub const FORBIDDEN: f32 = 1e6;
+
+/// Finds the row -> column assignment of minimal total `cost`,
+/// returning `(row, column)` pairs whose cost is at most `max_cost`.
+///
+/// `cost` is a `rows x columns` matrix, either dimension may be larger.
+pub fn linear_assignment(cost: &[Vec<f32>], max_cost: f32) -> Vec<(usize, usize)> {
+    let rows = cost.len();
+    let columns = cost.first().map_or(0, Vec::len);
+    if rows == 0 || columns == 0 {
+        return Vec::new();
+    }
+
+    // The algorithm below needs rows <= columns, so solve the transpose otherwise.
+    let transposed = rows > columns;
+    let (n, m) = if transposed {
+        (columns, rows)
+    } else {
+        (rows, columns)
+    };
+    let at = |i: usize, j: usize| {
+        let value = if transposed { cost[j][i] } else { cost[i][j] };
+        value.min(FORBIDDEN) as f64
+    };
+
+    // Shortest augmenting path with potentials, 1-based with a virtual row/column 0.
+    let mut u = vec![0.0f64; n + 1];
+    let mut v = vec![0.0f64; m + 1];
+    let mut row_of = vec![0usize; m + 1];
+    let mut way = vec![0usize; m + 1];
+    for i in 1..=n {
+        row_of[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![f64::INFINITY; m + 1];
+        let mut used = vec![false; m + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=m {
+                if used[j] {
+                    continue;
+                }
+                let reduced = at(i0 - 1, j - 1) - u[i0] - v[j];
+                if reduced < min_v[j] {
+                    min_v[j] = reduced;
+                    way[j] = j0;
+                }
+                if min_v[j] < delta {
+                    delta = min_v[j];
+                    j1 = j;
+                }
+            }
+            for j in 0..=m {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_v[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            row_of[j0] = row_of[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    (1..=m)
+        .filter(|&j| row_of[j] != 0)
+        .map(|j| {
+            let (i, j) = (row_of[j] - 1, j - 1);
+            if transposed {
+                (j, i)
+            } else {
+                (i, j)
+            }
+        })
+        .filter(|&(row, column)| cost[row][column] <= max_cost)
+        .collect()
+}
+
+#[test]
+fn assignment_is_optimal_and_thresholded() {
+    // Greedy would take (0, 0) first and end up with a total of 1 + 10.
+    let cost = vec![vec![1.0, 2.0], vec![2.0, 10.0]];
+    let mut pairs = linear_assignment(&cost, 5.0);
+    pairs.sort();
+    assert_eq!(pairs, vec![(0, 1), (1, 0)]);
+
+    // More rows than columns, and an over the threshold pair.
+    let cost = vec![vec![0.9], vec![0.1], vec![0.5]];
+    assert_eq!(linear_assignment(&cost, 0.5), vec![(1, 0)]);
+    assert_eq!(linear_assignment(&cost, 0.05), vec![]);
+
+    let cost = vec![vec![FORBIDDEN, 0.3, 0.2]];
+    assert_eq!(linear_assignment(&cost, 1.0), vec![(0, 2)]);
+}
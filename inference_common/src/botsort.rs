@@ -0,0 +1,330 @@
+//! [BoT-SORT](https://arxiv.org/abs/2206.14651) without re-identification features.
+//!
+//! ByteTrack style two stage association (confident detections first, then the low confidence ones
+//! against still tracked objects), a size-aware kalman filter and camera motion compensation
+//! via [Tracker::set_camera_motion].
+
+use crate::assignment::{linear_assignment, FORBIDDEN};
+use crate::bbox::{iou, Bbox, TrackInfo};
+use crate::img_dimensions::ImgDimensions;
+use crate::kalman::{bbox_from_cxcywh, bbox_to_cxcywh, KalmanBoxFilter};
+use crate::tracker::{clamp_bbox, CameraMotion, Tracker};
+
+#[derive(Debug, Clone)]
+pub struct BotSortParams {
+    /// Detections at least this confident take part in the first association.
+    pub high_confidence: f32,
+    /// Detections below this are dropped entirely.
+    pub low_confidence: f32,
+    /// Minimum detection confidence for starting new tracks.
+    pub new_track_confidence: f32,
+    /// Maximum `1 - IoU * confidence` cost of the first association.
+    pub match_cost: f32,
+    /// Frames a lost track is kept for re-association.
+    pub max_lost_frames: u32,
+    /// Report lost tracks (for up to `max_lost_frames` frames) as predicted boxes.
+    pub report_predicted: bool,
+}
+
+impl Default for BotSortParams {
+    fn default() -> Self {
+        Self {
+            high_confidence: 0.5,
+            low_confidence: 0.1,
+            new_track_confidence: 0.6,
+            match_cost: 0.8,
+            max_lost_frames: 30,
+            report_predicted: false,
+        }
+    }
+}
+
+/// Maximum `1 - IoU` cost of matching low confidence detections.
+const LOW_CONFIDENCE_MATCH_COST: f32 = 0.5;
+/// Maximum cost of confirming a new track on its second frame.
+const UNCONFIRMED_MATCH_COST: f32 = 0.7;
+
+#[derive(Debug, PartialEq)]
+enum TrackState {
+    /// Seen on its first frame only (after the very first frame of the stream).
+    Unconfirmed,
+    Tracked,
+    Lost,
+}
+
+#[derive(Debug)]
+struct Track {
+    id: i64,
+    filter: KalmanBoxFilter,
+    last_observation: Bbox,
+    state: TrackState,
+    lost_at_frame: u64,
+    /// Consecutive frames matched, reset when lost.
+    hit_streak: u32,
+    /// IoU of the predicted box with the last matched detection.
+    match_iou: Option<f32>,
+}
+
+#[derive(Debug, Default)]
+pub struct BotSort {
+    params: BotSortParams,
+    tracks: Vec<Track>,
+    next_id: i64,
+    frame: u64,
+    camera_motion: Option<CameraMotion>,
+}
+
+impl BotSort {
+    pub fn new(params: BotSortParams) -> Self {
+        Self {
+            params,
+            ..Default::default()
+        }
+    }
+
+    /// Matches `detections` against tracks in `track_indices` by IoU (optionally weighted by detection confidence),
+    /// returning `(track, detection)` index pairs into the given slices.
+    fn associate(
+        &self,
+        track_indices: &[usize],
+        detections: &[&Bbox],
+        max_cost: f32,
+        fuse_confidence: bool,
+    ) -> Vec<(usize, usize)> {
+        let cost: Vec<Vec<f32>> = track_indices
+            .iter()
+            .map(|&t| {
+                let track = &self.tracks[t];
+                let predicted = bbox_from_cxcywh(track.filter.state(), &track.last_observation);
+                detections
+                    .iter()
+                    .map(|det| {
+                        if det.class != predicted.class {
+                            return FORBIDDEN;
+                        }
+                        let mut similarity = iou(&predicted, det);
+                        if fuse_confidence {
+                            similarity *= det.detector_confidence;
+                        }
+                        1.0 - similarity
+                    })
+                    .collect()
+            })
+            .collect();
+        linear_assignment(&cost, max_cost)
+    }
+
+    fn observe(&mut self, track_index: usize, detection: &Bbox) {
+        let track = &mut self.tracks[track_index];
+        let predicted = bbox_from_cxcywh(track.filter.state(), &track.last_observation);
+        track.match_iou = Some(iou(&predicted, detection));
+        track.filter.update(bbox_to_cxcywh(detection));
+        track.last_observation = detection.clone();
+        track.state = TrackState::Tracked;
+        track.hit_streak += 1;
+    }
+}
+
+/// Indices `0..len` not in `used`.
+fn unused(len: usize, used: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut mask = vec![true; len];
+    for index in used {
+        mask[index] = false;
+    }
+    (0..len).filter(|&i| mask[i]).collect()
+}
+
+impl Tracker for BotSort {
+    fn name(&self) -> &'static str {
+        "botsort"
+    }
+
+    fn update(&mut self, bboxes_per_class: &[Vec<Bbox>], scaled_dims: ImgDimensions) -> Vec<Bbox> {
+        self.frame += 1;
+        let camera_motion = self.camera_motion.take();
+        for track in &mut self.tracks {
+            track.filter.predict();
+            if let Some(warp) = &camera_motion {
+                track.filter.apply_camera_motion(warp);
+            }
+        }
+
+        let (high, low): (Vec<&Bbox>, Vec<&Bbox>) = bboxes_per_class
+            .iter()
+            .flatten()
+            .filter(|det| det.detector_confidence >= self.params.low_confidence)
+            .partition(|det| det.detector_confidence >= self.params.high_confidence);
+
+        let (unconfirmed, pool): (Vec<usize>, Vec<usize>) =
+            (0..self.tracks.len()).partition(|&t| self.tracks[t].state == TrackState::Unconfirmed);
+
+        // 1. Confident detections against tracked and lost tracks.
+        let matches = self.associate(&pool, &high, self.params.match_cost, true);
+        for &(t, d) in &matches {
+            self.observe(pool[t], high[d]);
+        }
+        let remaining_pool: Vec<usize> = unused(pool.len(), matches.iter().map(|&(t, _)| t))
+            .into_iter()
+            .map(|t| pool[t])
+            .collect();
+        let remaining_high: Vec<&Bbox> = unused(high.len(), matches.iter().map(|&(_, d)| d))
+            .into_iter()
+            .map(|d| high[d])
+            .collect();
+
+        // 2. Low confidence detections against tracks still being tracked, eg. partially occluded.
+        let still_tracked: Vec<usize> = remaining_pool
+            .iter()
+            .copied()
+            .filter(|&t| self.tracks[t].state == TrackState::Tracked)
+            .collect();
+        let matches = self.associate(&still_tracked, &low, LOW_CONFIDENCE_MATCH_COST, false);
+        for &(t, d) in &matches {
+            self.observe(still_tracked[t], low[d]);
+        }
+        for t in unused(still_tracked.len(), matches.iter().map(|&(t, _)| t)) {
+            let track = &mut self.tracks[still_tracked[t]];
+            track.state = TrackState::Lost;
+            track.lost_at_frame = self.frame;
+            track.hit_streak = 0;
+        }
+
+        // 3. Unconfirmed tracks against leftover confident detections, unmatched ones are dropped.
+        let matches = self.associate(&unconfirmed, &remaining_high, UNCONFIRMED_MATCH_COST, true);
+        for &(t, d) in &matches {
+            self.observe(unconfirmed[t], remaining_high[d]);
+        }
+        let mut removed: Vec<usize> = unused(unconfirmed.len(), matches.iter().map(|&(t, _)| t))
+            .into_iter()
+            .map(|t| unconfirmed[t])
+            .collect();
+
+        // 4. New tracks from what's left.
+        let first_frame = self.frame == 1;
+        for d in unused(remaining_high.len(), matches.iter().map(|&(_, d)| d)) {
+            let detection = remaining_high[d];
+            if detection.detector_confidence < self.params.new_track_confidence {
+                continue;
+            }
+            self.next_id += 1;
+            self.tracks.push(Track {
+                id: self.next_id,
+                filter: KalmanBoxFilter::new(bbox_to_cxcywh(detection)),
+                last_observation: detection.clone(),
+                state: if first_frame {
+                    TrackState::Tracked
+                } else {
+                    TrackState::Unconfirmed
+                },
+                lost_at_frame: 0,
+                hit_streak: 1,
+                match_iou: None,
+            });
+        }
+
+        let max_lost = self.params.max_lost_frames as u64;
+        removed.extend((0..self.tracks.len()).filter(|&t| {
+            let track = &self.tracks[t];
+            track.state == TrackState::Lost && self.frame - track.lost_at_frame > max_lost
+        }));
+        removed.sort_unstable();
+        for t in removed.into_iter().rev() {
+            self.tracks.swap_remove(t);
+        }
+
+        self.tracks
+            .iter()
+            .filter(|track| match track.state {
+                TrackState::Tracked => true,
+                TrackState::Lost => self.params.report_predicted,
+                TrackState::Unconfirmed => false,
+            })
+            .map(|track| {
+                let mut bbox = bbox_from_cxcywh(track.filter.state(), &track.last_observation);
+                bbox.tracker_confidence = track.last_observation.detector_confidence;
+                bbox.tracker_id = Some(track.id);
+                // Tracked ones were matched in this frame, lost ones last the frame before
+                // they were lost.
+                let frames_since_update = match track.state {
+                    TrackState::Lost => (self.frame - track.lost_at_frame + 1) as u32,
+                    _ => 0,
+                };
+                bbox.track = Some(TrackInfo {
+                    match_iou: track.match_iou,
+                    frames_since_update,
+                    hit_streak: track.hit_streak,
+                    predicted: frames_since_update > 0,
+                });
+                clamp_bbox(bbox, scaled_dims)
+            })
+            .collect()
+    }
+
+    fn set_camera_motion(&mut self, warp: CameraMotion) {
+        self.camera_motion = Some(warp);
+    }
+
+    fn set_report_predicted(&mut self, enabled: bool) {
+        self.params.report_predicted = enabled;
+    }
+}
+
+#[test]
+fn camera_motion_keeps_ids_while_panning() {
+    let dims = ImgDimensions::new(640.0, 384.0);
+
+    // A static object, while the camera pans so it jumps 30px left each frame:
+    // no overlap between consecutive frames, so only motion compensation can keep the id.
+    let run = |compensate: bool| {
+        let mut tracker = BotSort::new(BotSortParams::default());
+        let mut ids = Vec::new();
+        for frame in 0..8 {
+            if compensate && frame > 0 {
+                tracker.set_camera_motion([[1.0, 0.0, -30.0], [0.0, 1.0, 0.0]]);
+            }
+            let x = 400.0 - frame as f32 * 30.0;
+            let tracked = tracker.update(&[vec![Bbox::test(x, 10.0, x + 20.0, 50.0)]], dims);
+            ids.extend(tracked.iter().filter_map(|b| b.tracker_id));
+        }
+        ids
+    };
+
+    let ids = run(true);
+    assert_eq!(ids, vec![1; 8]);
+    // Otherwise the track is lost after the first frame, and new ones never get confirmed.
+    let ids = run(false);
+    assert_eq!(ids, vec![1]);
+}
+
+#[test]
+fn reports_track_state() {
+    let dims = ImgDimensions::new(640.0, 384.0);
+    let mut tracker = BotSort::new(BotSortParams::default());
+    tracker.set_report_predicted(true);
+
+    let tracked = tracker.update(&[vec![Bbox::test(100.0, 10.0, 120.0, 50.0)]], dims);
+    assert_eq!(tracked[0].track.unwrap().match_iou, None);
+    let tracked = tracker.update(&[vec![Bbox::test(102.0, 10.0, 122.0, 50.0)]], dims);
+    let track = tracked[0].track.unwrap();
+    assert_eq!(track.hit_streak, 2);
+    assert_eq!(track.frames_since_update, 0);
+    assert!(!track.predicted);
+    assert!(track.match_iou.unwrap() > 0.7, "{track:?}");
+
+    // Lost tracks are reported as predicted ones, until they're found again.
+    for frames_since_update in 1..=3 {
+        let tracked = tracker.update(&[vec![]], dims);
+        assert_eq!(tracked.len(), 1);
+        assert_eq!(tracked[0].tracker_id, Some(1));
+        let track = tracked[0].track.unwrap();
+        assert!(track.predicted);
+        assert_eq!(track.frames_since_update, frames_since_update);
+    }
+    let tracked = tracker.update(&[vec![Bbox::test(110.0, 10.0, 130.0, 50.0)]], dims);
+    assert_eq!(tracked[0].tracker_id, Some(1));
+    assert!(!tracked[0].track.unwrap().predicted);
+
+    // Not reported by default.
+    tracker.set_report_predicted(false);
+    assert!(tracker.update(&[vec![]], dims).is_empty());
+}
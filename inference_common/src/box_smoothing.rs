@@ -0,0 +1,104 @@
+//! Anti-jitter smoothing of tracked boxes, for rendering only.
+//!
+//! Detector boxes jitter a few pixels between frames even for static objects, which looks shaky
+//! in exported videos. [BoxSmoother] applies an exponential moving average per track id.
+//! Smoothed boxes lag behind the actual detections, so they should only be drawn,
+//! never logged or used for analytics.
+
+use std::collections::HashMap;
+
+use crate::bbox::{iou, BBoxesByClass, Bbox};
+
+/// Tracks not seen for this many frames are forgotten.
+const MAX_AGE: u64 = 30;
+/// Below this IoU with the smoothed box, a track is assumed to have jumped (eg. id reuse)
+/// and smoothing restarts from the new box.
+const RESET_IOU: f32 = 0.1;
+
+struct SmoothedTrack {
+    bbox: Bbox,
+    last_seen: u64,
+}
+
+/// Exponential moving average of box coordinates, keyed on tracker id.
+pub struct BoxSmoother {
+    alpha: f32,
+    frame: u64,
+    tracks: HashMap<i64, SmoothedTrack>,
+}
+
+impl BoxSmoother {
+    /// `alpha` in `(0, 1]` is the weight of the newest box,
+    /// lower values are smoother but lag more, `1.0` disables smoothing.
+    pub fn new(alpha: f32) -> Self {
+        Self {
+            alpha: alpha.clamp(f32::EPSILON, 1.0),
+            frame: 0,
+            tracks: HashMap::new(),
+        }
+    }
+
+    /// Returns a copy of `bboxes` with smoothed coordinates for tracked boxes,
+    /// untracked boxes are passed through as is.
+    pub fn smooth(&mut self, bboxes: &[Vec<Bbox>]) -> BBoxesByClass {
+        self.frame += 1;
+        let frame = self.frame;
+        let alpha = self.alpha;
+
+        let smoothed = bboxes
+            .iter()
+            .map(|bboxes_for_class| {
+                bboxes_for_class
+                    .iter()
+                    .map(|bbox| {
+                        let Some(id) = bbox.tracker_id else {
+                            return bbox.clone();
+                        };
+                        let track = self.tracks.entry(id).or_insert_with(|| SmoothedTrack {
+                            bbox: bbox.clone(),
+                            last_seen: frame,
+                        });
+                        if iou(&track.bbox, bbox) < RESET_IOU {
+                            track.bbox = bbox.clone();
+                        } else {
+                            let ema = |prev: f32, new: f32| prev + alpha * (new - prev);
+                            track.bbox = Bbox {
+                                xmin: ema(track.bbox.xmin, bbox.xmin),
+                                ymin: ema(track.bbox.ymin, bbox.ymin),
+                                xmax: ema(track.bbox.xmax, bbox.xmax),
+                                ymax: ema(track.bbox.ymax, bbox.ymax),
+                                ..bbox.clone()
+                            };
+                        }
+                        track.last_seen = frame;
+                        track.bbox.clone()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        self.tracks
+            .retain(|_, track| frame - track.last_seen <= MAX_AGE);
+        smoothed
+    }
+}
+
+#[test]
+fn smoothing_dampens_jitter_per_track() {
+    let bbox = |x: f32, tracker_id: Option<i64>| Bbox {
+        tracker_id,
+        ..Bbox::test(x, 0., x + 100., 100.)
+    };
+
+    let mut smoother = BoxSmoother::new(0.25);
+    smoother.smooth(&[vec![bbox(0., Some(1))]]);
+    let smoothed = smoother.smooth(&[vec![bbox(8., Some(1)), bbox(8., None)]]);
+    // Tracked box only moves a quarter of the way, untracked one is unchanged.
+    assert_eq!(smoothed[0][0].xmin, 2.);
+    assert_eq!(smoothed[0][0].xmax, 102.);
+    assert_eq!(smoothed[0][1].xmin, 8.);
+
+    // Jumping far away restarts smoothing.
+    let smoothed = smoother.smooth(&[vec![bbox(500., Some(1))]]);
+    assert_eq!(smoothed[0][0].xmin, 500.);
+}
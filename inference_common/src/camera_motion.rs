@@ -0,0 +1,393 @@
+//! Global camera motion (pan, zoom, shake) estimation from sparse optical flow.
+//!
+//! Corners are picked on a grid over the previous frame (skipping detected objects, which move on their own),
+//! followed into the current frame with pyramidal Lucas-Kanade, and a similarity transform
+//! (translation, rotation, uniform scale) is fit to them with RANSAC.
+//! The result is fed to [Tracker::set_camera_motion](crate::tracker::Tracker::set_camera_motion)
+//! so track predictions follow the camera.
+
+use image::DynamicImage;
+
+use crate::bbox::Bbox;
+use crate::img_dimensions::ImgDimensions;
+use crate::tracker::CameraMotion;
+
+/// Frames are downscaled to this width for estimation.
+const WORK_WIDTH: u32 = 320;
+/// At most one feature is picked per grid cell.
+const GRID_COLUMNS: usize = 16;
+const GRID_ROWS: usize = 10;
+/// Minimum corner response (smaller structure tensor eigenvalue) of features.
+const MIN_CORNER_RESPONSE: f32 = 1e-3;
+const LK_HALF_WINDOW: i32 = 4;
+const LK_LEVELS: usize = 3;
+const LK_ITERATIONS: usize = 10;
+const RANSAC_ITERATIONS: usize = 64;
+/// Max reprojection error of RANSAC inliers, in work resolution pixels.
+const INLIER_THRESHOLD: f32 = 1.0;
+const MIN_INLIERS: usize = 8;
+
+/// Grayscale image with intensities in `[0, 1]`.
+#[derive(Debug, Clone)]
+struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    /// Nearest neighbour downscale of `image` into `width` x `height` luma.
+    fn from_image(image: &DynamicImage, width: usize, height: usize) -> Self {
+        let rgb = image.as_rgb8();
+        let converted;
+        let rgb = match rgb {
+            Some(rgb) => rgb,
+            None => {
+                converted = image.to_rgb8();
+                &converted
+            }
+        };
+        let (src_w, src_h) = rgb.dimensions();
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let src_y = ((y as f32 + 0.5) * src_h as f32 / height as f32) as u32;
+            for x in 0..width {
+                let src_x = ((x as f32 + 0.5) * src_w as f32 / width as f32) as u32;
+                let [r, g, b] = rgb.get_pixel(src_x.min(src_w - 1), src_y.min(src_h - 1)).0;
+                data.push((0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0);
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Half resolution, by averaging 2x2 blocks.
+    fn downsample(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x as i32 * 2, y as i32 * 2);
+                let sum = self.at(sx, sy)
+                    + self.at(sx + 1, sy)
+                    + self.at(sx, sy + 1)
+                    + self.at(sx + 1, sy + 1);
+                data.push(sum / 4.0);
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Pixel value, clamped to the image borders.
+    fn at(&self, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, self.width as i32 - 1) as usize;
+        let y = y.clamp(0, self.height as i32 - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    /// Bilinearly interpolated value.
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let top = self.at(x0, y0) * (1.0 - fx) + self.at(x0 + 1, y0) * fx;
+        let bottom = self.at(x0, y0 + 1) * (1.0 - fx) + self.at(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    /// Central difference gradient.
+    fn gradient(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (self.sample(x + 1.0, y) - self.sample(x - 1.0, y)) / 2.0,
+            (self.sample(x, y + 1.0) - self.sample(x, y - 1.0)) / 2.0,
+        )
+    }
+
+    /// Structure tensor `(xx, xy, yy)` summed over the LK window around `(x, y)`.
+    fn structure_tensor(&self, x: f32, y: f32) -> (f32, f32, f32) {
+        let mut tensor = (0.0, 0.0, 0.0);
+        for dy in -LK_HALF_WINDOW..=LK_HALF_WINDOW {
+            for dx in -LK_HALF_WINDOW..=LK_HALF_WINDOW {
+                let (gx, gy) = self.gradient(x + dx as f32, y + dy as f32);
+                tensor.0 += gx * gx;
+                tensor.1 += gx * gy;
+                tensor.2 += gy * gy;
+            }
+        }
+        tensor
+    }
+}
+
+fn pyramid(base: Gray) -> Vec<Gray> {
+    let mut levels = vec![base];
+    while levels.len() < LK_LEVELS {
+        let next = levels.last().unwrap().downsample();
+        levels.push(next);
+    }
+    levels
+}
+
+/// Smaller eigenvalue of the structure tensor (Shi-Tomasi corner response).
+fn corner_response((xx, xy, yy): (f32, f32, f32)) -> f32 {
+    let half_trace = (xx + yy) / 2.0;
+    half_trace
+        - (half_trace * half_trace - (xx * yy - xy * xy))
+            .max(0.0)
+            .sqrt()
+}
+
+/// Estimates camera motion between consecutive frames of a stream.
+#[derive(Debug, Default)]
+pub struct MotionEstimator {
+    previous: Option<Vec<Gray>>,
+    /// Detections on the previous frame, in work resolution.
+    previous_objects: Vec<[f32; 4]>,
+}
+
+impl MotionEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds the next `frame`, returning the camera motion since the previous one,
+    /// in the coordinate space of `dims` (eg. model input scaled dims, where tracking happens).
+    ///
+    /// `objects` are detections on this frame (in `dims` coords), these areas are ignored
+    /// when estimating the motion of the next frame. Returns `None` on the first frame, or when
+    /// there is not enough texture for a reliable estimate.
+    pub fn estimate(
+        &mut self,
+        frame: &DynamicImage,
+        dims: ImgDimensions,
+        objects: &[Bbox],
+    ) -> Option<CameraMotion> {
+        let work_width = WORK_WIDTH.min(frame.width()) as usize;
+        let work_height = ((frame.height() as f32 * work_width as f32 / frame.width() as f32)
+            .round() as usize)
+            .max(1);
+        let (scale_x, scale_y) = (
+            dims.width / work_width as f32,
+            dims.height / work_height as f32,
+        );
+
+        let current = pyramid(Gray::from_image(frame, work_width, work_height));
+        let previous = self.previous.replace(current);
+        let previous_objects = std::mem::replace(
+            &mut self.previous_objects,
+            objects
+                .iter()
+                .map(|b| {
+                    [
+                        b.xmin / scale_x,
+                        b.ymin / scale_y,
+                        b.xmax / scale_x,
+                        b.ymax / scale_y,
+                    ]
+                })
+                .collect(),
+        );
+        let previous = previous?;
+        let current = self.previous.as_ref().unwrap();
+        if previous[0].width != current[0].width || previous[0].height != current[0].height {
+            return None;
+        }
+
+        let features = select_features(&previous[0], &previous_objects);
+        let matches: Vec<([f32; 2], [f32; 2])> = features
+            .into_iter()
+            .filter_map(|point| track_point(&previous, current, point).map(|moved| (point, moved)))
+            .collect();
+        let [a, b, tx, ty] = fit_similarity_ransac(&matches)?;
+        log::trace!(
+            "camera motion from {} points: scale+rot ({a:.4}, {b:.4}), translation ({tx:.2}, {ty:.2})",
+            matches.len()
+        );
+        Some([[a, -b, tx * scale_x], [b, a, ty * scale_y]])
+    }
+}
+
+/// Best corner of each grid cell, outside of `objects`.
+fn select_features(image: &Gray, objects: &[[f32; 4]]) -> Vec<[f32; 2]> {
+    let margin = LK_HALF_WINDOW as usize + 2;
+    if image.width <= 2 * margin || image.height <= 2 * margin {
+        return Vec::new();
+    }
+    let cell_w = (image.width - 2 * margin) / GRID_COLUMNS;
+    let cell_h = (image.height - 2 * margin) / GRID_ROWS;
+    if cell_w == 0 || cell_h == 0 {
+        return Vec::new();
+    }
+
+    let inside_object = |x: f32, y: f32| {
+        objects
+            .iter()
+            .any(|[x0, y0, x1, y1]| x >= *x0 && x <= *x1 && y >= *y0 && y <= *y1)
+    };
+    let mut features = Vec::new();
+    for row in 0..GRID_ROWS {
+        for column in 0..GRID_COLUMNS {
+            let mut best: Option<(f32, [f32; 2])> = None;
+            for y in (0..cell_h).step_by(2) {
+                for x in (0..cell_w).step_by(2) {
+                    let px = (margin + column * cell_w + x) as f32;
+                    let py = (margin + row * cell_h + y) as f32;
+                    if inside_object(px, py) {
+                        continue;
+                    }
+                    let response = corner_response(image.structure_tensor(px, py));
+                    if response > best.map_or(MIN_CORNER_RESPONSE, |(r, _)| r) {
+                        best = Some((response, [px, py]));
+                    }
+                }
+            }
+            features.extend(best.map(|(_, point)| point));
+        }
+    }
+    features
+}
+
+/// Pyramidal Lucas-Kanade, returns where `point` of `previous` moved to in `current`.
+fn track_point(previous: &[Gray], current: &[Gray], point: [f32; 2]) -> Option<[f32; 2]> {
+    let mut flow = [0.0f32; 2];
+    for level in (0..previous.len()).rev() {
+        let scale = (1 << level) as f32;
+        let (prev, next) = (&previous[level], &current[level]);
+        let (px, py) = (point[0] / scale, point[1] / scale);
+        let (xx, xy, yy) = prev.structure_tensor(px, py);
+        let det = xx * yy - xy * xy;
+        if det < 1e-9 {
+            return None;
+        }
+
+        for _ in 0..LK_ITERATIONS {
+            let (mut bx, mut by) = (0.0, 0.0);
+            for dy in -LK_HALF_WINDOW..=LK_HALF_WINDOW {
+                for dx in -LK_HALF_WINDOW..=LK_HALF_WINDOW {
+                    let (x, y) = (px + dx as f32, py + dy as f32);
+                    let (gx, gy) = prev.gradient(x, y);
+                    let diff = prev.sample(x, y) - next.sample(x + flow[0], y + flow[1]);
+                    bx += diff * gx;
+                    by += diff * gy;
+                }
+            }
+            let step = [(yy * bx - xy * by) / det, (xx * by - xy * bx) / det];
+            flow[0] += step[0];
+            flow[1] += step[1];
+            if step[0].abs() < 0.01 && step[1].abs() < 0.01 {
+                break;
+            }
+        }
+        if level > 0 {
+            flow = [flow[0] * 2.0, flow[1] * 2.0];
+        }
+    }
+
+    let moved = [point[0] + flow[0], point[1] + flow[1]];
+    let base = &current[0];
+    let inside = moved[0] >= 0.0
+        && moved[1] >= 0.0
+        && moved[0] < base.width as f32
+        && moved[1] < base.height as f32;
+    (inside && flow.iter().all(|f| f.is_finite())).then_some(moved)
+}
+
+/// Least squares similarity `[a, b, tx, ty]`, mapping `(x, y)` to `(a x - b y + tx, b x + a y + ty)`.
+fn fit_similarity(matches: &[([f32; 2], [f32; 2])]) -> Option<[f32; 4]> {
+    let n = matches.len() as f32;
+    if matches.len() < 2 {
+        return None;
+    }
+    let mean =
+        |select: fn(&([f32; 2], [f32; 2])) -> f32| matches.iter().map(select).sum::<f32>() / n;
+    let (px, py) = (mean(|m| m.0[0]), mean(|m| m.0[1]));
+    let (qx, qy) = (mean(|m| m.1[0]), mean(|m| m.1[1]));
+
+    let (mut dot, mut cross, mut norm) = (0.0, 0.0, 0.0);
+    for ([x, y], [u, v]) in matches {
+        let (x, y, u, v) = (x - px, y - py, u - qx, v - qy);
+        dot += x * u + y * v;
+        cross += x * v - y * u;
+        norm += x * x + y * y;
+    }
+    if norm < 1e-6 {
+        return None;
+    }
+    let (a, b) = (dot / norm, cross / norm);
+    Some([a, b, qx - (a * px - b * py), qy - (b * px + a * py)])
+}
+
+fn reprojection_error([a, b, tx, ty]: [f32; 4], ([x, y], [u, v]): &([f32; 2], [f32; 2])) -> f32 {
+    let (ex, ey) = (a * x - b * y + tx - u, b * x + a * y + ty - v);
+    (ex * ex + ey * ey).sqrt()
+}
+
+/// Fits a similarity to `matches`, ignoring outliers (eg. points on moving objects).
+fn fit_similarity_ransac(matches: &[([f32; 2], [f32; 2])]) -> Option<[f32; 4]> {
+    if matches.len() < MIN_INLIERS {
+        return None;
+    }
+    // Deterministic xorshift sampling, so results are reproducible.
+    let mut state = 0x2545_f491_u32;
+    let mut next_index = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as usize % matches.len()
+    };
+
+    let mut best_inliers: Vec<([f32; 2], [f32; 2])> = Vec::new();
+    for _ in 0..RANSAC_ITERATIONS {
+        let (i, j) = (next_index(), next_index());
+        if i == j {
+            continue;
+        }
+        let Some(model) = fit_similarity(&[matches[i], matches[j]]) else {
+            continue;
+        };
+        let inliers: Vec<_> = matches
+            .iter()
+            .filter(|m| reprojection_error(model, m) < INLIER_THRESHOLD)
+            .copied()
+            .collect();
+        if inliers.len() > best_inliers.len() {
+            best_inliers = inliers;
+        }
+    }
+    if best_inliers.len() < MIN_INLIERS {
+        return None;
+    }
+    fit_similarity(&best_inliers)
+}
+
+#[test]
+fn estimates_camera_pan() {
+    let texture = |x: f32, y: f32| {
+        let value = (x * 0.21).sin() * (y * 0.17).cos() + (x * 0.05 + y * 0.11).sin() * 0.5;
+        ((value + 1.5) / 3.0 * 255.0) as u8
+    };
+    let frame = |shift_x: f32, shift_y: f32| {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(320, 192, |x, y| {
+            let v = texture(x as f32 - shift_x, y as f32 - shift_y);
+            image::Rgb([v, v, v])
+        }))
+    };
+    let dims = ImgDimensions::new(640.0, 384.0);
+    let mut estimator = MotionEstimator::new();
+
+    assert!(estimator.estimate(&frame(0.0, 0.0), dims, &[]).is_none());
+    // Content moves 4px right, 2px up at 320 wide, ie. double that in `dims`.
+    let warp = estimator.estimate(&frame(4.0, -2.0), dims, &[]).unwrap();
+    assert!(
+        (warp[0][0] - 1.0).abs() < 0.01 && warp[1][0].abs() < 0.01,
+        "{warp:?}"
+    );
+    assert!((warp[0][2] - 8.0).abs() < 0.5, "{warp:?}");
+    assert!((warp[1][2] + 4.0).abs() < 0.5, "{warp:?}");
+}
@@ -0,0 +1,127 @@
+//! Clock skew between a source clock (eg. a camera's NTP time from RTCP) and the host clock.
+//!
+//! Every frame gives a sample of `source capture time - host receive time`, which is the clock
+//! offset minus the transit delay (network, decoding, queueing). The delay is never negative,
+//! so the largest sample of a recent window is the best estimate of the offset.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Offset samples of the last this many frames are used for an estimate.
+const WINDOW: usize = 250;
+
+/// Skew estimator of a single source.
+#[derive(Debug)]
+pub struct ClockSkew {
+    source: String,
+    threshold: Duration,
+    /// `(host_ms, source_ms - host_ms)` of the most recent frames.
+    samples: VecDeque<(u64, i64)>,
+    /// First full window estimate, `(host_ms, offset_ms)`, the reference for drift.
+    first_estimate: Option<(u64, i64)>,
+    alerting: bool,
+}
+
+impl ClockSkew {
+    /// Skew estimator of `source`, warning when its clock is off by more than `threshold`.
+    pub fn new(source: impl Into<String>, threshold: Duration) -> Self {
+        Self {
+            source: source.into(),
+            threshold,
+            samples: VecDeque::with_capacity(WINDOW),
+            first_estimate: None,
+            alerting: false,
+        }
+    }
+
+    /// Records a frame captured at `source_ms` (source clock) and received at `host_ms`,
+    /// both in ms since the unix epoch.
+    pub fn observe(&mut self, source_ms: u64, host_ms: u64) {
+        if self.samples.len() == WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back((host_ms, source_ms as i64 - host_ms as i64));
+        let Some(offset) = self.offset_ms() else {
+            return;
+        };
+        if self.samples.len() == WINDOW && self.first_estimate.is_none() {
+            self.first_estimate = Some((host_ms, offset));
+        }
+
+        let exceeded = offset.unsigned_abs() > self.threshold.as_millis() as u64;
+        if exceeded && !self.alerting {
+            log::warn!(
+                "Clock of {:?} is off by {offset} ms from the host (threshold {:?})",
+                self.source,
+                self.threshold
+            );
+        } else if !exceeded && self.alerting {
+            log::info!(
+                "Clock of {:?} is back within {:?} of the host ({offset} ms)",
+                self.source,
+                self.threshold
+            );
+        }
+        self.alerting = exceeded;
+    }
+
+    /// Estimated source minus host clock offset in ms, add it to host times to get source times.
+    pub fn offset_ms(&self) -> Option<i64> {
+        self.samples.iter().map(|&(_, offset)| offset).max()
+    }
+
+    /// Drift of the source clock relative to the host in ppm (µs per second), positive if it runs
+    /// fast. `None` until enough time has passed since the first full window.
+    pub fn drift_ppm(&self) -> Option<f64> {
+        let (first_host_ms, first_offset) = self.first_estimate?;
+        let (host_ms, _) = *self.samples.back()?;
+        let elapsed_ms = host_ms.checked_sub(first_host_ms)?;
+        if elapsed_ms < 60_000 {
+            return None;
+        }
+        let drift_ms = (self.offset_ms()? - first_offset) as f64;
+        Some(drift_ms / elapsed_ms as f64 * 1e6)
+    }
+
+    /// Logs the current offset and drift, if any frames had a source time.
+    pub fn log_summary(&self) {
+        let Some(offset) = self.offset_ms() else {
+            return;
+        };
+        let drift = self
+            .drift_ppm()
+            .map_or("unknown".to_string(), |ppm| format!("{ppm:.1} ppm"));
+        log::info!(
+            "Clock of {:?}: offset {offset} ms from the host, drift {drift}",
+            self.source
+        );
+    }
+}
+
+#[test]
+fn estimates_offset_and_drift() {
+    let mut skew = ClockSkew::new("camera", Duration::from_millis(500));
+    assert_eq!(skew.offset_ms(), None);
+
+    // Camera runs 2 s ahead, frames arrive 40-100 ms after capture.
+    let host_start = 1_700_000_000_000;
+    for i in 0..WINDOW as u64 {
+        let host_ms = host_start + i * 40;
+        let delay = 40 + (i * 7) % 60;
+        skew.observe(host_ms + 2_000 - delay, host_ms);
+    }
+    assert_eq!(skew.offset_ms(), Some(2_000 - 40));
+    assert!(skew.alerting);
+    assert_eq!(skew.drift_ppm(), None);
+
+    // 10 minutes later, the camera gained another 60 ms: 100 ppm.
+    let host_start = host_start + 600_000;
+    for i in 0..WINDOW as u64 {
+        let host_ms = host_start + i * 40;
+        skew.observe(host_ms + 2_060 - 40, host_ms);
+    }
+    assert_eq!(skew.offset_ms(), Some(2_020));
+    let drift = skew.drift_ppm().unwrap();
+    assert!((drift - 100.0).abs() < 10.0, "{drift}");
+}
@@ -1,142 +1,59 @@
 //! Enhanced detection logging with explicit object identification and attributes.
 
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use crate::bbox::Bbox;
 use crate::coco_classes;
 use crate::onnx_attributes::AttributeDetector;
+use crate::wall_time::UtcOffset;
 
-/// Color information extracted from bounding box region
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ColorInfo {
-    pub dominant_color: String,
-    pub rgb: (u8, u8, u8),
-    pub color_name: String,
-}
-
-/// Extended attributes for detected objects
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct ObjectAttributes {
-    pub color_info: Option<ColorInfo>,
-    pub position: Position,
-    pub size: Size,
-    pub person_attrs: Option<PersonAttributesLog>,
-    pub custom_metadata: HashMap<String, String>,
-}
-
-/// Person-specific attributes from neural network
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PersonAttributesLog {
-    pub gender: Option<String>,
-    pub gender_confidence: Option<f32>,
-    pub age_group: Option<String>,
-    pub age_confidence: Option<f32>,
-    pub upper_body_color: Option<String>,
-    pub lower_body_color: Option<String>,
-}
-
-/// Position information
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct Position {
-    pub x_center: f32,
-    pub y_center: f32,
-    pub area: f32,
-}
-
-/// Size information
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct Size {
-    pub width: f32,
-    pub height: f32,
-    pub relative_size: f32, // Percentage of frame
-}
+pub use video_intel_types::detection::{
+    BBoxCoords, ColorInfo, DetectionLog, ObjectAttributes, PersonAttributesLog, Position, Size,
+};
 
-/// Enhanced detection log entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DetectionLog {
-    pub frame_number: u64,
-    pub timestamp_ms: u64,
-    pub object_id: String,
-    pub tracker_id: Option<i64>,
-    pub class_name: String,
-    pub confidence: f32,
-    pub bbox: BBoxCoords,
-    pub attributes: ObjectAttributes,
-}
+/// Constructors of [DetectionLog]s from detector output, kept out of `video_intel_types` as they
+/// need the class names and attribute models.
+pub trait DetectionLogExt: Sized {
+    /// Create a detection log from a bbox with ONNX-based attribute detection
+    #[allow(clippy::too_many_arguments)]
+    fn from_bbox_with_detector(
+        frame_number: u64,
+        timestamp_ms: u64,
+        bbox: &Bbox,
+        class_idx: usize,
+        frame_width: f32,
+        frame_height: f32,
+        image: &image::DynamicImage,
+        attr_detector: &mut AttributeDetector,
+    ) -> Self;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct BBoxCoords {
-    pub xmin: f32,
-    pub ymin: f32,
-    pub xmax: f32,
-    pub ymax: f32,
-}
+    /// Legacy method for backward compatibility (uses simple color extraction)
+    fn from_bbox(
+        frame_number: u64,
+        timestamp_ms: u64,
+        bbox: &Bbox,
+        class_idx: usize,
+        frame_width: f32,
+        frame_height: f32,
+        dominant_color_rgb: Option<(u8, u8, u8)>,
+    ) -> Self;
 
-impl ColorInfo {
-    /// Create color info from RGB values
-    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
-        Self {
-            dominant_color: format!("rgb({}, {}, {})", r, g, b),
-            rgb: (r, g, b),
-            color_name: Self::rgb_to_color_name(r, g, b),
-        }
-    }
+    /// Create a detection log with pre-computed attributes
+    fn from_bbox_with_attributes(
+        frame_number: u64,
+        timestamp_ms: u64,
+        bbox: &Bbox,
+        class_idx: usize,
+        frame_width: f32,
+        frame_height: f32,
+        attributes: ObjectAttributes,
+    ) -> Self;
 
-    /// Convert RGB to human-readable color name
-    fn rgb_to_color_name(r: u8, g: u8, b: u8) -> String {
-        // Simple color classification
-        let (r, g, b) = (r as f32, g as f32, b as f32);
-        
-        // Calculate brightness
-        let brightness = (r + g + b) / 3.0;
-        
-        if brightness < 50.0 {
-            return "black".to_string();
-        }
-        if brightness > 200.0 {
-            return "white".to_string();
-        }
-        
-        // Determine dominant color
-        let max_val = r.max(g).max(b);
-        let min_val = r.min(g).min(b);
-        let diff = max_val - min_val;
-        
-        if diff < 30.0 {
-            if brightness < 128.0 {
-                return "gray".to_string();
-            } else {
-                return "light_gray".to_string();
-            }
-        }
-        
-        if r == max_val {
-            if g > b * 1.5 {
-                "orange".to_string()
-            } else if g > b {
-                "yellow".to_string()
-            } else {
-                "red".to_string()
-            }
-        } else if g == max_val {
-            if r > b * 1.2 {
-                "yellow".to_string()
-            } else {
-                "green".to_string()
-            }
-        } else {
-            if r > g * 1.2 {
-                "purple".to_string()
-            } else {
-                "blue".to_string()
-            }
-        }
-    }
+    /// Sets the capture time, see [DetectionLog::capture_time_ms], formatted with `utc_offset`.
+    fn with_capture_time(self, capture_time_ms: Option<u64>, utc_offset: UtcOffset) -> Self;
 }
 
-impl DetectionLog {
-    /// Create a detection log from a bbox with ONNX-based attribute detection
-    pub fn from_bbox_with_detector(
+impl DetectionLogExt for DetectionLog {
+    fn from_bbox_with_detector(
         frame_number: u64,
         timestamp_ms: u64,
         bbox: &Bbox,
@@ -216,8 +133,11 @@ impl DetectionLog {
         Self {
             frame_number,
             timestamp_ms,
+            capture_time_ms: None,
+            capture_time_local: None,
             object_id,
             tracker_id: bbox.tracker_id,
+            track: bbox.track,
             class_name,
             confidence: bbox.detector_confidence,
             bbox: BBoxCoords {
@@ -230,8 +150,7 @@ impl DetectionLog {
         }
     }
 
-    /// Legacy method for backward compatibility (uses simple color extraction)
-    pub fn from_bbox(
+    fn from_bbox(
         frame_number: u64,
         timestamp_ms: u64,
         bbox: &Bbox,
@@ -278,8 +197,11 @@ impl DetectionLog {
         Self {
             frame_number,
             timestamp_ms,
+            capture_time_ms: None,
+            capture_time_local: None,
             object_id,
             tracker_id: bbox.tracker_id,
+            track: bbox.track,
             class_name,
             confidence: bbox.detector_confidence,
             bbox: BBoxCoords {
@@ -292,8 +214,7 @@ impl DetectionLog {
         }
     }
 
-    /// Create a detection log with pre-computed attributes
-    pub fn from_bbox_with_attributes(
+    fn from_bbox_with_attributes(
         frame_number: u64,
         timestamp_ms: u64,
         bbox: &Bbox,
@@ -319,8 +240,11 @@ impl DetectionLog {
         Self {
             frame_number,
             timestamp_ms,
+            capture_time_ms: None,
+            capture_time_local: None,
             object_id,
             tracker_id: bbox.tracker_id,
+            track: bbox.track,
             class_name,
             confidence: bbox.detector_confidence,
             bbox: BBoxCoords {
@@ -332,6 +256,16 @@ impl DetectionLog {
             attributes,
         }
     }
+
+    fn with_capture_time(
+        mut self,
+        capture_time_ms: Option<u64>,
+        utc_offset: UtcOffset,
+    ) -> Self {
+        self.capture_time_ms = capture_time_ms;
+        self.capture_time_local = capture_time_ms.map(|ms| utc_offset.format(ms));
+        self
+    }
 }
 
 /// Logger for managing detection logs
@@ -444,7 +378,7 @@ impl DetectionLogger {
         let mut by_class: HashMap<String, Vec<&DetectionLog>> = HashMap::new();
         for det in detections {
             by_class.entry(det.class_name.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(det);
         }
         
@@ -0,0 +1,410 @@
+//! Simple text queries over [DetectionLog]s.
+//!
+//! Turns phrases like `"person wearing red between 10s and 25s"` or
+//! `"person wearing red between 2 and 3pm"` into a structured [DetectionQuery], which can then be
+//! matched against logged detections. Only class names, color names, stream time ranges and
+//! local times of day are understood, any other words are ignored.
+
+use crate::coco_classes;
+use crate::detection_logger::DetectionLog;
+
+/// Color names produced by the color classifiers.
+const COLOR_NAMES: [&str; 14] = [
+    "red",
+    "blue",
+    "green",
+    "yellow",
+    "orange",
+    "purple",
+    "pink",
+    "brown",
+    "black",
+    "white",
+    "gray",
+    "light_gray",
+    "beige",
+    "unknown",
+];
+
+/// Structured filter parsed from a text query.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DetectionQuery {
+    /// Coco class name, eg. `person` or `traffic light`.
+    pub class_name: Option<String>,
+    /// Color of the object, or of either upper/lower body for persons.
+    pub color: Option<String>,
+    /// Inclusive lower bound of stream timestamp in ms.
+    pub from_ms: Option<u64>,
+    /// Inclusive upper bound of stream timestamp in ms.
+    pub to_ms: Option<u64>,
+    /// Inclusive lower bound of the local time of day of capture, in ms since midnight.
+    pub from_clock_ms: Option<u64>,
+    /// Inclusive upper bound of the local time of day of capture, in ms since midnight.
+    /// Before [DetectionQuery::from_clock_ms] for ranges across midnight.
+    pub to_clock_ms: Option<u64>,
+}
+
+/// Time bound of a query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QueryTime {
+    /// Stream timestamp in ms.
+    Stream(u64),
+    /// Local time of day in ms since midnight.
+    Clock(u64),
+}
+
+impl DetectionQuery {
+    /// Parses a free-form text query.
+    ///
+    /// Stream-relative time bounds accept `ms`, `s` and `m` suffixes (plain numbers are seconds):
+    /// `between 10s and 1m`, `after 5s`, `before 300ms`. Times of day are matched against the
+    /// local capture time, with `am`/`pm` or in 24 hour format: `after 2pm`, `before 14:30`.
+    /// A plain number next to one is an hour, taking its `am`/`pm` unless that would wrap around
+    /// midnight: `between 2 and 3pm`, `between 11 and 1pm`.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let text = text.to_lowercase();
+        let words: Vec<&str> = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|w| !w.is_empty())
+            .collect();
+
+        let mut query = Self::default();
+        let mut i = 0;
+        while i < words.len() {
+            let word = words[i];
+            match word {
+                "between" | "from" => {
+                    let (from, and, to) = (words.get(i + 1), words.get(i + 2), words.get(i + 3));
+                    let (Some(from), Some(&"and" | &"to"), Some(to)) = (from, and, to) else {
+                        anyhow::bail!("Expected `{word} <time> and <time>` in query: {text:?}");
+                    };
+                    let (from, to) = parse_range(from, to)?;
+                    query.set_from(from);
+                    query.set_to(to);
+                    i += 4;
+                    continue;
+                }
+                "after" | "since" => {
+                    if let Some(from) = words.get(i + 1) {
+                        query.set_from(parse_time(from)?);
+                    }
+                    i += 2;
+                    continue;
+                }
+                "before" | "until" => {
+                    if let Some(to) = words.get(i + 1) {
+                        query.set_to(parse_time(to)?);
+                    }
+                    i += 2;
+                    continue;
+                }
+                _ => {}
+            }
+
+            // Coco has some two-word class names, so try those first.
+            if let Some(next) = words.get(i + 1) {
+                let two_words = format!("{word} {}", next.trim_end_matches('s'));
+                if coco_classes::NAMES.contains(&two_words.as_str()) {
+                    query.class_name = Some(two_words);
+                    i += 2;
+                    continue;
+                }
+            }
+            if word == "people" {
+                query.class_name = Some("person".to_string());
+            } else if coco_classes::NAMES.contains(&word) {
+                query.class_name = Some(word.to_string());
+            } else if let Some(singular) = word.strip_suffix('s') {
+                if coco_classes::NAMES.contains(&singular) {
+                    query.class_name = Some(singular.to_string());
+                }
+            }
+            if COLOR_NAMES.contains(&word) {
+                query.color = Some(word.to_string());
+            }
+            i += 1;
+        }
+
+        if let (Some(from), Some(to)) = (query.from_ms, query.to_ms) {
+            if from > to {
+                anyhow::bail!("Query time range is reversed: {from}ms > {to}ms");
+            }
+        }
+
+        Ok(query)
+    }
+
+    fn set_from(&mut self, time: QueryTime) {
+        match time {
+            QueryTime::Stream(ms) => self.from_ms = Some(ms),
+            QueryTime::Clock(ms) => self.from_clock_ms = Some(ms),
+        }
+    }
+
+    fn set_to(&mut self, time: QueryTime) {
+        match time {
+            QueryTime::Stream(ms) => self.to_ms = Some(ms),
+            QueryTime::Clock(ms) => self.to_clock_ms = Some(ms),
+        }
+    }
+
+    /// Whether the local time of day `ms` (since midnight) is within the clock bounds.
+    fn matches_time_of_day(&self, ms: u64) -> bool {
+        let after_from = self.from_clock_ms.is_none_or(|from| ms >= from);
+        let before_to = self.to_clock_ms.is_none_or(|to| ms <= to);
+        match (self.from_clock_ms, self.to_clock_ms) {
+            // Across midnight, eg. between 10pm and 2am.
+            (Some(from), Some(to)) if from > to => after_from || before_to,
+            _ => after_from && before_to,
+        }
+    }
+
+    /// Whether the given detection satisfies all parts of this query.
+    pub fn matches(&self, detection: &DetectionLog) -> bool {
+        if let Some(class_name) = &self.class_name {
+            if &detection.class_name != class_name {
+                return false;
+            }
+        }
+        if let Some(from) = self.from_ms {
+            if detection.timestamp_ms < from {
+                return false;
+            }
+        }
+        if let Some(to) = self.to_ms {
+            if detection.timestamp_ms > to {
+                return false;
+            }
+        }
+        if self.from_clock_ms.is_some() || self.to_clock_ms.is_some() {
+            // Detections without a capture time can't be placed in the day.
+            let time_of_day = detection
+                .capture_time_local
+                .as_deref()
+                .and_then(time_of_day_ms);
+            if !time_of_day.is_some_and(|ms| self.matches_time_of_day(ms)) {
+                return false;
+            }
+        }
+        if let Some(color) = &self.color {
+            let attrs = &detection.attributes;
+            let object_color = attrs.color_info.as_ref().map(|c| &c.color_name);
+            let person_colors = attrs
+                .person_attrs
+                .iter()
+                .flat_map(|p| [&p.upper_body_color, &p.lower_body_color])
+                .flatten();
+            let matched = object_color
+                .into_iter()
+                .chain(person_colors)
+                .any(|c| c == color);
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the detections matching this query, in log order.
+    pub fn filter<'a>(&self, detections: &'a [DetectionLog]) -> Vec<&'a DetectionLog> {
+        detections.iter().filter(|d| self.matches(d)).collect()
+    }
+
+    /// Returns sorted, deduplicated frame numbers which contain at least one match.
+    pub fn matching_frames(&self, detections: &[DetectionLog]) -> Vec<u64> {
+        let mut frames: Vec<u64> = self
+            .filter(detections)
+            .into_iter()
+            .map(|d| d.frame_number)
+            .collect();
+        frames.sort_unstable();
+        frames.dedup();
+        frames
+    }
+}
+
+/// Parses the bounds of a `between` range, see [DetectionQuery::parse].
+fn parse_range(from: &str, to: &str) -> anyhow::Result<(QueryTime, QueryTime)> {
+    let is_hour = |word: &str| word.parse::<u64>().is_ok();
+    // Completes a plain `hour` with the am/pm of the `clock` time at the other end of the range:
+    // `2` in `between 2 and 3pm` is 2pm, but `11` in `between 11 and 1pm` is 11am, as 11pm would
+    // wrap around midnight. Next to a 24 hour time, 10 in `between 10 and 11:30` is 10:00.
+    let complete = |hour: &str, clock: &str, hour_is_from: bool| {
+        let Some(meridiem) = ["am", "pm"].into_iter().find(|m| clock.ends_with(m)) else {
+            return format!("{hour}:00");
+        };
+        let same = format!("{hour}{meridiem}");
+        let ms = |word: &str| parse_clock_ms(word).and_then(Result::ok);
+        let ordered = match (ms(&same), ms(clock)) {
+            (Some(hour), Some(clock)) if hour_is_from => hour <= clock,
+            (Some(hour), Some(clock)) => clock <= hour,
+            // Left for parse_time to reject.
+            _ => true,
+        };
+        if ordered {
+            return same;
+        }
+        let opposite = if meridiem == "am" { "pm" } else { "am" };
+        format!("{hour}{opposite}")
+    };
+    let (from, to) = match (parse_clock_ms(from), parse_clock_ms(to)) {
+        (None, Some(_)) if is_hour(from) => (complete(from, to, true), to.to_string()),
+        (Some(_), None) if is_hour(to) => (from.to_string(), complete(to, from, false)),
+        _ => (from.to_string(), to.to_string()),
+    };
+    match (parse_time(&from)?, parse_time(&to)?) {
+        range @ ((QueryTime::Stream(_), QueryTime::Stream(_))
+        | (QueryTime::Clock(_), QueryTime::Clock(_))) => Ok(range),
+        _ => anyhow::bail!("Query time range mixes stream times and times of day: {from} and {to}"),
+    }
+}
+
+/// Parses a time of day or a stream time.
+fn parse_time(word: &str) -> anyhow::Result<QueryTime> {
+    match parse_clock_ms(word) {
+        Some(ms) => Ok(QueryTime::Clock(ms?)),
+        None => Ok(QueryTime::Stream(parse_time_ms(word)?)),
+    }
+}
+
+/// Parses `3pm`, `11:30am` or `14:30` into ms since midnight, None for words which aren't times
+/// of day.
+fn parse_clock_ms(word: &str) -> Option<anyhow::Result<u64>> {
+    let (time, pm) = if let Some(time) = word.strip_suffix("am") {
+        (time, Some(false))
+    } else if let Some(time) = word.strip_suffix("pm") {
+        (time, Some(true))
+    } else if word.contains(':') {
+        (word, None)
+    } else {
+        return None;
+    };
+    let ms = || {
+        let (hours, minutes) = time.split_once(':').unwrap_or((time, "00"));
+        let (hours, minutes): (u64, u64) = (hours.parse().ok()?, minutes.parse().ok()?);
+        let hours = match pm {
+            Some(pm) if (1..=12).contains(&hours) => hours % 12 + if pm { 12 } else { 0 },
+            None if hours < 24 => hours,
+            _ => return None,
+        };
+        (minutes < 60).then_some((hours * 60 + minutes) * 60_000)
+    };
+    Some(ms().ok_or_else(|| anyhow::anyhow!("Invalid time of day in query: {word:?}")))
+}
+
+/// Time of day in ms since midnight of an RFC 3339 timestamp, eg. `2024-03-01T17:30:00.250+05:30`
+/// as formatted by [crate::wall_time::UtcOffset::format].
+fn time_of_day_ms(timestamp: &str) -> Option<u64> {
+    let time = timestamp.split_once('T')?.1;
+    let field = |start: usize, len: usize| time.get(start..start + len)?.parse::<u64>().ok();
+    let seconds = (field(0, 2)? * 60 + field(3, 2)?) * 60 + field(6, 2)?;
+    Some(seconds * 1000 + field(9, 3)?)
+}
+
+/// Parses `300ms`, `10s`, `2m` or a plain number of seconds into milliseconds.
+fn parse_time_ms(word: &str) -> anyhow::Result<u64> {
+    let (number, multiplier) = if let Some(n) = word.strip_suffix("ms") {
+        (n, 1.0)
+    } else if let Some(n) = word.strip_suffix('s') {
+        (n, 1000.0)
+    } else if let Some(n) = word.strip_suffix('m') {
+        (n, 60_000.0)
+    } else {
+        (word, 1000.0)
+    };
+    let value = number
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite() && *value >= 0.0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid time in query: {word:?}"))?;
+    Ok((value * multiplier) as u64)
+}
+
+#[test]
+fn parse_class_color_and_range() {
+    let query = DetectionQuery::parse("Person wearing red between 10s and 1m").unwrap();
+    assert_eq!(
+        query,
+        DetectionQuery {
+            class_name: Some("person".to_string()),
+            color: Some("red".to_string()),
+            from_ms: Some(10_000),
+            to_ms: Some(60_000),
+            ..Default::default()
+        }
+    );
+
+    let query = DetectionQuery::parse("blue traffic lights after 500ms").unwrap();
+    assert_eq!(query.class_name.as_deref(), Some("traffic light"));
+    assert_eq!(query.color.as_deref(), Some("blue"));
+    assert_eq!(query.from_ms, Some(500));
+    assert_eq!(query.to_ms, None);
+}
+
+#[test]
+fn parse_rejects_bad_ranges() {
+    assert!(DetectionQuery::parse("cars between 20s and 10s").is_err());
+    assert!(DetectionQuery::parse("cars between soon and later").is_err());
+    assert!(DetectionQuery::parse("cars between 10s").is_err());
+}
+
+#[test]
+fn parse_times_of_day() {
+    let hours = |h: u64| Some(h * 3_600_000);
+    let query = DetectionQuery::parse("person wearing red between 2 and 3pm").unwrap();
+    assert_eq!(query.class_name.as_deref(), Some("person"));
+    assert_eq!(query.color.as_deref(), Some("red"));
+    assert_eq!(
+        (query.from_clock_ms, query.to_clock_ms),
+        (hours(14), hours(15))
+    );
+    assert_eq!((query.from_ms, query.to_ms), (None, None));
+
+    // The plain hour takes the other am/pm rather than wrapping around midnight.
+    let query = DetectionQuery::parse("cars between 11 and 1pm").unwrap();
+    assert_eq!(
+        (query.from_clock_ms, query.to_clock_ms),
+        (hours(11), hours(13))
+    );
+    let query = DetectionQuery::parse("cars between 9pm and 2").unwrap();
+    assert_eq!(
+        (query.from_clock_ms, query.to_clock_ms),
+        (hours(21), hours(2))
+    );
+
+    let query = DetectionQuery::parse("cars between 10 and 11:30").unwrap();
+    assert_eq!(query.from_clock_ms, hours(10));
+    assert_eq!(query.to_clock_ms, Some(11 * 3_600_000 + 30 * 60_000));
+    let query = DetectionQuery::parse("cars after 12am before 12pm").unwrap();
+    assert_eq!(
+        (query.from_clock_ms, query.to_clock_ms),
+        (hours(0), hours(12))
+    );
+
+    // Across midnight.
+    let query = DetectionQuery::parse("cars between 10pm and 2am").unwrap();
+    assert!(query.matches_time_of_day(hours(23).unwrap()));
+    assert!(query.matches_time_of_day(hours(1).unwrap()));
+    assert!(!query.matches_time_of_day(hours(12).unwrap()));
+
+    assert_eq!(
+        time_of_day_ms("2024-03-01T17:30:00.250+05:30"),
+        Some(63_000_250)
+    );
+    assert_eq!(time_of_day_ms("2024-03-01"), None);
+}
+
+#[test]
+fn parse_rejects_bad_times() {
+    for query in [
+        "cars after -5s",
+        "cars after nan",
+        "cars before infs",
+        "cars after 13pm",
+        "cars after 25:00",
+        "cars after 2:60pm",
+        "cars between 10s and 3pm",
+    ] {
+        assert!(DetectionQuery::parse(query).is_err(), "{query}");
+    }
+}
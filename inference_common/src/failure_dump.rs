@@ -0,0 +1,131 @@
+//! Saving frames which failed inference, or produced pathological output, for offline reproduction.
+//!
+//! For every failure, the raw frame (`.png`), model input tensor (`.npy`, loadable with numpy)
+//! and a small `.json` describing why it was dumped are written into the dump directory.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use ndarray::Array4;
+use serde::Serialize;
+
+use crate::img_dimensions::ImgDimensions;
+use crate::preproc;
+
+/// Stop dumping after this many failures, so a broken model doesn't fill up the disk.
+const MAX_DUMPS: usize = 100;
+
+#[derive(Serialize)]
+struct FailureInfo<'a> {
+    frame_number: u64,
+    timestamp_ms: u64,
+    reason: &'a str,
+    frame_width: u32,
+    frame_height: u32,
+    model_input_width: u32,
+    model_input_height: u32,
+}
+
+/// Writes failed frames into a directory.
+pub struct FailureDumper {
+    dir: PathBuf,
+    dumped: usize,
+}
+
+impl FailureDumper {
+    pub fn new(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            dumped: 0,
+        })
+    }
+
+    /// Dumps `frame` along with the model input tensor computed from it.
+    ///
+    /// Errors are logged rather than returned, dumping is best effort and must not break processing.
+    pub fn dump(
+        &mut self,
+        frame_number: u64,
+        timestamp_ms: u64,
+        frame: &DynamicImage,
+        model_input_dims: ImgDimensions,
+        reason: &str,
+    ) {
+        if self.dumped >= MAX_DUMPS {
+            return;
+        }
+        self.dumped += 1;
+        if self.dumped == MAX_DUMPS {
+            log::warn!("Dumped {MAX_DUMPS} failed frames, not dumping any more");
+        }
+
+        let stem = self.dir.join(format!("frame_{frame_number:06}"));
+        let result = (|| -> anyhow::Result<()> {
+            frame.save(stem.with_extension("png"))?;
+            let (input, _) = preproc::preprocess(frame, model_input_dims);
+            write_npy(&stem.with_extension("input.npy"), &input)?;
+            let info = FailureInfo {
+                frame_number,
+                timestamp_ms,
+                reason,
+                frame_width: frame.width(),
+                frame_height: frame.height(),
+                model_input_width: model_input_dims.width as u32,
+                model_input_height: model_input_dims.height as u32,
+            };
+            serde_json::to_writer_pretty(File::create(stem.with_extension("json"))?, &info)?;
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => log::warn!("Frame {frame_number}: {reason}, dumped to {stem:?}.*"),
+            Err(e) => log::error!("Frame {frame_number}: {reason}, failed to dump: {e}"),
+        }
+    }
+}
+
+/// Writes `array` in numpy `.npy` (v1.0) format.
+fn write_npy(path: &Path, array: &Array4<f32>) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&npy_header(array.shape()))?;
+    for value in array.iter() {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn npy_header(shape: &[usize]) -> Vec<u8> {
+    let shape = shape
+        .iter()
+        .map(|dim| format!("{dim}, "))
+        .collect::<String>();
+    let mut dict = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}), }}",
+        shape.trim_end_matches(' ')
+    );
+    // Magic (6) + version (2) + header len (2) + dict, padded with spaces and a newline
+    // to a multiple of 64 bytes.
+    let unpadded = 10 + dict.len() + 1;
+    dict.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+#[test]
+fn npy_header_is_aligned() {
+    let header = npy_header(&[1, 3, 384, 640]);
+    assert_eq!(header.len() % 64, 0);
+    assert!(header.ends_with(b"\n"));
+    let dict = std::str::from_utf8(&header[10..]).unwrap();
+    assert!(
+        dict.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (1, 3, 384, 640,), }")
+    );
+}
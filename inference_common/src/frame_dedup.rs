@@ -0,0 +1,102 @@
+//! Dropping near-identical frames of static scenes from detection logs, so continuous
+//! recordings of eg. a parking lot don't log the same parked cars every frame.
+//!
+//! A frame is a duplicate of the last logged one if both look alike (perceptual hash) and their
+//! detections match up, any moving object gets its frame logged. Only logs are deduplicated,
+//! the annotated video keeps every frame.
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+
+use crate::detection_logger::DetectionLog;
+
+/// Minimum IoU of a detection with one of the last logged frame for it to be unchanged.
+const MIN_IOU: f32 = 0.9;
+
+/// 64 bit difference hash of `image`: whether brightness increases between horizontally
+/// adjacent cells of a 9x8 grid.
+pub fn difference_hash(image: &DynamicImage) -> u64 {
+    let cells = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = cells.get_pixel(x + 1, y).0[0] > cells.get_pixel(x, y).0[0];
+            hash = hash << 1 | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+/// Tracks the last logged frame of a stream, see [FrameDedup::is_duplicate].
+#[derive(Debug)]
+pub struct FrameDedup {
+    /// Maximum number of differing hash bits of duplicate frames.
+    max_distance: u32,
+    last_hash: Option<u64>,
+    last_detections: Vec<DetectionLog>,
+    skipped: u64,
+}
+
+impl FrameDedup {
+    pub fn new(max_distance: u32) -> Self {
+        Self {
+            max_distance,
+            last_hash: None,
+            last_detections: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Whether the frame `image` with `detections` duplicates the last logged frame, and should
+    /// be left out of the logs. If not, it becomes the frame following ones are compared to.
+    pub fn is_duplicate(&mut self, image: &DynamicImage, detections: &[DetectionLog]) -> bool {
+        let hash = difference_hash(image);
+        let looks_alike = self
+            .last_hash
+            .is_some_and(|last| (last ^ hash).count_ones() <= self.max_distance);
+        if looks_alike && self.same_detections(detections) {
+            self.skipped += 1;
+            return true;
+        }
+        self.last_hash = Some(hash);
+        self.last_detections = detections.to_vec();
+        false
+    }
+
+    /// Whether `detections` match the last logged ones one to one, by class and IoU.
+    fn same_detections(&self, detections: &[DetectionLog]) -> bool {
+        if detections.len() != self.last_detections.len() {
+            return false;
+        }
+        let mut unmatched: Vec<_> = self.last_detections.iter().collect();
+        detections.iter().all(|detection| {
+            let matched = unmatched.iter().position(|last| {
+                last.class_name == detection.class_name && last.bbox.iou(&detection.bbox) >= MIN_IOU
+            });
+            matched.map(|i| unmatched.swap_remove(i)).is_some()
+        })
+    }
+
+    /// Number of frames left out of the logs so far.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+#[test]
+fn static_frames_are_duplicates() {
+    use image::{Rgb, RgbImage};
+
+    let detection = |x: f32| DetectionLog::test(0, x, 10., x + 100., 60.);
+    let gradient = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, _| Rgb([x as u8 * 4; 3])));
+    let flipped = gradient.fliph();
+
+    let mut dedup = FrameDedup::new(2);
+    assert!(!dedup.is_duplicate(&gradient, &[detection(0.)]));
+    assert!(dedup.is_duplicate(&gradient, &[detection(1.)]));
+    // A moved object, an extra one, or a different looking frame are logged.
+    assert!(!dedup.is_duplicate(&gradient, &[detection(30.)]));
+    assert!(!dedup.is_duplicate(&gradient, &[detection(30.), detection(200.)]));
+    assert!(!dedup.is_duplicate(&flipped, &[detection(30.), detection(200.)]));
+    assert_eq!(dedup.skipped(), 1);
+}
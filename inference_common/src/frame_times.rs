@@ -3,8 +3,14 @@ use std::iter::Sum;
 use std::time::Duration;
 
 /// Various timings for processing a single video frame.
+///
+/// `queue_wait` and `latency` are not processing stages, so they're not part of [FrameTimes::total].
 #[derive(Default, Clone, PartialEq)]
 pub struct FrameTimes {
+    /// Time the frame spent queued before processing started.
+    pub queue_wait: Duration,
+    /// Capture (buffer PTS) to processing result latency, zero if unknown.
+    pub latency: Duration,
     pub frame_to_buffer: Duration,
     pub buffer_resize: Duration,
     pub buffer_to_tensor: Duration,
@@ -29,8 +35,25 @@ impl FrameTimes {
             + self.buffer_to_frame
     }
 
+    /// Processing stages in pipeline order, along with their names.
+    pub fn stages(&self) -> [(&'static str, Duration); 9] {
+        [
+            ("frame_to_buffer", self.frame_to_buffer),
+            ("buffer_resize", self.buffer_resize),
+            ("buffer_to_tensor", self.buffer_to_tensor),
+            ("forward_pass", self.forward_pass),
+            ("bbox_extraction", self.bbox_extraction),
+            ("nms", self.nms),
+            ("tracking", self.tracking),
+            ("annotation", self.annotation),
+            ("buffer_to_frame", self.buffer_to_frame),
+        ]
+    }
+
     pub fn uniform(ms: u64) -> Self {
         FrameTimes {
+            queue_wait: Duration::from_millis(ms),
+            latency: Duration::from_millis(ms),
             frame_to_buffer: Duration::from_millis(ms),
             buffer_resize: Duration::from_millis(ms),
             buffer_to_tensor: Duration::from_millis(ms),
@@ -58,7 +81,9 @@ impl Debug for FrameTimes {
             nms: {:?},
             tracking: {:?},
             annotation: {:?},
-            buffer_to_frame: {:?}
+            buffer_to_frame: {:?},
+            queue_wait: {:?},
+            latency: {:?}
             ",
             self.total(),
             self.frame_to_buffer,
@@ -70,6 +95,8 @@ impl Debug for FrameTimes {
             self.tracking,
             self.annotation,
             self.buffer_to_frame,
+            self.queue_wait,
+            self.latency,
         )
     }
 }
@@ -77,6 +104,8 @@ impl Debug for FrameTimes {
 impl Sum for FrameTimes {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
         iter.fold(FrameTimes::default(), |mut acc, x| {
+            acc.queue_wait += x.queue_wait;
+            acc.latency += x.latency;
             acc.frame_to_buffer += x.frame_to_buffer;
             acc.buffer_resize += x.buffer_resize;
             acc.buffer_to_tensor += x.buffer_to_tensor;
@@ -100,6 +129,11 @@ impl AggregatedTimes {
         self.0.push(frame_times);
     }
 
+    /// All collected frame times, in processing order.
+    pub fn frames(&self) -> &[FrameTimes] {
+        &self.0
+    }
+
     /// Computes
     pub fn avg(&self, ignore_first: bool) -> FrameTimes {
         let sum: FrameTimes = self
@@ -120,6 +154,8 @@ impl AggregatedTimes {
         }
 
         FrameTimes {
+            queue_wait: sum.queue_wait / n,
+            latency: sum.latency / n,
             frame_to_buffer: sum.frame_to_buffer / n,
             buffer_resize: sum.buffer_resize / n,
             buffer_to_tensor: sum.buffer_to_tensor / n,
@@ -142,6 +178,8 @@ impl AggregatedTimes {
         for ft in frame_times_iter {
             empty = false;
 
+            min.queue_wait = comp(min.queue_wait, ft.queue_wait);
+            min.latency = comp(min.latency, ft.latency);
             min.frame_to_buffer = comp(min.frame_to_buffer, ft.frame_to_buffer);
             min.buffer_resize = comp(min.buffer_resize, ft.buffer_resize);
             min.buffer_to_tensor = comp(min.buffer_to_tensor, ft.buffer_to_tensor);
@@ -170,6 +208,8 @@ impl AggregatedTimes {
         for ft in frame_times_iter {
             empty = false;
 
+            max.queue_wait = comp(max.queue_wait, ft.queue_wait);
+            max.latency = comp(max.latency, ft.latency);
             max.frame_to_buffer = comp(max.frame_to_buffer, ft.frame_to_buffer);
             max.buffer_resize = comp(max.buffer_resize, ft.buffer_resize);
             max.buffer_to_tensor = comp(max.buffer_to_tensor, ft.buffer_to_tensor);
@@ -187,6 +227,48 @@ impl AggregatedTimes {
             FrameTimes::default()
         }
     }
+
+    /// Computes the `q`-th percentile (`0.0..=1.0`) of each timing separately, using nearest rank.
+    pub fn percentile(&self, q: f64, ignore_first: bool) -> FrameTimes {
+        let frame_times: Vec<&FrameTimes> = self
+            .0
+            .iter()
+            .skip(if ignore_first { 1 } else { 0 })
+            .collect();
+        if frame_times.is_empty() {
+            return FrameTimes::default();
+        }
+
+        let rank = ((q.clamp(0.0, 1.0) * frame_times.len() as f64).ceil() as usize).max(1) - 1;
+        let nth = |field: fn(&FrameTimes) -> Duration| {
+            let mut values: Vec<Duration> = frame_times.iter().map(|ft| field(ft)).collect();
+            *values.select_nth_unstable(rank).1
+        };
+
+        FrameTimes {
+            queue_wait: nth(|ft| ft.queue_wait),
+            latency: nth(|ft| ft.latency),
+            frame_to_buffer: nth(|ft| ft.frame_to_buffer),
+            buffer_resize: nth(|ft| ft.buffer_resize),
+            buffer_to_tensor: nth(|ft| ft.buffer_to_tensor),
+            forward_pass: nth(|ft| ft.forward_pass),
+            bbox_extraction: nth(|ft| ft.bbox_extraction),
+            nms: nth(|ft| ft.nms),
+            tracking: nth(|ft| ft.tracking),
+            annotation: nth(|ft| ft.annotation),
+            buffer_to_frame: nth(|ft| ft.buffer_to_frame),
+        }
+    }
+
+    /// Logs avg/min/max and p50/p90/p99 frame times.
+    pub fn log_summary(&self, ignore_first: bool) {
+        log::info!("Average frame times: {:?}", self.avg(ignore_first));
+        log::info!("Min frame times: {:?}", self.min(ignore_first));
+        log::info!("Max frame times: {:?}", self.max(ignore_first));
+        log::info!("p50 frame times: {:?}", self.percentile(0.5, ignore_first));
+        log::info!("p90 frame times: {:?}", self.percentile(0.9, ignore_first));
+        log::info!("p99 frame times: {:?}", self.percentile(0.99, ignore_first));
+    }
 }
 
 #[test]
@@ -249,3 +331,29 @@ fn aggregate_min_and_max() {
     max_tgt.forward_pass = Duration::from_millis(5000);
     assert_eq!(max, max_tgt);
 }
+
+#[test]
+fn aggregate_percentiles() {
+    let mut agg = AggregatedTimes::default();
+    for ms in 1..=100 {
+        let mut ft = FrameTimes::uniform(ms);
+        ft.latency = Duration::from_millis(101 - ms);
+        agg.push(ft);
+    }
+
+    let p50 = agg.percentile(0.5, false);
+    assert_eq!(p50.forward_pass, Duration::from_millis(50));
+    assert_eq!(p50.latency, Duration::from_millis(50));
+    assert_eq!(agg.percentile(0.9, false).nms, Duration::from_millis(90));
+    assert_eq!(
+        agg.percentile(0.99, false).queue_wait,
+        Duration::from_millis(99)
+    );
+    assert_eq!(agg.percentile(1.0, false), FrameTimes::uniform(100));
+    assert_eq!(agg.percentile(0.0, false), FrameTimes::uniform(1));
+
+    // Ignoring the only frame leaves nothing to summarize.
+    let mut agg = AggregatedTimes::default();
+    agg.push(FrameTimes::uniform(5));
+    assert_eq!(agg.percentile(0.5, true), FrameTimes::default());
+}
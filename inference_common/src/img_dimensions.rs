@@ -1,5 +1,5 @@
 /// Describes dimensions of an image.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct ImgDimensions {
     pub width: f32,
     pub height: f32,
@@ -0,0 +1,206 @@
+//! Constant velocity Kalman filter over box center and size, used by the native trackers
+//! ([OcSort](crate::ocsort::OcSort), [BotSort](crate::botsort::BotSort)).
+//!
+//! State is `[cx, cy, w, h, vcx, vcy, vw, vh]`, noise scales with box size as in BoT-SORT.
+
+use crate::bbox::Bbox;
+use crate::tracker::CameraMotion;
+
+/// Box as `[center x, center y, width, height]`.
+pub type Cxcywh = [f32; 4];
+
+const STD_WEIGHT_POSITION: f32 = 1.0 / 20.0;
+const STD_WEIGHT_VELOCITY: f32 = 1.0 / 160.0;
+
+pub fn bbox_to_cxcywh(bbox: &Bbox) -> Cxcywh {
+    let (w, h) = (bbox.xmax - bbox.xmin, bbox.ymax - bbox.ymin);
+    [bbox.xmin + w / 2.0, bbox.ymin + h / 2.0, w, h]
+}
+
+/// Copy of `template` (class, confidence etc.) moved to `cxcywh`.
+pub fn bbox_from_cxcywh(cxcywh: Cxcywh, template: &Bbox) -> Bbox {
+    let [cx, cy, w, h] = cxcywh;
+    Bbox {
+        xmin: cx - w / 2.0,
+        ymin: cy - h / 2.0,
+        xmax: cx + w / 2.0,
+        ymax: cy + h / 2.0,
+        ..template.clone()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KalmanBoxFilter {
+    mean: [f32; 8],
+    covariance: [[f32; 8]; 8],
+}
+
+/// Position and velocity noise std devs for a box of the given size.
+fn noise_std(w: f32, h: f32) -> ([f32; 4], [f32; 4]) {
+    // Degenerate boxes would make the innovation covariance singular.
+    let (w, h) = (w.max(1.0), h.max(1.0));
+    let pos = STD_WEIGHT_POSITION;
+    let vel = STD_WEIGHT_VELOCITY;
+    (
+        [pos * w, pos * h, pos * w, pos * h],
+        [vel * w, vel * h, vel * w, vel * h],
+    )
+}
+
+impl KalmanBoxFilter {
+    /// Starts a filter at `measurement` with zero velocity.
+    pub fn new(measurement: Cxcywh) -> Self {
+        let mut mean = [0.0; 8];
+        mean[..4].copy_from_slice(&measurement);
+        let (pos, vel) = noise_std(measurement[2], measurement[3]);
+        let mut covariance = [[0.0; 8]; 8];
+        for i in 0..4 {
+            covariance[i][i] = (2.0 * pos[i]).powi(2);
+            covariance[i + 4][i + 4] = (10.0 * vel[i]).powi(2);
+        }
+        Self { mean, covariance }
+    }
+
+    /// Current box estimate.
+    pub fn state(&self) -> Cxcywh {
+        [self.mean[0], self.mean[1], self.mean[2], self.mean[3]]
+    }
+
+    /// Advances the state by one frame.
+    pub fn predict(&mut self) {
+        let (pos, vel) = noise_std(self.mean[2], self.mean[3]);
+        for i in 0..4 {
+            self.mean[i] += self.mean[i + 4];
+        }
+        // Shrinking boxes must not flip inside out.
+        self.mean[2] = self.mean[2].max(0.0);
+        self.mean[3] = self.mean[3].max(0.0);
+
+        // P = F P F^T + Q, where F adds velocities to positions.
+        let p = &mut self.covariance;
+        for i in 0..4 {
+            let velocity_row = p[i + 4];
+            for (value, velocity) in p[i].iter_mut().zip(velocity_row) {
+                *value += velocity;
+            }
+        }
+        for row in p.iter_mut() {
+            for j in 0..4 {
+                row[j] += row[j + 4];
+            }
+        }
+        for i in 0..4 {
+            p[i][i] += pos[i].powi(2);
+            p[i + 4][i + 4] += vel[i].powi(2);
+        }
+    }
+
+    /// Corrects the state with an observed box.
+    pub fn update(&mut self, measurement: Cxcywh) {
+        let (pos, _) = noise_std(measurement[2], measurement[3]);
+        let p = &self.covariance;
+
+        // Innovation covariance S = H P H^T + R, where H selects the box part of the state.
+        let mut s = [[0.0; 4]; 4];
+        for i in 0..4 {
+            s[i].copy_from_slice(&p[i][..4]);
+            s[i][i] += pos[i].powi(2);
+        }
+        let Some(s_inv) = invert4(s) else {
+            log::warn!("Singular kalman innovation covariance, skipping update");
+            return;
+        };
+
+        // Kalman gain K = P H^T S^-1.
+        let mut gain = [[0.0; 4]; 8];
+        for (r, gain_row) in gain.iter_mut().enumerate() {
+            for (c, g) in gain_row.iter_mut().enumerate() {
+                *g = (0..4).map(|k| p[r][k] * s_inv[k][c]).sum();
+            }
+        }
+
+        let innovation: [f32; 4] = std::array::from_fn(|i| measurement[i] - self.mean[i]);
+        for (r, gain_row) in gain.iter().enumerate() {
+            self.mean[r] += (0..4).map(|c| gain_row[c] * innovation[c]).sum::<f32>();
+        }
+
+        // P = P - K H P
+        let mut updated = *p;
+        for (r, row) in updated.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value -= (0..4).map(|k| gain[r][k] * p[k][c]).sum::<f32>();
+            }
+        }
+        self.covariance = updated;
+    }
+
+    /// Maps the state into the current frame after the camera moved by `warp`.
+    ///
+    /// Each (x, y) pair of the state is multiplied by the linear part of `warp`,
+    /// and the box center also gets the translation.
+    pub fn apply_camera_motion(&mut self, warp: &CameraMotion) {
+        let rotate = |x: f32, y: f32| {
+            (
+                warp[0][0] * x + warp[0][1] * y,
+                warp[1][0] * x + warp[1][1] * y,
+            )
+        };
+        for pair in (0..8).step_by(2) {
+            let (x, y) = rotate(self.mean[pair], self.mean[pair + 1]);
+            self.mean[pair] = x;
+            self.mean[pair + 1] = y;
+        }
+        self.mean[0] += warp[0][2];
+        self.mean[1] += warp[1][2];
+
+        // P = R P R^T, with R the block diagonal of 2x2 linear parts.
+        let mut rp = self.covariance;
+        for pair in (0..8).step_by(2) {
+            let (top, bottom) = (self.covariance[pair], self.covariance[pair + 1]);
+            for (c, (&t, &b)) in top.iter().zip(&bottom).enumerate() {
+                let (x, y) = rotate(t, b);
+                rp[pair][c] = x;
+                rp[pair + 1][c] = y;
+            }
+        }
+        for row in rp.iter_mut() {
+            for pair in (0..8).step_by(2) {
+                let (x, y) = rotate(row[pair], row[pair + 1]);
+                row[pair] = x;
+                row[pair + 1] = y;
+            }
+        }
+        self.covariance = rp;
+    }
+}
+
+/// Inverts a 4x4 matrix with Gauss-Jordan elimination, `None` if it's singular.
+fn invert4(mut m: [[f32; 4]; 4]) -> Option<[[f32; 4]; 4]> {
+    let mut inv = [[0.0; 4]; 4];
+    for (i, row) in inv.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < f32::EPSILON {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let scale = m[col][col];
+        for c in 0..4 {
+            m[col][c] /= scale;
+            inv[col][c] /= scale;
+        }
+        for r in 0..4 {
+            if r != col {
+                let factor = m[r][col];
+                for c in 0..4 {
+                    m[r][c] -= factor * m[col][c];
+                    inv[r][c] -= factor * inv[col][c];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
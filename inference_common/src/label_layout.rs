@@ -0,0 +1,163 @@
+//! Label placement for detection overlays.
+//!
+//! Crowded scenes have lots of overlapping boxes, so drawing every label at its box's
+//! top left corner ends up illegible. [layout_labels] places labels greedily, trying spots
+//! around the box first and stacking labels further away (with a leader line back to the box) if needed.
+
+/// How many label heights a label may be stacked away from its box.
+const MAX_STACK: i32 = 4;
+
+/// Axis aligned rectangle in image pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl LabelRect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn overlaps(&self, other: &LabelRect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    /// Whether the rects overlap or share an edge.
+    fn touches(&self, other: &LabelRect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    /// Moves the rect inside `width` x `height` image bounds, as far as it fits.
+    fn clamped(self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width as i32 - self.width as i32).max(0);
+        let y = self.y.min(height as i32 - self.height as i32).max(0);
+        Self { x, y, ..self }
+    }
+}
+
+/// Final position of a label.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LabelPlacement {
+    pub rect: LabelRect,
+    /// Line from the label to its box, if the label was moved away from the box.
+    pub leader: Option<((f32, f32), (f32, f32))>,
+}
+
+/// Places labels of the given sizes next to their boxes, avoiding overlaps between labels.
+///
+/// Labels are placed in the given order, so earlier (eg. more confident) labels get the best spots.
+/// Candidate spots are above the box, inside its top edge and below it, followed by stacking
+/// further above/below. If no free spot is found, the label overlaps others above its box.
+pub fn layout_labels(
+    boxes: &[LabelRect],
+    label_sizes: &[(u32, u32)],
+    image_dims: (u32, u32),
+) -> Vec<LabelPlacement> {
+    assert_eq!(boxes.len(), label_sizes.len());
+    let (img_w, img_h) = image_dims;
+    let mut placed: Vec<LabelRect> = Vec::with_capacity(boxes.len());
+    let mut placements = Vec::with_capacity(boxes.len());
+
+    for (bbox, &(width, height)) in boxes.iter().zip(label_sizes) {
+        let h = height as i32;
+        let at = |y: i32| LabelRect::new(bbox.x, y, width, height).clamped(img_w, img_h);
+        let above = |k: i32| at(bbox.y - h * (k + 1));
+        let below = |k: i32| at(bbox.bottom() + h * k);
+
+        let candidates = [above(0), at(bbox.y), below(0)]
+            .into_iter()
+            .chain((1..=MAX_STACK).flat_map(|k| [above(k), below(k)]));
+        let rect = candidates
+            .into_iter()
+            .find(|candidate| placed.iter().all(|other| !candidate.overlaps(other)))
+            .unwrap_or_else(|| above(0));
+
+        let leader = (!rect.touches(bbox)).then(|| {
+            let label_x = (rect.x + 2) as f32;
+            if rect.bottom() <= bbox.y {
+                (
+                    (label_x, rect.bottom() as f32),
+                    (bbox.x as f32, bbox.y as f32),
+                )
+            } else {
+                (
+                    (label_x, rect.y as f32),
+                    (bbox.x as f32, bbox.bottom() as f32),
+                )
+            }
+        });
+
+        placed.push(rect);
+        placements.push(LabelPlacement { rect, leader });
+    }
+
+    placements
+}
+
+/// Black or white, whichever is more readable on top of `background`.
+pub fn contrasting_text_color(background: [u8; 3]) -> [u8; 3] {
+    let [r, g, b] = background.map(f32::from);
+    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+    if luma > 140.0 {
+        [0, 0, 0]
+    } else {
+        [255, 255, 255]
+    }
+}
+
+#[test]
+fn overlapping_labels_get_stacked() {
+    // Two nearly identical boxes, the second label can't go above the box anymore.
+    let boxes = [
+        LabelRect::new(100, 100, 50, 80),
+        LabelRect::new(105, 102, 50, 80),
+    ];
+    let placements = layout_labels(&boxes, &[(60, 10), (60, 10)], (640, 480));
+
+    assert_eq!(placements[0].rect, LabelRect::new(100, 90, 60, 10));
+    assert_eq!(placements[0].leader, None);
+    assert_eq!(placements[1].rect, LabelRect::new(105, 102, 60, 10));
+    assert!(!placements[0].rect.overlaps(&placements[1].rect));
+
+    // Once the spots around the box are taken, labels get stacked away with a leader line.
+    let boxes = [boxes[0], boxes[1], boxes[0], boxes[0]];
+    let placements = layout_labels(&boxes, &[(60, 10); 4], (640, 480));
+    assert_eq!(placements[2].rect, LabelRect::new(100, 180, 60, 10));
+    assert_eq!(placements[2].leader, None);
+    assert_eq!(placements[3].rect, LabelRect::new(100, 80, 60, 10));
+    assert_eq!(placements[3].leader, Some(((102.0, 90.0), (100.0, 100.0))));
+}
+
+#[test]
+fn labels_stay_inside_image() {
+    let placements = layout_labels(&[LabelRect::new(630, 0, 10, 10)], &[(40, 12)], (640, 480));
+    assert_eq!(placements[0].rect, LabelRect::new(600, 0, 40, 12));
+}
+
+#[test]
+fn text_contrast() {
+    assert_eq!(contrasting_text_color([170, 0, 0]), [255, 255, 255]);
+    assert_eq!(contrasting_text_color([255, 230, 0]), [0, 0, 0]);
+}
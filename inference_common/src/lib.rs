@@ -1,11 +1,37 @@
 pub mod annotate;
+pub mod assignment;
 pub mod bbox;
+pub mod botsort;
+pub mod box_smoothing;
+pub mod camera_motion;
+pub mod clock_skew;
 pub mod coco_classes;
 pub mod color_extractor;
 pub mod detection_logger;
+pub mod detection_query;
+pub mod failure_dump;
+pub mod frame_dedup;
 pub mod frame_meta;
 pub mod frame_times;
 pub mod img_dimensions;
+pub mod kalman;
+pub mod label_layout;
+pub mod model_switch;
+pub mod mot_metrics;
+pub mod ocsort;
 pub mod onnx_attributes;
+pub mod onvif_metadata;
+pub mod output_guard;
+pub mod palette;
+pub mod preproc;
+pub mod preview;
+pub mod processing_error;
+pub mod profile;
+pub mod run_diff;
+pub mod sub_view;
+pub mod thermal;
 pub mod tracker;
+pub mod undistort;
 pub mod video_meta;
+pub mod wall_time;
+pub mod yuv;
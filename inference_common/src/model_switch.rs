@@ -0,0 +1,193 @@
+//! Switching between a small model, run by default, and a larger one while the scene is busy or
+//! hard (many or uncertain detections), trading accuracy for efficiency as needed.
+//!
+//! The switch goes by stream time, and stays on the large model for a hold time after the last
+//! activity, so it doesn't flap between models on every frame.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// When a frame counts as activity, parsed from `KEY=VALUE` pairs, eg. `detections=5 hold=10`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityPolicy {
+    /// Frames with at least this many detections.
+    pub min_detections: usize,
+    /// Frames with a detection below this confidence.
+    pub low_confidence: f32,
+    /// Seconds to keep the large model after the last activity.
+    pub hold_secs: f32,
+}
+
+impl Default for ActivityPolicy {
+    fn default() -> Self {
+        Self {
+            min_detections: 5,
+            low_confidence: 0.4,
+            hold_secs: 10.,
+        }
+    }
+}
+
+impl FromStr for ActivityPolicy {
+    type Err = String;
+
+    /// Parses space or comma separated `detections`, `confidence` and `hold` values, left out
+    /// ones keep their defaults.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = Self::default();
+        for pair in s.split([' ', ',']).filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {pair:?}"))?;
+            match key {
+                "detections" => policy.min_detections = parse_value(key, value)?,
+                "confidence" => policy.low_confidence = parse_value(key, value)?,
+                "hold" => policy.hold_secs = parse_value(key, value)?,
+                _ => {
+                    return Err(format!(
+                        "unknown activity setting {key:?}, expected detections, confidence or hold"
+                    ))
+                }
+            }
+        }
+        if policy.min_detections == 0 {
+            return Err("detections must be at least 1".to_string());
+        }
+        if !(0. ..=1.).contains(&policy.low_confidence) {
+            return Err(format!(
+                "confidence must be in [0, 1], got {}",
+                policy.low_confidence
+            ));
+        }
+        if !policy.hold_secs.is_finite() || policy.hold_secs < 0. {
+            return Err(format!("hold must be positive, got {}", policy.hold_secs));
+        }
+        Ok(policy)
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("invalid {key} {value:?}: {e}"))
+}
+
+/// Chooses the model of each frame by the [ActivityPolicy].
+#[derive(Debug)]
+pub struct ModelSwitch {
+    policy: ActivityPolicy,
+    /// Stream time of the last frame with activity.
+    last_activity_ms: Option<u64>,
+    large: bool,
+    /// Frames run on the large model.
+    large_frames: u64,
+    frames: u64,
+    switches: u32,
+}
+
+impl ModelSwitch {
+    pub fn new(policy: ActivityPolicy) -> Self {
+        Self {
+            policy,
+            last_activity_ms: None,
+            large: false,
+            large_frames: 0,
+            frames: 0,
+            switches: 0,
+        }
+    }
+
+    /// Whether to run the next frame on the large model.
+    pub fn use_large(&self) -> bool {
+        self.large
+    }
+
+    /// Updates the choice with the `confidences` of the detections in the frame at
+    /// `timestamp_ms` (stream time), logging switches.
+    pub fn update(&mut self, timestamp_ms: u64, confidences: impl IntoIterator<Item = f32>) {
+        self.frames += 1;
+        if self.large {
+            self.large_frames += 1;
+        }
+        let mut count = 0;
+        let mut uncertain = false;
+        for confidence in confidences {
+            count += 1;
+            uncertain |= confidence < self.policy.low_confidence;
+        }
+        if count >= self.policy.min_detections || uncertain {
+            self.last_activity_ms = Some(timestamp_ms);
+        }
+
+        let hold_ms = (self.policy.hold_secs * 1000.) as u64;
+        let large = self
+            .last_activity_ms
+            .is_some_and(|last| timestamp_ms.saturating_sub(last) <= hold_ms);
+        if large != self.large {
+            self.switches += 1;
+            if large {
+                log::info!("Switching to the large model at {timestamp_ms} ms, {count} detections");
+            } else {
+                log::info!("Switching back to the small model at {timestamp_ms} ms");
+            }
+        }
+        self.large = large;
+    }
+
+    /// Logs how much of the stream ran on the large model.
+    pub fn log_summary(&self) {
+        if self.frames == 0 {
+            return;
+        }
+        log::info!(
+            "Large model ran on {} of {} frames ({:.1}%), {} switches",
+            self.large_frames,
+            self.frames,
+            self.large_frames as f64 / self.frames as f64 * 100.,
+            self.switches
+        );
+    }
+}
+
+#[test]
+fn parses_policy() {
+    assert_eq!("".parse::<ActivityPolicy>(), Ok(ActivityPolicy::default()));
+    assert_eq!(
+        "detections=3,confidence=0.5 hold=2.5".parse::<ActivityPolicy>(),
+        Ok(ActivityPolicy {
+            min_detections: 3,
+            low_confidence: 0.5,
+            hold_secs: 2.5,
+        })
+    );
+    assert!("detections=0".parse::<ActivityPolicy>().is_err());
+    assert!("confidence=2".parse::<ActivityPolicy>().is_err());
+    assert!("hold=-1".parse::<ActivityPolicy>().is_err());
+    assert!("speed=1".parse::<ActivityPolicy>().is_err());
+}
+
+#[test]
+fn switches_with_hold() {
+    let mut switch = ModelSwitch::new(ActivityPolicy {
+        min_detections: 3,
+        low_confidence: 0.4,
+        hold_secs: 1.,
+    });
+    switch.update(0, [0.9, 0.8]);
+    assert!(!switch.use_large());
+    switch.update(40, [0.9, 0.8, 0.7]);
+    assert!(switch.use_large());
+    // Quiet again, held for a second.
+    switch.update(1000, [0.9]);
+    assert!(switch.use_large());
+    switch.update(1080, [0.9]);
+    assert!(!switch.use_large());
+    // A single uncertain detection is activity too.
+    switch.update(1120, [0.3]);
+    assert!(switch.use_large());
+    assert_eq!(switch.switches, 3);
+    assert_eq!(switch.large_frames, 2);
+}
@@ -0,0 +1,258 @@
+//! Multi-object tracking accuracy metrics (CLEAR MOT's MOTA and IDF1),
+//! plus a loader for [MOTChallenge](https://motchallenge.net) style annotation files.
+//!
+//! Used to compare [Tracker](crate::tracker::Tracker) implementations on a labeled clip.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::bbox::{iou, Bbox};
+
+/// Accumulates per-frame matches between ground truth and tracker output.
+///
+/// Ground truth and hypothesis boxes are matched by IoU within the same class,
+/// correspondences of the previous frame are kept while they're still above `iou_threshold`
+/// (as in CLEAR MOT), the rest are matched greedily by highest IoU.
+#[derive(Debug)]
+pub struct MotAccumulator {
+    iou_threshold: f32,
+    frames: usize,
+    gt_count: usize,
+    hyp_count: usize,
+    matches: usize,
+    id_switches: usize,
+    /// Last hypothesis id each ground truth id was matched to.
+    last_match: HashMap<i64, i64>,
+    /// Number of frames each (ground truth id, hypothesis id) pair was matched in.
+    pair_frames: HashMap<(i64, i64), usize>,
+}
+
+/// Summary of a [MotAccumulator].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotMetrics {
+    pub frames: usize,
+    pub gt_count: usize,
+    pub hyp_count: usize,
+    pub matches: usize,
+    pub false_positives: usize,
+    pub misses: usize,
+    pub id_switches: usize,
+    /// `1 - (misses + false positives + id switches) / ground truth boxes`, can be negative.
+    pub mota: f32,
+    /// F1 score of identity-preserving matches.
+    pub idf1: f32,
+}
+
+impl MotAccumulator {
+    pub fn new(iou_threshold: f32) -> Self {
+        Self {
+            iou_threshold,
+            frames: 0,
+            gt_count: 0,
+            hyp_count: 0,
+            matches: 0,
+            id_switches: 0,
+            last_match: HashMap::new(),
+            pair_frames: HashMap::new(),
+        }
+    }
+
+    /// Adds a frame of ground truth and tracker output boxes,
+    /// boxes without a `tracker_id` are ignored.
+    pub fn update(&mut self, gt: &[Bbox], hyp: &[Bbox]) {
+        let gt: Vec<&Bbox> = gt.iter().filter(|b| b.tracker_id.is_some()).collect();
+        let hyp: Vec<&Bbox> = hyp.iter().filter(|b| b.tracker_id.is_some()).collect();
+        self.frames += 1;
+        self.gt_count += gt.len();
+        self.hyp_count += hyp.len();
+
+        let matched_iou = |g: &Bbox, h: &Bbox| {
+            let overlap = iou(g, h);
+            (g.class == h.class && overlap >= self.iou_threshold).then_some(overlap)
+        };
+
+        let mut gt_matched = vec![false; gt.len()];
+        let mut hyp_matched = vec![false; hyp.len()];
+        let mut pairs = Vec::new();
+
+        // Keep last frame's correspondences if they still overlap.
+        for (gi, g) in gt.iter().enumerate() {
+            let Some(&last) = self.last_match.get(&g.tracker_id.unwrap()) else {
+                continue;
+            };
+            let found = hyp.iter().enumerate().find(|(hi, h)| {
+                !hyp_matched[*hi] && h.tracker_id == Some(last) && matched_iou(g, h).is_some()
+            });
+            if let Some((hi, _)) = found {
+                gt_matched[gi] = true;
+                hyp_matched[hi] = true;
+                pairs.push((gi, hi));
+            }
+        }
+
+        // Greedily match the rest by highest IoU.
+        let mut candidates = Vec::new();
+        for (gi, g) in gt.iter().enumerate().filter(|(gi, _)| !gt_matched[*gi]) {
+            for (hi, h) in hyp.iter().enumerate().filter(|(hi, _)| !hyp_matched[*hi]) {
+                if let Some(overlap) = matched_iou(g, h) {
+                    candidates.push((overlap, gi, hi));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, gi, hi) in candidates {
+            if !gt_matched[gi] && !hyp_matched[hi] {
+                gt_matched[gi] = true;
+                hyp_matched[hi] = true;
+                pairs.push((gi, hi));
+            }
+        }
+
+        for (gi, hi) in pairs {
+            let gt_id = gt[gi].tracker_id.unwrap();
+            let hyp_id = hyp[hi].tracker_id.unwrap();
+            if let Some(last) = self.last_match.insert(gt_id, hyp_id) {
+                if last != hyp_id {
+                    self.id_switches += 1;
+                }
+            }
+            *self.pair_frames.entry((gt_id, hyp_id)).or_default() += 1;
+            self.matches += 1;
+        }
+    }
+
+    pub fn metrics(&self) -> MotMetrics {
+        let misses = self.gt_count - self.matches;
+        let false_positives = self.hyp_count - self.matches;
+        let mota = if self.gt_count == 0 {
+            0.0
+        } else {
+            1.0 - (misses + false_positives + self.id_switches) as f32 / self.gt_count as f32
+        };
+
+        // Identity true positives: frames matched by a one-to-one assignment of
+        // ground truth ids to hypothesis ids, greedily by most frames (approximates the optimal assignment).
+        let mut pairs: Vec<_> = self.pair_frames.iter().collect();
+        pairs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let mut assigned_gt = HashMap::new();
+        let mut assigned_hyp = HashMap::new();
+        let mut id_true_positives = 0;
+        for (&(gt_id, hyp_id), &frames) in pairs {
+            if assigned_gt.contains_key(&gt_id) || assigned_hyp.contains_key(&hyp_id) {
+                continue;
+            }
+            assigned_gt.insert(gt_id, hyp_id);
+            assigned_hyp.insert(hyp_id, gt_id);
+            id_true_positives += frames;
+        }
+        let total = self.gt_count + self.hyp_count;
+        let idf1 = if total == 0 {
+            0.0
+        } else {
+            2.0 * id_true_positives as f32 / total as f32
+        };
+
+        MotMetrics {
+            frames: self.frames,
+            gt_count: self.gt_count,
+            hyp_count: self.hyp_count,
+            matches: self.matches,
+            false_positives,
+            misses,
+            id_switches: self.id_switches,
+            mota,
+            idf1,
+        }
+    }
+}
+
+/// Loads a MOTChallenge `gt.txt` / `det.txt` file into boxes per frame (index 0 is frame 1).
+///
+/// Rows are `frame, id, left, top, width, height, confidence[, ...]`.
+/// Ids of `-1` (detections) become `tracker_id: None`, ground truth rows with zero confidence
+/// are marked as ignored by MOTChallenge and are skipped. All boxes get `class` 0 (person).
+pub fn load_mot(path: &Path) -> anyhow::Result<Vec<Vec<Bbox>>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Reading {path:?}"))?;
+    let mut frames: Vec<Vec<Bbox>> = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields = line
+            .split(',')
+            .take(7)
+            .map(|field| field.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("{path:?}:{}: invalid row", line_number + 1))?;
+        let &[frame, id, left, top, width, height, confidence] = fields.as_slice() else {
+            anyhow::bail!("{path:?}:{}: expected at least 7 columns", line_number + 1);
+        };
+        if frame < 1.0 {
+            anyhow::bail!("{path:?}:{}: frames start at 1", line_number + 1);
+        }
+        let is_gt = id >= 0.0;
+        if is_gt && confidence == 0.0 {
+            continue;
+        }
+
+        let index = frame as usize - 1;
+        if frames.len() <= index {
+            frames.resize(index + 1, Vec::new());
+        }
+        frames[index].push(Bbox {
+            xmin: left,
+            ymin: top,
+            xmax: left + width,
+            ymax: top + height,
+            detector_confidence: if is_gt { 1.0 } else { confidence },
+            tracker_confidence: 0.0,
+            data: vec![],
+            class: 0,
+            tracker_id: is_gt.then_some(id as i64),
+            track: None,
+        });
+    }
+    Ok(frames)
+}
+
+#[test]
+fn perfect_tracking_and_id_switch() {
+    let bbox = |x: f32, id: i64| Bbox {
+        tracker_id: Some(id),
+        ..Bbox::test(x, 0.0, x + 10.0, 10.0)
+    };
+
+    let mut acc = MotAccumulator::new(0.5);
+    for frame in 0..4 {
+        let x = frame as f32;
+        acc.update(
+            &[bbox(x, 1), bbox(x + 50.0, 2)],
+            &[bbox(x, 7), bbox(x + 50.0, 8)],
+        );
+    }
+    let metrics = acc.metrics();
+    assert_eq!((metrics.matches, metrics.id_switches), (8, 0));
+    assert_eq!((metrics.mota, metrics.idf1), (1.0, 1.0));
+
+    // Track 1 is picked up under a new id halfway, and one frame misses track 2.
+    let mut acc = MotAccumulator::new(0.5);
+    for frame in 0..4 {
+        let x = frame as f32;
+        let hyp_id = if frame < 2 { 7 } else { 9 };
+        let mut hyp = vec![bbox(x, hyp_id)];
+        if frame != 3 {
+            hyp.push(bbox(x + 50.0, 8));
+        }
+        acc.update(&[bbox(x, 1), bbox(x + 50.0, 2)], &hyp);
+    }
+    let metrics = acc.metrics();
+    assert_eq!(metrics.id_switches, 1);
+    assert_eq!(metrics.misses, 1);
+    assert_eq!(metrics.false_positives, 0);
+    assert_eq!(metrics.mota, 1.0 - 2.0 / 8.0);
+    // Identity matches: 1 -> 7 for 2 frames, 2 -> 8 for 3 frames.
+    assert_eq!(metrics.idf1, 2.0 * 5.0 / 15.0);
+}
@@ -0,0 +1,374 @@
+//! [OC-SORT](https://arxiv.org/abs/2203.14360), observation-centric SORT.
+//!
+//! Compared to plain SORT it
+//! - re-updates the kalman filter along a virtual trajectory when a lost track is re-associated (ORU),
+//!   so errors accumulated while occluded don't linger,
+//! - favors detections in the track's direction of motion during association (OCM),
+//! - matches leftovers against the last observed box of each track (OCR).
+
+use std::collections::VecDeque;
+
+use crate::assignment::{linear_assignment, FORBIDDEN};
+use crate::bbox::{iou, Bbox, TrackInfo};
+use crate::img_dimensions::ImgDimensions;
+use crate::kalman::{bbox_from_cxcywh, bbox_to_cxcywh, Cxcywh, KalmanBoxFilter};
+use crate::tracker::{clamp_bbox, CameraMotion, Tracker};
+
+#[derive(Debug, Clone)]
+pub struct OcSortParams {
+    /// Frames a track is kept alive without observations.
+    pub max_age: u32,
+    /// Consecutive observations before a track is reported.
+    pub min_hits: u32,
+    /// Minimum IoU for associating a detection with a track.
+    pub iou_threshold: f32,
+    /// Minimum detection confidence for starting new tracks.
+    pub new_track_confidence: f32,
+    /// Frame distance of the observations used to estimate direction of motion.
+    pub delta_t: u32,
+    /// Weight of direction consistency (OCM) relative to IoU.
+    pub inertia: f32,
+    /// Report confirmed tracks without an observation (for up to `max_age` frames) as predicted
+    /// boxes.
+    pub report_predicted: bool,
+}
+
+impl Default for OcSortParams {
+    fn default() -> Self {
+        Self {
+            max_age: 30,
+            min_hits: 3,
+            iou_threshold: 0.3,
+            new_track_confidence: 0.3,
+            delta_t: 3,
+            inertia: 0.2,
+            report_predicted: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Track {
+    id: i64,
+    filter: KalmanBoxFilter,
+    /// Filter right after the last observation, for re-updating after occlusions.
+    observed_filter: KalmanBoxFilter,
+    /// Recent observations along with the frame they were made in.
+    observations: VecDeque<(u64, Bbox)>,
+    hit_streak: u32,
+    frames_since_update: u32,
+    /// IoU of the predicted box with the last matched detection.
+    match_iou: Option<f32>,
+    /// Reported at least once, ie. it had `min_hits` observations in a row.
+    confirmed: bool,
+}
+
+impl Track {
+    fn last_observation(&self) -> &Bbox {
+        &self.observations.back().unwrap().1
+    }
+
+    /// Observation made about `delta_t` frames before the last one, for estimating direction.
+    fn previous_observation(&self, delta_t: u32) -> &Bbox {
+        let (last_frame, _) = self.observations.back().unwrap();
+        self.observations
+            .iter()
+            .find(|(frame, _)| last_frame - frame <= delta_t as u64)
+            .map(|(_, bbox)| bbox)
+            .unwrap()
+    }
+
+    /// Unit vector of the motion between the previous and last observations, if any.
+    fn direction(&self, delta_t: u32) -> Option<[f32; 2]> {
+        if self.observations.len() < 2 {
+            return None;
+        }
+        direction(self.previous_observation(delta_t), self.last_observation())
+    }
+}
+
+/// Unit vector from the center of `from` to the center of `to`.
+fn direction(from: &Bbox, to: &Bbox) -> Option<[f32; 2]> {
+    let [x1, y1, ..] = bbox_to_cxcywh(from);
+    let [x2, y2, ..] = bbox_to_cxcywh(to);
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let norm = (dx * dx + dy * dy).sqrt();
+    (norm > 1e-6).then(|| [dx / norm, dy / norm])
+}
+
+#[derive(Debug, Default)]
+pub struct OcSort {
+    params: OcSortParams,
+    tracks: Vec<Track>,
+    next_id: i64,
+    frame: u64,
+    camera_motion: Option<CameraMotion>,
+}
+
+impl OcSort {
+    pub fn new(params: OcSortParams) -> Self {
+        Self {
+            params,
+            ..Default::default()
+        }
+    }
+
+    /// Associates `detections` with tracks in `track_indices`, returning `(track, detection)` pairs.
+    fn associate(
+        &self,
+        track_indices: &[usize],
+        detections: &[&Bbox],
+        track_box: impl Fn(&Track) -> Bbox,
+        use_direction: bool,
+    ) -> Vec<(usize, usize)> {
+        let cost: Vec<Vec<f32>> = track_indices
+            .iter()
+            .map(|&t| {
+                let track = &self.tracks[t];
+                let predicted = track_box(track);
+                let track_direction = track.direction(self.params.delta_t);
+                detections
+                    .iter()
+                    .map(|det| {
+                        if det.class != predicted.class {
+                            return FORBIDDEN;
+                        }
+                        let overlap = iou(&predicted, det);
+                        if overlap < self.params.iou_threshold {
+                            return FORBIDDEN;
+                        }
+                        let consistency = match (use_direction, track_direction) {
+                            (true, Some([tx, ty])) => {
+                                let origin = track.previous_observation(self.params.delta_t);
+                                direction(origin, det).map_or(0.0, |[dx, dy]| {
+                                    let angle = (tx * dx + ty * dy).clamp(-1.0, 1.0).acos();
+                                    (std::f32::consts::FRAC_PI_2 - angle) / std::f32::consts::PI
+                                })
+                            }
+                            _ => 0.0,
+                        };
+                        -(overlap + consistency * self.params.inertia * det.detector_confidence)
+                    })
+                    .collect()
+            })
+            .collect();
+        linear_assignment(&cost, FORBIDDEN / 2.0)
+            .into_iter()
+            .map(|(t, d)| (track_indices[t], d))
+            .collect()
+    }
+
+    /// Updates `track` with a new observation, re-updating along a linear
+    /// virtual trajectory if it went unobserved for a while.
+    fn observe(&mut self, track_index: usize, detection: &Bbox) {
+        let max_observations = self.params.delta_t as usize + 1;
+        let frame = self.frame;
+        let track = &mut self.tracks[track_index];
+        let observed = bbox_to_cxcywh(detection);
+        let predicted = bbox_from_cxcywh(track.filter.state(), track.last_observation());
+        track.match_iou = Some(iou(&predicted, detection));
+
+        let gap = track.frames_since_update;
+        if gap > 1 {
+            let last = bbox_to_cxcywh(track.last_observation());
+            let mut filter = track.observed_filter.clone();
+            for step in 1..=gap {
+                let t = step as f32 / gap as f32;
+                let virtual_box: Cxcywh =
+                    std::array::from_fn(|i| last[i] + (observed[i] - last[i]) * t);
+                filter.predict();
+                filter.update(virtual_box);
+            }
+            track.filter = filter;
+        } else {
+            track.filter.update(observed);
+        }
+
+        track.observed_filter = track.filter.clone();
+        track.observations.push_back((frame, detection.clone()));
+        if track.observations.len() > max_observations {
+            track.observations.pop_front();
+        }
+        track.frames_since_update = 0;
+        track.hit_streak += 1;
+    }
+}
+
+impl Tracker for OcSort {
+    fn name(&self) -> &'static str {
+        "ocsort"
+    }
+
+    fn update(&mut self, bboxes_per_class: &[Vec<Bbox>], scaled_dims: ImgDimensions) -> Vec<Bbox> {
+        self.frame += 1;
+        let camera_motion = self.camera_motion.take();
+        for track in &mut self.tracks {
+            if let Some(warp) = &camera_motion {
+                track.filter.apply_camera_motion(warp);
+                track.observed_filter.apply_camera_motion(warp);
+            }
+            track.filter.predict();
+            if track.frames_since_update > 0 {
+                track.hit_streak = 0;
+            }
+            track.frames_since_update += 1;
+        }
+
+        let detections: Vec<&Bbox> = bboxes_per_class.iter().flatten().collect();
+        let all_tracks: Vec<usize> = (0..self.tracks.len()).collect();
+
+        // Predicted boxes, with direction consistency.
+        let mut matches = self.associate(
+            &all_tracks,
+            &detections,
+            |track| bbox_from_cxcywh(track.filter.state(), track.last_observation()),
+            true,
+        );
+
+        // Recover leftovers by their last observed boxes.
+        let unmatched_tracks: Vec<usize> = all_tracks
+            .iter()
+            .copied()
+            .filter(|t| !matches.iter().any(|(mt, _)| mt == t))
+            .collect();
+        let unmatched_detections: Vec<usize> = (0..detections.len())
+            .filter(|d| !matches.iter().any(|(_, md)| md == d))
+            .collect();
+        let leftovers: Vec<&Bbox> = unmatched_detections
+            .iter()
+            .map(|&d| detections[d])
+            .collect();
+        let recovered = self.associate(
+            &unmatched_tracks,
+            &leftovers,
+            |track| track.last_observation().clone(),
+            false,
+        );
+        matches.extend(
+            recovered
+                .into_iter()
+                .map(|(t, d)| (t, unmatched_detections[d])),
+        );
+
+        for &(t, d) in &matches {
+            self.observe(t, detections[d]);
+        }
+
+        for (d, detection) in detections.iter().enumerate() {
+            let matched = matches.iter().any(|&(_, md)| md == d);
+            if matched || detection.detector_confidence < self.params.new_track_confidence {
+                continue;
+            }
+            self.next_id += 1;
+            let filter = KalmanBoxFilter::new(bbox_to_cxcywh(detection));
+            self.tracks.push(Track {
+                id: self.next_id,
+                observed_filter: filter.clone(),
+                filter,
+                observations: VecDeque::from([(self.frame, (*detection).clone())]),
+                hit_streak: 1,
+                frames_since_update: 0,
+                match_iou: None,
+                confirmed: false,
+            });
+        }
+
+        let max_age = self.params.max_age;
+        self.tracks
+            .retain(|track| track.frames_since_update <= max_age);
+
+        let warming_up = self.frame <= self.params.min_hits as u64;
+        let min_hits = self.params.min_hits;
+        let report_predicted = self.params.report_predicted;
+        self.tracks
+            .iter_mut()
+            .filter_map(|track| {
+                let observed = track.frames_since_update == 0;
+                let hit = observed && (warming_up || track.hit_streak >= min_hits);
+                track.confirmed |= hit;
+                // Confirmed tracks stay reported throughout, when reporting predicted ones.
+                (hit || (report_predicted && track.confirmed)).then_some(&*track)
+            })
+            .map(|track| {
+                let observation = track.last_observation();
+                let mut bbox = bbox_from_cxcywh(track.filter.state(), observation);
+                bbox.tracker_confidence = observation.detector_confidence;
+                bbox.tracker_id = Some(track.id);
+                bbox.track = Some(TrackInfo {
+                    match_iou: track.match_iou,
+                    frames_since_update: track.frames_since_update,
+                    hit_streak: track.hit_streak,
+                    predicted: track.frames_since_update > 0,
+                });
+                clamp_bbox(bbox, scaled_dims)
+            })
+            .collect()
+    }
+
+    fn set_camera_motion(&mut self, warp: CameraMotion) {
+        self.camera_motion = Some(warp);
+    }
+
+    fn set_report_predicted(&mut self, enabled: bool) {
+        self.params.report_predicted = enabled;
+    }
+}
+
+#[test]
+fn track_survives_occlusion() {
+    let dims = ImgDimensions::new(640.0, 384.0);
+    let mut tracker = OcSort::new(OcSortParams::default());
+
+    let mut ids = Vec::new();
+    for frame in 0..20 {
+        let x = 10.0 + frame as f32 * 4.0;
+        // Occluded for frames 8..12.
+        let detections = if (8..12).contains(&frame) {
+            vec![vec![]]
+        } else {
+            vec![vec![Bbox::test(x, 10.0, x + 20.0, 50.0)]]
+        };
+        let tracked = tracker.update(&detections, dims);
+        ids.extend(tracked.iter().map(|b| b.tracker_id.unwrap()));
+        if frame == 19 {
+            assert_eq!(tracked.len(), 1);
+            assert!((tracked[0].xmin - x).abs() < 2.0, "{:?}", tracked[0]);
+            let track = tracked[0].track.unwrap();
+            assert_eq!(track.frames_since_update, 0);
+            assert_eq!(track.hit_streak, 8);
+            assert!(!track.predicted);
+            assert!(track.match_iou.unwrap() > 0.8, "{track:?}");
+        }
+    }
+    // Reported throughout (except while occluded and min_hits frames after), under the same id.
+    assert!(ids.len() > 10);
+    assert!(ids.iter().all(|&id| id == 1), "{ids:?}");
+}
+
+#[test]
+fn reports_predicted_tracks_while_occluded() {
+    let dims = ImgDimensions::new(640.0, 384.0);
+    let mut tracker = OcSort::new(OcSortParams::default());
+    tracker.set_report_predicted(true);
+
+    for frame in 0..16 {
+        let x = 10.0 + frame as f32 * 4.0;
+        // Occluded for frames 8..12.
+        let occluded = (8..12).contains(&frame);
+        let detections = if occluded {
+            vec![vec![]]
+        } else {
+            vec![vec![Bbox::test(x, 10.0, x + 20.0, 50.0)]]
+        };
+        let tracked = tracker.update(&detections, dims);
+        assert_eq!(tracked.len(), 1, "frame {frame}");
+        assert_eq!(tracked[0].tracker_id, Some(1));
+        let track = tracked[0].track.unwrap();
+        assert_eq!(track.predicted, occluded, "frame {frame}");
+        if occluded {
+            assert_eq!(track.frames_since_update, frame - 7);
+            // Predicted along its motion.
+            assert!((tracked[0].xmin - x).abs() < 4.0, "{:?}", tracked[0]);
+        }
+    }
+}
@@ -13,6 +13,8 @@ use ort::session::builder::GraphOptimizationLevel;
 use ort::value::TensorRef;
 use std::path::Path;
 
+use crate::preproc;
+
 /// Attribute detection using ONNX models
 pub struct AttributeDetector {
     // Color classification model (optional)
@@ -322,20 +324,8 @@ impl AttributeDetector {
     /// Convert image to ndarray for model input
     fn image_to_array(&self, image: &DynamicImage) -> Result<Array4<f32>> {
         let rgb_image = image.to_rgb8();
-        let (width, height) = rgb_image.dimensions();
-
-        let mut array = Array4::<f32>::zeros((1, 3, height as usize, width as usize));
-
-        for y in 0..height {
-            for x in 0..width {
-                let pixel = rgb_image.get_pixel(x, y);
-                array[[0, 0, y as usize, x as usize]] = pixel[0] as f32 / 255.0;
-                array[[0, 1, y as usize, x as usize]] = pixel[1] as f32 / 255.0;
-                array[[0, 2, y as usize, x as usize]] = pixel[2] as f32 / 255.0;
-            }
-        }
-
-        Ok(array)
+        let dims = rgb_image.dimensions().into();
+        Ok(preproc::image_to_tensor(&rgb_image, dims))
     }
 
     /// Convert color name to approximate RGB
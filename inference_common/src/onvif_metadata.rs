@@ -0,0 +1,131 @@
+//! Detection logs as ONVIF analytics metadata (`tt:MetadataStream` scene descriptions, as consumed
+//! by Profile M clients), so VMSs understanding ONVIF can import tracks without a custom parser.
+//!
+//! Boxes stay in frame pixel coordinates, each frame carries the `tt:Transformation` into ONVIF's
+//! normalized `[-1, 1]` (y up) space.
+//!
+//! Metadata is only exported as a file, for importing or replaying. Profile M devices also serve it
+//! live as the metadata track of their RTSP streams, which isn't supported: there's no RTSP server
+//! in this tree.
+
+use std::io::{self, Write};
+
+use crate::detection_logger::DetectionLog;
+use crate::wall_time::UtcOffset;
+
+/// ONVIF object type of a COCO class, other classes keep their name.
+fn object_type(class_name: &str) -> &str {
+    match class_name {
+        "person" => "Human",
+        "bicycle" => "Bicycle",
+        "car" | "motorcycle" | "bus" | "truck" | "train" => "Vehicle",
+        "bird" | "cat" | "dog" | "horse" | "sheep" | "cow" | "elephant" | "bear" | "zebra"
+        | "giraffe" => "Animal",
+        other => other,
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes `logs` of `width`x`height` frames as a single `tt:MetadataStream` document,
+/// with one `tt:Frame` per frame with detections.
+///
+/// Frames are timed by their capture time if known, otherwise by `start_ms` (unix epoch) plus
+/// their stream time. Untracked detections are skipped, ONVIF objects need a stable id.
+pub fn write_metadata_stream(
+    mut writer: impl Write,
+    logs: &[DetectionLog],
+    width: u32,
+    height: u32,
+    start_ms: u64,
+) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<tt:MetadataStream xmlns:tt="http://www.onvif.org/ver10/schema">"#
+    )?;
+    writeln!(writer, "  <tt:VideoAnalytics>")?;
+    for frame in logs.chunk_by(|a, b| a.frame_number == b.frame_number) {
+        let first = &frame[0];
+        let time_ms = first
+            .capture_time_ms
+            .unwrap_or(start_ms + first.timestamp_ms);
+        writeln!(
+            writer,
+            r#"    <tt:Frame UtcTime="{}">"#,
+            UtcOffset::UTC.format(time_ms)
+        )?;
+        writeln!(writer, "      <tt:Transformation>")?;
+        writeln!(writer, r#"        <tt:Translate x="-1" y="1"/>"#)?;
+        writeln!(
+            writer,
+            r#"        <tt:Scale x="{}" y="{}"/>"#,
+            2.0 / width as f32,
+            -2.0 / height as f32
+        )?;
+        writeln!(writer, "      </tt:Transformation>")?;
+        for detection in frame {
+            let Some(id) = detection.tracker_id else {
+                continue;
+            };
+            let bbox = &detection.bbox;
+            writeln!(writer, r#"      <tt:Object ObjectId="{id}">"#)?;
+            writeln!(writer, "        <tt:Appearance>")?;
+            writeln!(writer, "          <tt:Shape>")?;
+            writeln!(
+                writer,
+                r#"            <tt:BoundingBox left="{:.1}" top="{:.1}" right="{:.1}" bottom="{:.1}"/>"#,
+                bbox.xmin, bbox.ymin, bbox.xmax, bbox.ymax
+            )?;
+            writeln!(
+                writer,
+                r#"            <tt:CenterOfGravity x="{:.1}" y="{:.1}"/>"#,
+                (bbox.xmin + bbox.xmax) / 2.0,
+                (bbox.ymin + bbox.ymax) / 2.0
+            )?;
+            writeln!(writer, "          </tt:Shape>")?;
+            writeln!(writer, "          <tt:Class>")?;
+            writeln!(
+                writer,
+                r#"            <tt:Type Likelihood="{:.2}">{}</tt:Type>"#,
+                detection.confidence,
+                escape(object_type(&detection.class_name))
+            )?;
+            writeln!(writer, "          </tt:Class>")?;
+            writeln!(writer, "        </tt:Appearance>")?;
+            writeln!(writer, "      </tt:Object>")?;
+        }
+        writeln!(writer, "    </tt:Frame>")?;
+    }
+    writeln!(writer, "  </tt:VideoAnalytics>")?;
+    writeln!(writer, "</tt:MetadataStream>")?;
+    Ok(())
+}
+
+#[test]
+fn metadata_stream() {
+    let logs = [
+        DetectionLog::test(0, 10., 20., 30., 60.),
+        DetectionLog {
+            tracker_id: None,
+            class_name: "car".to_string(),
+            ..DetectionLog::test(0, 10., 20., 30., 60.)
+        },
+        DetectionLog::test(1, 10., 20., 30., 60.),
+    ];
+    let mut xml = Vec::new();
+    write_metadata_stream(&mut xml, &logs, 640, 480, 1_709_294_400_000).unwrap();
+    let xml = String::from_utf8(xml).unwrap();
+
+    assert_eq!(xml.matches("<tt:Frame ").count(), 2);
+    assert_eq!(xml.matches("<tt:Object ").count(), 2);
+    assert!(xml.contains(r#"UtcTime="2024-03-01T12:00:00.040Z""#));
+    assert!(xml.contains(r#"<tt:BoundingBox left="10.0" top="20.0" right="30.0" bottom="60.0"/>"#));
+    assert!(xml.contains(r#"<tt:Type Likelihood="0.90">Human</tt:Type>"#));
+}
@@ -0,0 +1,188 @@
+//! Sanity checks of model output, applied before boxes reach trackers and logs.
+//!
+//! A broken model (or a broken export of one) can produce NaN/Inf coordinates, boxes far outside
+//! the frame or thousands of boxes per frame. Those are dropped (and partially outside boxes
+//! clamped), the [OutputIssues] found are reported so callers can count and dump such frames.
+//!
+//! [sanitize] runs on the decoded boxes before NMS, so broken boxes can't suppress valid ones,
+//! [drop_excess] after it, as a crowded frame has far more than [MAX_BOXES] boxes before NMS.
+
+use std::fmt;
+
+use crate::bbox::Bbox;
+use crate::img_dimensions::ImgDimensions;
+
+/// More boxes than this in a single frame are considered absurd, only the most confident are kept.
+pub const MAX_BOXES: usize = 500;
+
+/// Problems found in the model output of a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputIssues {
+    /// Boxes with non-finite coordinates or confidence, dropped.
+    pub non_finite: usize,
+    /// Boxes without any area inside the frame, dropped.
+    pub outside: usize,
+    /// Least confident boxes over [MAX_BOXES], dropped.
+    pub excess: usize,
+    /// Boxes reaching out of the frame, clamped to it. Common for objects at the frame edges,
+    /// so these alone don't make the output bad.
+    pub clamped: usize,
+}
+
+impl OutputIssues {
+    /// Whether the output was broken, ie. boxes had to be dropped.
+    pub fn is_bad(&self) -> bool {
+        self.non_finite + self.outside + self.excess > 0
+    }
+}
+
+impl fmt::Display for OutputIssues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let issues = [
+            (self.non_finite, "non-finite boxes"),
+            (self.outside, "boxes outside the frame"),
+            (self.excess, "least confident boxes over the limit"),
+            (self.clamped, "clamped boxes"),
+        ];
+        let mut first = true;
+        for (count, issue) in issues.into_iter().filter(|(count, _)| *count > 0) {
+            let separator = if first { "" } else { ", " };
+            write!(f, "{separator}{count} {issue}")?;
+            first = false;
+        }
+        if first {
+            write!(f, "no issues")?;
+        }
+        Ok(())
+    }
+}
+
+/// Drops broken boxes of `bboxes` (per class, in `dims` sized frame coordinates) and clamps
+/// the rest into the frame. Leaves [OutputIssues::excess] to [drop_excess].
+pub fn sanitize(bboxes: &mut [Vec<Bbox>], dims: ImgDimensions) -> OutputIssues {
+    let mut issues = OutputIssues::default();
+    for class_bboxes in bboxes.iter_mut() {
+        class_bboxes.retain_mut(|bbox| {
+            let values = [
+                bbox.xmin,
+                bbox.ymin,
+                bbox.xmax,
+                bbox.ymax,
+                bbox.detector_confidence,
+            ];
+            if !values.iter().all(|value| value.is_finite()) {
+                issues.non_finite += 1;
+                return false;
+            }
+            let xmin = bbox.xmin.clamp(0., dims.width);
+            let ymin = bbox.ymin.clamp(0., dims.height);
+            let xmax = bbox.xmax.clamp(0., dims.width);
+            let ymax = bbox.ymax.clamp(0., dims.height);
+            if xmax <= xmin || ymax <= ymin {
+                issues.outside += 1;
+                return false;
+            }
+            if [xmin, ymin, xmax, ymax] != [bbox.xmin, bbox.ymin, bbox.xmax, bbox.ymax] {
+                issues.clamped += 1;
+                (bbox.xmin, bbox.ymin, bbox.xmax, bbox.ymax) = (xmin, ymin, xmax, ymax);
+            }
+            true
+        });
+    }
+    issues
+}
+
+/// Drops the least confident boxes of `bboxes` over [MAX_BOXES], returning how many.
+pub fn drop_excess(bboxes: &mut [Vec<Bbox>]) -> usize {
+    let count: usize = bboxes.iter().map(Vec::len).sum();
+    if count <= MAX_BOXES {
+        return 0;
+    }
+    let mut all: Vec<(usize, Bbox)> = bboxes
+        .iter_mut()
+        .enumerate()
+        .flat_map(|(class, class_bboxes)| {
+            std::mem::take(class_bboxes)
+                .into_iter()
+                .map(move |b| (class, b))
+        })
+        .collect();
+    all.sort_by(|(_, a), (_, b)| b.detector_confidence.total_cmp(&a.detector_confidence));
+    all.truncate(MAX_BOXES);
+    for (class, bbox) in all {
+        bboxes[class].push(bbox);
+    }
+    count - MAX_BOXES
+}
+
+#[test]
+fn sanitize_drops_and_clamps() {
+    let dims = ImgDimensions::new(640., 384.);
+    let mut bboxes = vec![
+        vec![
+            Bbox::test(10., 10., 20., 20.),
+            Bbox::test(f32::NAN, 10., 20., 20.),
+            Bbox::test(700., 10., 800., 20.),
+            Bbox::test(600., 10., 700., 20.),
+        ],
+        vec![Bbox {
+            detector_confidence: f32::INFINITY,
+            ..Bbox::test(10., 10., 20., 20.)
+        }],
+    ];
+    let issues = sanitize(&mut bboxes, dims);
+    assert_eq!(
+        issues,
+        OutputIssues {
+            non_finite: 2,
+            outside: 1,
+            excess: 0,
+            clamped: 1
+        }
+    );
+    assert!(issues.is_bad());
+    assert_eq!(bboxes[0].len(), 2);
+    assert_eq!(bboxes[0][1].xmax, 640.);
+    assert_eq!(
+        issues.to_string(),
+        "2 non-finite boxes, 1 boxes outside the frame, 1 clamped boxes"
+    );
+}
+
+#[test]
+fn drop_excess_keeps_most_confident() {
+    let bbox = |detector_confidence: f32| Bbox {
+        detector_confidence,
+        ..Bbox::test(10., 10., 20., 20.)
+    };
+    let mut bboxes = vec![vec![bbox(0.5); MAX_BOXES], vec![bbox(0.4), bbox(0.9)]];
+    assert_eq!(drop_excess(&mut bboxes), 2);
+    assert_eq!(bboxes[0].len(), MAX_BOXES - 1);
+    assert_eq!(bboxes[1].len(), 1);
+    assert_eq!(bboxes[1][0].detector_confidence, 0.9);
+}
+
+#[test]
+fn sanitize_sees_raw_predictions() {
+    // 1 class, 3 anchors: a NaN box, a NaN score and a box reaching out of the frame.
+    #[rustfmt::skip]
+    let preds = [
+        f32::NAN, 50., 95.,
+        50., 50., 50.,
+        20., 20., 20.,
+        20., 20., 20.,
+        0.9, f32::NAN, 0.8,
+    ];
+    let mut bboxes = crate::bbox::decode_yolov8(&preds, 3, 1, 0.25);
+    let issues = sanitize(&mut bboxes, ImgDimensions::new(100., 100.));
+    assert_eq!(
+        issues,
+        OutputIssues {
+            non_finite: 2,
+            clamped: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(bboxes[0].len(), 1);
+    assert_eq!((bboxes[0][0].xmin, bboxes[0][0].xmax), (85., 100.));
+}
@@ -0,0 +1,49 @@
+//! Deterministic per-track colors.
+//!
+//! The same track id always maps to the same color, so an object can be followed across
+//! the annotated video, the TUI and the rerun export.
+
+/// Distinct, reasonably saturated colors (based on matplotlib tab20).
+pub const PALETTE: [[u8; 3]; 16] = [
+    [31, 119, 180],
+    [255, 127, 14],
+    [44, 160, 44],
+    [214, 39, 40],
+    [148, 103, 189],
+    [140, 86, 75],
+    [227, 119, 194],
+    [188, 189, 34],
+    [23, 190, 207],
+    [174, 199, 232],
+    [255, 187, 120],
+    [152, 223, 138],
+    [255, 152, 150],
+    [197, 176, 213],
+    [247, 182, 210],
+    [219, 219, 141],
+];
+
+/// Color of boxes without a tracker id.
+pub const UNTRACKED_COLOR: [u8; 3] = [255, 0, 0];
+
+/// Palette color of the given track id.
+///
+/// Uses fibonacci hashing, so consecutive ids get visibly different colors.
+pub fn track_color(tracker_id: i64) -> [u8; 3] {
+    let hash = (tracker_id as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    PALETTE[(hash >> 60) as usize % PALETTE.len()]
+}
+
+/// Track color for tracked boxes, [UNTRACKED_COLOR] otherwise.
+pub fn bbox_color(tracker_id: Option<i64>) -> [u8; 3] {
+    tracker_id.map_or(UNTRACKED_COLOR, track_color)
+}
+
+#[test]
+fn track_colors_are_stable_and_spread() {
+    assert_eq!(track_color(7), track_color(7));
+    assert_eq!(bbox_color(None), UNTRACKED_COLOR);
+    for id in 0..8 {
+        assert_ne!(track_color(id), track_color(id + 1));
+    }
+}
@@ -0,0 +1,299 @@
+//! Model input preprocessing shared by all inference backends.
+//!
+//! Frames are scaled to fit inside the model input dimensions (keeping aspect ratio),
+//! padded at the right/bottom, and converted to a normalized planar `[1, 3, h, w]` f32 tensor.
+//! Model outputs are in coordinates of the scaled image, see [Letterbox] to map them back.
+
+use image::{DynamicImage, RgbImage};
+use ndarray::Array4;
+use rayon::prelude::*;
+
+use crate::bbox::{Bbox, KeyPoint};
+use crate::img_dimensions::ImgDimensions;
+
+/// Dimensions of `og_dims` scaled to fit inside `target_dims`, keeping aspect ratio.
+pub fn fit_dims(og_dims: ImgDimensions, target_dims: ImgDimensions) -> ImgDimensions {
+    let ratio = (target_dims.width / og_dims.width).min(target_dims.height / og_dims.height);
+    let scaled = og_dims.scale(ratio);
+    // Round down to whole pixels, so the scaled image is never larger than the target.
+    ImgDimensions::new(scaled.width.floor(), scaled.height.floor())
+}
+
+/// Mapping between a frame and its scaled (letterboxed) copy in the model input.
+#[derive(Debug, Clone, Copy)]
+pub struct Letterbox {
+    pub frame_dims: ImgDimensions,
+    pub scaled_dims: ImgDimensions,
+}
+
+impl Letterbox {
+    /// Letterbox of `frame_dims` sized frames preprocessed into `input_dims`.
+    pub fn new(frame_dims: ImgDimensions, input_dims: ImgDimensions) -> Self {
+        Self {
+            frame_dims,
+            scaled_dims: fit_dims(frame_dims, input_dims),
+        }
+    }
+
+    /// `bbox` in scaled image coordinates (as output by the model) mapped to frame coordinates,
+    /// clamped to the frame.
+    ///
+    /// Sides are scaled separately, as [fit_dims] rounds them down independently.
+    pub fn unmap(&self, bbox: &Bbox) -> Bbox {
+        let sx = self.frame_dims.width / self.scaled_dims.width;
+        let sy = self.frame_dims.height / self.scaled_dims.height;
+        let x = |x: f32| (x * sx).clamp(0.0, self.frame_dims.width);
+        let y = |y: f32| (y * sy).clamp(0.0, self.frame_dims.height);
+        Bbox {
+            xmin: x(bbox.xmin),
+            ymin: y(bbox.ymin),
+            xmax: x(bbox.xmax),
+            ymax: y(bbox.ymax),
+            data: bbox
+                .data
+                .iter()
+                .map(|kp| KeyPoint {
+                    x: x(kp.x),
+                    y: y(kp.y),
+                    ..*kp
+                })
+                .collect(),
+            ..bbox.clone()
+        }
+    }
+}
+
+/// Model input dimensions for an image of `og_dims`, where the longer side is `max_side`
+/// and both sides are multiples of `stride`.
+///
+/// Useful for models with dynamic input shapes, such as the candle yolov8.
+pub fn stride_aligned_dims(og_dims: ImgDimensions, max_side: u32, stride: u32) -> ImgDimensions {
+    let fitted = fit_dims(
+        og_dims,
+        ImgDimensions::new(max_side as f32, max_side as f32),
+    );
+    let align = |side: f32| ((side as u32).div_ceil(stride) * stride).min(max_side) as f32;
+    ImgDimensions::new(align(fitted.width), align(fitted.height))
+}
+
+/// Resizes `image` to `width` x `height` using nearest neighbour sampling.
+pub fn resize_nearest(image: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let (src_w, src_h) = image.dimensions();
+    if (src_w, src_h) == (width, height) {
+        return image.clone();
+    }
+    let src = image.as_raw();
+    let src_stride = src_w as usize * 3;
+
+    // Source byte offset of each destination column, sampled at pixel centers.
+    let x_offsets: Vec<usize> = (0..width)
+        .map(|x| {
+            let src_x = ((x as f32 + 0.5) * src_w as f32 / width as f32) as u32;
+            src_x.min(src_w - 1) as usize * 3
+        })
+        .collect();
+
+    let mut dst = vec![0u8; width as usize * height as usize * 3];
+    for (y, dst_row) in dst.chunks_exact_mut(width as usize * 3).enumerate() {
+        let src_y = ((y as f32 + 0.5) * src_h as f32 / height as f32) as u32;
+        let src_row = &src[src_y.min(src_h - 1) as usize * src_stride..][..src_stride];
+        for (dst_px, &offset) in dst_row.chunks_exact_mut(3).zip(&x_offsets) {
+            dst_px.copy_from_slice(&src_row[offset..offset + 3]);
+        }
+    }
+
+    RgbImage::from_raw(width, height, dst).expect("resized buffer matches its dimensions")
+}
+
+/// Images with at least this many pixels are converted on multiple threads.
+const PARALLEL_MIN_PIXELS: usize = 64 * 1024;
+
+/// Converts interleaved RGB8 pixels of a `width` x `height` image into planar f32 values in `[0, 1]`.
+///
+/// `planes` holds the R, G and B planes of `plane_width` x `plane_height` each,
+/// which may be larger than the image, in which case the remainder is left untouched (padding).
+///
+/// Rows of larger images are converted in parallel using rayon,
+/// and each row is a straight-line chunked loop, so the compiler can auto-vectorize it.
+pub fn rgb_to_planar_f32(
+    rgb: &[u8],
+    width: usize,
+    height: usize,
+    planes: &mut [f32],
+    plane_width: usize,
+    plane_height: usize,
+) {
+    assert!(width <= plane_width && height <= plane_height);
+    assert_eq!(rgb.len(), width * height * 3);
+    assert_eq!(planes.len(), 3 * plane_width * plane_height);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let plane_len = plane_width * plane_height;
+    let (r_plane, rest) = planes.split_at_mut(plane_len);
+    let (g_plane, b_plane) = rest.split_at_mut(plane_len);
+
+    if width * height >= PARALLEL_MIN_PIXELS {
+        rgb.par_chunks_exact(width * 3)
+            .zip(r_plane.par_chunks_exact_mut(plane_width))
+            .zip(g_plane.par_chunks_exact_mut(plane_width))
+            .zip(b_plane.par_chunks_exact_mut(plane_width))
+            .for_each(|(((src_row, r_row), g_row), b_row)| {
+                rgb_row_to_planar_f32(src_row, r_row, g_row, b_row)
+            });
+    } else {
+        rgb.chunks_exact(width * 3)
+            .zip(r_plane.chunks_exact_mut(plane_width))
+            .zip(g_plane.chunks_exact_mut(plane_width))
+            .zip(b_plane.chunks_exact_mut(plane_width))
+            .for_each(|(((src_row, r_row), g_row), b_row)| {
+                rgb_row_to_planar_f32(src_row, r_row, g_row, b_row)
+            });
+    }
+}
+
+/// Converts a single row of interleaved RGB8 pixels into the start of the given plane rows.
+#[inline]
+fn rgb_row_to_planar_f32(src_row: &[u8], r_row: &mut [f32], g_row: &mut [f32], b_row: &mut [f32]) {
+    let width = src_row.len() / 3;
+    let (r_row, g_row, b_row) = (
+        &mut r_row[..width],
+        &mut g_row[..width],
+        &mut b_row[..width],
+    );
+    for (i, px) in src_row.chunks_exact(3).enumerate() {
+        r_row[i] = px[0] as f32 / 255.0;
+        g_row[i] = px[1] as f32 / 255.0;
+        b_row[i] = px[2] as f32 / 255.0;
+    }
+}
+
+/// Loads `image` into a zero padded `[1, 3, target_height, target_width]` array.
+pub fn image_to_tensor(image: &RgbImage, target_dims: ImgDimensions) -> Array4<f32> {
+    let (width, height) = image.dimensions();
+    let (target_w, target_h) = (target_dims.width as usize, target_dims.height as usize);
+    let mut array = Array4::zeros([1, 3, target_h, target_w]);
+    rgb_to_planar_f32(
+        image.as_raw(),
+        width as usize,
+        height as usize,
+        array
+            .as_slice_mut()
+            .expect("freshly allocated array is contiguous"),
+        target_w,
+        target_h,
+    );
+    array
+}
+
+/// Transforms the input `image` by converting colors, resizing and loading the image buffer into an [Array4].
+///
+/// Returns the scaled image inside ndarray [Array4] and scaled dims inside [ImgDimensions],
+/// the scaled image occupies the top left corner of the `target_dims` sized tensor.
+pub fn preprocess(
+    image: &DynamicImage,
+    target_dims: ImgDimensions,
+) -> (Array4<f32>, ImgDimensions) {
+    log::debug!("image.color: {:?}", image.color());
+
+    // Avoid a copy for the (usual) case of already rgb8 frames.
+    let converted;
+    let rgb = match image {
+        DynamicImage::ImageRgb8(rgb) => rgb,
+        other => {
+            converted = other.to_rgb8();
+            &converted
+        }
+    };
+
+    let scaled_dims = fit_dims(rgb.dimensions().into(), target_dims);
+    log::debug!("scaled dims: {scaled_dims:?}");
+    let scaled = resize_nearest(rgb, scaled_dims.width as u32, scaled_dims.height as u32);
+
+    (image_to_tensor(&scaled, target_dims), scaled_dims)
+}
+
+#[test]
+fn fit_and_align_dims() {
+    let dims = fit_dims(
+        ImgDimensions::new(1920.0, 1080.0),
+        ImgDimensions::new(640.0, 384.0),
+    );
+    assert_eq!((dims.width, dims.height), (640.0, 360.0));
+
+    let dims = stride_aligned_dims(ImgDimensions::new(1920.0, 1080.0), 640, 32);
+    assert_eq!((dims.width, dims.height), (640.0, 384.0));
+
+    let dims = stride_aligned_dims(ImgDimensions::new(480.0, 640.0), 640, 32);
+    assert_eq!((dims.width, dims.height), (480.0, 640.0));
+}
+
+#[test]
+fn letterbox_unmaps_to_frame_coords() {
+    let letterbox = Letterbox::new(
+        ImgDimensions::new(1920.0, 1080.0),
+        ImgDimensions::new(640.0, 384.0),
+    );
+    let bbox = Bbox {
+        tracker_id: Some(3),
+        ..Bbox::test(10.0, 20.0, 630.0, 370.0)
+    };
+    let unmapped = letterbox.unmap(&bbox);
+    assert_eq!((unmapped.xmin, unmapped.ymin), (30.0, 60.0));
+    // Boxes reaching into the padding are clamped to the frame.
+    assert_eq!((unmapped.xmax, unmapped.ymax), (1890.0, 1080.0));
+    assert_eq!(unmapped.tracker_id, Some(3));
+}
+
+#[test]
+fn tensor_is_planar_normalized_and_padded() {
+    let image = RgbImage::from_fn(2, 1, |x, _| {
+        if x == 0 {
+            image::Rgb([255, 0, 51])
+        } else {
+            image::Rgb([0, 255, 102])
+        }
+    });
+    let tensor = image_to_tensor(&image, ImgDimensions::new(3.0, 2.0));
+    assert_eq!(tensor.shape(), &[1, 3, 2, 3]);
+    assert_eq!(tensor[[0, 0, 0, 0]], 1.0);
+    assert_eq!(tensor[[0, 1, 0, 1]], 1.0);
+    assert_eq!(tensor[[0, 2, 0, 0]], 0.2);
+    assert_eq!(tensor[[0, 2, 0, 1]], 0.4);
+    // Padding column and row stay zero.
+    assert_eq!(tensor[[0, 0, 0, 2]], 0.0);
+    assert_eq!(tensor[[0, 1, 1, 1]], 0.0);
+}
+
+#[test]
+fn parallel_conversion_matches_sequential() {
+    let (width, height) = (400, 300);
+    assert!(width * height >= PARALLEL_MIN_PIXELS);
+    let rgb: Vec<u8> = (0..width * height * 3)
+        .map(|i| (i * 7 % 256) as u8)
+        .collect();
+    let (plane_w, plane_h) = (416, 320);
+
+    let mut parallel = vec![0f32; 3 * plane_w * plane_h];
+    rgb_to_planar_f32(&rgb, width, height, &mut parallel, plane_w, plane_h);
+
+    let mut expected = vec![0f32; 3 * plane_w * plane_h];
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..3 {
+                let value = rgb[(y * width + x) * 3 + c] as f32 / 255.0;
+                expected[c * plane_w * plane_h + y * plane_w + x] = value;
+            }
+        }
+    }
+    assert_eq!(parallel, expected);
+}
+
+#[test]
+fn nearest_resize_samples_pixel_centers() {
+    let image = RgbImage::from_fn(4, 2, |x, y| image::Rgb([x as u8, y as u8, 0]));
+    let resized = resize_nearest(&image, 2, 1);
+    assert_eq!(resized.get_pixel(0, 0).0, [1, 1, 0]);
+    assert_eq!(resized.get_pixel(1, 0).0, [3, 1, 0]);
+}
@@ -0,0 +1,88 @@
+//! Latest annotated frame of a running stream, for sanity checking headless machines over ssh.
+//!
+//! Every n-th frame is written to a fixed path (replaced atomically, so viewers never see a
+//! partial image), and optionally printed inline into terminals supporting the kitty graphics
+//! or iTerm2 image protocols.
+
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{ImageFormat, RgbImage};
+
+/// Kitty graphics protocol payloads are sent in chunks of at most this many base64 bytes.
+const KITTY_CHUNK: usize = 4096;
+
+/// Writes every `every`th frame to `path` (`.jpg` or `.png`).
+#[derive(Debug, Clone)]
+pub struct FramePreview {
+    pub path: PathBuf,
+    pub every: u64,
+    /// Also print the frame into the terminal.
+    pub inline: bool,
+}
+
+impl FramePreview {
+    /// Whether frame `frame_num` should be written.
+    pub fn is_due(&self, frame_num: u64) -> bool {
+        frame_num.is_multiple_of(self.every)
+    }
+
+    /// Replaces the preview with `frame` (and prints it).
+    pub fn write(&self, frame: &RgbImage) -> anyhow::Result<()> {
+        let format = ImageFormat::from_path(&self.path)?;
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+        frame.save_with_format(&tmp_path, format)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        if self.inline {
+            let mut png = Vec::new();
+            frame.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{}", inline_image(&png, is_kitty()))?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the terminal speaks the kitty graphics protocol, otherwise iTerm2's is used
+/// (also understood by WezTerm and others).
+fn is_kitty() -> bool {
+    std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || std::env::var("TERM").is_ok_and(|term| term.contains("kitty"))
+}
+
+/// Escape sequence displaying `png` in the terminal.
+fn inline_image(png: &[u8], kitty: bool) -> String {
+    let data = STANDARD.encode(png);
+    if !kitty {
+        return format!("\x1b]1337;File=inline=1;size={}:{data}\x07", png.len());
+    }
+    let chunks: Vec<_> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut escape = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        // Only the first chunk carries the control data, `m` marks whether more follow.
+        let control = if i == 0 { "f=100,a=T," } else { "" };
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        escape.push_str(&format!("\x1b_G{control}m={more};{chunk}\x1b\\"));
+    }
+    escape
+}
+
+#[test]
+fn kitty_chunks() {
+    let png = vec![0u8; KITTY_CHUNK];
+    let escape = inline_image(&png, true);
+    let chunks: Vec<_> = escape.split("\x1b\\").filter(|c| !c.is_empty()).collect();
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks[0].starts_with("\x1b_Gf=100,a=T,m=1;"));
+    assert!(chunks[1].starts_with("\x1b_Gm=0;"));
+
+    let iterm = inline_image(&png, false);
+    assert!(iterm.starts_with("\x1b]1337;File=inline=1;size=4096:AAAA"));
+}
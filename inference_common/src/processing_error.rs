@@ -0,0 +1,162 @@
+//! Categorized processing errors and how they're recovered from, with per-category error rates.
+//!
+//! Errors stay [anyhow::Error]s, [ProcessingError] tags them with an [ErrorCategory] at the point
+//! they're raised, so per-frame code can apply the category's [RecoveryPolicy] and count them.
+
+use std::fmt;
+
+/// Broad origin of an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Reading or decoding frames, eg. unmappable buffers or unexpected frame sizes.
+    Source,
+    /// Writing processed frames back for the pipeline's outputs, eg. unwritable buffers.
+    Sink,
+    /// Preprocessing, model run or output parsing.
+    Inference,
+    /// Writing outputs (json, detection logs, dumps). Outputs written once processing ends fail
+    /// the run regardless of the policy.
+    Storage,
+    /// Optional enrichment around inference, eg. attribute models.
+    Integration,
+    /// Model output failing sanity checks (eg. NaN or absurdly many boxes), see
+    /// [output_guard](crate::output_guard). The broken boxes are dropped.
+    ModelOutput,
+}
+
+/// What to do with the current frame after an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Pass the frame through without (further) results, processing continues with the next one.
+    SkipFrame,
+    /// Keep processing the frame without the failed part.
+    Continue,
+}
+
+impl ErrorCategory {
+    pub const ALL: [ErrorCategory; 6] = [
+        ErrorCategory::Source,
+        ErrorCategory::Sink,
+        ErrorCategory::Inference,
+        ErrorCategory::Storage,
+        ErrorCategory::Integration,
+        ErrorCategory::ModelOutput,
+    ];
+
+    pub fn policy(self) -> RecoveryPolicy {
+        match self {
+            ErrorCategory::Source | ErrorCategory::Sink | ErrorCategory::Inference => {
+                RecoveryPolicy::SkipFrame
+            }
+            ErrorCategory::Storage | ErrorCategory::Integration | ErrorCategory::ModelOutput => {
+                RecoveryPolicy::Continue
+            }
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Error tagged with its category, converts into [anyhow::Error] (and can be downcast back).
+#[derive(Debug)]
+pub struct ProcessingError {
+    pub category: ErrorCategory,
+    pub error: anyhow::Error,
+}
+
+impl ProcessingError {
+    pub fn new(category: ErrorCategory, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            category,
+            error: error.into(),
+        }
+    }
+}
+
+impl fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} error: {:#}", self.category, self.error)
+    }
+}
+
+impl std::error::Error for ProcessingError {}
+
+/// Error counts per category of a stream.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorStats {
+    counts: [u64; ErrorCategory::ALL.len()],
+    frames: u64,
+}
+
+impl ErrorStats {
+    /// Counts a processed frame, the denominator of [ErrorStats::rate].
+    pub fn frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Logs and counts `error` raised on `frame_num`, returning how to recover from it.
+    #[must_use = "the frame or stream has to be recovered by the returned policy"]
+    pub fn record(&mut self, frame_num: u64, error: &ProcessingError) -> RecoveryPolicy {
+        self.counts[error.category.index()] += 1;
+        let policy = error.category.policy();
+        match policy {
+            RecoveryPolicy::Continue => log::warn!("Frame {frame_num}: {error}"),
+            RecoveryPolicy::SkipFrame => {
+                log::error!("Frame {frame_num}: {error}, {policy:?}")
+            }
+        }
+        policy
+    }
+
+    pub fn count(&self, category: ErrorCategory) -> u64 {
+        self.counts[category.index()]
+    }
+
+    /// Errors of `category` per processed frame.
+    pub fn rate(&self, category: ErrorCategory) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.count(category) as f64 / self.frames as f64
+    }
+
+    /// Logs error counts and rates of categories with any errors.
+    pub fn log_summary(&self) {
+        for category in ErrorCategory::ALL {
+            let count = self.count(category);
+            if count > 0 {
+                log::warn!(
+                    "{category:?} errors: {count} ({:.2}% of {} frames)",
+                    self.rate(category) * 100.0,
+                    self.frames
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn error_rates_by_category() {
+    let mut stats = ErrorStats::default();
+    for _ in 0..10 {
+        stats.frame();
+    }
+    let inference = ProcessingError::new(ErrorCategory::Inference, anyhow::anyhow!("ort failed"));
+    assert_eq!(stats.record(3, &inference), RecoveryPolicy::SkipFrame);
+    let attributes = ProcessingError::new(ErrorCategory::Integration, anyhow::anyhow!("no model"));
+    assert_eq!(stats.record(4, &attributes), RecoveryPolicy::Continue);
+    let _ = stats.record(5, &attributes);
+
+    assert_eq!(stats.count(ErrorCategory::Integration), 2);
+    assert_eq!(stats.rate(ErrorCategory::Inference), 0.1);
+    assert_eq!(stats.rate(ErrorCategory::Storage), 0.0);
+
+    // Category survives the round trip through anyhow.
+    let error: anyhow::Error = ProcessingError::new(ErrorCategory::Storage, inference.error).into();
+    let category = error.downcast_ref::<ProcessingError>().map(|e| e.category);
+    assert_eq!(category, Some(ErrorCategory::Storage));
+    assert_eq!(ErrorCategory::Storage.policy(), RecoveryPolicy::Continue);
+    assert_eq!(ErrorCategory::Sink.policy(), RecoveryPolicy::SkipFrame);
+}
@@ -0,0 +1,218 @@
+//! Per-frame profiling reports, written with `--profile`.
+//!
+//! The report contains the environment (model, execution provider, resolution)
+//! and the full [FrameTimes] series, so runs can be compared offline.
+//! It can also be converted to the chrome trace event format, which can be opened in
+//! `chrome://tracing`, [Perfetto](https://ui.perfetto.dev) or [speedscope](https://speedscope.app).
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::json;
+
+use crate::frame_times::{AggregatedTimes, FrameTimes};
+use crate::img_dimensions::ImgDimensions;
+
+/// Environment a profile was recorded in.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileEnv {
+    pub model: String,
+    pub execution_provider: String,
+    pub width: u32,
+    pub height: u32,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub cpus: usize,
+}
+
+impl ProfileEnv {
+    pub fn new(model: &str, execution_provider: &str, frame_dims: ImgDimensions) -> Self {
+        Self {
+            model: model.to_string(),
+            execution_provider: execution_provider.to_string(),
+            width: frame_dims.width as u32,
+            height: frame_dims.height as u32,
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            cpus: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// Frame times serialized as flat milliseconds, eg. `forward_pass_ms`.
+struct FrameTimesMs<'a> {
+    frame: Option<usize>,
+    times: &'a FrameTimes,
+}
+
+impl Serialize for FrameTimesMs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(frame) = self.frame {
+            map.serialize_entry("frame", &frame)?;
+        }
+        map.serialize_entry("total_ms", &ms(self.times.total()))?;
+        for (name, duration) in self.times.stages() {
+            map.serialize_entry(&format!("{name}_ms"), &ms(duration))?;
+        }
+        map.serialize_entry("queue_wait_ms", &ms(self.times.queue_wait))?;
+        map.serialize_entry("latency_ms", &ms(self.times.latency))?;
+        map.end()
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e3
+}
+
+fn us(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1e6
+}
+
+/// Profiling report of a whole run.
+pub struct Profile {
+    pub env: ProfileEnv,
+    pub times: AggregatedTimes,
+}
+
+impl Profile {
+    pub fn new(env: ProfileEnv, times: AggregatedTimes) -> Self {
+        Self { env, times }
+    }
+
+    /// The report as json: environment, summary (ignoring the first frame) and per-frame times.
+    pub fn to_json(&self) -> serde_json::Value {
+        let summary = |times: FrameTimes| {
+            serde_json::to_value(FrameTimesMs {
+                frame: None,
+                times: &times,
+            })
+            .expect("frame times serialize to json")
+        };
+        let frames: Vec<_> = self
+            .times
+            .frames()
+            .iter()
+            .enumerate()
+            .map(|(i, times)| FrameTimesMs {
+                frame: Some(i),
+                times,
+            })
+            .collect();
+
+        json!({
+            "env": self.env,
+            "summary": {
+                "avg": summary(self.times.avg(true)),
+                "p50": summary(self.times.percentile(0.5, true)),
+                "p90": summary(self.times.percentile(0.9, true)),
+                "p99": summary(self.times.percentile(0.99, true)),
+            },
+            "frames": frames,
+        })
+    }
+
+    /// The report in chrome trace event format.
+    ///
+    /// Frames are laid out back to back on the "inference" track, with processing stages nested
+    /// inside each frame, and the time each frame waited in queue is shown on a separate track.
+    pub fn to_chrome_trace(&self) -> serde_json::Value {
+        const PID: u32 = 1;
+        const INFERENCE_TID: u32 = 1;
+        const QUEUE_TID: u32 = 2;
+
+        let mut events = vec![
+            json!({"name": "process_name", "ph": "M", "pid": PID,
+                   "args": {"name": format!("{} ({})", self.env.model, self.env.execution_provider)}}),
+            json!({"name": "thread_name", "ph": "M", "pid": PID, "tid": INFERENCE_TID,
+                   "args": {"name": "inference"}}),
+            json!({"name": "thread_name", "ph": "M", "pid": PID, "tid": QUEUE_TID,
+                   "args": {"name": "queue"}}),
+        ];
+
+        let mut cursor = Duration::ZERO;
+        for (i, times) in self.times.frames().iter().enumerate() {
+            if !times.queue_wait.is_zero() {
+                let queued_at = cursor.saturating_sub(times.queue_wait);
+                events.push(json!({
+                    "name": "queue_wait", "cat": "queue", "ph": "X",
+                    "ts": us(queued_at), "dur": us(cursor - queued_at),
+                    "pid": PID, "tid": QUEUE_TID, "args": {"frame": i},
+                }));
+            }
+
+            events.push(json!({
+                "name": format!("frame {i}"), "cat": "frame", "ph": "X",
+                "ts": us(cursor), "dur": us(times.total()),
+                "pid": PID, "tid": INFERENCE_TID,
+                "args": {"frame": i, "latency_ms": ms(times.latency)},
+            }));
+            let mut stage_start = cursor;
+            for (name, duration) in times.stages() {
+                events.push(json!({
+                    "name": name, "cat": "stage", "ph": "X",
+                    "ts": us(stage_start), "dur": us(duration),
+                    "pid": PID, "tid": INFERENCE_TID, "args": {"frame": i},
+                }));
+                stage_start += duration;
+            }
+            cursor += times.total();
+        }
+
+        json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+            "otherData": self.env,
+        })
+    }
+
+    /// Writes the json report to `path`, and the chrome trace next to it, returning its path.
+    ///
+    /// Eg. `profile.json` results in `profile.json` + `profile.trace.json`.
+    pub fn write(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &self.to_json())?;
+
+        let trace_path = path.with_extension("trace.json");
+        serde_json::to_writer(
+            BufWriter::new(File::create(&trace_path)?),
+            &self.to_chrome_trace(),
+        )?;
+        Ok(trace_path)
+    }
+}
+
+#[test]
+fn chrome_trace_nests_stages_in_frames() {
+    let mut times = AggregatedTimes::default();
+    let mut first = FrameTimes::uniform(1);
+    first.queue_wait = Duration::ZERO;
+    times.push(first);
+    times.push(FrameTimes::uniform(2));
+    let profile = Profile::new(
+        ProfileEnv::new("yolov8s.onnx", "cpu", ImgDimensions::new(640.0, 384.0)),
+        times,
+    );
+
+    let json = profile.to_json();
+    assert_eq!(json["frames"].as_array().unwrap().len(), 2);
+    assert_eq!(json["frames"][1]["forward_pass_ms"], 2.0);
+    assert_eq!(json["summary"]["p50"]["total_ms"], 18.0);
+
+    let trace = profile.to_chrome_trace();
+    let events = trace["traceEvents"].as_array().unwrap();
+    let frame_1 = events.iter().find(|e| e["name"] == "frame 1").unwrap();
+    // Second frame starts after the first one, which took 9 stages x 1ms.
+    assert_eq!(frame_1["ts"], 9000.0);
+    assert_eq!(frame_1["dur"], 18000.0);
+    // Only the second frame waited in queue, right before it started.
+    let queue_waits: Vec<_> = events
+        .iter()
+        .filter(|e| e["name"] == "queue_wait")
+        .collect();
+    assert_eq!(queue_waits.len(), 1);
+    assert_eq!(queue_waits[0]["ts"], 7000.0);
+}
@@ -0,0 +1,235 @@
+//! Comparison of two runs over the same input, eg. before/after a model or threshold change.
+//!
+//! Detections of both runs are matched per frame and class by IoU, and reported as
+//! missing (baseline only), new (candidate only) or matched with their confidence delta.
+//! Tracker ids of the two runs are unrelated, so an id switch is counted whenever a baseline
+//! track gets matched to a different candidate track than before.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::detection_logger::DetectionLog;
+
+/// Detection present in both runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchedDetection {
+    pub class_name: String,
+    pub iou: f32,
+    /// Candidate confidence minus baseline confidence.
+    pub confidence_delta: f32,
+    pub baseline_tracker_id: Option<i64>,
+    pub candidate_tracker_id: Option<i64>,
+    /// Whether the baseline track was matched to a different candidate track before.
+    pub id_switch: bool,
+}
+
+/// Differences between the two runs in a single frame.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FrameDiff {
+    pub frame_number: u64,
+    pub matched: Vec<MatchedDetection>,
+    /// Detections only found by the baseline run.
+    pub missing: Vec<DetectionLog>,
+    /// Detections only found by the candidate run.
+    pub new: Vec<DetectionLog>,
+}
+
+impl FrameDiff {
+    pub fn id_switches(&self) -> usize {
+        self.matched.iter().filter(|m| m.id_switch).count()
+    }
+
+    /// Whether anything differs beyond small confidence changes.
+    pub fn has_changes(&self, confidence_tolerance: f32) -> bool {
+        !self.missing.is_empty()
+            || !self.new.is_empty()
+            || self
+                .matched
+                .iter()
+                .any(|m| m.id_switch || m.confidence_delta.abs() > confidence_tolerance)
+    }
+}
+
+/// Per-frame differences between a baseline and a candidate run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunDiff {
+    pub frames: Vec<FrameDiff>,
+}
+
+impl RunDiff {
+    pub fn matched_count(&self) -> usize {
+        self.frames.iter().map(|f| f.matched.len()).sum()
+    }
+
+    pub fn missing_count(&self) -> usize {
+        self.frames.iter().map(|f| f.missing.len()).sum()
+    }
+
+    pub fn new_count(&self) -> usize {
+        self.frames.iter().map(|f| f.new.len()).sum()
+    }
+
+    pub fn id_switches(&self) -> usize {
+        self.frames.iter().map(FrameDiff::id_switches).sum()
+    }
+
+    /// Mean of absolute confidence deltas over matched detections.
+    pub fn mean_abs_confidence_delta(&self) -> f32 {
+        let matched = self.matched_count();
+        if matched == 0 {
+            return 0.;
+        }
+        let sum: f32 = self
+            .frames
+            .iter()
+            .flat_map(|f| &f.matched)
+            .map(|m| m.confidence_delta.abs())
+            .sum();
+        sum / matched as f32
+    }
+}
+
+/// Loads a detections json file, as written by [crate::detection_logger::DetectionLogger::export_json].
+pub fn load_detections(path: &Path) -> anyhow::Result<Vec<DetectionLog>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open detections file {path:?}: {e}"))?;
+    let detections = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| anyhow::anyhow!("Failed to parse detections file {path:?}: {e}"))?;
+    Ok(detections)
+}
+
+fn group_by_frame(detections: &[DetectionLog]) -> BTreeMap<u64, Vec<&DetectionLog>> {
+    let mut frames: BTreeMap<u64, Vec<&DetectionLog>> = BTreeMap::new();
+    for detection in detections {
+        frames
+            .entry(detection.frame_number)
+            .or_default()
+            .push(detection);
+    }
+    frames
+}
+
+/// Compares `candidate` detections against `baseline` ones, frame by frame.
+///
+/// Detections of the same class are matched greedily by highest IoU, pairs below
+/// `iou_threshold` are not considered a match.
+pub fn compare_runs(
+    baseline: &[DetectionLog],
+    candidate: &[DetectionLog],
+    iou_threshold: f32,
+) -> RunDiff {
+    let baseline_frames = group_by_frame(baseline);
+    let mut candidate_frames = group_by_frame(candidate);
+    let mut frame_numbers: Vec<u64> = baseline_frames
+        .keys()
+        .chain(candidate_frames.keys())
+        .copied()
+        .collect();
+    frame_numbers.sort_unstable();
+    frame_numbers.dedup();
+
+    // Baseline track id -> candidate track id it was last matched to.
+    let mut track_mapping: HashMap<i64, i64> = HashMap::new();
+    let mut diff = RunDiff::default();
+
+    for frame_number in frame_numbers {
+        let base = baseline_frames
+            .get(&frame_number)
+            .cloned()
+            .unwrap_or_default();
+        let cand = candidate_frames.remove(&frame_number).unwrap_or_default();
+
+        let mut pairs: Vec<(usize, usize, f32)> = vec![];
+        for (bi, b) in base.iter().enumerate() {
+            for (ci, c) in cand.iter().enumerate() {
+                if b.class_name != c.class_name {
+                    continue;
+                }
+                let iou = b.bbox.iou(&c.bbox);
+                if iou >= iou_threshold {
+                    pairs.push((bi, ci, iou));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        let mut frame_diff = FrameDiff {
+            frame_number,
+            ..Default::default()
+        };
+        let mut base_used = vec![false; base.len()];
+        let mut cand_used = vec![false; cand.len()];
+        for (bi, ci, iou) in pairs {
+            if base_used[bi] || cand_used[ci] {
+                continue;
+            }
+            base_used[bi] = true;
+            cand_used[ci] = true;
+            let (b, c) = (base[bi], cand[ci]);
+
+            let id_switch = match (b.tracker_id, c.tracker_id) {
+                (Some(base_id), Some(cand_id)) => track_mapping
+                    .insert(base_id, cand_id)
+                    .is_some_and(|previous| previous != cand_id),
+                _ => false,
+            };
+            frame_diff.matched.push(MatchedDetection {
+                class_name: b.class_name.clone(),
+                iou,
+                confidence_delta: c.confidence - b.confidence,
+                baseline_tracker_id: b.tracker_id,
+                candidate_tracker_id: c.tracker_id,
+                id_switch,
+            });
+        }
+
+        frame_diff.missing = base
+            .iter()
+            .zip(&base_used)
+            .filter(|(_, used)| !**used)
+            .map(|(d, _)| (*d).clone())
+            .collect();
+        frame_diff.new = cand
+            .iter()
+            .zip(&cand_used)
+            .filter(|(_, used)| !**used)
+            .map(|(d, _)| (*d).clone())
+            .collect();
+        diff.frames.push(frame_diff);
+    }
+
+    diff
+}
+
+#[test]
+fn compare_matches_by_iou_and_counts_id_switches() {
+    let detection = |frame_number: u64, x: f32, confidence: f32, tracker_id: i64| DetectionLog {
+        object_id: format!("{tracker_id}"),
+        tracker_id: Some(tracker_id),
+        confidence,
+        ..DetectionLog::test(frame_number, x, 0., x + 100., 100.)
+    };
+
+    let baseline = vec![
+        detection(0, 0., 0.8, 1),
+        detection(0, 300., 0.5, 2),
+        detection(1, 2., 0.8, 1),
+    ];
+    let candidate = vec![
+        detection(0, 5., 0.9, 10),
+        detection(1, 0., 0.7, 11),
+        detection(1, 600., 0.6, 12),
+    ];
+
+    let diff = compare_runs(&baseline, &candidate, 0.5);
+    assert_eq!(diff.frames.len(), 2);
+    assert_eq!(diff.matched_count(), 2);
+    assert_eq!(diff.missing_count(), 1);
+    assert_eq!(diff.new_count(), 1);
+    // Baseline track 1 was matched to candidate track 10, then 11.
+    assert_eq!(diff.id_switches(), 1);
+    assert!((diff.frames[0].matched[0].confidence_delta - 0.1).abs() < 1e-6);
+    assert!((diff.mean_abs_confidence_delta() - 0.1).abs() < 1e-6);
+}
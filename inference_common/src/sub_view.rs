@@ -0,0 +1,167 @@
+//! Splitting frames of multi-imager or 360 cameras into sub-views,
+//! each processed as its own virtual camera (separate tracking and detection logs).
+
+use std::str::FromStr;
+
+use image::RgbImage;
+
+/// Rectangular region of a frame, processed as a virtual camera.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubView {
+    /// Used in output file names, eg. `input.<name>.detections.json`.
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SubView {
+    /// Copies this view's region out of `frame`.
+    pub fn crop(&self, frame: &RgbImage) -> RgbImage {
+        image::imageops::crop_imm(frame, self.x, self.y, self.width, self.height).to_image()
+    }
+
+    /// Writes `view_image` (eg. annotated [SubView::crop] output) back into this view's region of `frame`.
+    pub fn paste(&self, frame: &mut RgbImage, view_image: &RgbImage) {
+        image::imageops::replace(frame, view_image, self.x as i64, self.y as i64);
+    }
+}
+
+/// How frames are split into views.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ViewLayout {
+    /// The whole frame is a single view (no splitting).
+    #[default]
+    Whole,
+    /// Equally sized `columns` x `rows` grid, eg. `2x1` for side by side dual imagers,
+    /// or `4x1` to cut an equirectangular 360 frame into quarters.
+    Grid { columns: u32, rows: u32 },
+    /// Explicit regions.
+    Custom(Vec<SubView>),
+}
+
+impl ViewLayout {
+    /// Resolves the layout into views of a `width` x `height` frame,
+    /// empty for [ViewLayout::Whole].
+    pub fn views(&self, width: u32, height: u32) -> anyhow::Result<Vec<SubView>> {
+        match self {
+            ViewLayout::Whole => Ok(Vec::new()),
+            ViewLayout::Grid { columns, rows } => {
+                let (view_w, view_h) = (width / columns, height / rows);
+                if view_w == 0 || view_h == 0 {
+                    anyhow::bail!(
+                        "Can't split {width}x{height} frames into a {columns}x{rows} grid"
+                    );
+                }
+                let mut views = Vec::new();
+                for row in 0..*rows {
+                    for column in 0..*columns {
+                        views.push(SubView {
+                            name: format!("view{}", views.len()),
+                            x: column * view_w,
+                            y: row * view_h,
+                            width: view_w,
+                            height: view_h,
+                        });
+                    }
+                }
+                Ok(views)
+            }
+            ViewLayout::Custom(views) => {
+                for view in views {
+                    if view.width == 0
+                        || view.height == 0
+                        || u64::from(view.x) + u64::from(view.width) > u64::from(width)
+                        || u64::from(view.y) + u64::from(view.height) > u64::from(height)
+                    {
+                        anyhow::bail!("View {view:?} is not within the {width}x{height} frame");
+                    }
+                }
+                Ok(views.clone())
+            }
+        }
+    }
+}
+
+impl FromStr for ViewLayout {
+    type Err = String;
+
+    /// Parses a `COLUMNSxROWS` grid, or `;` separated `NAME=X,Y,WIDTH,HEIGHT` regions.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains('=') {
+            let grid = s.split_once('x').and_then(|(columns, rows)| {
+                Some((columns.trim().parse().ok()?, rows.trim().parse().ok()?))
+            });
+            return match grid {
+                Some((columns, rows)) if columns > 0 && rows > 0 => {
+                    Ok(ViewLayout::Grid { columns, rows })
+                }
+                _ => Err(format!(
+                    "expected COLUMNSxROWS or NAME=X,Y,W,H[;...], got {s:?}"
+                )),
+            };
+        }
+
+        let views = s
+            .split(';')
+            .filter(|view| !view.trim().is_empty())
+            .map(|view| {
+                let (name, rect) = view.split_once('=').unwrap_or(("", view));
+                let rect = rect
+                    .split(',')
+                    .map(|v| v.trim().parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>();
+                match (name.trim(), rect.as_deref()) {
+                    (name, Ok(&[x, y, width, height])) if !name.is_empty() => Ok(SubView {
+                        name: name.to_string(),
+                        x,
+                        y,
+                        width,
+                        height,
+                    }),
+                    _ => Err(format!("expected NAME=X,Y,W,H, got {view:?}")),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut names: Vec<&str> = views.iter().map(|v| v.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        if names.len() != views.len() {
+            return Err(format!("view names must be unique: {s:?}"));
+        }
+        Ok(ViewLayout::Custom(views))
+    }
+}
+
+#[test]
+fn parse_and_resolve_layouts() {
+    let grid: ViewLayout = "2x1".parse().unwrap();
+    let views = grid.views(3840, 1080).unwrap();
+    assert_eq!(views.len(), 2);
+    assert_eq!(
+        (views[1].name.as_str(), views[1].x, views[1].width),
+        ("view1", 1920, 1920)
+    );
+
+    let custom: ViewLayout = "front=0,0,100,50; back=100,0,100,50".parse().unwrap();
+    let views = custom.views(200, 50).unwrap();
+    assert_eq!(views[1].name, "back");
+    assert!(custom.views(150, 50).is_err());
+    let overflowing: ViewLayout = "a=4294967295,0,1,1".parse().unwrap();
+    assert!(overflowing.views(200, 50).is_err());
+
+    assert!("0x2".parse::<ViewLayout>().is_err());
+    assert!("a=0,0,1,1;a=1,1,1,1".parse::<ViewLayout>().is_err());
+    assert!("a=0,0,1".parse::<ViewLayout>().is_err());
+
+    // Crop + paste round trips.
+    let mut frame = RgbImage::from_fn(4, 2, |x, y| image::Rgb([x as u8, y as u8, 0]));
+    let view = &grid.views(4, 2).unwrap()[1];
+    let crop = view.crop(&frame);
+    assert_eq!(crop.get_pixel(0, 1).0, [2, 1, 0]);
+    let before = frame.clone();
+    view.paste(&mut frame, &crop);
+    assert_eq!(frame, before);
+}
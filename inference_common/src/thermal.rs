@@ -0,0 +1,360 @@
+//! Thermal and power throttling of embedded devices (eg. Jetson or Raspberry Pi), which slow down
+//! or shut off when running inference at full rate in a hot enclosure.
+//!
+//! Temperatures are read from `/sys/class/thermal/thermal_zone*/temp` (millidegrees), power draw
+//! from hwmon `power*_input` sensors (µW), or voltage and current sensors, as on the INA3221 of
+//! Jetsons. Throttling starts above a limit and only stops once readings are back below it with
+//! some margin, so it doesn't flap around the limit.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Throttling stops once the temperature is this many degrees below its limit...
+const TEMP_HYSTERESIS_C: f32 = 5.0;
+/// ... and the power draw this fraction below its limit.
+const POWER_HYSTERESIS: f32 = 0.1;
+/// Sensors are read at most this often.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Label of the rail powering the whole Jetson module, its INA3221 also measures the CPU, GPU and
+/// SoC rails drawing from it.
+const INPUT_RAIL: &str = "VDD_IN";
+
+/// Sensor readings, `None` for sensors the device doesn't have.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThermalReading {
+    /// Hottest thermal zone.
+    pub temp_c: Option<f32>,
+    /// The input rail if the device has one (see [INPUT_RAIL]), otherwise the total of all power
+    /// sensors.
+    pub power_w: Option<f32>,
+}
+
+impl ThermalReading {
+    /// Reads the sensors under the sysfs mount `sys` (`/sys` outside of tests), the power draw
+    /// from the `power_sensor` file (in µW) instead if set.
+    pub fn read(sys: &Path, power_sensor: Option<&Path>) -> Self {
+        let temps = sensor_values(&sys.join("class/thermal"), "thermal_zone", |name| {
+            name == "temp"
+        });
+        let microwatts = match power_sensor {
+            Some(path) => read_value(path),
+            None => {
+                let rails = power_rails(&sys.join("class/hwmon"));
+                match rails
+                    .iter()
+                    .find(|(label, _)| label.as_deref() == Some(INPUT_RAIL))
+                {
+                    Some((_, microwatts)) => Some(*microwatts),
+                    // Without an input rail, rails are taken to be separate.
+                    None => rails
+                        .into_iter()
+                        .map(|(_, microwatts)| microwatts)
+                        .reduce(|a, b| a + b),
+                }
+            }
+        };
+        Self {
+            temp_c: temps
+                .into_iter()
+                .map(|millidegrees| millidegrees as f32 / 1000.)
+                .reduce(f32::max),
+            power_w: microwatts.map(|microwatts| microwatts as f32 / 1e6),
+        }
+    }
+}
+
+impl fmt::Display for ThermalReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.temp_c {
+            Some(temp) => write!(f, "{temp:.1}°C")?,
+            None => write!(f, "unknown temperature")?,
+        }
+        if let Some(power) = self.power_w {
+            write!(f, ", {power:.1} W")?;
+        }
+        Ok(())
+    }
+}
+
+/// Integer values of the files matching `is_sensor` in the `prefix*` directories of `class_dir`.
+fn sensor_values(class_dir: &Path, prefix: &str, is_sensor: impl Fn(&str) -> bool) -> Vec<i64> {
+    let Ok(devices) = std::fs::read_dir(class_dir) else {
+        return Vec::new();
+    };
+    let mut values = Vec::new();
+    for device in devices.flatten() {
+        if !device.file_name().to_string_lossy().starts_with(prefix) {
+            continue;
+        }
+        let Ok(files) = std::fs::read_dir(device.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            if !is_sensor(&file.file_name().to_string_lossy()) {
+                continue;
+            }
+            // Zones of powered down devices fail to read, skip them.
+            if let Some(value) = read_value(&file.path()) {
+                values.push(value);
+            }
+        }
+    }
+    values
+}
+
+/// Power draw (µW) and label of the channels of the `hwmon*` devices in `class_dir`, from their
+/// `power*_input` sensors, or from their voltage (mV) and current (mA) for labelled channels
+/// without one.
+fn power_rails(class_dir: &Path) -> Vec<(Option<String>, i64)> {
+    let Ok(devices) = std::fs::read_dir(class_dir) else {
+        return Vec::new();
+    };
+    let mut rails = Vec::new();
+    for device in devices.flatten() {
+        if !device.file_name().to_string_lossy().starts_with("hwmon") {
+            continue;
+        }
+        let dir = device.path();
+        let Ok(files) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let label = |kind: &str, channel: &str| {
+            std::fs::read_to_string(dir.join(format!("{kind}{channel}_label")))
+                .ok()
+                .map(|label| label.trim().to_string())
+        };
+        for file in files.flatten() {
+            let name = file.file_name().to_string_lossy().into_owned();
+            if let Some(channel) = sensor_channel(&name, "power", "_input") {
+                if let Some(microwatts) = read_value(&file.path()) {
+                    let label = label("power", channel).or_else(|| label("in", channel));
+                    rails.push((label, microwatts));
+                }
+            } else if let Some(channel) = sensor_channel(&name, "in", "_label") {
+                if dir.join(format!("power{channel}_input")).exists() {
+                    continue;
+                }
+                let millivolts = read_value(&dir.join(format!("in{channel}_input")));
+                let milliamps = read_value(&dir.join(format!("curr{channel}_input")));
+                if let (Some(millivolts), Some(milliamps)) = (millivolts, milliamps) {
+                    rails.push((label("in", channel), millivolts * milliamps));
+                }
+            }
+        }
+    }
+    rails
+}
+
+/// Channel number of hwmon sensor file `name`, eg. `1` of `power1_input`.
+fn sensor_channel<'a>(name: &'a str, kind: &str, suffix: &str) -> Option<&'a str> {
+    let channel = name.strip_prefix(kind)?.strip_suffix(suffix)?;
+    (!channel.is_empty() && channel.bytes().all(|b| b.is_ascii_digit())).then_some(channel)
+}
+
+fn read_value(path: &Path) -> Option<i64> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Readings above which to throttle, at least one of them is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalLimits {
+    pub max_temp_c: Option<f32>,
+    pub max_power_w: Option<f32>,
+}
+
+impl ThermalLimits {
+    fn exceeded(&self, reading: &ThermalReading) -> bool {
+        let hot =
+            matches!((reading.temp_c, self.max_temp_c), (Some(temp), Some(max)) if temp > max);
+        let power =
+            matches!((reading.power_w, self.max_power_w), (Some(power), Some(max)) if power > max);
+        hot || power
+    }
+
+    fn recovered(&self, reading: &ThermalReading) -> bool {
+        let cool = match (reading.temp_c, self.max_temp_c) {
+            (Some(temp), Some(max)) => temp < max - TEMP_HYSTERESIS_C,
+            _ => true,
+        };
+        let power = match (reading.power_w, self.max_power_w) {
+            (Some(power), Some(max)) => power < max * (1. - POWER_HYSTERESIS),
+            _ => true,
+        };
+        cool && power
+    }
+}
+
+/// Throttles while sensor readings are over their [ThermalLimits].
+#[derive(Debug)]
+pub struct ThermalThrottle {
+    limits: ThermalLimits,
+    power_sensor: Option<PathBuf>,
+    last_reading: ThermalReading,
+    last_poll: Option<Instant>,
+    throttled_since: Option<Instant>,
+    /// Throttled time of finished throttle periods.
+    throttled_for: Duration,
+    throttle_count: u32,
+}
+
+impl ThermalThrottle {
+    pub fn new(limits: ThermalLimits) -> Self {
+        Self {
+            limits,
+            power_sensor: None,
+            last_reading: ThermalReading::default(),
+            last_poll: None,
+            throttled_since: None,
+            throttled_for: Duration::ZERO,
+            throttle_count: 0,
+        }
+    }
+
+    /// Reads the power draw from the `path` file (in µW), eg. a single rail of a device without
+    /// an input rail.
+    pub fn with_power_sensor(mut self, path: PathBuf) -> Self {
+        self.power_sensor = Some(path);
+        self
+    }
+
+    /// Whether to throttle now, reading the sensors of the device if they're due.
+    pub fn poll(&mut self) -> bool {
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_none_or(|last_poll| now - last_poll >= POLL_INTERVAL)
+        {
+            self.last_poll = Some(now);
+            let reading = ThermalReading::read(Path::new("/sys"), self.power_sensor.as_deref());
+            self.update(reading, now);
+        }
+        self.is_throttled()
+    }
+
+    /// Updates the throttle state with a `reading` taken at `now`, logging changes.
+    pub fn update(&mut self, reading: ThermalReading, now: Instant) {
+        self.last_reading = reading;
+        match self.throttled_since {
+            None if self.limits.exceeded(&reading) => {
+                log::warn!("Throttling, {reading} is over the limits");
+                self.throttled_since = Some(now);
+                self.throttle_count += 1;
+            }
+            Some(since) if self.limits.recovered(&reading) => {
+                let throttled = now - since;
+                log::info!("Stopped throttling after {throttled:?}, back to {reading}");
+                self.throttled_since = None;
+                self.throttled_for += throttled;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.throttled_since.is_some()
+    }
+
+    /// Latest sensor reading.
+    pub fn reading(&self) -> ThermalReading {
+        self.last_reading
+    }
+
+    /// Logs how often and long processing was throttled.
+    pub fn log_summary(&self) {
+        let ongoing = self
+            .throttled_since
+            .map_or(Duration::ZERO, |since| since.elapsed());
+        log::info!(
+            "Throttled {} times for {:?} in total, last reading {}",
+            self.throttle_count,
+            self.throttled_for + ongoing,
+            self.last_reading
+        );
+    }
+}
+
+#[test]
+fn reads_sysfs_sensors() {
+    let sys = std::env::temp_dir().join(format!("thermal-{}", std::process::id()));
+    for (file, value) in [
+        ("class/thermal/thermal_zone0/temp", "45000\n"),
+        ("class/thermal/thermal_zone1/temp", "61500\n"),
+        ("class/thermal/thermal_zone1/type", "GPU-therm\n"),
+        ("class/hwmon/hwmon0/power1_input", "4200000\n"),
+        ("class/hwmon/hwmon0/power2_input", "1800000\n"),
+        ("class/hwmon/hwmon0/curr1_input", "500\n"),
+    ] {
+        let path = sys.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, value).unwrap();
+    }
+    let reading = ThermalReading::read(&sys, None);
+    std::fs::remove_dir_all(&sys).unwrap();
+    assert_eq!(reading.temp_c, Some(61.5));
+    assert!((reading.power_w.unwrap() - 6.0).abs() < 1e-4);
+
+    let reading = ThermalReading::read(&sys, None);
+    assert_eq!(reading, ThermalReading::default());
+}
+
+#[test]
+fn reads_the_input_rail() {
+    let sys = std::env::temp_dir().join(format!("thermal-rails-{}", std::process::id()));
+    // Jetson Orin INA3221, the CPU/GPU and SoC rails are part of VDD_IN.
+    for (file, value) in [
+        ("class/hwmon/hwmon1/in1_label", "VDD_IN\n"),
+        ("class/hwmon/hwmon1/in1_input", "5000\n"),
+        ("class/hwmon/hwmon1/curr1_input", "1500\n"),
+        ("class/hwmon/hwmon1/in2_label", "VDD_CPU_GPU_CV\n"),
+        ("class/hwmon/hwmon1/in2_input", "5000\n"),
+        ("class/hwmon/hwmon1/curr2_input", "600\n"),
+        ("class/hwmon/hwmon1/in3_label", "VDD_SOC\n"),
+        ("class/hwmon/hwmon1/in3_input", "5000\n"),
+        ("class/hwmon/hwmon1/curr3_input", "400\n"),
+        ("rail", "2000000\n"),
+    ] {
+        let path = sys.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, value).unwrap();
+    }
+    let input = ThermalReading::read(&sys, None);
+    let configured = ThermalReading::read(&sys, Some(&sys.join("rail")));
+    std::fs::remove_dir_all(&sys).unwrap();
+    assert!((input.power_w.unwrap() - 7.5).abs() < 1e-4);
+    assert!((configured.power_w.unwrap() - 2.0).abs() < 1e-4);
+}
+
+#[test]
+fn throttles_with_hysteresis() {
+    let mut throttle = ThermalThrottle::new(ThermalLimits {
+        max_temp_c: Some(80.),
+        max_power_w: Some(10.),
+    });
+    let start = Instant::now();
+    let reading = |temp, power| ThermalReading {
+        temp_c: Some(temp),
+        power_w: Some(power),
+    };
+
+    throttle.update(reading(79., 8.), start);
+    assert!(!throttle.is_throttled());
+    throttle.update(reading(81., 8.), start);
+    assert!(throttle.is_throttled());
+    // Below the limit, but not by the margin yet.
+    throttle.update(reading(77., 8.), start + Duration::from_secs(10));
+    assert!(throttle.is_throttled());
+    throttle.update(reading(74., 8.), start + Duration::from_secs(20));
+    assert!(!throttle.is_throttled());
+    assert_eq!(throttle.throttled_for, Duration::from_secs(20));
+
+    throttle.update(reading(60., 11.), start + Duration::from_secs(30));
+    assert!(throttle.is_throttled());
+    throttle.update(reading(60., 9.5), start + Duration::from_secs(40));
+    assert!(throttle.is_throttled());
+    throttle.update(reading(60., 8.5), start + Duration::from_secs(50));
+    assert!(!throttle.is_throttled());
+    assert_eq!(throttle.throttle_count, 2);
+}
@@ -3,8 +3,10 @@
 use std::sync::Mutex;
 
 use crate::bbox::{BBoxesByClass, Bbox};
+use crate::botsort::{BotSort, BotSortParams};
 use crate::coco_classes;
 use crate::img_dimensions::ImgDimensions;
+use crate::ocsort::{OcSort, OcSortParams};
 use similari::prelude::PositionalMetricType::IoU;
 use similari::prelude::{SortTrack, Universal2DBox};
 use similari::trackers::sort::metric::DEFAULT_MINIMAL_SORT_CONFIDENCE;
@@ -13,9 +15,104 @@ use similari::{prelude::Sort, trackers::sort::DEFAULT_SORT_IOU_THRESHOLD};
 // `similari` re-export so types can be named etc.
 pub use similari;
 
+/// 2x3 affine transform mapping points of the previous frame into the current one,
+/// describing camera motion (pan, zoom, shake).
+pub type CameraMotion = [[f32; 3]; 2];
+
+/// Multi-object tracker, associates detections of consecutive frames into tracks.
+pub trait Tracker: Send {
+    /// Short name of the algorithm, eg. for logs and benchmark output.
+    fn name(&self) -> &'static str;
+
+    /// Feeds detections of the next frame into the tracker,
+    /// returning tracked bboxes (with `tracker_id` set) clamped to `scaled_dims`.
+    fn update(&mut self, bboxes_per_class: &[Vec<Bbox>], scaled_dims: ImgDimensions) -> Vec<Bbox>;
+
+    /// Camera motion since the previous frame, applied to track predictions on the next [Tracker::update].
+    ///
+    /// Ignored by trackers without camera motion compensation.
+    fn set_camera_motion(&mut self, _warp: CameraMotion) {}
+
+    /// Also report tracks without a detection in the frame while they're kept alive, as predicted
+    /// boxes (see [TrackInfo::predicted](crate::bbox::TrackInfo::predicted)).
+    ///
+    /// Ignored by SORT, similari only returns the tracks matched in the frame.
+    fn set_report_predicted(&mut self, _enabled: bool) {}
+}
+
+/// Available [Tracker] implementations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrackerKind {
+    #[default]
+    Sort,
+    OcSort,
+    BotSort,
+}
+
+impl TrackerKind {
+    pub const ALL: [TrackerKind; 3] =
+        [TrackerKind::Sort, TrackerKind::OcSort, TrackerKind::BotSort];
+
+    /// Whether tracks (and their ids) are the same on every run over the same detections.
+    ///
+    /// SORT runs in similari, which numbers match candidates with `rand::thread_rng()`: they
+    /// decide the order of the assignment problem, and so which track wins a tie.
+    pub fn is_reproducible(self) -> bool {
+        self != TrackerKind::Sort
+    }
+
+    /// Tracker to use if none is chosen, a reproducible one for `--deterministic` runs.
+    pub fn default_for(deterministic: bool) -> Self {
+        if deterministic {
+            TrackerKind::OcSort
+        } else {
+            TrackerKind::default()
+        }
+    }
+}
+
+impl std::str::FromStr for TrackerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "sort" => Ok(TrackerKind::Sort),
+            "ocsort" => Ok(TrackerKind::OcSort),
+            "botsort" => Ok(TrackerKind::BotSort),
+            _ => Err(format!(
+                "unknown tracker {s:?}, expected one of: sort, ocsort, botsort"
+            )),
+        }
+    }
+}
+
+/// Creates a tracker of the given kind, with default params.
+pub fn new_tracker(kind: TrackerKind) -> Box<dyn Tracker> {
+    match kind {
+        TrackerKind::Sort => Box::new(new_sort()),
+        TrackerKind::OcSort => Box::new(OcSort::new(OcSortParams::default())),
+        TrackerKind::BotSort => Box::new(BotSort::new(BotSortParams::default())),
+    }
+}
+
+/// Creates a tracker of the given kind, ready to be shared between pipeline callbacks.
+pub fn shared_tracker(kind: TrackerKind) -> Mutex<Box<dyn Tracker>> {
+    Mutex::new(new_tracker(kind))
+}
+
+impl Tracker for Sort {
+    fn name(&self) -> &'static str {
+        "sort"
+    }
+
+    fn update(&mut self, bboxes_per_class: &[Vec<Bbox>], scaled_dims: ImgDimensions) -> Vec<Bbox> {
+        predict_tracked_bboxes(self, scaled_dims, bboxes_per_class)
+    }
+}
+
 /// Creates a SORT tracker with some default params, largely untuned.
-pub fn sort_tracker() -> Mutex<Sort> {
-    Mutex::new(Sort::new(
+pub fn new_sort() -> Sort {
+    Sort::new(
         1,
         1,
         10,
@@ -24,7 +121,23 @@ pub fn sort_tracker() -> Mutex<Sort> {
         None,
         1.0 / 20.0,
         1.0 / 160.0,
-    ))
+    )
+}
+
+/// Creates the default tracker (SORT), ready to be shared between pipeline callbacks.
+pub fn sort_tracker() -> Mutex<Box<dyn Tracker>> {
+    shared_tracker(TrackerKind::Sort)
+}
+
+/// Clamps `bbox` to lie within `scaled_dims`.
+pub fn clamp_bbox(bbox: Bbox, scaled_dims: ImgDimensions) -> Bbox {
+    Bbox {
+        xmin: bbox.xmin.max(0.0f32).min(scaled_dims.width),
+        ymin: bbox.ymin.max(0.0f32).min(scaled_dims.height),
+        xmax: bbox.xmax.max(0.0f32).min(scaled_dims.width),
+        ymax: bbox.ymax.max(0.0f32).min(scaled_dims.height),
+        ..bbox
+    }
 }
 
 /// Maps from [SortTrack] back to our [Bbox].
@@ -48,11 +161,11 @@ pub fn tracks_to_bboxes(tracks: &[SortTrack], scaled_dims: ImgDimensions) -> Vec
         let xmax = xmin + w;
         let ymax = ymin + h;
 
-        out.push(Bbox {
-            xmin: xmin.max(0.0f32).min(scaled_dims.width),
-            ymin: ymin.max(0.0f32).min(scaled_dims.height),
-            xmax: xmax.max(0.0f32).min(scaled_dims.width),
-            ymax: ymax.max(0.0f32).min(scaled_dims.height),
+        let bbox = Bbox {
+            xmin,
+            ymin,
+            xmax,
+            ymax,
             // FIXME this, unfortunately, does not retain og yolo confidence...
             detector_confidence: track.observed_bbox.confidence,
             // FIXME tracker confidence is always very high?
@@ -61,7 +174,9 @@ pub fn tracks_to_bboxes(tracks: &[SortTrack], scaled_dims: ImgDimensions) -> Vec
             data: vec![],
             class: class_id as usize,
             tracker_id: Some(id as i64),
-        });
+            track: None,
+        };
+        out.push(clamp_bbox(bbox, scaled_dims));
     }
     out
 }
@@ -109,3 +224,31 @@ pub fn unflatten_bboxes(flat_bboxes: Vec<Bbox>) -> BBoxesByClass {
     }
     bboxes_by_class
 }
+
+#[test]
+fn deterministic_default_is_reproducible() {
+    assert_eq!(TrackerKind::default_for(false), TrackerKind::Sort);
+    assert!(TrackerKind::default_for(true).is_reproducible());
+
+    let detections = |frame: usize| -> Vec<Vec<Bbox>> {
+        // Two objects crossing, so association has to pick between close candidates.
+        let x = [10.0 + frame as f32 * 6.0, 130.0 - frame as f32 * 6.0];
+        vec![x
+            .iter()
+            .map(|&x| Bbox::test(x, 10.0, x + 30.0, 60.0))
+            .collect()]
+    };
+    let dims = ImgDimensions::new(640.0, 384.0);
+    for kind in TrackerKind::ALL
+        .into_iter()
+        .filter(|kind| kind.is_reproducible())
+    {
+        let run = || {
+            let mut tracker = new_tracker(kind);
+            (0..20)
+                .map(|frame| tracker.update(&detections(frame), dims))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run(), "{kind:?}");
+    }
+}
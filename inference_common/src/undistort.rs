@@ -0,0 +1,177 @@
+//! Lens undistortion for wide-angle and fisheye cameras, applied to frames before inference.
+//!
+//! Straight lines stay straight after undistortion, so boxes (and any geometry drawn on the frame)
+//! are no longer skewed towards the image edges.
+
+use std::str::FromStr;
+
+use image::RgbImage;
+use rayon::prelude::*;
+
+/// Lens distortion of a camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LensModel {
+    /// Brown-Conrady radial distortion, with coordinates normalized by half of the image diagonal.
+    /// Barrel distortion (typical of wide-angle lenses) has a negative `k1`.
+    Radial { k1: f32, k2: f32 },
+    /// Equidistant fisheye covering `fov_degrees` across the shorter image side,
+    /// dewarped into a rectilinear view of at most 120 degrees.
+    Fisheye { fov_degrees: f32 },
+}
+
+/// Widest rectilinear view produced from a fisheye, wider views stretch the edges too much.
+const MAX_RECTILINEAR_HALF_FOV: f32 = std::f32::consts::FRAC_PI_3;
+
+impl FromStr for LensModel {
+    type Err = String;
+
+    /// Parses `radial:K1[,K2]` or `fisheye:FOV_DEGREES`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, params) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected radial:K1[,K2] or fisheye:FOV, got {s:?}"))?;
+        let params = params
+            .split(',')
+            .map(|p| p.trim().parse::<f32>().map_err(|e| format!("{p:?}: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        match (kind, params.as_slice()) {
+            ("radial", [k1]) => Ok(LensModel::Radial { k1: *k1, k2: 0.0 }),
+            ("radial", [k1, k2]) => Ok(LensModel::Radial { k1: *k1, k2: *k2 }),
+            ("fisheye", [fov]) if *fov > 0.0 && *fov <= 360.0 => {
+                Ok(LensModel::Fisheye { fov_degrees: *fov })
+            }
+            _ => Err(format!(
+                "expected radial:K1[,K2] or fisheye:FOV (0-360 degrees), got {s:?}"
+            )),
+        }
+    }
+}
+
+impl LensModel {
+    /// Point of the distorted `width` x `height` source image that ends up at `(x, y)` after undistortion.
+    fn source_point(&self, width: f32, height: f32, x: f32, y: f32) -> [f32; 2] {
+        let (cx, cy) = (width / 2.0, height / 2.0);
+        let (dx, dy) = (x - cx, y - cy);
+        let scale = match *self {
+            LensModel::Radial { k1, k2 } => {
+                let r2 = (dx * dx + dy * dy) / (cx * cx + cy * cy);
+                1.0 + k1 * r2 + k2 * r2 * r2
+            }
+            LensModel::Fisheye { fov_degrees } => {
+                let half_side = cx.min(cy);
+                let half_fov = fov_degrees.to_radians() / 2.0;
+                let fisheye_focal = half_side / half_fov;
+                let rectilinear_focal = half_side / half_fov.min(MAX_RECTILINEAR_HALF_FOV).tan();
+                let r = (dx * dx + dy * dy).sqrt();
+                if r < 1e-6 {
+                    fisheye_focal / rectilinear_focal
+                } else {
+                    fisheye_focal * (r / rectilinear_focal).atan() / r
+                }
+            }
+        };
+        [cx + dx * scale, cy + dy * scale]
+    }
+}
+
+/// Undistorts frames of a stream, caching the per-pixel source map for the current frame size.
+#[derive(Debug)]
+pub struct Undistorter {
+    lens: LensModel,
+    dims: (u32, u32),
+    /// Source point of every output pixel, row major.
+    map: Vec<[f32; 2]>,
+}
+
+impl Undistorter {
+    pub fn new(lens: LensModel) -> Self {
+        Self {
+            lens,
+            dims: (0, 0),
+            map: Vec::new(),
+        }
+    }
+
+    /// Returns the undistorted `image`, areas outside of the source are black.
+    pub fn apply(&mut self, image: &RgbImage) -> RgbImage {
+        let (width, height) = image.dimensions();
+        if self.dims != (width, height) {
+            self.dims = (width, height);
+            self.map = (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| {
+                    self.lens.source_point(
+                        width as f32,
+                        height as f32,
+                        x as f32 + 0.5,
+                        y as f32 + 0.5,
+                    )
+                })
+                .collect();
+        }
+
+        let mut out = vec![0u8; width as usize * height as usize * 3];
+        out.par_chunks_exact_mut(width as usize * 3)
+            .zip(self.map.par_chunks_exact(width as usize))
+            .for_each(|(row, sources)| {
+                for (pixel, &[sx, sy]) in row.chunks_exact_mut(3).zip(sources) {
+                    pixel.copy_from_slice(&sample_bilinear(image, sx - 0.5, sy - 0.5));
+                }
+            });
+        RgbImage::from_raw(width, height, out).expect("undistorted buffer matches its dimensions")
+    }
+}
+
+/// Bilinearly interpolated pixel at `(x, y)` (pixel centers at integer coords), black outside of `image`.
+fn sample_bilinear(image: &RgbImage, x: f32, y: f32) -> [u8; 3] {
+    let (width, height) = image.dimensions();
+    if x < -0.5 || y < -0.5 || x > width as f32 - 0.5 || y > height as f32 - 0.5 {
+        return [0; 3];
+    }
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |px: f32, py: f32| {
+        let px = (px.max(0.0) as u32).min(width - 1);
+        let py = (py.max(0.0) as u32).min(height - 1);
+        image.get_pixel(px, py).0
+    };
+    let (tl, tr) = (pixel(x0, y0), pixel(x0 + 1.0, y0));
+    let (bl, br) = (pixel(x0, y0 + 1.0), pixel(x0 + 1.0, y0 + 1.0));
+    std::array::from_fn(|c| {
+        let top = tl[c] as f32 * (1.0 - fx) + tr[c] as f32 * fx;
+        let bottom = bl[c] as f32 * (1.0 - fx) + br[c] as f32 * fx;
+        (top * (1.0 - fy) + bottom * fy).round() as u8
+    })
+}
+
+#[test]
+fn lens_models() {
+    assert_eq!(
+        "radial:-0.25,0.05".parse(),
+        Ok(LensModel::Radial {
+            k1: -0.25,
+            k2: 0.05
+        })
+    );
+    assert_eq!(
+        "fisheye:180".parse(),
+        Ok(LensModel::Fisheye { fov_degrees: 180.0 })
+    );
+    assert!("fisheye:0".parse::<LensModel>().is_err());
+    assert!("pinhole:1".parse::<LensModel>().is_err());
+
+    // Without distortion, frames are unchanged.
+    let image = RgbImage::from_fn(8, 6, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 7]));
+    let mut identity = Undistorter::new(LensModel::Radial { k1: 0.0, k2: 0.0 });
+    assert_eq!(identity.apply(&image), image);
+
+    // Barrel distortion compresses the edges towards the center, so undistortion samples closer to it.
+    let barrel = LensModel::Radial { k1: -0.2, k2: 0.0 };
+    let [x, _] = barrel.source_point(100.0, 100.0, 90.0, 50.0);
+    assert!(x < 90.0);
+    // The fisheye center stays put, and points map inside of the fisheye circle.
+    let fisheye = LensModel::Fisheye { fov_degrees: 180.0 };
+    assert_eq!(fisheye.source_point(100.0, 100.0, 50.0, 50.0), [50.0, 50.0]);
+    let [x, y] = fisheye.source_point(100.0, 100.0, 0.0, 0.0);
+    assert!(((x - 50.0).powi(2) + (y - 50.0).powi(2)).sqrt() < 50.0);
+}
@@ -0,0 +1,119 @@
+//! Formatting wall clock times (ms since the unix epoch) for a site's local time.
+//!
+//! Only fixed UTC offsets are supported, there's no timezone database: set the offset a site is
+//! at (and adjust it for daylight saving time) rather than a zone name.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Fixed offset from UTC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UtcOffset {
+    minutes: i32,
+}
+
+impl UtcOffset {
+    pub const UTC: UtcOffset = UtcOffset { minutes: 0 };
+
+    /// `ms` since the unix epoch as an RFC 3339 timestamp with this offset,
+    /// eg. `2024-03-01T17:30:00.250+05:30` (or `...Z` for UTC).
+    pub fn format(self, ms: u64) -> String {
+        let local_ms = ms as i64 + i64::from(self.minutes) * 60_000;
+        let (days, ms_of_day) = (
+            local_ms.div_euclid(86_400_000),
+            local_ms.rem_euclid(86_400_000),
+        );
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}{self}",
+            ms_of_day / 3_600_000,
+            ms_of_day / 60_000 % 60,
+            ms_of_day / 1000 % 60,
+            ms_of_day % 1000
+        )
+    }
+}
+
+/// `(year, month, day)` of `days` since the unix epoch,
+/// see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+impl fmt::Display for UtcOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.minutes == 0 {
+            return write!(f, "Z");
+        }
+        let sign = if self.minutes < 0 { '-' } else { '+' };
+        let minutes = self.minutes.unsigned_abs();
+        write!(f, "{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+impl FromStr for UtcOffset {
+    type Err = String;
+
+    /// Parses `Z`, `+HH:MM` or `-HH:MM`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value == "Z" || value == "UTC" {
+            return Ok(Self::UTC);
+        }
+        let invalid = || format!("expected Z or +HH:MM/-HH:MM, got {value:?}");
+        let (sign, rest) = match value.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+        let hours = two_digits(hours).ok_or_else(invalid)?;
+        let minutes = two_digits(minutes).ok_or_else(invalid)?;
+        if hours > 14 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            minutes: sign * (hours * 60 + minutes),
+        })
+    }
+}
+
+/// Value of exactly two ASCII digits, as `i32::from_str` also takes signs and other lengths.
+fn two_digits(value: &str) -> Option<i32> {
+    match value.as_bytes() {
+        &[tens, ones] if tens.is_ascii_digit() && ones.is_ascii_digit() => {
+            Some(i32::from(tens - b'0') * 10 + i32::from(ones - b'0'))
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn format_with_offset() {
+    let ms = 1_709_294_400_250; // 2024-03-01T12:00:00.250Z
+    assert_eq!(UtcOffset::UTC.format(0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(UtcOffset::UTC.format(ms), "2024-03-01T12:00:00.250Z");
+    let india: UtcOffset = "+05:30".parse().unwrap();
+    assert_eq!(india.format(ms), "2024-03-01T17:30:00.250+05:30");
+    // Crosses back into February of a leap year.
+    let hawaii: UtcOffset = "-10:00".parse().unwrap();
+    assert_eq!(
+        hawaii.format(ms - 3 * 3_600_000),
+        "2024-02-29T23:00:00.250-10:00"
+    );
+    assert!("+5".parse::<UtcOffset>().is_err());
+    assert!("05:30".parse::<UtcOffset>().is_err());
+    for invalid in [
+        "--05:00", "+-5:00", "++5:00", "+5:00", "+05:3", "+005:00", "+05:+3",
+    ] {
+        assert!(invalid.parse::<UtcOffset>().is_err(), "{invalid}");
+    }
+}
@@ -0,0 +1,442 @@
+//! Frames in the YUV layouts decoders output (and encoders take) natively, so pipelines don't
+//! have to convert every frame to packed RGB and back: frames are converted to RGB once for
+//! inference, and only rows changed by annotation are converted back into the YUV frame.
+//!
+//! Plane offsets and strides come from the buffer (see [FrameLayout]), as decoders may pad rows
+//! and planes beyond GStreamer's default layout. Colors use the limited range BT.601 or BT.709
+//! matrix of the caps' colorimetry.
+
+use std::fmt;
+use std::str::FromStr;
+
+use image::RgbImage;
+
+/// Pixel format of raw frames passed to processing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Packed 8-bit RGB.
+    #[default]
+    Rgb,
+    /// Y plane followed by an interleaved, 2x2 subsampled UV plane.
+    Nv12,
+    /// Y plane followed by 2x2 subsampled U and V planes.
+    I420,
+}
+
+/// Color matrix of YUV frames, from the colorimetry of their caps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMatrix {
+    #[default]
+    Bt601,
+    Bt709,
+}
+
+/// Where the planes of a frame are in its buffer, and how its colors are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLayout {
+    pub width: u32,
+    pub height: u32,
+    /// Byte offsets of the planes: RGB or Y, then the UV plane for NV12, or U and V for I420.
+    pub offsets: [usize; 3],
+    /// Bytes per row of each plane.
+    pub strides: [usize; 3],
+    pub matrix: ColorMatrix,
+}
+
+/// Fixed point (8 fractional bits) YUV <-> RGB coefficients of a color matrix.
+#[derive(Debug, Clone, Copy)]
+struct Matrix {
+    /// V to R, U to G, V to G and U to B.
+    rv: i32,
+    gu: i32,
+    gv: i32,
+    bu: i32,
+    /// RGB to Y, U and V.
+    y: [i32; 3],
+    u: [i32; 3],
+    v: [i32; 3],
+}
+
+const BT601: Matrix = Matrix {
+    rv: 409,
+    gu: 100,
+    gv: 208,
+    bu: 516,
+    y: [66, 129, 25],
+    u: [-38, -74, 112],
+    v: [112, -94, -18],
+};
+
+const BT709: Matrix = Matrix {
+    rv: 459,
+    gu: 55,
+    gv: 136,
+    bu: 541,
+    y: [47, 157, 16],
+    u: [-26, -87, 112],
+    v: [112, -102, -10],
+};
+
+impl ColorMatrix {
+    /// GStreamer's default for YUV caps without colorimetry: BT.601 below 720p, BT.709 from 720p.
+    pub fn for_height(height: u32) -> Self {
+        if height >= 720 {
+            ColorMatrix::Bt709
+        } else {
+            ColorMatrix::Bt601
+        }
+    }
+
+    fn coefficients(self) -> &'static Matrix {
+        match self {
+            ColorMatrix::Bt601 => &BT601,
+            ColorMatrix::Bt709 => &BT709,
+        }
+    }
+}
+
+impl Matrix {
+    fn rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let c = 298 * (i32::from(y) - 16);
+        let (d, e) = (i32::from(u) - 128, i32::from(v) - 128);
+        let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+        [
+            clamp(c + self.rv * e),
+            clamp(c - self.gu * d - self.gv * e),
+            clamp(c + self.bu * d),
+        ]
+    }
+
+    fn luma(&self, [r, g, b]: [i32; 3]) -> u8 {
+        (((self.y[0] * r + self.y[1] * g + self.y[2] * b + 128) >> 8) + 16).clamp(0, 255) as u8
+    }
+
+    fn chroma(&self, [r, g, b]: [i32; 3]) -> (u8, u8) {
+        let u = ((self.u[0] * r + self.u[1] * g + self.u[2] * b + 128) >> 8) + 128;
+        let v = ((self.v[0] * r + self.v[1] * g + self.v[2] * b + 128) >> 8) + 128;
+        (u.clamp(0, 255) as u8, v.clamp(0, 255) as u8)
+    }
+}
+
+fn align4(value: usize) -> usize {
+    value.next_multiple_of(4)
+}
+
+impl PixelFormat {
+    /// `format` of the `video/x-raw` caps.
+    pub fn caps_format(self) -> &'static str {
+        match self {
+            PixelFormat::Rgb => "RGB",
+            PixelFormat::Nv12 => "NV12",
+            PixelFormat::I420 => "I420",
+        }
+    }
+
+    /// GStreamer's default layout of a `width`x`height` frame, for buffers without a video meta:
+    /// contiguous planes with 4 byte aligned rows (unpadded for RGB).
+    pub fn default_layout(self, width: u32, height: u32) -> FrameLayout {
+        let (w, h) = (width as usize, height as usize);
+        let (chroma_width, chroma_height) = (w.div_ceil(2), h.div_ceil(2));
+        let y_stride = align4(w);
+        let u_offset = y_stride * h;
+        let (offsets, strides) = match self {
+            PixelFormat::Rgb => ([0; 3], [w * 3, 0, 0]),
+            PixelFormat::Nv12 => {
+                let chroma_stride = align4(chroma_width * 2);
+                ([0, u_offset, 0], [y_stride, chroma_stride, 0])
+            }
+            PixelFormat::I420 => {
+                let chroma_stride = align4(chroma_width);
+                let v_offset = u_offset + chroma_stride * chroma_height;
+                ([0, u_offset, v_offset], [y_stride, chroma_stride, chroma_stride])
+            }
+        };
+        FrameLayout {
+            width,
+            height,
+            offsets,
+            strides,
+            matrix: ColorMatrix::for_height(height),
+        }
+    }
+
+    /// Size in bytes of a `width`x`height` frame in the default layout.
+    pub fn frame_size(self, width: u32, height: u32) -> usize {
+        let layout = self.default_layout(width, height);
+        self.planes(&layout)
+            .into_iter()
+            .enumerate()
+            .map(|(plane, (_, rows))| layout.offsets[plane] + layout.strides[plane] * rows)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Planes of the format, with the bytes per row and rows of each in a frame of `layout`.
+    fn planes(self, layout: &FrameLayout) -> Vec<(usize, usize)> {
+        let (width, height) = (layout.width as usize, layout.height as usize);
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        match self {
+            PixelFormat::Rgb => vec![(width * 3, height)],
+            PixelFormat::Nv12 => vec![(width, height), (chroma_width * 2, chroma_height)],
+            PixelFormat::I420 => vec![
+                (width, height),
+                (chroma_width, chroma_height),
+                (chroma_width, chroma_height),
+            ],
+        }
+    }
+
+    /// Bytes up to the end of the last row of the last plane of a frame of `layout`.
+    fn required_size(self, layout: &FrameLayout) -> usize {
+        self.planes(layout)
+            .into_iter()
+            .enumerate()
+            .map(|(plane, (row_bytes, rows))| match rows {
+                0 => 0,
+                rows => layout.offsets[plane] + layout.strides[plane] * (rows - 1) + row_bytes,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Offsets of the U and V samples of chroma position `(cx, cy)`.
+    fn chroma_offsets(self, layout: &FrameLayout, cx: usize, cy: usize) -> (usize, usize) {
+        match self {
+            PixelFormat::Nv12 => {
+                let u = layout.offsets[1] + cy * layout.strides[1] + cx * 2;
+                (u, u + 1)
+            }
+            _ => (
+                layout.offsets[1] + cy * layout.strides[1] + cx,
+                layout.offsets[2] + cy * layout.strides[2] + cx,
+            ),
+        }
+    }
+
+    fn check_size(self, data: &[u8], layout: &FrameLayout) -> anyhow::Result<()> {
+        let rows = self.planes(layout);
+        anyhow::ensure!(
+            layout
+                .strides
+                .iter()
+                .zip(&rows)
+                .all(|(&stride, &(row_bytes, _))| stride >= row_bytes),
+            "Rows of {layout:?} overlap for a {self} frame"
+        );
+        anyhow::ensure!(
+            data.len() >= self.required_size(layout),
+            "{} byte buffer doesn't hold a {}x{} {self} frame of {layout:?}",
+            data.len(),
+            layout.width,
+            layout.height
+        );
+        Ok(())
+    }
+
+    /// Converts a frame of `layout` in this format to RGB.
+    pub fn to_rgb(self, data: &[u8], layout: &FrameLayout) -> anyhow::Result<RgbImage> {
+        self.check_size(data, layout)?;
+        let (width, height) = (layout.width, layout.height);
+        let stride = layout.strides[0];
+        let mut rgb = RgbImage::new(width, height);
+        if self == PixelFormat::Rgb {
+            let row_bytes = width as usize * 3;
+            for (y, row) in rgb.chunks_exact_mut(row_bytes.max(1)).enumerate() {
+                let start = layout.offsets[0] + y * stride;
+                row.copy_from_slice(&data[start..start + row_bytes]);
+            }
+            return Ok(rgb);
+        }
+        let matrix = layout.matrix.coefficients();
+        for (y, row) in rgb.rows_mut().enumerate() {
+            let luma = &data[layout.offsets[0] + y * stride..];
+            for (x, pixel) in row.enumerate() {
+                let (u, v) = self.chroma_offsets(layout, x / 2, y / 2);
+                pixel.0 = matrix.rgb(luma[x], data[u], data[v]);
+            }
+        }
+        Ok(rgb)
+    }
+
+    /// Writes `frame` into `data`, a frame of `layout` in this format currently holding
+    /// `original`.
+    ///
+    /// YUV formats only convert the row pairs (sharing chroma rows) where `frame` differs from
+    /// `original`, eg. those with annotations drawn on them.
+    pub fn write_rgb(
+        self,
+        data: &mut [u8],
+        layout: &FrameLayout,
+        frame: &RgbImage,
+        original: &RgbImage,
+    ) -> anyhow::Result<()> {
+        let (width, height) = frame.dimensions();
+        anyhow::ensure!(
+            (width, height) == (layout.width, layout.height),
+            "Frame is {width}x{height}, not {}x{}",
+            layout.width,
+            layout.height
+        );
+        self.check_size(data, layout)?;
+        let row_bytes = width as usize * 3;
+        if self == PixelFormat::Rgb {
+            for (y, row) in frame.chunks_exact(row_bytes.max(1)).enumerate() {
+                let start = layout.offsets[0] + y * layout.strides[0];
+                data[start..start + row_bytes].copy_from_slice(row);
+            }
+            return Ok(());
+        }
+        anyhow::ensure!(
+            original.dimensions() == frame.dimensions(),
+            "Original frame is {:?}, not {:?}",
+            original.dimensions(),
+            frame.dimensions()
+        );
+        let matrix = layout.matrix.coefficients();
+        let pixel = |x: u32, y: u32| frame.get_pixel(x, y).0.map(i32::from);
+        for cy in 0..height.div_ceil(2) {
+            let first_row = cy as usize * 2;
+            let rows = first_row * row_bytes..((first_row + 2) * row_bytes).min(frame.len());
+            if frame.as_raw()[rows.clone()] == original.as_raw()[rows] {
+                continue;
+            }
+            let ys = cy * 2..(cy * 2 + 2).min(height);
+            for y in ys.clone() {
+                let luma = &mut data[layout.offsets[0] + y as usize * layout.strides[0]..];
+                for x in 0..width {
+                    luma[x as usize] = matrix.luma(pixel(x, y));
+                }
+            }
+            for cx in 0..width.div_ceil(2) {
+                // Chroma of the average color of the (up to) 2x2 block.
+                let (mut sum, mut count) = ([0; 3], 0);
+                for y in ys.clone() {
+                    for x in cx * 2..(cx * 2 + 2).min(width) {
+                        let rgb = pixel(x, y);
+                        (0..3).for_each(|i| sum[i] += rgb[i]);
+                        count += 1;
+                    }
+                }
+                let (u, v) = matrix.chroma(sum.map(|channel| channel / count));
+                let (u_offset, v_offset) = self.chroma_offsets(layout, cx as usize, cy as usize);
+                data[u_offset] = u;
+                data[v_offset] = v;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.caps_format())
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rgb" => Ok(PixelFormat::Rgb),
+            "nv12" => Ok(PixelFormat::Nv12),
+            "i420" => Ok(PixelFormat::I420),
+            _ => Err(format!(
+                "unknown pixel format {s:?}, expected one of: rgb, nv12, i420"
+            )),
+        }
+    }
+}
+
+#[test]
+fn yuv_round_trip() {
+    let (width, height) = (6, 4);
+    let gray = RgbImage::from_pixel(width, height, image::Rgb([128, 128, 128]));
+    let mut annotated = gray.clone();
+    // Only touches the second row pair.
+    annotated.put_pixel(1, 2, image::Rgb([255, 0, 0]));
+    annotated.put_pixel(0, 2, image::Rgb([255, 0, 0]));
+    annotated.put_pixel(0, 3, image::Rgb([255, 0, 0]));
+    annotated.put_pixel(1, 3, image::Rgb([255, 0, 0]));
+
+    for format in [PixelFormat::Nv12, PixelFormat::I420] {
+        // 8 byte (aligned) luma rows, 4 byte chroma rows.
+        assert_eq!(format.frame_size(width, height), 8 * 4 + 4 * 2 * 2);
+        let layout = format.default_layout(width, height);
+        let mut data = vec![0; format.frame_size(width, height)];
+        format
+            .write_rgb(&mut data, &layout, &gray, &RgbImage::new(width, height))
+            .unwrap();
+        let rgb = format.to_rgb(&data, &layout).unwrap();
+        for (a, b) in rgb.pixels().zip(gray.pixels()) {
+            assert!(
+                a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 2),
+                "{a:?} {b:?}"
+            );
+        }
+
+        // Rows matching the original are left alone.
+        data[0] = 0;
+        format.write_rgb(&mut data, &layout, &annotated, &gray).unwrap();
+        assert_eq!(data[0], 0);
+        let rgb = format.to_rgb(&data, &layout).unwrap();
+        let red = rgb.get_pixel(0, 3).0;
+        assert!(
+            red[0] > 240 && red[1] < 15 && red[2] < 15,
+            "{format}: {red:?}"
+        );
+    }
+    assert!("yuy2".parse::<PixelFormat>().is_err());
+}
+
+#[test]
+fn padded_layouts_and_color_matrices() {
+    let (width, height) = (4, 2);
+    let mut frame = RgbImage::from_pixel(width, height, image::Rgb([40, 160, 90]));
+    // A red 2x2 block, sharing chroma samples.
+    for (x, y) in [(2, 0), (3, 0), (2, 1), (3, 1)] {
+        frame.put_pixel(x, y, image::Rgb([200, 30, 30]));
+    }
+    let blank = RgbImage::new(width, height);
+
+    // Decoder output with padded rows and a gap between the planes.
+    let padded = FrameLayout {
+        width,
+        height,
+        offsets: [0, 40, 56],
+        strides: [16, 8, 8],
+        matrix: ColorMatrix::Bt709,
+    };
+    for (format, size) in [(PixelFormat::Nv12, 44), (PixelFormat::I420, 58)] {
+        let mut data = vec![0; size];
+        format.write_rgb(&mut data, &padded, &frame, &blank).unwrap();
+        // Padding is left alone.
+        assert!(data[4..16].iter().all(|&byte| byte == 0), "{format}");
+        let rgb = format.to_rgb(&data, &padded).unwrap();
+        for (a, b) in rgb.pixels().zip(frame.pixels()) {
+            assert!(
+                a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff(b) <= 2),
+                "{format}: {a:?} {b:?}"
+            );
+        }
+        assert!(format.to_rgb(&data[..size - 1], &padded).is_err());
+
+        // Read with the other matrix, the colors are off.
+        let bt601 = FrameLayout {
+            matrix: ColorMatrix::Bt601,
+            ..padded
+        };
+        let rgb = format.to_rgb(&data, &bt601).unwrap();
+        assert_ne!(rgb.get_pixel(0, 0), frame.get_pixel(0, 0));
+    }
+
+    let rgb = FrameLayout {
+        offsets: [0; 3],
+        strides: [16, 0, 0],
+        ..padded
+    };
+    let mut data = vec![0; 28];
+    PixelFormat::Rgb.write_rgb(&mut data, &rgb, &frame, &blank).unwrap();
+    assert_eq!(PixelFormat::Rgb.to_rgb(&data, &rgb).unwrap(), frame);
+    assert_eq!(ColorMatrix::for_height(720), ColorMatrix::Bt709);
+}
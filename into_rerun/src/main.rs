@@ -5,8 +5,19 @@ use std::{
 };
 
 use clap::Parser;
+use inference_common::bbox::Bbox;
+use inference_common::img_dimensions::ImgDimensions;
+use inference_common::preproc::Letterbox;
 use inference_common::video_meta::VideoMeta;
-use rerun::{AssetVideo, VideoFrameReference};
+use inference_common::{coco_classes, palette};
+use rerun::{AssetVideo, Boxes2D, VideoFrameReference};
+
+/// Model input dimensions of gstreamed_ort runs before `model_input_size` was recorded,
+/// bboxes in the json are relative to the input frame scaled to fit inside these.
+const DEFAULT_MODEL_INPUT_DIMS: ImgDimensions = ImgDimensions {
+    width: 640.,
+    height: 384.,
+};
 
 #[derive(Debug, Parser)]
 pub struct Args {
@@ -22,6 +33,26 @@ fn read_video_meta(input: &Path) -> VideoMeta {
     serde_json::from_reader(reader).unwrap()
 }
 
+/// Bboxes of a frame in video coordinates, colored by track id
+/// (same colors as the annotated video and TUI).
+fn frame_boxes(bboxes: &[Bbox]) -> Boxes2D {
+    Boxes2D::from_mins_and_sizes(
+        bboxes.iter().map(|b| (b.xmin, b.ymin)),
+        bboxes.iter().map(|b| (b.xmax - b.xmin, b.ymax - b.ymin)),
+    )
+    .with_colors(bboxes.iter().map(|bbox| {
+        let [r, g, b] = palette::bbox_color(bbox.tracker_id);
+        rerun::Color::from_rgb(r, g, b)
+    }))
+    .with_labels(bboxes.iter().map(|b| {
+        let class_name = coco_classes::NAMES.get(b.class).unwrap_or(&"unknown");
+        match b.tracker_id {
+            Some(id) => format!("{class_name} #{id}"),
+            None => class_name.to_string(),
+        }
+    }))
+}
+
 fn main() {
     let args: Args = Args::parse();
 
@@ -36,11 +67,26 @@ fn main() {
     // let frame_timestamps = video_asset.read_frame_timestamps_ns().unwrap();
     rec.log("video", &video_asset).unwrap();
 
+    let video_dims = ImgDimensions::new(video_meta.width as f32, video_meta.height as f32);
+    let model_input_dims = video_meta
+        .model_input_size
+        .map_or(DEFAULT_MODEL_INPUT_DIMS, |[width, height]| {
+            ImgDimensions::new(width as f32, height as f32)
+        });
+    let letterbox = Letterbox::new(video_dims, model_input_dims);
+
     // Log per frame data.
-    for (_idx, frame) in video_meta.frames.iter().enumerate() {
+    for frame in video_meta.frames.iter() {
+        rec.set_time_nanos("video_time", frame.pts as i64);
         rec.log("video", &VideoFrameReference::new(frame.pts as i64))
             .unwrap();
-        // rr.log(format!("bboxes/{idx}"), Boxes2D::)
+        let bboxes: Vec<Bbox> = frame
+            .bboxes_by_class
+            .iter()
+            .flatten()
+            .map(|bbox| letterbox.unmap(bbox))
+            .collect();
+        rec.log("video/bboxes", &frame_boxes(&bboxes)).unwrap();
     }
 
     println!("Finished writing rerun log to {:?}", args.output);
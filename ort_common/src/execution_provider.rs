@@ -0,0 +1,264 @@
+//! Execution provider (hardware acceleration) selection, shared by the ort binaries.
+
+use std::path::PathBuf;
+
+use ort::execution_providers::coreml::CoreMLComputeUnits;
+use ort::execution_providers::cuda::CuDNNConvAlgorithmSearch;
+use ort::execution_providers::{
+    CUDAExecutionProvider, CoreMLExecutionProvider, DirectMLExecutionProvider, ExecutionProvider,
+    OpenVINOExecutionProvider, TensorRTExecutionProvider,
+};
+use ort::session::Session;
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+
+/// Execution provider command line flags, flatten into the binary's args.
+///
+/// Providers other than cuda are only compiled into onnxruntime with the matching cargo feature
+/// (`openvino`, `directml`, `coreml`, `tensorrt`). Providers which can't run on this platform or
+/// fail to register are skipped, falling back to cpu.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ExecutionProviderArgs {
+    /// Whether to attempt to use `cuda` hw acceleration, falls back to cpu (with a warning) if
+    /// it can't be used.
+    #[arg(long, action, default_value = "false")]
+    pub cuda: bool,
+    /// Use OpenVINO for Intel CPUs, integrated GPUs and NPUs (build with `--features openvino`).
+    #[arg(long, action, default_value = "false", conflicts_with = "cuda")]
+    pub openvino: bool,
+    /// OpenVINO device: `CPU`, `GPU`, `GPU.1`, `NPU` or heterogeneous eg. `HETERO:GPU,CPU`.
+    #[arg(long, default_value = "GPU")]
+    pub openvino_device: String,
+    /// Cache compiled OpenVINO models in this directory, speeds up startup on GPU/NPU.
+    #[arg(long)]
+    pub openvino_cache_dir: Option<PathBuf>,
+    /// Use DirectML for DirectX 12 GPUs on Windows (build with `--features directml`).
+    #[arg(long, action, default_value = "false", conflicts_with_all = ["cuda", "openvino"])]
+    pub directml: bool,
+    /// DirectML adapter index.
+    #[arg(long, default_value_t = 0)]
+    pub directml_device: i32,
+    /// Use CoreML for the Apple Neural Engine / GPU on macOS (build with `--features coreml`).
+    #[arg(long, action, default_value = "false", conflicts_with_all = ["cuda", "openvino", "directml"])]
+    pub coreml: bool,
+    /// CoreML compute units: `all`, `ane` (neural engine + cpu), `gpu` (gpu + cpu) or `cpu`.
+    #[arg(long, default_value = "all", value_parser = parse_compute_units)]
+    pub coreml_units: CoreMLComputeUnits,
+    /// Use TensorRT, eg. on Jetson (build with `--features tensorrt`).
+    /// Nodes TensorRT can't handle run on cuda.
+    #[arg(
+        long,
+        action,
+        default_value = "false",
+        conflicts_with_all = ["cuda", "openvino", "directml", "coreml"]
+    )]
+    pub tensorrt: bool,
+    /// Build TensorRT engines with fp16 precision.
+    #[arg(long, action, default_value = "false")]
+    pub tensorrt_fp16: bool,
+    /// Cache built TensorRT engines in this directory, building takes minutes on Jetson.
+    #[arg(long)]
+    pub tensorrt_cache_dir: Option<PathBuf>,
+    /// Reproducible inference, eg. for comparing runs: single threaded sessions with
+    /// deterministic kernels, and cuDNN's default convolution algorithm without TF32 on cuda.
+    /// Slower, and results still differ between providers and hardware.
+    #[arg(long, action, default_value = "false")]
+    pub deterministic: bool,
+}
+
+fn parse_compute_units(value: &str) -> Result<CoreMLComputeUnits, String> {
+    match value {
+        "all" => Ok(CoreMLComputeUnits::All),
+        "ane" => Ok(CoreMLComputeUnits::CPUAndNeuralEngine),
+        "gpu" => Ok(CoreMLComputeUnits::CPUAndGPU),
+        "cpu" => Ok(CoreMLComputeUnits::CPUOnly),
+        _ => Err(format!("expected all, ane, gpu or cpu, got {value:?}")),
+    }
+}
+
+/// Provider with its short name, for logs and profiles.
+type NamedProvider = (&'static str, Box<dyn ExecutionProvider>);
+
+/// `ep`, named `name`, if it can run on this platform and onnxruntime was built with it.
+fn checked(name: &'static str, ep: impl ExecutionProvider + 'static) -> Option<NamedProvider> {
+    if !ep.supported_by_platform() {
+        log::warn!(
+            "{} is not supported on this platform, skipping it",
+            ep.name()
+        );
+        return None;
+    }
+    match ep.is_available() {
+        Ok(true) => Some((name, Box::new(ep))),
+        Ok(false) => {
+            log::warn!("onnxruntime was built without {}, skipping it", ep.name());
+            None
+        }
+        Err(e) => {
+            log::warn!(
+                "Failed to check {} availability, trying it anyway: {e}",
+                ep.name()
+            );
+            Some((name, Box::new(ep)))
+        }
+    }
+}
+
+impl ExecutionProviderArgs {
+    fn cuda(&self) -> CUDAExecutionProvider {
+        let ep = CUDAExecutionProvider::default();
+        if self.deterministic {
+            // Benchmarked algorithm choices and TF32 rounding vary between runs.
+            ep.with_conv_algorithm_search(CuDNNConvAlgorithmSearch::Default)
+                .with_tf32(false)
+        } else {
+            ep
+        }
+    }
+
+    /// Short name of the provider selected by the flags, which may fall back to cpu, see
+    /// [Self::configure_session].
+    pub fn requested(&self) -> &'static str {
+        if self.cuda {
+            "cuda"
+        } else if self.openvino {
+            "openvino"
+        } else if self.directml {
+            "directml"
+        } else if self.coreml {
+            "coreml"
+        } else if self.tensorrt {
+            "tensorrt"
+        } else {
+            "cpu"
+        }
+    }
+
+    /// The selected providers usable here, configured from the flags, most preferred first.
+    fn providers(&self) -> Vec<NamedProvider> {
+        let mut providers = Vec::new();
+        if self.cuda {
+            providers.extend(checked("cuda", self.cuda()));
+        } else if self.openvino {
+            let mut ep = OpenVINOExecutionProvider::default()
+                .with_device_type(&self.openvino_device)
+                // Models exported with dynamic input axes won't compile for GPU/NPU otherwise.
+                .with_dynamic_shapes(true);
+            if let Some(dir) = &self.openvino_cache_dir {
+                ep = ep.with_cache_dir(dir.to_string_lossy());
+            }
+            providers.extend(checked("openvino", ep));
+        } else if self.directml {
+            let ep = DirectMLExecutionProvider::default().with_device_id(self.directml_device);
+            providers.extend(checked("directml", ep));
+        } else if self.coreml {
+            let ep = CoreMLExecutionProvider::default()
+                .with_compute_units(self.coreml_units)
+                // Preprocessing pads frames to fixed size NCHW tensors, so CoreML can take the
+                // whole graph and compile it ahead of time.
+                .with_static_input_shapes(true)
+                .with_subgraphs(true);
+            providers.extend(checked("coreml", ep));
+        } else if self.tensorrt {
+            let mut ep = TensorRTExecutionProvider::default().with_fp16(self.tensorrt_fp16);
+            if let Some(dir) = &self.tensorrt_cache_dir {
+                ep = ep
+                    .with_engine_cache(true)
+                    .with_engine_cache_path(dir.display())
+                    .with_timing_cache(true)
+                    .with_timing_cache_path(dir.display());
+            }
+            providers.extend(checked("tensorrt", ep));
+            providers.extend(checked("cuda", self.cuda()));
+        }
+        providers
+    }
+
+    /// Applies session settings required by the selected provider and by `--deterministic`, and
+    /// registers the usable providers, most preferred first, skipping those failing to.
+    ///
+    /// Returns the configured builder and the short name of the provider its sessions prefer,
+    /// cpu if none registered.
+    pub fn configure_session(
+        &self,
+        builder: SessionBuilder,
+    ) -> ort::Result<(SessionBuilder, &'static str)> {
+        let builder = if self.directml {
+            // DirectML supports neither memory patterns nor parallel execution.
+            builder
+                .with_memory_pattern(false)?
+                .with_parallel_execution(false)?
+        } else {
+            builder
+        };
+        let mut builder = if self.deterministic {
+            // Multithreaded kernels may sum up in varying order.
+            builder
+                .with_intra_threads(1)?
+                .with_inter_threads(1)?
+                .with_parallel_execution(false)?
+                .with_deterministic_compute(true)?
+        } else {
+            builder
+        };
+
+        let mut registered = None;
+        for (name, ep) in self.providers() {
+            match ep.register(&mut builder) {
+                Ok(()) => {
+                    registered.get_or_insert(name);
+                }
+                Err(e) => log::warn!("Failed to register {name}, skipping it: {e}"),
+            }
+        }
+        let name = registered.unwrap_or("cpu");
+        if name != self.requested() {
+            log::warn!("Running on {name} instead of {}", self.requested());
+        }
+        Ok((builder, name))
+    }
+
+    /// Loads `model` into a session configured by [Self::configure_session], along with the short
+    /// name of the provider it runs on.
+    pub fn load_session(&self, model: &str) -> anyhow::Result<(Session, &'static str)> {
+        let (builder, provider) = self.configure_session(SessionBuilder::new()?)?;
+        let session = builder
+            .with_optimization_level(GraphOptimizationLevel::Level3)?
+            // .with_intra_threads(1)?
+            .commit_from_file(model)?;
+        log::debug!("{session:?}");
+        Ok((session, provider))
+    }
+}
+
+#[test]
+fn execution_provider_flags() {
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Args {
+        #[command(flatten)]
+        ep: ExecutionProviderArgs,
+    }
+
+    let args = Args::parse_from(["test"]);
+    assert_eq!(args.ep.requested(), "cpu");
+    let args = Args::parse_from(["test", "--openvino", "--openvino-device", "NPU"]);
+    assert_eq!(args.ep.requested(), "openvino");
+    assert_eq!(args.ep.openvino_device, "NPU");
+    let args = Args::parse_from(["test", "--directml", "--directml-device", "1"]);
+    assert_eq!(
+        (args.ep.requested(), args.ep.directml_device),
+        ("directml", 1)
+    );
+    assert!(Args::try_parse_from(["test", "--cuda", "--directml"]).is_err());
+    let args = Args::parse_from(["test", "--coreml", "--coreml-units", "ane"]);
+    assert_eq!(args.ep.requested(), "coreml");
+    assert_eq!(args.ep.coreml_units, CoreMLComputeUnits::CPUAndNeuralEngine);
+    assert!(Args::try_parse_from(["test", "--coreml-units", "npu"]).is_err());
+    let args = Args::parse_from(["test", "--tensorrt", "--tensorrt-fp16"]);
+    assert_eq!(args.ep.requested(), "tensorrt");
+    assert!(args.ep.tensorrt_fp16);
+    assert!(!args.ep.deterministic);
+    let args = Args::parse_from(["test", "--cuda", "--deterministic"]);
+    assert!(args.ep.deterministic);
+}
@@ -1 +1,4 @@
+pub mod execution_provider;
+pub mod model_input;
+pub mod session_pool;
 pub mod yolo_parser;
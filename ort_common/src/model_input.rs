@@ -0,0 +1,75 @@
+//! Model input (inference) resolution, independent of the decoded frame resolution.
+//!
+//! Frames are letterboxed into these dimensions by [preprocess](inference_common::preproc::preprocess),
+//! annotated output & logs keep the original frame resolution and aspect ratio.
+
+use inference_common::img_dimensions::ImgDimensions;
+use ort::session::Session;
+
+/// Input dimensions used for models with dynamic input shapes, fits 16:9 frames with little padding.
+pub const DEFAULT_INPUT_DIMS: ImgDimensions = ImgDimensions {
+    width: 640.,
+    height: 384.,
+};
+
+/// Yolov8 downsamples by up to 32, so input sides must be multiples of it.
+const INPUT_STRIDE: u32 = 32;
+
+/// Static `[batch, 3, height, width]` input dimensions of the model, `None` if they're dynamic.
+pub fn static_input_dims(session: &Session) -> Option<ImgDimensions> {
+    let shape = session.inputs.first()?.input_type.tensor_shape()?;
+    match shape[..] {
+        [_, 3, height, width] if height > 0 && width > 0 => {
+            Some(ImgDimensions::new(width as f32, height as f32))
+        }
+        _ => None,
+    }
+}
+
+/// Input dimensions to run the model with: `requested` if given (must match static model inputs),
+/// otherwise the model's static input dims, or [DEFAULT_INPUT_DIMS].
+pub fn resolve_input_dims(
+    session: &Session,
+    requested: Option<ImgDimensions>,
+) -> anyhow::Result<ImgDimensions> {
+    let dims = match (requested, static_input_dims(session)) {
+        (Some(requested), Some(model))
+            if (requested.width, requested.height) != (model.width, model.height) =>
+        {
+            anyhow::bail!(
+                "Requested input size {}x{} doesn't match the model's static input size {}x{}",
+                requested.width,
+                requested.height,
+                model.width,
+                model.height
+            );
+        }
+        (Some(dims), _) | (None, Some(dims)) => dims,
+        (None, None) => DEFAULT_INPUT_DIMS,
+    };
+    log::info!("Model input size: {}x{}", dims.width, dims.height);
+    Ok(dims)
+}
+
+/// Parses `WIDTHxHEIGHT` model input dimensions, eg. `1280x736`.
+pub fn parse_input_size(value: &str) -> Result<ImgDimensions, String> {
+    let (width, height) = value
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)))
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {value:?}"))?;
+    if width == 0 || height == 0 || width % INPUT_STRIDE != 0 || height % INPUT_STRIDE != 0 {
+        return Err(format!(
+            "input size must be positive multiples of {INPUT_STRIDE}, got {width}x{height}"
+        ));
+    }
+    Ok(ImgDimensions::new(width as f32, height as f32))
+}
+
+#[test]
+fn input_size() {
+    let dims = parse_input_size("1280x736").unwrap();
+    assert_eq!((dims.width, dims.height), (1280.0, 736.0));
+    assert!(parse_input_size("640x360").is_err());
+    assert!(parse_input_size("0x384").is_err());
+    assert!(parse_input_size("640").is_err());
+}
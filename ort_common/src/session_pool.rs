@@ -0,0 +1,262 @@
+//! Pool of ort sessions shared by concurrently processed streams.
+//!
+//! Streams are served first come first served, so a stream which just released a session can't
+//! grab it again while others are waiting. Time spent waiting is recorded per stream,
+//! to spot streams starved of inference capacity.
+
+use std::collections::VecDeque;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use ort::session::Session;
+
+pub type SessionPool = Pool<Session>;
+
+/// Waiting times of a single stream.
+#[derive(Debug, Clone, Default)]
+pub struct StreamWaitStats {
+    pub name: String,
+    pub acquisitions: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl StreamWaitStats {
+    pub fn mean_wait(&self) -> Duration {
+        if self.acquisitions == 0 {
+            return Duration::ZERO;
+        }
+        self.total_wait / self.acquisitions as u32
+    }
+
+    /// Logs the waiting times, as a warning if longer than `starvation` on average.
+    fn log(&self, starvation: Duration) {
+        let mean = self.mean_wait();
+        let message = format!(
+            "Stream {:?}: {} inferences, session wait mean {mean:?}, max {:?}",
+            self.name, self.acquisitions, self.max_wait
+        );
+        if mean > starvation {
+            log::warn!("{message} (starved, consider more sessions)");
+        } else {
+            log::info!("{message}");
+        }
+    }
+}
+
+struct State<T> {
+    idle: Vec<T>,
+    /// Tickets of waiting acquisitions, in arrival order.
+    queue: VecDeque<u64>,
+    next_ticket: u64,
+    streams: Vec<StreamWaitStats>,
+}
+
+/// Fair pool of `T`s (usually [Session]s), see the module docs.
+pub struct Pool<T> {
+    state: Mutex<State<T>>,
+    released: Condvar,
+    size: usize,
+}
+
+impl<T> Pool<T> {
+    pub fn new(items: Vec<T>) -> anyhow::Result<Arc<Self>> {
+        anyhow::ensure!(!items.is_empty(), "Session pool needs at least one session");
+        Ok(Arc::new(Self {
+            size: items.len(),
+            state: Mutex::new(State {
+                idle: items,
+                queue: VecDeque::new(),
+                next_ticket: 0,
+                streams: Vec::new(),
+            }),
+            released: Condvar::new(),
+        }))
+    }
+
+    /// Number of pooled items.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Registers a stream, whose acquisitions are tracked under `name`.
+    pub fn register_stream(self: &Arc<Self>, name: impl Into<String>) -> StreamHandle<T> {
+        let mut state = self.state.lock().unwrap();
+        state.streams.push(StreamWaitStats {
+            name: name.into(),
+            ..Default::default()
+        });
+        StreamHandle {
+            pool: Arc::clone(self),
+            stream: state.streams.len() - 1,
+        }
+    }
+
+    /// Waiting times of all registered streams.
+    pub fn stream_stats(&self) -> Vec<StreamWaitStats> {
+        self.state.lock().unwrap().streams.clone()
+    }
+
+    /// Logs waiting times per stream, warning about streams waiting longer than `starvation` on average.
+    pub fn log_summary(&self, starvation: Duration) {
+        for stats in self.stream_stats() {
+            stats.log(starvation);
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pool").field("size", &self.size).finish()
+    }
+}
+
+/// A stream's access to the pool, clones share the stream.
+pub struct StreamHandle<T> {
+    pool: Arc<Pool<T>>,
+    stream: usize,
+}
+
+impl<T> Clone for StreamHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: Arc::clone(&self.pool),
+            stream: self.stream,
+        }
+    }
+}
+
+impl<T> StreamHandle<T> {
+    /// Waiting times of this stream.
+    pub fn stats(&self) -> StreamWaitStats {
+        self.pool.state.lock().unwrap().streams[self.stream].clone()
+    }
+
+    /// Logs waiting times of this stream only, see [Pool::log_summary].
+    pub fn log_summary(&self, starvation: Duration) {
+        self.stats().log(starvation);
+    }
+
+    /// Blocks until an item is available and it's this stream's turn.
+    pub fn acquire(&self) -> Pooled<'_, T> {
+        let start = Instant::now();
+        let mut state = self.pool.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue.push_back(ticket);
+        while state.queue.front() != Some(&ticket) || state.idle.is_empty() {
+            state = self.pool.released.wait(state).unwrap();
+        }
+        state.queue.pop_front();
+        let item = state.idle.pop().expect("checked above");
+
+        let wait = start.elapsed();
+        let stats = &mut state.streams[self.stream];
+        stats.acquisitions += 1;
+        stats.total_wait += wait;
+        stats.max_wait = stats.max_wait.max(wait);
+        drop(state);
+        // The next waiter may be able to take another idle item.
+        self.pool.released.notify_all();
+
+        Pooled {
+            pool: &self.pool,
+            item: Some(item),
+        }
+    }
+}
+
+/// Item borrowed from the pool, returned to it on drop.
+pub struct Pooled<'a, T> {
+    pool: &'a Pool<T>,
+    item: Option<T>,
+}
+
+impl<T> Deref for Pooled<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().expect("only taken on drop")
+    }
+}
+
+impl<T> DerefMut for Pooled<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.item.as_mut().expect("only taken on drop")
+    }
+}
+
+impl<T> Drop for Pooled<'_, T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            self.pool.state.lock().unwrap().idle.push(item);
+            self.pool.released.notify_all();
+        }
+    }
+}
+
+#[test]
+fn streams_share_the_pool_fairly() {
+    let pool = Pool::new(vec![0u32, 0]).unwrap();
+    assert!(Pool::<u32>::new(vec![]).is_err());
+
+    // Twice as many streams as items, each item counts how many times it was used.
+    let streams: Vec<_> = (0..4)
+        .map(|i| pool.register_stream(format!("camera{i}")))
+        .collect();
+    std::thread::scope(|scope| {
+        for stream in &streams {
+            scope.spawn(move || {
+                for _ in 0..50 {
+                    let mut item = stream.acquire();
+                    *item += 1;
+                    std::thread::sleep(Duration::from_micros(100));
+                }
+            });
+        }
+    });
+
+    let stats = pool.stream_stats();
+    assert_eq!(stats.len(), 4);
+    assert!(stats.iter().all(|s| s.acquisitions == 50));
+    assert_eq!(stats[3].name, "camera3");
+    let total: u32 = pool.state.lock().unwrap().idle.iter().sum();
+    assert_eq!(total, 200);
+}
+
+#[test]
+fn waiting_streams_take_turns() {
+    // A single item, logging which stream used it.
+    let pool = Pool::new(vec![Vec::new()]).unwrap();
+    let streams: Vec<_> = (0..2)
+        .map(|i| pool.register_stream(format!("camera{i}")))
+        .collect();
+    std::thread::scope(|scope| {
+        for (i, stream) in streams.iter().enumerate() {
+            scope.spawn(move || {
+                for _ in 0..20 {
+                    let mut item = stream.acquire();
+                    item.push(i);
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            });
+        }
+    });
+
+    // Until the other stream first asks for it and after it's done, one stream has it to itself.
+    let order = pool.state.lock().unwrap().idle[0].clone();
+    assert_eq!(order.len(), 40);
+    let first_turn = order.iter().position(|&i| i != order[0]).unwrap();
+    let last = order[order.len() - 1];
+    let last_turn = order.iter().rposition(|&i| i != last).unwrap();
+    let contended = &order[first_turn - 1..=last_turn + 1];
+    assert!(
+        contended.windows(2).all(|pair| pair[0] != pair[1]),
+        "a stream took the item again while the other was waiting: {order:?}"
+    );
+    // Waits are bounded by the other stream's turns, rather than growing with the run.
+    for stats in pool.stream_stats() {
+        assert!(stats.max_wait < Duration::from_millis(500), "{stats:?}");
+    }
+}
@@ -1,13 +1,17 @@
 use std::time::Instant;
 
 use inference_common::{
-    bbox::{Bbox, non_maximum_suppression},
+    bbox::{Bbox, decode_yolov8, non_maximum_suppression},
     frame_times::FrameTimes,
     img_dimensions::ImgDimensions,
+    output_guard::{self, OutputIssues},
 };
-use ndarray::{ArrayView, Axis, Dim, IxDyn, s};
+use ndarray::{ArrayView, Dim, IxDyn, s};
 
-/// Parse yolov8 predictions via `ort`.
+/// Parse yolov8 predictions via `ort` into boxes in `scaled_dims` sized frame coordinates.
+///
+/// Broken boxes are dropped before NMS and absurd counts after it, along with the issues found,
+/// see [output_guard]. Fails if `preds` isn't shaped `[batch, 4 + num_clases (or more), anchors]`.
 pub fn parse_predictions(
     preds: ArrayView<f32, IxDyn>,
     scaled_dims: ImgDimensions,
@@ -15,70 +19,36 @@ pub fn parse_predictions(
     conf_threshold: f32,
     nms_threshold: f32,
     frame_times: &mut FrameTimes,
-) -> anyhow::Result<Vec<Vec<Bbox>>> {
+) -> anyhow::Result<(Vec<Vec<Bbox>>, OutputIssues)> {
     // preds.shape: [bsz, embedding, anchors]
     // [1, 84, 5040]
     // TODO batch support with another loop outside
 
     let start = Instant::now();
     log::debug!("preds.shape: {:?}", preds.shape());
+    let rows = 4 + num_clases as usize;
+    match preds.shape() {
+        [batch, embedding, _] if *batch > 0 && *embedding >= rows => {}
+        shape => anyhow::bail!(
+            "Model output of shape {shape:?} doesn't hold [batch, {rows}, anchors] predictions \
+             (box and {num_clases} class scores)"
+        ),
+    }
     // Get rid of the first axis.
     // Need to specify full dimensions here so rust can infer slices correctly later.
     let preds: ArrayView<f32, Dim<[usize; 2]>> = preds.slice(s![0, .., ..]);
     // Gives us a shape of [84, 5040].
     log::debug!("preds2.shape: {:?}", preds.shape());
 
-    let mut bboxes_per_class: Vec<Vec<Bbox>> = vec![Vec::new(); num_clases as usize];
-    for pred in preds.axis_iter(Axis(1)) {
-        log::trace!("pred.shape: {:?}", pred.shape());
-        // Separate bbox and class values.
-        // First 4 values correspond to bbox cx, cy, w, h
-        const BBOX_OFFSET: usize = 4;
-        let bbox = pred.slice(s![0..BBOX_OFFSET]);
-        let clss = pred.slice(s![BBOX_OFFSET..BBOX_OFFSET + num_clases as usize]);
-
-        // Determine top1 class and its confidence.
-        let mut max_class_id = 0;
-        let mut max_confidence = 0f32;
-        for (idx, cls_conf) in clss.into_iter().enumerate() {
-            if cls_conf > &max_confidence {
-                max_confidence = *cls_conf;
-                max_class_id = idx;
-            }
-        }
-
-        log::trace!("max class id {max_class_id:?}: {max_confidence:?}");
-
-        // Check confidence > threshold.
-        if max_confidence < conf_threshold {
-            continue;
-        }
-
-        let cx = bbox[0];
-        let cy = bbox[1];
-        let w = bbox[2];
-        let h = bbox[3];
-
-        let xmin = cx - w / 2.;
-        let ymin = cy - h / 2.;
-        let xmax = xmin + w;
-        let ymax = ymin + h;
-
-        // Bound coords to scaled dimensions, so bboxes don't go outside the image.
-        let y_bbox = Bbox {
-            xmin: xmin.max(0.0f32).min(scaled_dims.width),
-            ymin: ymin.max(0.0f32).min(scaled_dims.height),
-            xmax: xmax.max(0.0f32).min(scaled_dims.width),
-            ymax: ymax.max(0.0f32).min(scaled_dims.height),
-            detector_confidence: max_confidence,
-            tracker_confidence: 0f32,
-            data: vec![],
-            class: max_class_id,
-            tracker_id: None,
-        };
-
-        bboxes_per_class[max_class_id].push(y_bbox);
-    }
+    // Standard layout to pass the raw values, a no-op for outputs straight from the session.
+    let preds = preds.as_standard_layout();
+    let mut bboxes_per_class = decode_yolov8(
+        preds.as_slice().unwrap(),
+        preds.shape()[1],
+        num_clases as usize,
+        conf_threshold,
+    );
+    let mut issues = output_guard::sanitize(&mut bboxes_per_class, scaled_dims);
     frame_times.bbox_extraction = start.elapsed();
 
     // nms
@@ -88,7 +58,27 @@ pub fn parse_predictions(
         bboxes_per_class.iter().map(|v| v.len()).sum::<usize>()
     );
     non_maximum_suppression(&mut bboxes_per_class, nms_threshold);
+    issues.excess = output_guard::drop_excess(&mut bboxes_per_class);
     frame_times.nms = start.elapsed();
 
-    Ok(bboxes_per_class)
+    Ok((bboxes_per_class, issues))
+}
+
+#[test]
+fn parse_predictions_checks_the_shape() {
+    use ndarray::Array;
+
+    let dims = ImgDimensions::new(640., 640.);
+    let mut frame_times = FrameTimes::default();
+    // A box of class 1, as a [1, 4 + 2 classes, 1 anchor] output.
+    let preds = Array::from_shape_vec(vec![1, 6, 1], vec![50., 50., 20., 20., 0.1, 0.9]).unwrap();
+    let (bboxes, issues) =
+        parse_predictions(preds.view(), dims, 2, 0.25, 0.45, &mut frame_times).unwrap();
+    assert_eq!((bboxes[0].len(), bboxes[1].len()), (0, 1));
+    assert!(!issues.is_bad());
+
+    // Too few rows for 80 classes, and no batch axis.
+    assert!(parse_predictions(preds.view(), dims, 80, 0.25, 0.45, &mut frame_times).is_err());
+    let preds = preds.into_shape_with_order(vec![6, 1]).unwrap();
+    assert!(parse_predictions(preds.view(), dims, 2, 0.25, 0.45, &mut frame_times).is_err());
 }
@@ -0,0 +1,65 @@
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+/// A bounding box around an object.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Bbox {
+    pub xmin: f32,
+    pub ymin: f32,
+    pub xmax: f32,
+    pub ymax: f32,
+    pub detector_confidence: f32,
+    pub tracker_confidence: f32,
+    pub data: Vec<KeyPoint>,
+    pub class: usize,
+    pub tracker_id: Option<i64>,
+    /// Tracker internals, for boxes reported by a tracker that exposes them (ocsort and botsort,
+    /// not sort).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<TrackInfo>,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl Bbox {
+    /// Untracked class 0 box detected with confidence 0.9, for tests. Set other fields with struct
+    /// update syntax, eg. `Bbox { tracker_id: Some(1), ..Bbox::test(0., 0., 10., 10.) }`.
+    pub fn test(xmin: f32, ymin: f32, xmax: f32, ymax: f32) -> Self {
+        Self {
+            xmin,
+            ymin,
+            xmax,
+            ymax,
+            detector_confidence: 0.9,
+            tracker_confidence: 0.,
+            data: Vec::new(),
+            class: 0,
+            tracker_id: None,
+            track: None,
+        }
+    }
+}
+
+/// State of the track a box belongs to, to tell solid tracks from shaky or coasting ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct TrackInfo {
+    /// IoU of the track's predicted box with the detection matched to it, `None` for tracks
+    /// started in this frame.
+    pub match_iou: Option<f32>,
+    /// Frames since a detection was last matched to the track, 0 if one was in this frame.
+    pub frames_since_update: u32,
+    /// Consecutive frames a detection was matched to the track in.
+    pub hit_streak: u32,
+    /// The box is only predicted by the tracker, without a matched detection in this frame.
+    pub predicted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct KeyPoint {
+    pub x: f32,
+    pub y: f32,
+    pub mask: f32,
+}
+
+/// Type alias for a list of bboxes grouped by class.
+pub type BBoxesByClass = Vec<Vec<Bbox>>;
@@ -0,0 +1,219 @@
+//! Detection log entries, as exported by the detection logger (one json array per run).
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::bbox::TrackInfo;
+
+/// Color information extracted from bounding box region
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorInfo {
+    pub dominant_color: String,
+    pub rgb: (u8, u8, u8),
+    pub color_name: String,
+}
+
+/// Extended attributes for detected objects
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ObjectAttributes {
+    pub color_info: Option<ColorInfo>,
+    pub position: Position,
+    pub size: Size,
+    pub person_attrs: Option<PersonAttributesLog>,
+    pub custom_metadata: HashMap<String, String>,
+}
+
+/// Person-specific attributes from neural network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonAttributesLog {
+    pub gender: Option<String>,
+    pub gender_confidence: Option<f32>,
+    pub age_group: Option<String>,
+    pub age_confidence: Option<f32>,
+    pub upper_body_color: Option<String>,
+    pub lower_body_color: Option<String>,
+}
+
+/// Position information
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Position {
+    pub x_center: f32,
+    pub y_center: f32,
+    pub area: f32,
+}
+
+/// Size information
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Size {
+    pub width: f32,
+    pub height: f32,
+    pub relative_size: f32, // Percentage of frame
+}
+
+/// Enhanced detection log entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectionLog {
+    pub frame_number: u64,
+    /// Stream time (buffer PTS) of the frame.
+    pub timestamp_ms: u64,
+    /// Capture (wall clock) time of the frame in ms since the unix epoch, when the source reports it
+    /// (eg. RTP streams with RTCP sender reports), for correlating with external events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_time_ms: Option<u64>,
+    /// [DetectionLog::capture_time_ms] in the site's local time, with an explicit UTC offset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_time_local: Option<String>,
+    pub object_id: String,
+    pub tracker_id: Option<i64>,
+    /// State of the track, for trackers exposing it (ocsort, botsort).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<TrackInfo>,
+    pub class_name: String,
+    pub confidence: f32,
+    pub bbox: BBoxCoords,
+    pub attributes: ObjectAttributes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BBoxCoords {
+    pub xmin: f32,
+    pub ymin: f32,
+    pub xmax: f32,
+    pub ymax: f32,
+}
+
+#[cfg(any(test, feature = "test-utils"))]
+impl DetectionLog {
+    /// Person tracked with id 1 in `frame_number` of a 25 fps stream, detected with confidence 0.9,
+    /// for tests. Set other fields with struct update syntax.
+    pub fn test(frame_number: u64, xmin: f32, ymin: f32, xmax: f32, ymax: f32) -> Self {
+        Self {
+            frame_number,
+            timestamp_ms: frame_number * 40,
+            capture_time_ms: None,
+            capture_time_local: None,
+            object_id: "1".to_string(),
+            tracker_id: Some(1),
+            track: None,
+            class_name: "person".to_string(),
+            confidence: 0.9,
+            bbox: BBoxCoords {
+                xmin,
+                ymin,
+                xmax,
+                ymax,
+            },
+            attributes: Default::default(),
+        }
+    }
+}
+
+impl BBoxCoords {
+    /// Intersection over union with `other`.
+    pub fn iou(&self, other: &BBoxCoords) -> f32 {
+        let area = |b: &BBoxCoords| (b.xmax - b.xmin).max(0.) * (b.ymax - b.ymin).max(0.);
+        let i_w = (self.xmax.min(other.xmax) - self.xmin.max(other.xmin)).max(0.);
+        let i_h = (self.ymax.min(other.ymax) - self.ymin.max(other.ymin)).max(0.);
+        let i_area = i_w * i_h;
+        let union = area(self) + area(other) - i_area;
+        if union > 0. {
+            i_area / union
+        } else {
+            0.
+        }
+    }
+}
+
+impl ColorInfo {
+    /// Create color info from RGB values
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            dominant_color: format!("rgb({}, {}, {})", r, g, b),
+            rgb: (r, g, b),
+            color_name: Self::rgb_to_color_name(r, g, b),
+        }
+    }
+
+    /// Convert RGB to human-readable color name
+    fn rgb_to_color_name(r: u8, g: u8, b: u8) -> String {
+        // Simple color classification
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+
+        // Calculate brightness
+        let brightness = (r + g + b) / 3.0;
+
+        if brightness < 50.0 {
+            return "black".to_string();
+        }
+        if brightness > 200.0 {
+            return "white".to_string();
+        }
+
+        // Determine dominant color
+        let max_val = r.max(g).max(b);
+        let min_val = r.min(g).min(b);
+        let diff = max_val - min_val;
+
+        if diff < 30.0 {
+            if brightness < 128.0 {
+                return "gray".to_string();
+            } else {
+                return "light_gray".to_string();
+            }
+        }
+
+        if r == max_val {
+            if g > b * 1.5 {
+                "orange".to_string()
+            } else if g > b {
+                "yellow".to_string()
+            } else {
+                "red".to_string()
+            }
+        } else if g == max_val {
+            if r > b * 1.2 {
+                "yellow".to_string()
+            } else {
+                "green".to_string()
+            }
+        } else {
+            if r > g * 1.2 {
+                "purple".to_string()
+            } else {
+                "blue".to_string()
+            }
+        }
+    }
+}
+
+#[test]
+fn detection_log_json() {
+    // Logs of runs without capture times leave the fields out.
+    let json = r#"{
+        "frame_number": 3,
+        "timestamp_ms": 120,
+        "object_id": "car_7",
+        "tracker_id": 7,
+        "class_name": "car",
+        "confidence": 0.75,
+        "bbox": {"xmin": 10.0, "ymin": 20.0, "xmax": 110.0, "ymax": 70.0},
+        "attributes": {
+            "color_info": null,
+            "position": {"x_center": 60.0, "y_center": 45.0, "area": 5000.0},
+            "size": {"width": 100.0, "height": 50.0, "relative_size": 2.0},
+            "person_attrs": null,
+            "custom_metadata": {}
+        }
+    }"#;
+    let log: DetectionLog = serde_json::from_str(json).unwrap();
+    assert_eq!(log.tracker_id, Some(7));
+    assert_eq!(log.capture_time_ms, None);
+    assert_eq!(log.bbox.iou(&log.bbox), 1.);
+
+    let round_trip: serde_json::Value = serde_json::to_value(&log).unwrap();
+    assert_eq!(
+        round_trip,
+        serde_json::from_str::<serde_json::Value>(json).unwrap()
+    );
+    assert_eq!(ColorInfo::from_rgb(200, 30, 30).color_name, "red");
+}
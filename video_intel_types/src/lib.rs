@@ -0,0 +1,18 @@
+//! Types of the json outputs (detection logs and per-video metadata), for consumers reading them
+//! without pulling in GStreamer or onnxruntime.
+//!
+//! `inference_common` re-exports these, constructors needing models or frames live there.
+//!
+//! Without the default `std` feature only [bbox] and [postproc] are available, and the crate
+//! builds for `no_std` targets with an allocator, eg. `wasm32-unknown-unknown`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod bbox;
+#[cfg(feature = "std")]
+pub mod detection;
+pub mod postproc;
+#[cfg(feature = "std")]
+pub mod video;
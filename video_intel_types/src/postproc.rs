@@ -0,0 +1,166 @@
+//! Post-processing of raw detector output, usable without `std` (eg. compiled to WASM for
+//! browsers or edge functions) so it matches the pipelines' results exactly.
+//!
+//! IoU and NMS lifted wholesale from candle-transformers.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::bbox::{BBoxesByClass, Bbox};
+
+/// Intersection over union of two bounding boxes.
+pub fn iou(b1: &Bbox, b2: &Bbox) -> f32 {
+    let b1_area = (b1.xmax - b1.xmin + 1.) * (b1.ymax - b1.ymin + 1.);
+    let b2_area = (b2.xmax - b2.xmin + 1.) * (b2.ymax - b2.ymin + 1.);
+    let i_xmin = b1.xmin.max(b2.xmin);
+    let i_xmax = b1.xmax.min(b2.xmax);
+    let i_ymin = b1.ymin.max(b2.ymin);
+    let i_ymax = b1.ymax.min(b2.ymax);
+    let i_area = (i_xmax - i_xmin + 1.).max(0.) * (i_ymax - i_ymin + 1.).max(0.);
+    i_area / (b1_area + b2_area - i_area)
+}
+
+/// Drops boxes overlapping a more confident one of the same class by more than `threshold` IoU.
+///
+/// Boxes with a NaN confidence rank last, so they never suppress valid ones.
+pub fn non_maximum_suppression(bboxes: &mut [Vec<Bbox>], threshold: f32) {
+    // Perform non-maximum suppression.
+    for bboxes_for_class in bboxes.iter_mut() {
+        bboxes_for_class.sort_by(|b1, b2| {
+            let (c1, c2) = (b1.detector_confidence, b2.detector_confidence);
+            c1.is_nan().cmp(&c2.is_nan()).then(c2.total_cmp(&c1))
+        });
+        let mut current_index = 0;
+        for index in 0..bboxes_for_class.len() {
+            let mut drop = false;
+            for prev_index in 0..current_index {
+                let iou = iou(&bboxes_for_class[prev_index], &bboxes_for_class[index]);
+                if iou > threshold {
+                    drop = true;
+                    break;
+                }
+            }
+            if !drop {
+                bboxes_for_class.swap(current_index, index);
+                current_index += 1;
+            }
+        }
+        bboxes_for_class.truncate(current_index);
+    }
+}
+
+/// Boxes of a single image's YOLOv8 output `preds`, laid out `[rows, anchors]` (row major,
+/// eg. `[84, 8400]` for COCO at 640x640) with rows `cx, cy, w, h`, `num_classes` class scores
+/// and possibly further values (eg. mask coefficients), which are ignored.
+///
+/// Anchors are assigned their top class and those under `conf_threshold` are dropped. Boxes are
+/// left as the model output them, including ones reaching out of the model input and non-finite
+/// values, for `inference_common::output_guard` to count and drop or clamp. Apply
+/// [non_maximum_suppression] afterwards.
+///
+/// # Panics
+///
+/// If `preds` holds less than `(4 + num_classes) * anchors` values.
+pub fn decode_yolov8(
+    preds: &[f32],
+    anchors: usize,
+    num_classes: usize,
+    conf_threshold: f32,
+) -> BBoxesByClass {
+    // First 4 values correspond to bbox cx, cy, w, h
+    const BBOX_OFFSET: usize = 4;
+    assert!(
+        preds.len() >= (BBOX_OFFSET + num_classes) * anchors,
+        "{} predictions don't hold {anchors} anchors of {num_classes} classes",
+        preds.len()
+    );
+    let value = |row: usize, anchor: usize| preds[row * anchors + anchor];
+
+    let mut bboxes_per_class: BBoxesByClass = vec![Vec::new(); num_classes];
+    for anchor in 0..anchors {
+        // Determine top1 class and its confidence.
+        let mut max_class_id = 0;
+        let mut max_confidence = 0f32;
+        for class in 0..num_classes {
+            let cls_conf = value(BBOX_OFFSET + class, anchor);
+            // A NaN score marks the anchor as broken, rather than never being its top class.
+            if cls_conf > max_confidence || cls_conf.is_nan() {
+                max_confidence = cls_conf;
+                max_class_id = class;
+            }
+        }
+
+        // Check confidence > threshold, NaN passes.
+        if max_confidence < conf_threshold {
+            continue;
+        }
+
+        let (cx, cy, w, h) = (
+            value(0, anchor),
+            value(1, anchor),
+            value(2, anchor),
+            value(3, anchor),
+        );
+        let xmin = cx - w / 2.;
+        let ymin = cy - h / 2.;
+        let xmax = xmin + w;
+        let ymax = ymin + h;
+
+        bboxes_per_class[max_class_id].push(Bbox {
+            xmin,
+            ymin,
+            xmax,
+            ymax,
+            detector_confidence: max_confidence,
+            tracker_confidence: 0f32,
+            data: vec![],
+            class: max_class_id,
+            tracker_id: None,
+            track: None,
+        });
+    }
+    bboxes_per_class
+}
+
+#[test]
+fn decode_and_suppress() {
+    // 2 classes, 3 anchors: two overlapping class 1 boxes and one below the threshold.
+    #[rustfmt::skip]
+    let preds = [
+        50., 52., 200.,
+        50., 50., 200.,
+        20., 20., 20.,
+        20., 20., 20.,
+        0.1, 0.0, 0.2,
+        0.8, 0.7, 0.1,
+    ];
+    let mut bboxes = decode_yolov8(&preds, 3, 2, 0.25);
+    assert!(bboxes[0].is_empty());
+    assert_eq!(bboxes[1].len(), 2);
+    assert_eq!(
+        (bboxes[1][0].xmin, bboxes[1][0].xmax, bboxes[1][0].class),
+        (40., 60., 1)
+    );
+
+    non_maximum_suppression(&mut bboxes, 0.5);
+    assert_eq!(bboxes[1].len(), 1);
+    assert_eq!(bboxes[1][0].detector_confidence, 0.8);
+}
+
+#[test]
+#[should_panic(expected = "don't hold 3 anchors of 2 classes")]
+fn decode_rejects_short_predictions() {
+    decode_yolov8(&[0.; 17], 3, 2, 0.25);
+}
+
+#[test]
+fn suppression_handles_nan_confidences() {
+    let bbox = |detector_confidence: f32| Bbox {
+        detector_confidence,
+        ..Bbox::test(0., 0., 10., 10.)
+    };
+    let mut bboxes = vec![vec![bbox(f32::NAN), bbox(0.5), bbox(-f32::NAN)]];
+    non_maximum_suppression(&mut bboxes, 0.5);
+    assert_eq!(bboxes[0].len(), 1);
+    assert_eq!(bboxes[0][0].detector_confidence, 0.5);
+}
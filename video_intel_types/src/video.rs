@@ -0,0 +1,48 @@
+//! Per-video metadata, as written to a json file next to processed videos.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bbox::BBoxesByClass;
+
+/// Metadata corresponding to a processed video.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VideoMeta {
+    /// Path to original input video file.
+    pub input_file: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    /// Model input `[width, height]` the frames were letterboxed into,
+    /// bboxes are in coordinates of the frame scaled to fit inside it.
+    #[serde(default)]
+    pub model_input_size: Option<[u32; 2]>,
+    /// Optional path to output video file, with inference overlays.
+    pub output_file: Option<PathBuf>,
+    /// Per-frame information with timestamps + recognized objects.
+    pub frames: Vec<FrameMeta>,
+}
+
+impl VideoMeta {
+    pub fn new(input_file: PathBuf, output_file: Option<PathBuf>, width: u32, height: u32) -> Self {
+        Self {
+            input_file,
+            width,
+            height,
+            model_input_size: None,
+            output_file,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, frame: FrameMeta) {
+        self.frames.push(frame);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FrameMeta {
+    pub pts: u64,
+    pub dts: u64,
+    pub bboxes_by_class: BBoxesByClass,
+}